@@ -6,70 +6,101 @@
 //! A global step variable needs to be provided so that this module know what is the current dual value.
 //!
 
-use crate::heapless::binary_heap::{BinaryHeap, Min};
-use crate::heapless::Vec;
+use crate::storage::*;
 use crate::util::*;
 use core::cmp::Ordering;
-#[cfg(any(test, feature = "std"))]
-use derivative::Derivative;
 
 // We need to maintain information about the blossoms, e.g., the dual variables of them.
 // The blossom indices have nice property that they will never decreasing.
 // In fact, the indices are allocated linearly, meaning it's guaranteed that the next index after K is always K+1.
 // Utilizing this, we can reduce the memory usage of the mapping significantly.
-#[cfg_attr(any(test, feature = "std"), derive(Derivative))]
-#[cfg_attr(any(test, feature = "std"), derivative(Debug))]
-pub struct BlossomTracker<const N: usize> {
-    /// the priority queue of next timestamp
-    #[cfg_attr(any(test, feature = "std"), derivative(Debug(format_with = "binary_heap_debug_formatter")))]
-    hit_zero_events: BinaryHeap<HitZeroEvent, Min, N>,
+pub struct BlossomTrackerBase<S: Storage> {
+    /// the priority queue of next timestamp, a binary min-heap of `count_events` elements
+    hit_zero_events: S::HitZeroEvents,
+    count_events: usize,
     /// it is the responsibility of outer program to report the timestamp properly
     timestamp: CompactTimestamp,
     /// the index of the first blossom, meaningless when length=0
     first_index: CompactNodeIndex,
     /// the checkpoints of dual variables
-    checkpoints: Vec<(CompactTimestamp, CompactWeight), N>,
+    checkpoints: S::Checkpoints,
     /// speed of the blossom
-    grow_states: Vec<CompactGrowState, N>,
+    grow_states: S::GrowStates,
+    /// the number of blossoms, i.e., the length of `checkpoints` and `grow_states`
+    count_blossoms: usize,
 }
 
-#[derive(Debug)]
-struct HitZeroEvent {
+pub type BlossomTracker<const N: usize> = BlossomTrackerBase<ArrayStorage<N>>;
+pub type BlossomTrackerSlice<'a> = BlossomTrackerBase<SliceStorage<'a>>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct HitZeroEvent {
     timestamp: CompactTimestamp,
     /// the node that *probably* hits zero; it's probable because we never delete such event from the queue
     node_index: CompactNodeIndex,
 }
 
-#[cfg(any(test, feature = "std"))]
-fn binary_heap_debug_formatter<const N: usize, T: std::fmt::Debug + Ord>(
-    binary_heap: &BinaryHeap<T, Min, N>,
-    formatter: &mut std::fmt::Formatter,
-) -> Result<(), std::fmt::Error> {
-    formatter
-        .debug_struct("BinaryHeap")
-        .field("top", &binary_heap.peek())
-        .field("len", &binary_heap.len())
-        .finish()
+impl HitZeroEvent {
+    /// the initial value of the buffer, never read
+    const PLACEHOLDER: Self = Self {
+        timestamp: 0,
+        node_index: CompactNodeIndex::new(0).unwrap(),
+    };
 }
 
-impl<const N: usize> BlossomTracker<N> {
+impl<const N: usize, const VN: usize> BlossomTrackerBase<ArrayStorage<N, VN>> {
     pub const fn new() -> Self {
         Self {
-            hit_zero_events: BinaryHeap::new(),
+            hit_zero_events: [HitZeroEvent::PLACEHOLDER; N],
+            count_events: 0,
             timestamp: 0,
             first_index: match CompactNodeIndex::new(0).option() {
                 Some(index) => index,
                 None => unreachable!(),
             },
-            checkpoints: Vec::new(),
-            grow_states: Vec::new(),
+            checkpoints: [(0, 0); N],
+            grow_states: [CompactGrowState::Stay; N],
+            count_blossoms: 0,
+        }
+    }
+}
+
+impl<'a> BlossomTrackerSlice<'a> {
+    /// allocate a tracker of at most `capacity` blossoms in the arena
+    pub fn new_in(arena: &mut Arena<'a>, capacity: usize) -> Option<Self> {
+        Some(Self::from_buffers(
+            arena.alloc_slice(capacity, HitZeroEvent::PLACEHOLDER)?,
+            arena.alloc_slice(capacity, (0, 0))?,
+            arena.alloc_slice(capacity, CompactGrowState::Stay)?,
+        ))
+    }
+}
+
+impl<S: Storage> BlossomTrackerBase<S> {
+    /// the three buffers must have the same capacity; the content of the buffers is ignored
+    pub fn from_buffers(hit_zero_events: S::HitZeroEvents, checkpoints: S::Checkpoints, grow_states: S::GrowStates) -> Self {
+        let capacity = checkpoints.as_ref().len();
+        assert_eq!(capacity, hit_zero_events.as_ref().len());
+        assert_eq!(capacity, grow_states.as_ref().len());
+        Self {
+            hit_zero_events,
+            count_events: 0,
+            timestamp: 0,
+            first_index: CompactNodeIndex::new(0).unwrap(),
+            checkpoints,
+            grow_states,
+            count_blossoms: 0,
         }
     }
 
+    /// the maximum number of blossoms
+    pub fn capacity(&self) -> usize {
+        self.checkpoints.as_ref().len()
+    }
+
     pub fn clear(&mut self) {
-        self.hit_zero_events.clear();
-        self.checkpoints.clear();
-        self.grow_states.clear();
+        self.count_events = 0;
+        self.count_blossoms = 0;
     }
 
     #[inline(always)]
@@ -78,7 +109,7 @@ impl<const N: usize> BlossomTracker<N> {
         debug_assert!(
             {
                 self.remove_outdated_events();
-                if let Some(event) = self.hit_zero_events.peek() {
+                if let Some(event) = self.peek_event() {
                     self.timestamp <= event.timestamp
                 } else {
                     true
@@ -92,11 +123,11 @@ impl<const N: usize> BlossomTracker<N> {
     fn assert_valid_node_index(&self, node_index: CompactNodeIndex) {
         debug_assert!(
             node_index.get() >= self.first_index.get()
-                && node_index.get() < self.first_index.get() + self.checkpoints.len() as CompactNodeNum,
+                && node_index.get() < self.first_index.get() + self.count_blossoms as CompactNodeNum,
             "invalid node index {}, not within the range of [{}, {})",
             node_index,
             self.first_index,
-            self.first_index.get() + self.checkpoints.len() as CompactNodeNum
+            self.first_index.get() + self.count_blossoms as CompactNodeNum
         );
     }
 
@@ -108,41 +139,47 @@ impl<const N: usize> BlossomTracker<N> {
 
     pub fn create_blossom(&mut self, node_index: CompactNodeIndex) {
         debug_assert!(
-            self.checkpoints.is_empty()
-                || node_index.get() == self.first_index.get() + self.checkpoints.len() as CompactNodeNum
+            self.count_blossoms == 0 || node_index.get() == self.first_index.get() + self.count_blossoms as CompactNodeNum
         );
-        if self.checkpoints.is_empty() {
+        if self.count_blossoms == 0 {
             self.first_index = node_index;
         }
-        self.checkpoints.push((self.timestamp, 0)).ok().unwrap();
-        self.grow_states.push(CompactGrowState::Grow).ok().unwrap();
+        if self.count_blossoms == self.capacity() {
+            cfg_if::cfg_if! {
+                if #[cfg(feature="dangerous_unwrap")] {
+                    return;
+                } else {
+                    panic!("blossom tracker overflow");
+                }
+            }
+        }
+        set!(self.checkpoints.as_mut(), self.count_blossoms, (self.timestamp, 0));
+        set!(self.grow_states.as_mut(), self.count_blossoms, CompactGrowState::Grow);
+        self.count_blossoms += 1;
     }
 
     pub fn set_speed(&mut self, node_index: CompactNodeIndex, grow_state: CompactGrowState) {
         let local_index = self.local_index_of(node_index);
         // update checkpoint timestamp to the current timestamp and update its dual value accordingly
-        if &grow_state == get!(self.grow_states, local_index) {
+        if &grow_state == get!(self.grow_states.as_ref(), local_index) {
             return; // no need to set speed
         }
         let dual_value = self.local_get_dual_variable(local_index);
-        set!(self.checkpoints, local_index, (self.timestamp, dual_value));
-        set!(self.grow_states, local_index, grow_state);
+        set!(self.checkpoints.as_mut(), local_index, (self.timestamp, dual_value));
+        set!(self.grow_states.as_mut(), local_index, grow_state);
         // insert a hit-zero event if the blossom becomes shrinking
         if grow_state == CompactGrowState::Shrink {
-            self.hit_zero_events
-                .push(HitZeroEvent {
-                    timestamp: self.timestamp + dual_value as CompactTimestamp,
-                    node_index,
-                })
-                .ok()
-                .unwrap();
+            self.push_event(HitZeroEvent {
+                timestamp: self.timestamp + dual_value as CompactTimestamp,
+                node_index,
+            });
         }
     }
 
     fn local_get_dual_variable(&self, local_index: usize) -> CompactWeight {
-        let (timestamp, dual_value) = *get!(self.checkpoints, local_index);
+        let (timestamp, dual_value) = *get!(self.checkpoints.as_ref(), local_index);
         let delta = (self.timestamp - timestamp) as CompactWeight;
-        let dual_value = match *get!(self.grow_states, local_index) {
+        let dual_value = match *get!(self.grow_states.as_ref(), local_index) {
             CompactGrowState::Grow => dual_value + delta,
             CompactGrowState::Shrink => dual_value - delta,
             CompactGrowState::Stay => dual_value,
@@ -158,7 +195,7 @@ impl<const N: usize> BlossomTracker<N> {
     #[inline]
     fn is_valid_event(&self, first_event: &HitZeroEvent) -> bool {
         let local_index = self.local_index_of(first_event.node_index);
-        if self.grow_states[local_index] == CompactGrowState::Shrink {
+        if self.grow_states.as_ref()[local_index] == CompactGrowState::Shrink {
             let dual_value = self.local_get_dual_variable(local_index);
             let actual_timestamp = self.timestamp + dual_value as CompactTimestamp;
             debug_assert!(
@@ -174,22 +211,109 @@ impl<const N: usize> BlossomTracker<N> {
 
     #[inline(always)]
     fn remove_outdated_events(&mut self) {
-        while !self.hit_zero_events.is_empty() {
-            if self.is_valid_event(self.hit_zero_events.peek().unwrap()) {
+        while let Some(event) = self.peek_event() {
+            if self.is_valid_event(event) {
                 return;
             }
-            self.hit_zero_events.pop().unwrap();
+            self.pop_event();
         }
     }
 
     #[inline(always)]
     pub fn get_maximum_growth(&mut self) -> Option<(CompactWeight, CompactNodeIndex)> {
         self.remove_outdated_events();
-        self.hit_zero_events.peek().map(|event| {
+        self.peek_event().map(|event| {
             debug_assert!(event.timestamp >= self.timestamp);
             ((event.timestamp - self.timestamp) as CompactWeight, event.node_index)
         })
     }
+
+    /* binary min-heap of the hit-zero events, following `heapless::binary_heap` so that the order of events with
+    the same timestamp doesn't change */
+
+    fn peek_event(&self) -> Option<&HitZeroEvent> {
+        if self.count_events == 0 {
+            None
+        } else {
+            Some(get!(self.hit_zero_events.as_ref(), 0))
+        }
+    }
+
+    fn push_event(&mut self, event: HitZeroEvent) {
+        if self.count_events == self.hit_zero_events.as_ref().len() {
+            cfg_if::cfg_if! {
+                if #[cfg(feature="dangerous_unwrap")] {
+                    return;
+                } else {
+                    panic!("hit zero event queue overflow");
+                }
+            }
+        }
+        set!(self.hit_zero_events.as_mut(), self.count_events, event);
+        self.count_events += 1;
+        self.sift_up_event(0, self.count_events - 1);
+    }
+
+    fn pop_event(&mut self) {
+        debug_assert!(self.count_events > 0);
+        self.count_events -= 1;
+        if self.count_events > 0 {
+            let events = self.hit_zero_events.as_mut();
+            set!(events, 0, *get!(events, self.count_events));
+            self.sift_down_to_bottom_event(0);
+        }
+    }
+
+    fn sift_up_event(&mut self, start: usize, mut position: usize) {
+        let events = self.hit_zero_events.as_mut();
+        let event = *get!(events, position);
+        while position > start {
+            let parent = (position - 1) / 2;
+            if event.cmp(get!(events, parent)) != Ordering::Less {
+                break;
+            }
+            set!(events, position, *get!(events, parent));
+            position = parent;
+        }
+        set!(events, position, event);
+    }
+
+    fn sift_down_to_bottom_event(&mut self, start: usize) {
+        let end = self.count_events;
+        let events = self.hit_zero_events.as_mut();
+        let event = *get!(events, start);
+        let mut position = start;
+        let mut child = 2 * position + 1;
+        while child < end {
+            let right = child + 1;
+            // compare with the smaller of the two children
+            if right < end && get!(events, child).cmp(get!(events, right)) != Ordering::Less {
+                child = right;
+            }
+            set!(events, position, *get!(events, child));
+            position = child;
+            child = 2 * position + 1;
+        }
+        set!(events, position, event);
+        self.sift_up_event(start, position);
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl<S: Storage> std::fmt::Debug for BlossomTrackerBase<S> {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter
+            .debug_struct("BlossomTracker")
+            .field(
+                "hit_zero_events",
+                &format_args!("BinaryHeap {{ top: {:?}, len: {} }}", self.peek_event(), self.count_events),
+            )
+            .field("timestamp", &self.timestamp)
+            .field("first_index", &self.first_index)
+            .field("checkpoints", &&self.checkpoints.as_ref()[..self.count_blossoms])
+            .field("grow_states", &&self.grow_states.as_ref()[..self.count_blossoms])
+            .finish()
+    }
 }
 
 impl Ord for HitZeroEvent {
@@ -280,4 +404,63 @@ mod tests {
         tracker.set_speed(node_2, CompactGrowState::Shrink);
         assert_eq!(tracker.get_maximum_growth(), Some((60, node_2)));
     }
+
+    #[test]
+    fn blossom_tracker_slice() {
+        // cargo test blossom_tracker_slice -- --nocapture
+        const N: usize = 10;
+        let mut memory = [0u8; 1024];
+        let mut arena = Arena::new(&mut memory);
+        let mut slice_tracker = BlossomTrackerSlice::new_in(&mut arena, N).unwrap();
+        assert_eq!(slice_tracker.capacity(), N);
+        assert!(BlossomTrackerSlice::new_in(&mut arena, 1000).is_none());
+        let mut tracker = BlossomTracker::<N>::new();
+        // a simple linear congruential generator to drive random operations
+        let mut state: u64 = 12345;
+        let mut random = move || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) as usize
+        };
+        let blossom_bias = 0x1100;
+        for _ in 0..10000 {
+            match random() % 10 {
+                0 => {
+                    tracker.clear();
+                    slice_tracker.clear();
+                }
+                1..=3 => {
+                    if tracker.count_blossoms < N {
+                        let node_index = ni!(blossom_bias + tracker.count_blossoms as CompactNodeNum);
+                        tracker.create_blossom(node_index);
+                        slice_tracker.create_blossom(node_index);
+                    }
+                }
+                4..=6 => {
+                    if tracker.count_blossoms > 0 {
+                        let node_index = ni!(blossom_bias + (random() % tracker.count_blossoms) as CompactNodeNum);
+                        let grow_state =
+                            [CompactGrowState::Grow, CompactGrowState::Shrink, CompactGrowState::Stay][random() % 3];
+                        tracker.set_speed(node_index, grow_state);
+                        slice_tracker.set_speed(node_index, grow_state);
+                    }
+                }
+                _ => {
+                    let mut delta = (random() % 5) as CompactTimestamp;
+                    if let Some((length, _)) = tracker.get_maximum_growth() {
+                        delta = delta.min(length as CompactTimestamp);
+                    }
+                    tracker.advance_time(delta);
+                    slice_tracker.advance_time(delta);
+                }
+            }
+            assert_eq!(tracker.get_maximum_growth(), slice_tracker.get_maximum_growth());
+            for index in 0..tracker.count_blossoms {
+                let node_index = ni!(blossom_bias + index as CompactNodeNum);
+                assert_eq!(
+                    tracker.get_dual_variable(node_index),
+                    slice_tracker.get_dual_variable(node_index)
+                );
+            }
+        }
+    }
 }
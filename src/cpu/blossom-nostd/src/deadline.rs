@@ -0,0 +1,80 @@
+//! Deadline
+//!
+//! Real-time decoding needs an answer within a fixed budget. A deadline is checked before resolving each obstacle;
+//! once it expires, the primal-dual loop stops and the partial matching is completed by a best-effort fallback, see
+//! [`crate::primal_module_embedded::PrimalModuleEmbeddedBase::break_alternating_trees`]. The result is then a valid
+//! but not necessarily minimum-weight matching.
+//!
+
+pub trait Deadline {
+    /// whether the budget is exhausted after resolving `iteration` obstacles
+    fn is_expired(&mut self, iteration: usize) -> bool;
+}
+
+/// no deadline at all
+impl<D: Deadline> Deadline for Option<D> {
+    fn is_expired(&mut self, iteration: usize) -> bool {
+        self.as_mut().map_or(false, |deadline| deadline.is_expired(iteration))
+    }
+}
+
+/// expire after resolving a fixed number of obstacles
+#[cfg_attr(any(test, feature = "std"), derive(Debug))]
+#[derive(Clone, Copy)]
+pub struct IterationDeadline {
+    pub max_iterations: usize,
+}
+
+impl Deadline for IterationDeadline {
+    fn is_expired(&mut self, iteration: usize) -> bool {
+        iteration >= self.max_iterations
+    }
+}
+
+/// expire when the native time of the platform reaches `end`, e.g., `get_native_time` on embedded
+pub struct NativeTimeDeadline<F: FnMut() -> u64> {
+    pub get_native_time: F,
+    pub end: u64,
+}
+
+impl<F: FnMut() -> u64> NativeTimeDeadline<F> {
+    pub fn new(get_native_time: F, end: u64) -> Self {
+        Self { get_native_time, end }
+    }
+
+    /// expire `budget` after the current native time
+    pub fn after(mut get_native_time: F, budget: u64) -> Self {
+        let end = get_native_time().saturating_add(budget);
+        Self { get_native_time, end }
+    }
+}
+
+impl<F: FnMut() -> u64> Deadline for NativeTimeDeadline<F> {
+    fn is_expired(&mut self, _iteration: usize) -> bool {
+        (self.get_native_time)() >= self.end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    #[test]
+    fn deadline_basic() {
+        // cargo test deadline_basic -- --nocapture
+        let mut deadline = IterationDeadline { max_iterations: 3 };
+        assert!(!deadline.is_expired(2));
+        assert!(deadline.is_expired(3));
+        let mut no_deadline: Option<IterationDeadline> = None;
+        assert!(!no_deadline.is_expired(usize::MAX));
+        let time = Cell::new(100u64);
+        let mut deadline = NativeTimeDeadline::after(|| time.get(), 50);
+        assert_eq!(deadline.end, 150);
+        assert!(!deadline.is_expired(0));
+        time.set(150);
+        assert!(deadline.is_expired(0));
+        let mut deadline = NativeTimeDeadline::after(|| u64::MAX - 1, 50);
+        assert!(!deadline.is_expired(0));
+    }
+}
@@ -7,6 +7,7 @@
 use crate::blossom_tracker::*;
 use crate::dual_module_stackless::*;
 use crate::interface::*;
+use crate::storage::*;
 use crate::util::*;
 
 pub trait DualTrackedDriver {
@@ -15,19 +16,49 @@ pub trait DualTrackedDriver {
     fn find_conflict(&mut self, maximum_growth: CompactWeight) -> (CompactObstacle, CompactWeight);
 }
 
-pub struct DualDriverTracked<D: DualStacklessDriver + DualTrackedDriver, const N: usize> {
+/// the fallible version of [`DualTrackedDriver`]
+pub trait FallibleDualTrackedDriver {
+    fn try_find_conflict(
+        &mut self,
+        maximum_growth: CompactWeight,
+    ) -> Result<(CompactObstacle, CompactWeight), MicroBlossomError>;
+}
+
+impl<D: DualTrackedDriver> FallibleDualTrackedDriver for D {
+    fn try_find_conflict(
+        &mut self,
+        maximum_growth: CompactWeight,
+    ) -> Result<(CompactObstacle, CompactWeight), MicroBlossomError> {
+        Ok(self.find_conflict(maximum_growth))
+    }
+}
+
+/// the first error of the fallible driver is kept until it's taken; in the meantime, all the operations are skipped
+/// and no obstacle is reported, so that the primal module finishes the current shot quickly
+pub struct DualDriverTrackedBase<D: FallibleDualStacklessDriver + FallibleDualTrackedDriver, S: Storage> {
     pub driver: D,
-    pub blossom_tracker: BlossomTracker<N>,
+    pub blossom_tracker: BlossomTrackerBase<S>,
+    pub error: Option<MicroBlossomError>,
 }
 
-impl<D: DualStacklessDriver + DualTrackedDriver, const N: usize> DualStacklessDriver for DualDriverTracked<D, N> {
+pub type DualDriverTracked<D, const N: usize> = DualDriverTrackedBase<D, ArrayStorage<N>>;
+pub type DualDriverTrackedSlice<'a, D> = DualDriverTrackedBase<D, SliceStorage<'a>>;
+
+impl<D: FallibleDualStacklessDriver + FallibleDualTrackedDriver, S: Storage> DualStacklessDriver
+    for DualDriverTrackedBase<D, S>
+{
     fn reset(&mut self) {
-        self.driver.reset();
+        self.error = None;
+        let result = self.driver.try_reset();
+        self.record(result);
         self.blossom_tracker.clear();
     }
 
     fn set_speed(&mut self, is_blossom: bool, node: CompactNodeIndex, speed: CompactGrowState) {
-        self.driver.set_speed(is_blossom, node, speed);
+        if self.error.is_none() {
+            let result = self.driver.try_set_speed(is_blossom, node, speed);
+            self.record(result);
+        }
         if is_blossom {
             self.blossom_tracker.set_speed(node, speed);
         }
@@ -46,12 +77,18 @@ impl<D: DualStacklessDriver + DualTrackedDriver, const N: usize> DualStacklessDr
     }
 
     fn set_blossom(&mut self, node: CompactNodeIndex, blossom: CompactNodeIndex) {
-        self.driver.set_blossom(node, blossom);
+        if self.error.is_none() {
+            let result = self.driver.try_set_blossom(node, blossom);
+            self.record(result);
+        }
     }
 
     fn find_obstacle(&mut self) -> (CompactObstacle, CompactWeight) {
         let mut grown = 0;
         loop {
+            if self.error.is_some() {
+                return (CompactObstacle::None, grown);
+            }
             let maximum_growth = if let Some((length, blossom)) = self.blossom_tracker.get_maximum_growth() {
                 if length == 0 {
                     return (CompactObstacle::BlossomNeedExpand { blossom }, grown);
@@ -61,7 +98,13 @@ impl<D: DualStacklessDriver + DualTrackedDriver, const N: usize> DualStacklessDr
             } else {
                 CompactWeight::MAX
             };
-            let (obstacle, local_grown) = self.driver.find_conflict(maximum_growth);
+            let (obstacle, local_grown) = match self.driver.try_find_conflict(maximum_growth) {
+                Ok(response) => response,
+                Err(error) => {
+                    self.error = Some(error);
+                    continue;
+                }
+            };
             self.blossom_tracker.advance_time(local_grown as CompactTimestamp);
             grown += local_grown;
             if !obstacle.is_finite_growth() {
@@ -71,15 +114,182 @@ impl<D: DualStacklessDriver + DualTrackedDriver, const N: usize> DualStacklessDr
     }
 
     fn add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) {
-        self.driver.add_defect(vertex, node);
+        if self.error.is_none() {
+            let result = self.driver.try_add_defect(vertex, node);
+            self.record(result);
+        }
+    }
+
+    fn take_error(&mut self) -> Option<MicroBlossomError> {
+        self.error.take()
     }
 }
 
-impl<D: DualStacklessDriver + DualTrackedDriver, const N: usize> DualDriverTracked<D, N> {
+impl<D: FallibleDualStacklessDriver + FallibleDualTrackedDriver, const N: usize> DualDriverTracked<D, N> {
     pub const fn new(driver: D) -> Self {
+        Self::new_with_tracker(driver, BlossomTracker::new())
+    }
+}
+
+impl<D: FallibleDualStacklessDriver + FallibleDualTrackedDriver, S: Storage> DualDriverTrackedBase<D, S> {
+    /// use a blossom tracker of any storage, e.g., [`BlossomTrackerSlice::new_in`]
+    pub const fn new_with_tracker(driver: D, blossom_tracker: BlossomTrackerBase<S>) -> Self {
         Self {
             driver,
-            blossom_tracker: BlossomTracker::new(),
+            blossom_tracker,
+            error: None,
+        }
+    }
+
+    /// keep the first error
+    fn record(&mut self, result: Result<(), MicroBlossomError>) {
+        if let Err(error) = result {
+            if self.error.is_none() {
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primal_module_embedded::*;
+
+    /// a driver that fails after a given number of operations
+    struct FailingDriver {
+        remaining: usize,
+    }
+
+    impl FailingDriver {
+        fn operate(&mut self) -> Result<(), MicroBlossomError> {
+            if self.remaining == 0 {
+                return Err(MicroBlossomError::Transport {
+                    kind: TransportErrorKind::TimedOut,
+                });
+            }
+            self.remaining -= 1;
+            Ok(())
+        }
+    }
+
+    impl FallibleDualStacklessDriver for FailingDriver {
+        fn try_reset(&mut self) -> Result<(), MicroBlossomError> {
+            self.operate()
+        }
+        fn try_set_speed(&mut self, _: bool, _: CompactNodeIndex, _: CompactGrowState) -> Result<(), MicroBlossomError> {
+            self.operate()
         }
+        fn try_set_blossom(&mut self, _: CompactNodeIndex, _: CompactNodeIndex) -> Result<(), MicroBlossomError> {
+            self.operate()
+        }
+        fn try_find_obstacle(&mut self) -> Result<(CompactObstacle, CompactWeight), MicroBlossomError> {
+            self.operate()?;
+            Ok((CompactObstacle::None, 0))
+        }
+        fn try_add_defect(&mut self, _: CompactVertexIndex, _: CompactNodeIndex) -> Result<(), MicroBlossomError> {
+            self.operate()
+        }
+    }
+
+    impl FallibleDualTrackedDriver for FailingDriver {
+        fn try_find_conflict(&mut self, _: CompactWeight) -> Result<(CompactObstacle, CompactWeight), MicroBlossomError> {
+            self.operate()?;
+            // always conflict between the first two defects
+            let conflict = CompactObstacle::Conflict {
+                node_1: ni!(0).option(),
+                node_2: ni!(1).option(),
+                touch_1: ni!(0).option(),
+                touch_2: ni!(1).option(),
+                vertex_1: ni!(0),
+                vertex_2: ni!(1),
+            };
+            Ok((conflict, 1))
+        }
+    }
+
+    #[test]
+    fn dual_driver_tracked_error_latch() {
+        // cargo test dual_driver_tracked_error_latch -- --nocapture
+        let mut dual_module = DualModuleStackless::new(DualDriverTracked::<_, 16>::new(FailingDriver { remaining: 3 }));
+        dual_module.reset();
+        dual_module.add_defect(ni!(0), ni!(0));
+        dual_module.add_defect(ni!(1), ni!(1));
+        assert_eq!(dual_module.take_error(), None);
+        // the driver fails in the following `find_conflict`, after which no obstacle is reported
+        assert_eq!(dual_module.find_obstacle(), (CompactObstacle::None, 0));
+        dual_module.set_speed(false, ni!(0), CompactGrowState::Stay);
+        assert_eq!(
+            dual_module.take_error(),
+            Some(MicroBlossomError::Transport {
+                kind: TransportErrorKind::TimedOut,
+            })
+        );
+        assert_eq!(dual_module.take_error(), None);
+        // a new shot starts clean once the driver recovers
+        dual_module.driver.driver.remaining = 10;
+        dual_module.reset();
+        assert_eq!(dual_module.take_error(), None);
+    }
+
+    #[test]
+    fn dual_driver_tracked_try_resolve() {
+        // cargo test dual_driver_tracked_try_resolve -- --nocapture
+        let mut dual_module = DualModuleStackless::new(DualDriverTracked::<_, 4>::new(FailingDriver { remaining: 10 }));
+        let mut primal_module: PrimalModuleEmbedded<4> = PrimalModuleEmbedded::new();
+        dual_module.reset();
+        dual_module.add_defect(ni!(0), ni!(0));
+        dual_module.add_defect(ni!(1), ni!(1));
+        let (obstacle, _) = dual_module.find_obstacle();
+        assert_eq!(primal_module.try_resolve(&mut dual_module, obstacle), Ok(true));
+        // the driver fails when the primal module sets the speed
+        dual_module.driver.driver.remaining = 0;
+        primal_module.reset();
+        let obstacle = CompactObstacle::Conflict {
+            node_1: ni!(0).option(),
+            node_2: ni!(1).option(),
+            touch_1: ni!(0).option(),
+            touch_2: ni!(1).option(),
+            vertex_1: ni!(0),
+            vertex_2: ni!(1),
+        };
+        assert_eq!(
+            primal_module.try_resolve(&mut dual_module, obstacle.clone()),
+            Err(MicroBlossomError::Transport {
+                kind: TransportErrorKind::TimedOut,
+            })
+        );
+        // no space for any blossom: only the conflict that creates a blossom fails
+        dual_module.driver.driver.remaining = usize::MAX;
+        primal_module.reset();
+        primal_module.nodes.blossom_begin = 4;
+        let conflict = |node_1: usize, node_2: usize| CompactObstacle::Conflict {
+            node_1: ni!(node_1).option(),
+            node_2: ni!(node_2).option(),
+            touch_1: ni!(node_1).option(),
+            touch_2: ni!(node_2).option(),
+            vertex_1: ni!(node_1),
+            vertex_2: ni!(node_2),
+        };
+        assert_eq!(primal_module.try_resolve(&mut dual_module, obstacle), Ok(true));
+        assert_eq!(primal_module.try_resolve(&mut dual_module, conflict(2, 1)), Ok(true));
+        assert_eq!(
+            primal_module.try_resolve(&mut dual_module, conflict(2, 0)),
+            Err(MicroBlossomError::CapacityExhausted)
+        );
+        // a free node augments the tree without allocating a blossom
+        assert_eq!(primal_module.try_resolve(&mut dual_module, conflict(3, 0)), Ok(true));
+        let overflow = CompactObstacle::Conflict {
+            node_1: ni!(0).option(),
+            node_2: ni!(7).option(),
+            touch_1: ni!(0).option(),
+            touch_2: ni!(7).option(),
+            vertex_1: ni!(0),
+            vertex_2: ni!(7),
+        };
+        assert_eq!(
+            primal_module.try_resolve(&mut dual_module, overflow),
+            Err(MicroBlossomError::NodeIndexOverflow { node_index: 7 })
+        );
     }
 }
@@ -16,6 +16,64 @@ pub trait DualStacklessDriver {
     fn on_blossom_created(&mut self, _blossom: CompactNodeIndex) {}
     fn on_blossom_expanded(&mut self, _blossom: CompactNodeIndex) {}
     fn on_blossom_absorbed_into_blossom(&mut self, _child: CompactNodeIndex) {}
+    /// take the first error happened since the last call, if the driver records errors
+    fn take_error(&mut self) -> Option<MicroBlossomError> {
+        None
+    }
+}
+
+/// a driver whose operations may fail, e.g. when the accelerator is behind a bus or a network connection;
+/// every infallible driver is also a fallible driver that never fails
+pub trait FallibleDualStacklessDriver {
+    fn try_reset(&mut self) -> Result<(), MicroBlossomError>;
+    fn try_set_speed(
+        &mut self,
+        is_blossom: bool,
+        node: CompactNodeIndex,
+        speed: CompactGrowState,
+    ) -> Result<(), MicroBlossomError>;
+    fn try_set_blossom(&mut self, node: CompactNodeIndex, blossom: CompactNodeIndex) -> Result<(), MicroBlossomError>;
+    fn try_find_obstacle(&mut self) -> Result<(CompactObstacle, CompactWeight), MicroBlossomError>;
+    fn try_add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) -> Result<(), MicroBlossomError>;
+    fn on_blossom_created(&mut self, _blossom: CompactNodeIndex) {}
+    fn on_blossom_expanded(&mut self, _blossom: CompactNodeIndex) {}
+    fn on_blossom_absorbed_into_blossom(&mut self, _child: CompactNodeIndex) {}
+}
+
+impl<D: DualStacklessDriver> FallibleDualStacklessDriver for D {
+    fn try_reset(&mut self) -> Result<(), MicroBlossomError> {
+        self.reset();
+        Ok(())
+    }
+    fn try_set_speed(
+        &mut self,
+        is_blossom: bool,
+        node: CompactNodeIndex,
+        speed: CompactGrowState,
+    ) -> Result<(), MicroBlossomError> {
+        self.set_speed(is_blossom, node, speed);
+        Ok(())
+    }
+    fn try_set_blossom(&mut self, node: CompactNodeIndex, blossom: CompactNodeIndex) -> Result<(), MicroBlossomError> {
+        self.set_blossom(node, blossom);
+        Ok(())
+    }
+    fn try_find_obstacle(&mut self) -> Result<(CompactObstacle, CompactWeight), MicroBlossomError> {
+        Ok(self.find_obstacle())
+    }
+    fn try_add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) -> Result<(), MicroBlossomError> {
+        self.add_defect(vertex, node);
+        Ok(())
+    }
+    fn on_blossom_created(&mut self, blossom: CompactNodeIndex) {
+        DualStacklessDriver::on_blossom_created(self, blossom)
+    }
+    fn on_blossom_expanded(&mut self, blossom: CompactNodeIndex) {
+        DualStacklessDriver::on_blossom_expanded(self, blossom)
+    }
+    fn on_blossom_absorbed_into_blossom(&mut self, child: CompactNodeIndex) {
+        DualStacklessDriver::on_blossom_absorbed_into_blossom(self, child)
+    }
 }
 
 pub struct DualModuleStackless<D: DualStacklessDriver> {
@@ -74,6 +132,10 @@ impl<D: DualStacklessDriver> DualInterface for DualModuleStackless<D> {
     fn add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) {
         self.driver.add_defect(vertex, node);
     }
+
+    fn take_error(&mut self) -> Option<MicroBlossomError> {
+        self.driver.take_error()
+    }
 }
 
 impl<D: DualStacklessDriver> DualModuleStackless<D> {
@@ -27,6 +27,8 @@ pub const EXTENDED_OP_CODE_LOAD_WEIGHTS_EXTERNAL: u32 = 0b011 << 3;
 pub const EXTENDED_OP_CODE_RESET: u32 = 0b100 << 3;
 pub const EXTENDED_OP_CODE_LOAD_DEFECTS_EXTERNAL: u32 = 0b101 << 3;
 pub const EXTENDED_OP_CODE_GROW: u32 = 0b110 << 3;
+/// `SetAttribute` in the hardware, only used for debugging and not decoded here
+pub const EXTENDED_OP_CODE_RESERVED: u32 = 0b111 << 3;
 
 impl Instruction32 {
     pub fn set_speed(node: CompactNodeIndex, speed: CompactGrowState) -> Self {
@@ -61,6 +63,19 @@ impl Instruction32 {
     pub fn find_obstacle() -> Self {
         Self(EXTENDED_OP_CODE_ENABLE | EXTENDED_OP_CODE_FIND_OBSTACLE)
     }
+    pub fn match_vertices(vertex_1: CompactVertexIndex, vertex_2: CompactVertexIndex) -> Self {
+        let field_vertex_1 = (vertex_1.get() as u32) << 17;
+        let field_vertex_2 = (vertex_2.get() as u32) << 2;
+        Self(field_vertex_1 | field_vertex_2 | OP_CODE_MATCH)
+    }
+    pub fn clear_accumulator(address: u32) -> Self {
+        let field_address = address << 17;
+        Self(field_address | EXTENDED_OP_CODE_ENABLE | EXTENDED_OP_CODE_CLEAR_ACCUMULATOR)
+    }
+    pub fn accumulate_edge(edge: CompactEdgeIndex) -> Self {
+        let field_edge = edge << 17;
+        Self(field_edge | EXTENDED_OP_CODE_ENABLE | EXTENDED_OP_CODE_ACCUMULATE_EDGE)
+    }
 
     pub fn is_extended(self) -> bool {
         self.op_code() == OP_CODE_SET_SPEED && (self.0 & EXTENDED_OP_CODE_ENABLE) != 0
@@ -88,8 +103,42 @@ impl Instruction32 {
     pub fn field1(self) -> u32 {
         (self.0 >> 17) & ((1 << 15) - 1)
     }
+    pub fn field2(self) -> u32 {
+        (self.0 >> 2) & ((1 << 15) - 1)
+    }
+    pub fn get_length(self) -> u32 {
+        self.0 >> 6
+    }
+    /// the channel of `LoadDefectsExternal`
+    pub fn field_channel(self) -> u32 {
+        (self.0 >> 6) & ((1 << 11) - 1)
+    }
     pub fn get_speed(self) -> CompactGrowState {
-        FromPrimitive::from_u32((self.0 >> 15) & ((1 << 2) - 1)).unwrap()
+        self.try_get_speed().unwrap()
+    }
+    pub fn try_get_speed(self) -> Option<CompactGrowState> {
+        FromPrimitive::from_u32((self.0 >> 15) & ((1 << 2) - 1))
+    }
+
+    /// the bits that are not covered by any field of the instruction and should be zero;
+    /// `None` if the instruction cannot be decoded at all
+    pub fn reserved_bits(self) -> Option<u32> {
+        let bits = |lsb: usize, msb: usize| (u32::MAX >> (31 - msb)) & (u32::MAX << lsb);
+        if self.is_set_speed() {
+            self.try_get_speed()?;
+            return Some(self.0 & bits(3, 14));
+        }
+        if !self.is_extended() {
+            return Some(0); // SetBlossom, Match and AddDefectVertex use all the bits
+        }
+        Some(
+            self.0
+                & match self.extended_op_code() {
+                    EXTENDED_OP_CODE_LOAD_DEFECTS_EXTERNAL | EXTENDED_OP_CODE_GROW => 0,
+                    EXTENDED_OP_CODE_LOAD_WEIGHTS_EXTERNAL | EXTENDED_OP_CODE_RESET => bits(6, 31),
+                    _ => bits(6, 16),
+                },
+        )
     }
 
     #[cfg(any(test, feature = "std"))]
@@ -108,17 +157,173 @@ impl Into<u32> for Instruction32 {
     }
 }
 
+/// the name and the fields of an instruction, in the order of the textual form; `None` if the instruction is not valid
+#[cfg(any(test, feature = "std"))]
+fn instruction_fields(instruction: Instruction32) -> Option<(&'static str, Vec<(&'static str, u32)>)> {
+    if instruction.reserved_bits()? != 0 {
+        return None;
+    }
+    let field1 = instruction.field1();
+    Some(match instruction.op_code() {
+        OP_CODE_SET_BLOSSOM => ("SetBlossom", vec![("node", field1), ("blossom", instruction.field2())]),
+        OP_CODE_MATCH => ("Match", vec![("vertex_1", field1), ("vertex_2", instruction.field2())]),
+        OP_CODE_ADD_DEFECT_VERTEX => ("AddDefectVertex", vec![("vertex", field1), ("node", instruction.field2())]),
+        _ if instruction.is_set_speed() => (
+            "SetSpeed",
+            vec![("node", field1), ("speed", instruction.try_get_speed()? as u32)],
+        ),
+        _ => match instruction.extended_op_code() {
+            EXTENDED_OP_CODE_FIND_OBSTACLE => ("FindObstacle", vec![("region_preference", field1)]),
+            EXTENDED_OP_CODE_CLEAR_ACCUMULATOR => ("ClearAccumulator", vec![("address", field1)]),
+            EXTENDED_OP_CODE_ACCUMULATE_EDGE => ("AccumulateEdge", vec![("edge", field1)]),
+            EXTENDED_OP_CODE_LOAD_WEIGHTS_EXTERNAL => ("LoadWeightsExternal", vec![]),
+            EXTENDED_OP_CODE_RESET => ("Reset", vec![]),
+            EXTENDED_OP_CODE_LOAD_DEFECTS_EXTERNAL => (
+                "LoadDefectsExternal",
+                vec![("time", field1), ("channel", instruction.field_channel())],
+            ),
+            EXTENDED_OP_CODE_GROW => ("Grow", vec![("length", instruction.get_length())]),
+            EXTENDED_OP_CODE_RESERVED => return None,
+            _ => unreachable!(),
+        },
+    })
+}
+
+/// fields that can be omitted in the textual form when they are zero
+#[cfg(any(test, feature = "std"))]
+const INSTRUCTION_OPTIONAL_FIELDS: [(&str, &str); 2] =
+    [("FindObstacle", "region_preference"), ("LoadDefectsExternal", "channel")];
+
 #[cfg(any(test, feature = "std"))]
 impl std::fmt::Debug for Instruction32 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.is_set_speed() {
-            f.debug_struct("SetSpeed")
+        match instruction_fields(*self) {
+            Some(("SetSpeed", _)) => f
+                .debug_struct("SetSpeed")
                 .field("node", &self.field1())
                 .field("speed", &self.get_speed())
-                .finish()
-        } else {
-            unimplemented!("instruction {:#08X} = {:#032b}", self.0, self.0)
+                .finish(),
+            Some((name, fields)) => {
+                let mut debug = f.debug_struct(name);
+                for (key, value) in fields.iter() {
+                    debug.field(key, value);
+                }
+                debug.finish()
+            }
+            None => write!(f, "Unknown({:#010X})", self.0),
+        }
+    }
+}
+
+/// the textual form used by the assembler, e.g. `SetSpeed(node=1, speed=Grow)` or `Grow(length=20)`;
+/// instructions that cannot be decoded are printed as `Unknown(value=0x...)` to keep the exact value
+#[cfg(any(test, feature = "std"))]
+impl std::fmt::Display for Instruction32 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some((name, fields)) = instruction_fields(*self) else {
+            return write!(f, "Unknown(value={:#010X})", self.0);
+        };
+        write!(f, "{name}(")?;
+        let mut is_first = true;
+        for (key, value) in fields {
+            if value == 0 && INSTRUCTION_OPTIONAL_FIELDS.contains(&(name, key)) {
+                continue;
+            }
+            if !is_first {
+                write!(f, ", ")?;
+            }
+            is_first = false;
+            if key == "speed" {
+                write!(f, "{key}={:?}", self.get_speed())?;
+            } else {
+                write!(f, "{key}={value}")?;
+            }
         }
+        write!(f, ")")
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl std::str::FromStr for Instruction32 {
+    type Err = String;
+
+    /// parse the textual form; field values can be written in decimal or in hexadecimal with a `0x` prefix
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let (name, arguments) = text
+            .strip_suffix(')')
+            .and_then(|text| text.split_once('('))
+            .ok_or_else(|| format!("expect `Name(key=value, ...)`, found `{text}`"))?;
+        let name = name.trim();
+        let mut values: Vec<(&str, u32)> = vec![];
+        for argument in arguments.split(',').map(str::trim).filter(|argument| !argument.is_empty()) {
+            let (key, value) = argument
+                .split_once('=')
+                .ok_or_else(|| format!("expect `key=value`, found `{argument}`"))?;
+            let (key, value) = (key.trim(), value.trim());
+            let value = if key == "speed" && value.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                match value.to_ascii_lowercase().as_str() {
+                    "stay" => CompactGrowState::Stay as u32,
+                    "grow" => CompactGrowState::Grow as u32,
+                    "shrink" => CompactGrowState::Shrink as u32,
+                    _ => return Err(format!("unknown speed `{value}`")),
+                }
+            } else if let Some(hex) = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+                u32::from_str_radix(&hex.replace('_', ""), 16).map_err(|e| format!("invalid value `{value}`: {e}"))?
+            } else {
+                value.parse().map_err(|e| format!("invalid value `{value}`: {e}"))?
+            };
+            if values.iter().any(|(existing, _)| *existing == key) {
+                return Err(format!("duplicate field `{key}`"));
+            }
+            values.push((key, value));
+        }
+        if name == "Unknown" {
+            return match values.as_slice() {
+                [("value", value)] => Ok(Self(*value)),
+                _ => Err("expect `Unknown(value=...)`".to_string()),
+            };
+        }
+        let mut get = |key: &str, bits: usize| -> Result<u32, String> {
+            let index = values.iter().position(|(existing, _)| *existing == key);
+            let value = match index {
+                Some(index) => values.remove(index).1,
+                None if INSTRUCTION_OPTIONAL_FIELDS.contains(&(name, key)) => 0,
+                None => return Err(format!("missing field `{key}` in {name}")),
+            };
+            if bits < 32 && value >= (1 << bits) {
+                return Err(format!("field `{key}` = {value} does not fit in {bits} bits"));
+            }
+            Ok(value)
+        };
+        let extended = |extended_op_code: u32| EXTENDED_OP_CODE_ENABLE | extended_op_code;
+        let instruction = match name {
+            "SetSpeed" => {
+                let node = get("node", 15)?;
+                let speed = get("speed", 2)?;
+                if speed == 3 {
+                    return Err("speed must be Stay, Grow or Shrink".to_string());
+                }
+                Self((node << 17) | (speed << 15) | OP_CODE_SET_SPEED)
+            }
+            "SetBlossom" => Self((get("node", 15)? << 17) | (get("blossom", 15)? << 2) | OP_CODE_SET_BLOSSOM),
+            "Match" => Self((get("vertex_1", 15)? << 17) | (get("vertex_2", 15)? << 2) | OP_CODE_MATCH),
+            "AddDefectVertex" => Self((get("vertex", 15)? << 17) | (get("node", 15)? << 2) | OP_CODE_ADD_DEFECT_VERTEX),
+            "FindObstacle" => Self((get("region_preference", 15)? << 17) | extended(EXTENDED_OP_CODE_FIND_OBSTACLE)),
+            "ClearAccumulator" => Self((get("address", 15)? << 17) | extended(EXTENDED_OP_CODE_CLEAR_ACCUMULATOR)),
+            "AccumulateEdge" => Self((get("edge", 15)? << 17) | extended(EXTENDED_OP_CODE_ACCUMULATE_EDGE)),
+            "LoadWeightsExternal" => Self(extended(EXTENDED_OP_CODE_LOAD_WEIGHTS_EXTERNAL)),
+            "Reset" => Self(extended(EXTENDED_OP_CODE_RESET)),
+            "LoadDefectsExternal" => Self(
+                (get("time", 15)? << 17) | (get("channel", 11)? << 6) | extended(EXTENDED_OP_CODE_LOAD_DEFECTS_EXTERNAL),
+            ),
+            "Grow" => Self((get("length", 26)? << 6) | extended(EXTENDED_OP_CODE_GROW)),
+            _ => return Err(format!("unknown instruction `{name}`")),
+        };
+        if let Some((key, _)) = values.first() {
+            return Err(format!("unknown field `{key}` in {name}"));
+        }
+        Ok(instruction)
     }
 }
 
@@ -145,4 +350,88 @@ mod tests {
             "SetSpeed { node: 32766, speed: Stay }"
         );
     }
+
+    #[test]
+    fn instruction32_text_form() {
+        // cargo test instruction32_text_form -- --nocapture
+        let instructions = [
+            (
+                Instruction32::set_speed(ni!(1), CompactGrowState::Grow),
+                "SetSpeed(node=1, speed=Grow)",
+            ),
+            (Instruction32::set_blossom(ni!(2), ni!(30)), "SetBlossom(node=2, blossom=30)"),
+            (Instruction32::match_vertices(ni!(3), ni!(4)), "Match(vertex_1=3, vertex_2=4)"),
+            (
+                Instruction32::add_defect_vertex(ni!(5), ni!(0)),
+                "AddDefectVertex(vertex=5, node=0)",
+            ),
+            (Instruction32::find_obstacle(), "FindObstacle()"),
+            (Instruction32::clear_accumulator(7), "ClearAccumulator(address=7)"),
+            (Instruction32::accumulate_edge(8), "AccumulateEdge(edge=8)"),
+            (Instruction32::load_weights_external(), "LoadWeightsExternal()"),
+            (Instruction32::reset(), "Reset()"),
+            (Instruction32::load_syndrome_external(ni!(9)), "LoadDefectsExternal(time=9)"),
+            (Instruction32::grow(1000), "Grow(length=1000)"),
+            (
+                Instruction32(EXTENDED_OP_CODE_ENABLE | EXTENDED_OP_CODE_RESERVED | (11 << 17)),
+                "Unknown(value=0x0016003C)",
+            ),
+            (Instruction32(0b111 << 15), "Unknown(value=0x00038000)"),
+            (
+                Instruction32(Instruction32::reset().0 | (1 << 20)),
+                "Unknown(value=0x00100024)",
+            ),
+        ];
+        for (instruction, text) in instructions {
+            instruction.print_detailed();
+            assert_eq!(instruction.to_string(), text);
+            assert_eq!(text.parse::<Instruction32>(), Ok(instruction));
+        }
+        assert_eq!(
+            format!("{:?}", Instruction32::set_blossom(ni!(2), ni!(30))),
+            "SetBlossom { node: 2, blossom: 30 }"
+        );
+        assert_eq!(format!("{:?}", Instruction32(0b111 << 15)), "Unknown(0x00038000)");
+        // the optional fields and other number formats
+        assert_eq!(
+            " LoadDefectsExternal( time = 0x9, channel=3 ) ".parse::<Instruction32>(),
+            Ok(Instruction32(Instruction32::load_syndrome_external(ni!(9)).0 | (3 << 6)))
+        );
+        assert_eq!(
+            "SetSpeed(speed=shrink, node=0x10)".parse(),
+            Ok(Instruction32::set_speed(ni!(16), CompactGrowState::Shrink))
+        );
+        assert_eq!(
+            "SetSpeed(node=1, speed=0)".parse(),
+            Ok(Instruction32::set_speed(ni!(1), CompactGrowState::Stay))
+        );
+        for invalid in [
+            "Grow",
+            "Grow()",
+            "Grow(length=1, length=2)",
+            "Grow(length=0x4000000)",
+            "SetSpeed(node=1, speed=3)",
+            "SetBlossom(node=1, blossom=2, vertex=3)",
+            "Jump(address=1)",
+            "Reset(value=x)",
+        ] {
+            let error = invalid.parse::<Instruction32>().unwrap_err();
+            println!("{invalid}: {error}");
+        }
+    }
+
+    #[test]
+    fn instruction32_text_form_round_trip() {
+        // cargo test instruction32_text_form_round_trip -- --nocapture
+        let mut word: u32 = 1;
+        for _ in 0..100000 {
+            word ^= word << 13;
+            word ^= word >> 17;
+            word ^= word << 5;
+            let instruction = Instruction32(word);
+            let text = instruction.to_string();
+            assert_eq!(text.parse::<Instruction32>(), Ok(instruction), "{text}");
+            format!("{instruction:?}");
+        }
+    }
 }
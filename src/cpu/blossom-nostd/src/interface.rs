@@ -35,6 +35,120 @@ pub enum CompactObstacle {
     BlossomNeedExpand { blossom: CompactNodeIndex },
 }
 
+/// structured errors of the fallible interfaces, so that a failed shot can be reported instead of aborting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MicroBlossomError {
+    /// the communication with the dual module (e.g. the simulator or the memory-mapped bus) failed
+    Transport { kind: TransportErrorKind },
+    /// the hardware error counter is non-zero, e.g. an invalid address or context is accessed
+    HardwareError { error_counter: u32 },
+    /// the node index does not fit into the index space of the primal or dual module
+    NodeIndexOverflow { node_index: usize },
+    /// there is no more space to allocate a new blossom
+    CapacityExhausted,
+    /// the context id exceeds the number of contexts of the accelerator
+    ContextOverflow { context_id: usize },
+    /// the dynamic weight of this edge is out of range, or not a non-negative even number
+    InvalidDynamicWeight { edge_index: usize },
+    /// the dual module does not implement this feature
+    Unsupported { feature: UnsupportedFeature },
+}
+
+/// the cause of a [`MicroBlossomError::Transport`], a subset of `std::io::ErrorKind` that is also available in
+/// `no_std` environment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TransportErrorKind {
+    ConnectionRefused,
+    ConnectionReset,
+    ConnectionAborted,
+    NotConnected,
+    BrokenPipe,
+    TimedOut,
+    UnexpectedEof,
+    InvalidData,
+    Other,
+}
+
+impl core::fmt::Display for TransportErrorKind {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::ConnectionRefused => write!(fmt, "connection refused"),
+            Self::ConnectionReset => write!(fmt, "connection reset"),
+            Self::ConnectionAborted => write!(fmt, "connection aborted"),
+            Self::NotConnected => write!(fmt, "not connected"),
+            Self::BrokenPipe => write!(fmt, "broken pipe"),
+            Self::TimedOut => write!(fmt, "timed out"),
+            Self::UnexpectedEof => write!(fmt, "unexpected end of file"),
+            Self::InvalidData => write!(fmt, "invalid data"),
+            Self::Other => write!(fmt, "other error"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::ErrorKind> for TransportErrorKind {
+    fn from(kind: std::io::ErrorKind) -> Self {
+        match kind {
+            std::io::ErrorKind::ConnectionRefused => Self::ConnectionRefused,
+            std::io::ErrorKind::ConnectionReset => Self::ConnectionReset,
+            std::io::ErrorKind::ConnectionAborted => Self::ConnectionAborted,
+            std::io::ErrorKind::NotConnected => Self::NotConnected,
+            std::io::ErrorKind::BrokenPipe => Self::BrokenPipe,
+            std::io::ErrorKind::TimedOut => Self::TimedOut,
+            std::io::ErrorKind::UnexpectedEof => Self::UnexpectedEof,
+            std::io::ErrorKind::InvalidData => Self::InvalidData,
+            _ => Self::Other,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for MicroBlossomError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Transport {
+            kind: error.kind().into(),
+        }
+    }
+}
+
+/// the optional features of a dual module, see [`MicroBlossomError::Unsupported`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum UnsupportedFeature {
+    /// temporarily set the weights of erased edges to 0
+    Erasure,
+    /// load the edge weights of a shot from the external weights buffer
+    DynamicWeights,
+    /// erasures and dynamic weights in the same shot
+    ErasuresWithDynamicWeights,
+}
+
+impl core::fmt::Display for UnsupportedFeature {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::Erasure => write!(fmt, "erasure"),
+            Self::DynamicWeights => write!(fmt, "dynamic weights"),
+            Self::ErasuresWithDynamicWeights => write!(fmt, "erasures together with dynamic weights"),
+        }
+    }
+}
+
+impl core::fmt::Display for MicroBlossomError {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::Transport { kind } => write!(fmt, "transport failure: {kind}"),
+            Self::HardwareError { error_counter } => write!(fmt, "hardware error counter = {error_counter}"),
+            Self::NodeIndexOverflow { node_index } => write!(fmt, "node index {node_index} overflow"),
+            Self::CapacityExhausted => write!(fmt, "node capacity exhausted"),
+            Self::ContextOverflow { context_id } => write!(fmt, "context {context_id} overflow"),
+            Self::InvalidDynamicWeight { edge_index } => write!(fmt, "invalid dynamic weight of edge {edge_index}"),
+            Self::Unsupported { feature } => write!(fmt, "{feature} not supported by the dual module"),
+        }
+    }
+}
+
 impl CompactObstacle {
     pub fn reduce(resp1: CompactObstacle, resp2: CompactObstacle) -> CompactObstacle {
         if matches!(resp1, CompactObstacle::None) {
@@ -105,6 +219,19 @@ pub trait PrimalInterface {
     /// this design allows multiple level of primal module to be designed, each handling a simple subset
     fn resolve(&mut self, dual_module: &mut impl DualInterface, max_update_length: CompactObstacle) -> bool;
 
+    /// resolve one obstacle like [`PrimalInterface::resolve`], but report the error from either module
+    fn try_resolve(
+        &mut self,
+        dual_module: &mut impl DualInterface,
+        obstacle: CompactObstacle,
+    ) -> Result<bool, MicroBlossomError> {
+        let handled = self.resolve(dual_module, obstacle);
+        match dual_module.take_error() {
+            Some(error) => Err(error),
+            None => Ok(handled),
+        }
+    }
+
     /// iterate the perfect matching between defect nodes
     fn iterate_perfect_matching(&mut self, func: impl FnMut(&Self, CompactNodeIndex, CompactMatchTarget, &TouchingLink));
 }
@@ -127,6 +254,11 @@ pub trait DualInterface {
 
     /// add a defect at given vertex
     fn add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex);
+
+    /// take the first error happened since the last call; the dual module reports no obstacle after an error
+    fn take_error(&mut self) -> Option<MicroBlossomError> {
+        None
+    }
 }
 
 impl CompactObstacle {
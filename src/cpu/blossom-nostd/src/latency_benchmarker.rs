@@ -4,31 +4,79 @@
 //! whose log value is within certain range. This is useful to plot a log-log plot where the x axis
 //! is the log value of the latency and the y axis is log value of the probability
 //!
+//! The range and the resolution (the number of buckets actually in use, at most N) can be changed at runtime.
+//! Histograms of the same range and resolution can be merged, e.g., across runs and contexts, and exported either
+//! in a compact binary form or as a single JSON line that the host parses from the embedded output.
+//!
 
 #[allow(unused_imports)]
 use crate::util::*;
-use libm::{floor, log, pow};
+use core::convert::TryInto;
+use core::fmt::{self, Write};
+use libm::{exp, floor, log, pow};
 
 pub struct LatencyBenchmarker<const N: usize = 2000> {
     pub lower: f64,
     pub upper: f64,
+    /// the number of buckets in use, at most N
+    pub resolution: usize,
     pub counter: [usize; N],
     /// the number of samples below `lower`
     pub underflow_count: usize,
     /// the number of samples above `upper`
     pub overflow_count: usize,
+    /// the exact sum of all the samples, including underflow and overflow
+    pub sum_latency: f64,
+    /// the exact minimum sample; infinity if not tracked, e.g., parsed from the legacy print
+    pub min_latency: f64,
+    /// the exact maximum sample; negative infinity if not tracked
+    pub max_latency: f64,
+}
+
+/// the magic bytes at the beginning of the binary form
+pub const LATENCY_BENCHMARKER_MAGIC: [u8; 4] = *b"MBLH";
+/// the binary form begins with the magic, then `lower`, `upper`, `resolution` (u32), `underflow_count` (u64),
+/// `overflow_count` (u64), `sum_latency`, `min_latency`, `max_latency` and the number of nonzero buckets (u32);
+/// it is followed by (index: u32, count: u64) of each nonzero bucket; all in little endian
+pub const LATENCY_BENCHMARKER_HEADER_BYTES: usize = 4 + 8 + 8 + 4 + 8 + 8 + 8 + 8 + 8 + 4;
+pub const LATENCY_BENCHMARKER_BUCKET_BYTES: usize = 4 + 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LatencyBenchmarkerError {
+    /// the latency is NaN and cannot be placed in any bucket
+    NotANumber,
+    /// the histograms have different range or resolution
+    Incompatible,
+}
+
+impl fmt::Display for LatencyBenchmarkerError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotANumber => write!(fmt, "latency is not a number"),
+            Self::Incompatible => write!(fmt, "cannot merge histograms of different range or resolution"),
+        }
+    }
 }
 
 impl<const N: usize> LatencyBenchmarker<N> {
     pub const fn new(lower: f64, upper: f64) -> Self {
+        Self::new_with_resolution(lower, upper, N)
+    }
+
+    pub const fn new_with_resolution(lower: f64, upper: f64, resolution: usize) -> Self {
         assert!(lower > 0.);
         assert!(upper > lower);
+        assert!(resolution > 0 && resolution <= N);
         Self {
             lower,
             upper,
+            resolution,
             counter: [0; N],
             underflow_count: 0,
             overflow_count: 0,
+            sum_latency: 0.,
+            min_latency: f64::INFINITY,
+            max_latency: f64::NEG_INFINITY,
         }
     }
 
@@ -38,47 +86,178 @@ impl<const N: usize> LatencyBenchmarker<N> {
         Self::new(1e-9, 1.)
     }
 
+    /// change the range and resolution at runtime, which also clears all the records
+    pub fn configure(&mut self, lower: f64, upper: f64, resolution: usize) {
+        assert!(lower > 0.);
+        assert!(upper > lower);
+        assert!(resolution > 0 && resolution <= N);
+        self.lower = lower;
+        self.upper = upper;
+        self.resolution = resolution;
+        self.clear();
+    }
+
     pub fn clear(&mut self) {
         for i in 0..N {
             self.counter[i] = 0;
         }
         self.underflow_count = 0;
         self.overflow_count = 0;
+        self.sum_latency = 0.;
+        self.min_latency = f64::INFINITY;
+        self.max_latency = f64::NEG_INFINITY;
     }
 
-    pub fn record(&mut self, latency: f64) {
+    pub fn record(&mut self, latency: f64) -> Result<(), LatencyBenchmarkerError> {
+        if latency.is_nan() {
+            return Err(LatencyBenchmarkerError::NotANumber);
+        }
+        self.sum_latency += latency;
+        if latency < self.min_latency {
+            self.min_latency = latency;
+        }
+        if latency > self.max_latency {
+            self.max_latency = latency;
+        }
         if latency < self.lower {
             self.underflow_count += 1;
         } else if latency >= self.upper {
             self.overflow_count += 1;
         } else {
             let ratio = log(latency / self.lower) / log(self.upper / self.lower);
-            let index = floor((N as f64) * ratio) as usize;
-            assert!(index < N);
+            // the rounding error may put a latency right below `upper` out of range
+            let index = (floor((self.resolution as f64) * ratio) as usize).min(self.resolution - 1);
             self.counter[index] += 1;
         }
+        Ok(())
     }
 
     pub fn iter_nonzero(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
-        (0..N)
+        (0..self.resolution)
             .filter(move |index| self.counter[*index] > 0)
             .map(move |index| (index, self.counter[index]))
     }
 
     pub fn println(&self) {
-        print!("<lower>{:.3e}<upper>{:.3e}<N>{}", self.lower, self.upper, N);
+        print!("<lower>{:.3e}<upper>{:.3e}<N>{}", self.lower, self.upper, self.resolution);
         for (index, counter) in self.iter_nonzero() {
             print!("[{index}]{counter}");
         }
         println!("[underflow]{}[overflow]{}", self.underflow_count, self.overflow_count);
     }
 
+    /// write the histogram as a single-line JSON object, with exact `lower`, `upper`, `sum`, `min` and `max`
+    pub fn write_json(&self, writer: &mut impl Write) -> fmt::Result {
+        write!(
+            writer,
+            "{{\"lower\":{:e},\"upper\":{:e},\"resolution\":{},\"counter\":[",
+            self.lower, self.upper, self.resolution
+        )?;
+        for (i, (index, counter)) in self.iter_nonzero().enumerate() {
+            if i != 0 {
+                write!(writer, ",")?;
+            }
+            write!(writer, "[{index},{counter}]")?;
+        }
+        write!(
+            writer,
+            "],\"underflow\":{},\"overflow\":{},\"sum\":{:e}",
+            self.underflow_count, self.overflow_count, self.sum_latency
+        )?;
+        if self.is_min_max_tracked() {
+            write!(writer, ",\"min\":{:e},\"max\":{:e}}}", self.min_latency, self.max_latency)
+        } else {
+            write!(writer, ",\"min\":null,\"max\":null}}")
+        }
+    }
+
+    pub fn println_json(&self) {
+        self.write_json(&mut PrintWriter).unwrap();
+        println!();
+    }
+
+    pub fn binary_bytes(&self) -> usize {
+        LATENCY_BENCHMARKER_HEADER_BYTES + LATENCY_BENCHMARKER_BUCKET_BYTES * self.iter_nonzero().count()
+    }
+
+    /// write the binary form into `buffer` and return the number of bytes written, or None if it doesn't fit
+    pub fn write_bytes(&self, buffer: &mut [u8]) -> Option<usize> {
+        if buffer.len() < self.binary_bytes() {
+            return None;
+        }
+        let mut cursor = 0;
+        let mut put = |bytes: &[u8]| {
+            buffer[cursor..cursor + bytes.len()].copy_from_slice(bytes);
+            cursor += bytes.len();
+        };
+        put(&LATENCY_BENCHMARKER_MAGIC);
+        put(&self.lower.to_le_bytes());
+        put(&self.upper.to_le_bytes());
+        put(&(self.resolution as u32).to_le_bytes());
+        put(&(self.underflow_count as u64).to_le_bytes());
+        put(&(self.overflow_count as u64).to_le_bytes());
+        put(&self.sum_latency.to_le_bytes());
+        put(&self.min_latency.to_le_bytes());
+        put(&self.max_latency.to_le_bytes());
+        put(&(self.iter_nonzero().count() as u32).to_le_bytes());
+        for (index, counter) in self.iter_nonzero() {
+            put(&(index as u32).to_le_bytes());
+            put(&(counter as u64).to_le_bytes());
+        }
+        Some(cursor)
+    }
+
+    /// load the binary form, replacing all the records; return the number of bytes read, or None if the bytes
+    /// are malformed or the resolution exceeds N, in which case `self` is left unchanged
+    pub fn read_bytes(&mut self, bytes: &[u8]) -> Option<usize> {
+        let mut reader = ByteReader { bytes, cursor: 0 };
+        if reader.take(4)? != LATENCY_BENCHMARKER_MAGIC {
+            return None;
+        }
+        let lower = reader.f64()?;
+        let upper = reader.f64()?;
+        let resolution = reader.u32()? as usize;
+        let underflow_count = reader.u64()? as usize;
+        let overflow_count = reader.u64()? as usize;
+        let sum_latency = reader.f64()?;
+        let min_latency = reader.f64()?;
+        let max_latency = reader.f64()?;
+        let num_nonzero = reader.u32()? as usize;
+        if !(lower > 0. && upper > lower && resolution > 0 && resolution <= N) {
+            return None;
+        }
+        // validate all the buckets before modifying anything
+        let buckets_begin = reader.cursor;
+        for _ in 0..num_nonzero {
+            if reader.u32()? as usize >= resolution {
+                return None;
+            }
+            reader.u64()?;
+        }
+        let end = reader.cursor;
+        self.configure(lower, upper, resolution);
+        reader.cursor = buckets_begin;
+        for _ in 0..num_nonzero {
+            let index = reader.u32()? as usize;
+            self.counter[index] += reader.u64()? as usize;
+        }
+        self.underflow_count = underflow_count;
+        self.overflow_count = overflow_count;
+        self.sum_latency = sum_latency;
+        self.min_latency = min_latency;
+        self.max_latency = max_latency;
+        Some(end)
+    }
+
     pub fn latency_of(&self, index: usize) -> f64 {
-        self.lower * pow(self.upper / self.lower, (index as f64 + 0.5) / (N as f64))
+        self.lower * pow(self.upper / self.lower, (index as f64 + 0.5) / (self.resolution as f64))
     }
 
     pub fn debug_println(&self) {
-        println!("lower: {:.3e}s, upper: {:.3e}s, N: {}", self.lower, self.upper, N);
+        println!(
+            "lower: {:.3e}s, upper: {:.3e}s, N: {}",
+            self.lower, self.upper, self.resolution
+        );
         for (index, counter) in self.iter_nonzero() {
             println!("    [{index}] {counter} ( ~ {:.3e}s )", self.latency_of(index));
         }
@@ -90,6 +269,11 @@ impl<const N: usize> LatencyBenchmarker<N> {
         self.iter_nonzero().map(|(_index, count)| count).sum()
     }
 
+    /// the number of records including underflow and overflow
+    pub fn count_all_records(&self) -> usize {
+        self.count_records() + self.underflow_count + self.overflow_count
+    }
+
     pub fn average_latency(&self) -> f64 {
         let sum_latency: f64 = self
             .iter_nonzero()
@@ -98,6 +282,11 @@ impl<const N: usize> LatencyBenchmarker<N> {
         sum_latency / (self.count_records() as f64)
     }
 
+    /// the exact average of all the samples, including underflow and overflow
+    pub fn exact_average_latency(&self) -> f64 {
+        self.sum_latency / (self.count_all_records() as f64)
+    }
+
     pub fn percentile_latency_index(&self, percentile: f64) -> usize {
         let num_records = self.count_records();
         let mut sum_counter = 0;
@@ -108,7 +297,72 @@ impl<const N: usize> LatencyBenchmarker<N> {
                 return index;
             }
         }
-        N - 1
+        self.resolution - 1
+    }
+
+    pub fn is_min_max_tracked(&self) -> bool {
+        self.min_latency <= self.max_latency
+    }
+
+    /// the latency below which a `quantile` fraction of all the samples lie, interpolated within the bucket
+    /// assuming the samples are evenly distributed in log scale; the underflow and overflow samples are assumed
+    /// to be evenly distributed between the exact min/max and the range; return NaN if there is no record
+    pub fn quantile(&self, quantile: f64) -> f64 {
+        let num_records = self.count_all_records();
+        if num_records == 0 {
+            return f64::NAN;
+        }
+        let quantile = quantile.clamp(0., 1.);
+        let rank = quantile * (num_records as f64);
+        let (min_latency, max_latency) = if self.is_min_max_tracked() {
+            (self.min_latency, self.max_latency)
+        } else {
+            (self.lower, self.upper)
+        };
+        let clamp = |latency: f64| latency.clamp(min_latency, max_latency);
+        let mut accumulated = 0;
+        if self.underflow_count > 0 {
+            if rank <= self.underflow_count as f64 {
+                let fraction = rank / (self.underflow_count as f64);
+                return clamp(min_latency + (self.lower - min_latency) * fraction);
+            }
+            accumulated += self.underflow_count;
+        }
+        let log_ratio = log(self.upper / self.lower);
+        for (index, counter) in self.iter_nonzero() {
+            if rank <= (accumulated + counter) as f64 {
+                let fraction = (rank - accumulated as f64) / (counter as f64);
+                let position = (index as f64 + fraction) / (self.resolution as f64);
+                return clamp(self.lower * exp(log_ratio * position));
+            }
+            accumulated += counter;
+        }
+        let fraction = (rank - accumulated as f64) / (self.overflow_count as f64);
+        clamp(self.upper + (max_latency - self.upper) * fraction)
+    }
+
+    pub fn is_compatible_with<const M: usize>(&self, other: &LatencyBenchmarker<M>) -> bool {
+        self.lower == other.lower && self.upper == other.upper && self.resolution == other.resolution
+    }
+
+    /// add all the records of another histogram of the same range and resolution
+    pub fn merge<const M: usize>(&mut self, other: &LatencyBenchmarker<M>) -> Result<(), LatencyBenchmarkerError> {
+        if !self.is_compatible_with(other) {
+            return Err(LatencyBenchmarkerError::Incompatible);
+        }
+        for (index, counter) in other.iter_nonzero() {
+            self.counter[index] += counter;
+        }
+        self.underflow_count += other.underflow_count;
+        self.overflow_count += other.overflow_count;
+        self.sum_latency += other.sum_latency;
+        if other.min_latency < self.min_latency {
+            self.min_latency = other.min_latency;
+        }
+        if other.max_latency > self.max_latency {
+            self.max_latency = other.max_latency;
+        }
+        Ok(())
     }
 
     /// print useful statistics like average, 80 percentile, 90, 99, 99.9 percentile, etc.
@@ -122,11 +376,50 @@ impl<const N: usize> LatencyBenchmarker<N> {
         println!("average latency: {:.3e}s", self.average_latency());
         for percentile in [0.8, 0.9, 0.99, 0.999] {
             let index = self.percentile_latency_index(percentile);
-            println!("{percentile} percentile: [{index}] ( ~ {:.3e}s )", self.latency_of(index));
+            println!(
+                "{percentile} percentile: [{index}] ( ~ {:.3e}s, interpolated {:.3e}s )",
+                self.latency_of(index),
+                self.quantile(percentile)
+            );
         }
     }
 }
 
+/// forward the formatted output to `print!`, which works with or without `std`
+struct PrintWriter;
+
+impl Write for PrintWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        print!("{s}");
+        Ok(())
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let slice = self.bytes.get(self.cursor..self.cursor + length)?;
+        self.cursor += length;
+        Some(slice)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        Some(f64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -137,7 +430,7 @@ mod tests {
         let mut benchmarker: LatencyBenchmarker = LatencyBenchmarker::new_default();
         let mut record_multiple = |latency: f64, count: usize| {
             for _ in 0..count {
-                benchmarker.record(latency);
+                benchmarker.record(latency).unwrap();
             }
         };
         record_multiple(1e-9, 10);
@@ -148,5 +441,102 @@ mod tests {
         // print out the result
         benchmarker.debug_println();
         benchmarker.println();
+        benchmarker.println_json();
+    }
+
+    #[test]
+    fn latency_benchmarker_quantile() {
+        // cargo test latency_benchmarker_quantile -- --nocapture
+        let mut benchmarker: LatencyBenchmarker<100> = LatencyBenchmarker::new_with_resolution(1e-6, 1e-3, 30);
+        assert!(benchmarker.quantile(0.5).is_nan());
+        for i in 1..=1000 {
+            benchmarker.record(i as f64 * 1e-6).unwrap();
+        }
+        assert_eq!(benchmarker.count_all_records(), 1000);
+        assert_eq!(benchmarker.overflow_count, 1);
+        assert_eq!(benchmarker.quantile(0.), 1e-6);
+        assert_eq!(benchmarker.quantile(1.), 1e-3);
+        // the bucket is 1/10 of a decade wide, i.e., about 26% wide, and the interpolation is much more accurate
+        for quantile in [0.1, 0.5, 0.8, 0.99] {
+            let expected = quantile * 1e-3;
+            assert!(
+                (benchmarker.quantile(quantile) - expected).abs() / expected < 0.05,
+                "quantile {}",
+                quantile
+            );
+        }
+        assert!((benchmarker.exact_average_latency() - 500.5e-6).abs() < 1e-12);
+        // quantiles are monotonic
+        let mut last = 0.;
+        for i in 0..=100 {
+            let latency = benchmarker.quantile(i as f64 / 100.);
+            assert!(latency >= last);
+            last = latency;
+        }
+        benchmarker.configure(1e-3, 1., 10);
+        assert_eq!(benchmarker.count_all_records(), 0);
+        benchmarker.record(1e-4).unwrap();
+        assert_eq!(benchmarker.underflow_count, 1);
+        assert_eq!(benchmarker.record(f64::NAN), Err(LatencyBenchmarkerError::NotANumber));
+        assert_eq!(benchmarker.count_all_records(), 1);
+        // a latency right below `upper` stays in the last bucket
+        benchmarker.record(1. - f64::EPSILON).unwrap();
+        assert_eq!(benchmarker.counter[9], 1);
+    }
+
+    #[test]
+    fn latency_benchmarker_merge_and_export() {
+        // cargo test latency_benchmarker_merge_and_export -- --nocapture
+        let mut first: LatencyBenchmarker = LatencyBenchmarker::new_default();
+        let mut second: LatencyBenchmarker<4000> = LatencyBenchmarker::new_with_resolution(1e-9, 1., 2000);
+        let mut all: LatencyBenchmarker = LatencyBenchmarker::new_default();
+        for i in 0..100 {
+            let latency = 1e-10 * (1.2f64).powi(i);
+            if i % 3 == 0 {
+                first.record(latency).unwrap();
+            } else {
+                second.record(latency).unwrap();
+            }
+            all.record(latency).unwrap();
+        }
+        assert!(first.is_compatible_with(&second));
+        first.merge(&second).unwrap();
+        assert_eq!(first.counter, all.counter);
+        assert_eq!(first.underflow_count, all.underflow_count);
+        assert_eq!(first.overflow_count, all.overflow_count);
+        assert_eq!((first.min_latency, first.max_latency), (all.min_latency, all.max_latency));
+        let incompatible = LatencyBenchmarker::<2000>::new(1e-9, 10.);
+        assert!(!first.is_compatible_with(&incompatible));
+        assert_eq!(first.merge(&incompatible), Err(LatencyBenchmarkerError::Incompatible));
+        assert_eq!(first.counter, all.counter);
+        // binary round trip
+        let mut buffer = [0u8; 4096];
+        let length = first.write_bytes(&mut buffer).unwrap();
+        assert_eq!(length, first.binary_bytes());
+        assert!(first.write_bytes(&mut buffer[..length - 1]).is_none());
+        let mut loaded: LatencyBenchmarker<4000> = LatencyBenchmarker::new(1., 2.);
+        assert_eq!(loaded.read_bytes(&buffer[..length]), Some(length));
+        assert!(loaded.is_compatible_with(&first));
+        assert_eq!(loaded.counter[..2000], first.counter[..]);
+        assert_eq!(loaded.sum_latency, first.sum_latency);
+        assert_eq!(loaded.quantile(0.9), first.quantile(0.9));
+        assert_eq!(loaded.read_bytes(&buffer[..length - 1]), None);
+        let mut small: LatencyBenchmarker<10> = LatencyBenchmarker::new(1., 2.);
+        assert_eq!(small.read_bytes(&buffer[..length]), None);
+        // JSON export
+        let mut json = std::string::String::new();
+        small.write_json(&mut json).unwrap();
+        assert_eq!(
+            json,
+            r#"{"lower":1e0,"upper":2e0,"resolution":10,"counter":[],"underflow":0,"overflow":0,"sum":0e0,"min":null,"max":null}"#
+        );
+        small.record(1.5).unwrap();
+        small.record(0.5).unwrap();
+        json.clear();
+        small.write_json(&mut json).unwrap();
+        assert_eq!(
+            json,
+            r#"{"lower":1e0,"upper":2e0,"resolution":10,"counter":[[5,1]],"underflow":1,"overflow":0,"sum":2e0,"min":5e-1,"max":1.5e0}"#
+        );
     }
 }
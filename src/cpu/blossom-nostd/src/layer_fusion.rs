@@ -1,15 +1,18 @@
+use crate::storage::*;
 use crate::util::*;
 
-#[cfg_attr(any(test, feature = "std"), derive(Debug))]
-pub struct LayerFusionData<const VN: usize> {
+pub struct LayerFusionDataBase<S: Storage> {
     /// const information about the fusion id; only vertices with layer id will be recorded as pending breaks
-    pub vertex_layer_id: [OptionCompactLayerId; VN],
+    pub vertex_layer_id: S::LayerIds,
     /// pending breaks will eventually be empty when the last round is fused
     pub count_pending_breaks: usize,
-    pub pending_breaks: [CompactNodeIndex; VN],
+    pub pending_breaks: S::PendingBreaks,
 }
 
-impl<const VN: usize> LayerFusionData<VN> {
+pub type LayerFusionData<const VN: usize> = LayerFusionDataBase<ArrayStorage<0, VN>>;
+pub type LayerFusionDataSlice<'a> = LayerFusionDataBase<SliceStorage<'a>>;
+
+impl<const N: usize, const VN: usize> LayerFusionDataBase<ArrayStorage<N, VN>> {
     pub const fn new() -> Self {
         Self {
             vertex_layer_id: [OptionCompactLayerId::NONE; VN],
@@ -17,16 +20,44 @@ impl<const VN: usize> LayerFusionData<VN> {
             pending_breaks: [CompactNodeIndex::new(0).unwrap(); VN],
         }
     }
+}
+
+impl<'a> LayerFusionDataSlice<'a> {
+    /// allocate the layer fusion data of `vertex_num` vertices in the arena
+    pub fn new_in(arena: &mut Arena<'a>, vertex_num: usize) -> Option<Self> {
+        Some(Self::from_buffers(
+            arena.alloc_slice(vertex_num, OptionCompactLayerId::NONE)?,
+            arena.alloc_slice(vertex_num, CompactNodeIndex::new(0).unwrap())?,
+        ))
+    }
+}
+
+impl<S: Storage> LayerFusionDataBase<S> {
+    /// the two buffers must have the same capacity; all the vertices are initialized as not belonging to any layer
+    pub fn from_buffers(mut vertex_layer_id: S::LayerIds, pending_breaks: S::PendingBreaks) -> Self {
+        assert_eq!(vertex_layer_id.as_ref().len(), pending_breaks.as_ref().len());
+        vertex_layer_id.as_mut().fill(OptionCompactLayerId::NONE);
+        Self {
+            vertex_layer_id,
+            count_pending_breaks: 0,
+            pending_breaks,
+        }
+    }
+
+    /// the maximum number of vertices; layer fusion is disabled when it's 0
+    pub fn capacity(&self) -> usize {
+        self.vertex_layer_id.as_ref().len()
+    }
 
     pub fn get_layer_id(&self, vertex_index: CompactVertexIndex) -> OptionCompactLayerId {
-        self.vertex_layer_id[vertex_index.get() as usize]
+        self.vertex_layer_id.as_ref()[vertex_index.get() as usize]
     }
 
     /// record a matching with some vertex that is currently virtual but will be fused later;
     /// when `fuse_layer` is called, we should break such matchings becausse they are no longer valid
     pub fn append_break(&mut self, node_index: CompactNodeIndex) {
-        debug_assert!(self.count_pending_breaks < VN);
-        self.pending_breaks[self.count_pending_breaks] = node_index;
+        debug_assert!(self.count_pending_breaks < self.capacity());
+        self.pending_breaks.as_mut()[self.count_pending_breaks] = node_index;
         self.count_pending_breaks += 1;
     }
 
@@ -34,9 +65,10 @@ impl<const VN: usize> LayerFusionData<VN> {
     pub fn iterate_pending_breaks(&mut self, mut func: impl FnMut(&Self, CompactNodeIndex) -> bool) {
         let mut new_length = 0;
         for index in 0..self.count_pending_breaks {
-            let remove = func(self, self.pending_breaks[index]);
+            let remove = func(self, self.pending_breaks.as_ref()[index]);
             if !remove {
-                self.pending_breaks[new_length] = self.pending_breaks[index];
+                let pending_breaks = self.pending_breaks.as_mut();
+                pending_breaks[new_length] = pending_breaks[index];
                 new_length += 1;
             }
         }
@@ -44,6 +76,18 @@ impl<const VN: usize> LayerFusionData<VN> {
     }
 }
 
+#[cfg(any(test, feature = "std"))]
+impl<S: Storage> std::fmt::Debug for LayerFusionDataBase<S> {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter
+            .debug_struct("LayerFusionData")
+            .field("vertex_layer_id", &self.vertex_layer_id.as_ref())
+            .field("count_pending_breaks", &self.count_pending_breaks)
+            .field("pending_breaks", &self.pending_breaks.as_ref())
+            .finish()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -78,7 +122,7 @@ mod tests {
         let check_state = |layer_fusion: &mut LayerFusionData<N>, expected: Vec<usize>| {
             assert_eq!(layer_fusion.count_pending_breaks, expected.len());
             for (index, value) in expected.iter().enumerate() {
-                assert_eq!(layer_fusion.pending_breaks[index].get() as usize, *value);
+                assert_eq!(layer_fusion.pending_breaks.as_ref()[index].get() as usize, *value);
             }
             // also check using iterate function
             let mut index = 0;
@@ -4,6 +4,7 @@
 
 pub mod benchmark;
 pub mod blossom_tracker;
+pub mod deadline;
 pub mod dual_driver_tracked;
 pub mod dual_module_stackless;
 pub mod heapless;
@@ -12,6 +13,8 @@ pub mod interface;
 pub mod latency_benchmarker;
 pub mod layer_fusion;
 pub mod nonmax;
+pub mod primal_invariants;
 pub mod primal_module_embedded;
 pub mod primal_nodes;
+pub mod storage;
 pub mod util;
@@ -0,0 +1,399 @@
+//! Primal Invariants
+//!
+//! The primal nodes encode alternating trees, blossom cycles and temporary matchings in the overloaded
+//! `parent`/`sibling`/`first_child`/`link` fields. A corrupted structure usually shows up only as a wrong matching much
+//! later, so this module checks the structure right away. It's slow (quadratic in the worst case) and only meant for
+//! debugging and fuzzing; with the `check_invariants` feature, it runs after every `resolve`.
+//!
+
+use crate::primal_module_embedded::*;
+use crate::primal_nodes::*;
+use crate::storage::*;
+use crate::util::*;
+
+/// the first invariant found to be broken, together with a copy of the violating node
+#[derive(Clone, Copy)]
+pub struct InvariantViolation {
+    /// `None` if the violation is not about a specific node, e.g., the bookkeeping of the counters
+    pub node_index: Option<CompactNodeIndex>,
+    pub node: Option<PrimalNode>,
+    pub reason: &'static str,
+}
+
+impl core::fmt::Display for InvariantViolation {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self.node_index {
+            Some(node_index) => write!(fmt, "primal invariant violated at node {node_index}: {}", self.reason)?,
+            None => write!(fmt, "primal invariant violated: {}", self.reason)?,
+        }
+        if let Some(node) = self.node.as_ref() {
+            write!(
+                fmt,
+                "\n    grow_state: {:?}, parent: {:?}, first_child: {:?}, sibling: {:?}",
+                node.grow_state, node.parent, node.first_child, node.sibling
+            )?;
+            write!(
+                fmt,
+                "\n    link: touch: {:?}, through: {:?}, peer_touch: {:?}, peer_through: {:?}",
+                node.link.touch, node.link.through, node.link.peer_touch, node.link.peer_through
+            )?;
+        } else if self.node_index.is_some() {
+            write!(fmt, "\n    (node does not exist)")?;
+        }
+        Ok(())
+    }
+}
+
+impl core::fmt::Debug for InvariantViolation {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        core::fmt::Display::fmt(self, fmt)
+    }
+}
+
+impl<S: Storage> PrimalModuleEmbeddedBase<S> {
+    /// check the following invariants of the primal nodes:
+    /// 1. the depth parity of a node in an alternating tree matches its grow state (+ node grows, - node shrinks),
+    ///     and the parent and children of a node point to each other;
+    /// 2. every blossom is an odd cycle of at least 3 inner nodes whose parent is the blossom;
+    /// 3. the `TouchingLink` of a matched pair mirrors each other, and every touching node is within the node;
+    /// 4. every defect is in exactly one state: free, matched, in an alternating tree or inside a blossom;
+    /// 5. `count_defects` and `count_blossoms` are consistent with `blossom_begin` and the allocated blossoms.
+    pub fn check_invariants(&self) -> Result<(), InvariantViolation> {
+        let nodes = &self.nodes;
+        let capacity = nodes.capacity();
+        if nodes.count_defects > nodes.blossom_begin || nodes.blossom_begin + nodes.count_blossoms > capacity {
+            return Err(InvariantViolation {
+                node_index: None,
+                node: None,
+                reason: "count_defects or count_blossoms out of the range",
+            });
+        }
+        for index in nodes.blossom_begin..nodes.blossom_begin + nodes.count_blossoms {
+            let node_index = ni!(index);
+            if nodes.has_node(node_index) != nodes.first_blossom_child.as_ref()[index].is_some() {
+                return Err(self.violation(node_index, "the first blossom child doesn't match the blossom allocation"));
+            }
+        }
+        // first make sure all the references are valid, so that the following checks can follow them safely
+        for index in nodes.index_iter() {
+            let node_index = ni!(index);
+            if !nodes.has_node(node_index) {
+                continue;
+            }
+            let node = nodes.get_node(node_index);
+            for reference in [
+                node.parent,
+                node.first_child,
+                node.sibling,
+                node.link.touch,
+                node.link.peer_touch,
+            ] {
+                if reference.is_some() && !self.is_valid_node(reference) {
+                    return Err(self.violation(node_index, "referencing a node that doesn't exist"));
+                }
+            }
+        }
+        let mut count_inner = 0;
+        for index in nodes.index_iter() {
+            let node_index = ni!(index);
+            if !nodes.has_node(node_index) {
+                continue;
+            }
+            let node = nodes.get_node(node_index);
+            if node.grow_state.is_none() {
+                count_inner += 1;
+                self.check_inner_node(node_index, node)?;
+            } else if node.in_alternating_tree() {
+                self.check_tree_node(node_index, node)?;
+            } else if node.is_matched() {
+                self.check_matched_node(node_index, node)?;
+            } else if node.grow_state != Some(CompactGrowState::Grow) || node.sibling.is_some() || !node.link.is_none() {
+                return Err(self.violation(node_index, "a free node should grow and have no sibling or link"));
+            }
+        }
+        // every inner node is in exactly one blossom cycle
+        let mut count_cycle_members = 0;
+        for index in nodes.blossom_begin..nodes.blossom_begin + nodes.count_blossoms {
+            let blossom_index = ni!(index);
+            if nodes.has_node(blossom_index) {
+                count_cycle_members += self.check_blossom_cycle(blossom_index)?;
+            }
+        }
+        if count_inner != count_cycle_members {
+            return Err(InvariantViolation {
+                node_index: None,
+                node: None,
+                reason: "some inner node is not in the cycle of its parent blossom",
+            });
+        }
+        Ok(())
+    }
+
+    /// panic with a dump of the violating node if any invariant is broken
+    pub fn assert_invariants(&self) {
+        if let Err(violation) = self.check_invariants() {
+            panic!("{}", violation);
+        }
+    }
+
+    fn violation(&self, node_index: CompactNodeIndex, reason: &'static str) -> InvariantViolation {
+        InvariantViolation {
+            node_index: Some(node_index),
+            node: self.nodes.buffer.as_ref().get(node_index.get() as usize).copied().flatten(),
+            reason,
+        }
+    }
+
+    fn is_valid_node(&self, node_index: OptionCompactNodeIndex) -> bool {
+        let Some(node_index) = node_index.option() else {
+            return false;
+        };
+        let index = node_index.get() as usize;
+        let nodes = &self.nodes;
+        (index < nodes.count_defects || (index >= nodes.blossom_begin && index < nodes.blossom_begin + nodes.count_blossoms))
+            && nodes.has_node(node_index)
+    }
+
+    /// whether `touch` is `node` itself or is inside the (possibly nested) blossom `node`
+    fn is_within(&self, touch: OptionCompactNodeIndex, node_index: CompactNodeIndex) -> bool {
+        let mut current = touch;
+        for _ in 0..self.nodes.capacity() {
+            if !self.is_valid_node(current) {
+                return false;
+            }
+            let current_index = usu!(current.option());
+            if current_index == node_index {
+                return true;
+            }
+            let current_node = self.nodes.get_node(current_index);
+            if current_node.grow_state.is_some() {
+                return false; // reaching an outer node other than `node_index`
+            }
+            current = current_node.parent;
+        }
+        false
+    }
+
+    fn check_inner_node(&self, node_index: CompactNodeIndex, node: &PrimalNode) -> Result<(), InvariantViolation> {
+        let is_parent_blossom = node
+            .parent
+            .option()
+            .map(|parent| parent.get() as usize >= self.nodes.blossom_begin)
+            .unwrap_or(false);
+        if !is_parent_blossom {
+            return Err(self.violation(node_index, "an inner node must have a parent blossom"));
+        }
+        if node.first_child.is_some() || node.sibling.is_none() {
+            return Err(self.violation(node_index, "an inner node must have a sibling but no child"));
+        }
+        // the outer blossom is reachable, i.e., no blossom contains itself
+        let mut ancestor = node_index;
+        for _ in 0..self.nodes.capacity() {
+            let ancestor_node = self.nodes.get_node(ancestor);
+            if ancestor_node.grow_state.is_some() {
+                return Ok(());
+            }
+            match ancestor_node.parent.option() {
+                Some(parent) => ancestor = parent,
+                None => break,
+            }
+        }
+        Err(self.violation(node_index, "cannot reach the outer blossom"))
+    }
+
+    fn check_tree_node(&self, node_index: CompactNodeIndex, node: &PrimalNode) -> Result<(), InvariantViolation> {
+        let nodes = &self.nodes;
+        let capacity = nodes.capacity();
+        // depth parity
+        let mut depth = 0;
+        let mut ancestor = node.parent;
+        while let Some(ancestor_index) = ancestor.option() {
+            let ancestor_node = nodes.get_node(ancestor_index);
+            if ancestor_node.grow_state.is_none() || !ancestor_node.in_alternating_tree() {
+                return Err(self.violation(node_index, "an ancestor is not an outer node in the alternating tree"));
+            }
+            depth += 1;
+            if depth > capacity {
+                return Err(self.violation(node_index, "the alternating tree has a cycle"));
+            }
+            ancestor = ancestor_node.parent;
+        }
+        let expected_grow_state = if depth % 2 == 0 {
+            CompactGrowState::Grow
+        } else {
+            CompactGrowState::Shrink
+        };
+        if node.grow_state != Some(expected_grow_state) {
+            return Err(self.violation(
+                node_index,
+                "the grow state doesn't match the depth parity in the alternating tree",
+            ));
+        }
+        // the parent has this node as a child and the touching nodes are on the correct sides
+        if let Some(parent_index) = node.parent.option() {
+            let mut found = false;
+            let mut child = nodes.get_node(parent_index).first_child;
+            for _ in 0..capacity {
+                let Some(child_index) = child.option() else {
+                    break;
+                };
+                if child_index == node_index {
+                    found = true;
+                    break;
+                }
+                child = nodes.get_node(child_index).sibling;
+            }
+            if !found {
+                return Err(self.violation(node_index, "not in the children list of its parent"));
+            }
+            if !self.is_within(node.link.touch, node_index) || !self.is_within(node.link.peer_touch, parent_index) {
+                return Err(self.violation(node_index, "the link doesn't connect to the parent"));
+            }
+        } else if node.sibling.is_some() {
+            return Err(self.violation(node_index, "the root of an alternating tree should not have a sibling"));
+        }
+        // the children point back to this node
+        let mut count_children = 0;
+        let mut child = node.first_child;
+        while let Some(child_index) = child.option() {
+            let child_node = nodes.get_node(child_index);
+            if child_node.parent != node_index.option() || child_node.grow_state.is_none() {
+                return Err(self.violation(child_index, "the child doesn't point back to its parent"));
+            }
+            count_children += 1;
+            if count_children > capacity {
+                return Err(self.violation(node_index, "the children list has a cycle"));
+            }
+            child = child_node.sibling;
+        }
+        if depth % 2 == 1 && count_children != 1 {
+            return Err(self.violation(node_index, "a - node must have exactly one child"));
+        }
+        Ok(())
+    }
+
+    fn check_matched_node(&self, node_index: CompactNodeIndex, node: &PrimalNode) -> Result<(), InvariantViolation> {
+        if node.grow_state != Some(CompactGrowState::Stay) {
+            return Err(self.violation(node_index, "a matched node should stay"));
+        }
+        if !self.is_within(node.link.touch, node_index) || node.link.through.is_none() {
+            return Err(self.violation(node_index, "the link doesn't touch the node itself"));
+        }
+        if let Some(peer_index) = node.sibling.option() {
+            let peer = self.nodes.get_node(peer_index);
+            if peer.grow_state != Some(CompactGrowState::Stay) || peer.in_alternating_tree() {
+                return Err(self.violation(node_index, "the peer is not a matched node"));
+            }
+            if peer.sibling != node_index.option() {
+                return Err(self.violation(node_index, "the peer is not matched to this node"));
+            }
+            if peer.link.touch != node.link.peer_touch
+                || peer.link.through != node.link.peer_through
+                || peer.link.peer_touch != node.link.touch
+                || peer.link.peer_through != node.link.through
+            {
+                return Err(self.violation(node_index, "the link is not symmetric to the link of the peer"));
+            }
+        } else if node.link.peer_touch.is_some() || node.link.peer_through.is_none() {
+            return Err(self.violation(node_index, "a node matched to a virtual vertex should only have peer_through"));
+        }
+        Ok(())
+    }
+
+    /// return the number of nodes in the cycle
+    fn check_blossom_cycle(&self, blossom_index: CompactNodeIndex) -> Result<usize, InvariantViolation> {
+        let nodes = &self.nodes;
+        let first_child = nodes.get_first_blossom_child(blossom_index);
+        let mut length = 0;
+        let mut child_index = first_child;
+        loop {
+            if !self.is_valid_node(child_index.option()) {
+                return Err(self.violation(blossom_index, "the blossom cycle contains a node that doesn't exist"));
+            }
+            let child = nodes.get_node(child_index);
+            if child.grow_state.is_some() || child.parent != blossom_index.option() {
+                return Err(self.violation(child_index, "a node in the blossom cycle is not an inner node of the blossom"));
+            }
+            if !self.is_within(child.link.touch, child_index)
+                || !self.is_within(child.link.peer_touch, usu!(child.sibling.option()))
+            {
+                return Err(self.violation(child_index, "the link doesn't connect to the sibling in the blossom cycle"));
+            }
+            length += 1;
+            if length > nodes.capacity() {
+                return Err(self.violation(blossom_index, "the blossom cycle doesn't return to the first child"));
+            }
+            child_index = usu!(child.sibling.option());
+            if child_index == first_child {
+                break;
+            }
+        }
+        if length < 3 || length % 2 == 0 {
+            return Err(self.violation(blossom_index, "a blossom must be an odd cycle of at least 3 nodes"));
+        }
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmark::dual_module_counter::*;
+    use crate::dual_module_stackless::*;
+    use crate::interface::*;
+
+    fn conflict(node_1: usize, node_2: usize) -> CompactObstacle {
+        CompactObstacle::Conflict {
+            node_1: ni!(node_1).option(),
+            node_2: ni!(node_2).option(),
+            touch_1: ni!(node_1).option(),
+            touch_2: ni!(node_2).option(),
+            vertex_1: ni!(node_1),
+            vertex_2: ni!(node_2),
+        }
+    }
+
+    #[test]
+    fn primal_invariants_blossom() {
+        // cargo test primal_invariants_blossom -- --nocapture
+        let mut dual_module = DualModuleStackless::new(DualModuleCounterDriver::new());
+        let mut primal_module: PrimalModuleEmbedded<16> = PrimalModuleEmbedded::new();
+        assert!(primal_module.check_invariants().is_ok());
+        // 0 - 1 matched, then 2 touches 1 to form a tree 2 -> 1 -> 0, and 0 touches 2 to form a blossom
+        for (node_1, node_2) in [(0, 1), (2, 1), (0, 2)] {
+            primal_module.resolve(&mut dual_module, conflict(node_1, node_2));
+            primal_module.check_invariants().unwrap();
+        }
+        assert_eq!(primal_module.nodes.count_blossoms, 1);
+        // break the odd cycle
+        let blossom = ni!(primal_module.nodes.blossom_begin);
+        let first_child = primal_module.nodes.get_first_blossom_child(blossom);
+        let second_child = usu!(primal_module.nodes.get_node(first_child).sibling.option());
+        let third_child = usu!(primal_module.nodes.get_node(second_child).sibling.option());
+        primal_module.nodes.get_node_mut(second_child).sibling = first_child.option();
+        let violation = primal_module.check_invariants().unwrap_err();
+        println!("{violation}");
+        primal_module.nodes.get_node_mut(second_child).sibling = third_child.option();
+        primal_module.check_invariants().unwrap();
+        // a wrong grow state
+        primal_module.nodes.get_node_mut(blossom).grow_state = Some(CompactGrowState::Shrink);
+        let violation = primal_module.check_invariants().unwrap_err();
+        println!("{violation}");
+        assert_eq!(violation.node_index, Some(blossom));
+    }
+
+    #[test]
+    fn primal_invariants_matching() {
+        // cargo test primal_invariants_matching -- --nocapture
+        let mut dual_module = DualModuleStackless::new(DualModuleCounterDriver::new());
+        let mut primal_module: PrimalModuleEmbedded<16> = PrimalModuleEmbedded::new();
+        primal_module.resolve(&mut dual_module, conflict(0, 1));
+        primal_module.check_invariants().unwrap();
+        // asymmetric link
+        primal_module.nodes.get_node_mut(ni!(1)).link.peer_through = ni!(5).option();
+        let violation = primal_module.check_invariants().unwrap_err();
+        println!("{violation}");
+        assert_eq!(violation.node_index, Some(ni!(0)));
+        assert_eq!(violation.reason, "the link is not symmetric to the link of the peer");
+    }
+}
@@ -6,31 +6,48 @@
 //! Besides, the primal module specifies the index of the blossom, which usually starts from an address
 //! that is guaranteed to be distinguishable from defect vertices.
 //! Only in this way, we can safely use primal offloading without worrying about mixing with a created blossom.
+//! The maximum number of nodes is either a const generic or chosen at runtime with caller-provided buffers,
+//! see [`crate::storage`].
 //!
 
 use crate::interface::*;
 use crate::layer_fusion::*;
 use crate::primal_nodes::*;
+use crate::storage::*;
 use crate::util::*;
 
 #[cfg_attr(any(test, feature = "std"), derive(Debug))]
-pub struct PrimalModuleEmbedded<const N: usize, const VN: usize = 0> {
+pub struct PrimalModuleEmbeddedBase<S: Storage> {
     /// the alternating tree nodes
-    pub nodes: PrimalNodes<N>,
+    pub nodes: PrimalNodesBase<S>,
     /// optionally, it can store the layer fusion table of the vertices
-    pub layer_fusion: LayerFusionData<VN>,
+    pub layer_fusion: LayerFusionDataBase<S>,
 }
 
+pub type PrimalModuleEmbedded<const N: usize, const VN: usize = 0> = PrimalModuleEmbeddedBase<ArrayStorage<N, VN>>;
+pub type PrimalModuleEmbeddedSlice<'a> = PrimalModuleEmbeddedBase<SliceStorage<'a>>;
+
 impl<const N: usize, const VN: usize> PrimalModuleEmbedded<N, VN> {
     pub const fn new() -> Self {
         Self {
-            nodes: PrimalNodes::new(),
-            layer_fusion: LayerFusionData::new(),
+            nodes: PrimalNodesBase::new(),
+            layer_fusion: LayerFusionDataBase::new(),
         }
     }
 }
 
-impl<const N: usize, const VN: usize> PrimalInterface for PrimalModuleEmbedded<N, VN> {
+impl<'a> PrimalModuleEmbeddedSlice<'a> {
+    /// allocate a primal module of at most `node_num` nodes in the arena; layer fusion is enabled when
+    /// `vertex_num` is not 0, in which case the caller should set `layer_fusion.vertex_layer_id`
+    pub fn new_in(arena: &mut Arena<'a>, node_num: usize, vertex_num: usize) -> Option<Self> {
+        Some(Self {
+            nodes: PrimalNodesSlice::new_in(arena, node_num)?,
+            layer_fusion: LayerFusionDataSlice::new_in(arena, vertex_num)?,
+        })
+    }
+}
+
+impl<S: Storage> PrimalInterface for PrimalModuleEmbeddedBase<S> {
     fn reset(&mut self) {
         self.nodes.clear();
     }
@@ -50,11 +67,11 @@ impl<const N: usize, const VN: usize> PrimalInterface for PrimalModuleEmbedded<N
             .iterate_blossom_children(blossom_index, |node_index, link| func(self, node_index, link));
     }
 
-    /// resolve one obstacle
-    #[allow(unused_mut)]
+    /// resolve one obstacle; with the `check_invariants` feature, the primal nodes are checked afterwards
+    #[allow(unused_mut, clippy::let_and_return)]
     fn resolve(&mut self, dual_module: &mut impl DualInterface, obstacle: CompactObstacle) -> bool {
         debug_assert!(obstacle.is_obstacle());
-        match obstacle {
+        let handled = match obstacle {
             CompactObstacle::Conflict {
                 node_1,
                 mut node_2,
@@ -131,6 +148,48 @@ impl<const N: usize, const VN: usize> PrimalInterface for PrimalModuleEmbedded<N
                 self.resolve_blossom_need_expand(dual_module, blossom)
             }
             _ => unimplemented_or_loop!(),
+        };
+        #[cfg(feature = "check_invariants")]
+        self.assert_invariants();
+        handled
+    }
+
+    /// besides the errors of the dual module, check the node indices of a conflict and the space for a new blossom
+    /// before resolving it, so that the primal module never panics or runs into undefined behavior
+    fn try_resolve(
+        &mut self,
+        dual_module: &mut impl DualInterface,
+        obstacle: CompactObstacle,
+    ) -> Result<bool, MicroBlossomError> {
+        if let CompactObstacle::Conflict {
+            node_1,
+            node_2,
+            touch_1,
+            touch_2,
+            ..
+        } = obstacle
+        {
+            for node_index in [node_1, node_2, touch_1, touch_2].iter() {
+                if let Some(node_index) = node_index.option() {
+                    if node_index.get() as usize >= self.nodes.capacity() {
+                        return Err(MicroBlossomError::NodeIndexOverflow {
+                            node_index: node_index.get() as usize,
+                        });
+                    }
+                }
+            }
+            if let (Some(node_1), Some(node_2)) = (node_1.option(), node_2.option()) {
+                if self.nodes.blossom_begin + self.nodes.count_blossoms >= self.nodes.capacity()
+                    && self.conflict_creates_blossom(node_1, node_2)
+                {
+                    return Err(MicroBlossomError::CapacityExhausted);
+                }
+            }
+        }
+        let handled = self.resolve(dual_module, obstacle);
+        match dual_module.take_error() {
+            Some(error) => Err(error),
+            None => Ok(handled),
         }
     }
 
@@ -144,7 +203,7 @@ impl<const N: usize, const VN: usize> PrimalInterface for PrimalModuleEmbedded<N
     }
 }
 
-impl<const N: usize, const VN: usize> PrimalModuleEmbedded<N, VN> {
+impl<S: Storage> PrimalModuleEmbeddedBase<S> {
     /// return the perfect matching between nodes
     #[inline]
     pub fn iterate_intermediate_matching(
@@ -335,7 +394,7 @@ impl<const N: usize, const VN: usize> PrimalModuleEmbedded<N, VN> {
         }
         self.nodes
             .temporary_match_virtual_vertex(dual_module, node, touch, vertex, virtual_vertex);
-        if VN > 0 {
+        if self.layer_fusion.capacity() > 0 {
             // layer fusion is enabled, record this matching in the list
             if self.layer_fusion.get_layer_id(virtual_vertex).is_some() {
                 self.layer_fusion.append_break(node);
@@ -632,6 +691,20 @@ impl<const N: usize, const VN: usize> PrimalModuleEmbedded<N, VN> {
         }
     }
 
+    /// whether resolving a conflict between the two nodes creates a new blossom, i.e., their outer blossoms are in
+    /// the same alternating tree; the other conflicts match, grow or augment the trees without allocating a node
+    fn conflict_creates_blossom(&self, node_1: CompactNodeIndex, node_2: CompactNodeIndex) -> bool {
+        if !self.nodes.has_node(node_1) || !self.nodes.has_node(node_2) {
+            return false; // outdated event
+        }
+        let node_1 = self.nodes.get_outer_blossom(node_1);
+        let node_2 = self.nodes.get_outer_blossom(node_2);
+        node_1 != node_2
+            && self.nodes.get_node(node_1).in_alternating_tree()
+            && self.nodes.get_node(node_2).in_alternating_tree()
+            && self.alternating_tree_root_of(node_1).0 == self.alternating_tree_root_of(node_2).0
+    }
+
     #[inline] // part of the `resolve` function, put it here for code clarity
     fn create_blossom_inside_alternating_tree(
         &mut self,
@@ -873,6 +946,30 @@ impl<const N: usize, const VN: usize> PrimalModuleEmbedded<N, VN> {
             }
         });
     }
+
+    /// the fallback when the deadline expires: match every - node with its + child along the tight edge, which
+    /// leaves only the roots of the alternating trees unmatched; return the number of unmatched outer nodes,
+    /// which the caller should complete, e.g., by matching them to the nearest virtual vertices
+    pub fn break_alternating_trees(&mut self, dual_module: &mut impl DualInterface) -> usize {
+        let mut count_free = 0;
+        for index in self.nodes.index_iter() {
+            let node_index = ni!(index);
+            if !self.nodes.has_node(node_index) {
+                continue; // disposed blossom
+            }
+            let primal_node = self.nodes.get_node(node_index);
+            if !primal_node.is_outer_blossom() {
+                continue;
+            }
+            if primal_node.in_alternating_tree() && primal_node.parent.is_none() {
+                self.augment_subtree(dual_module, node_index);
+            }
+            if self.nodes.get_node(node_index).is_free() {
+                count_free += 1;
+            }
+        }
+        count_free
+    }
 }
 
 #[cfg(test)]
@@ -909,4 +1006,57 @@ mod tests {
         primal_module.nodes.check_node_index(ni!(4));
         println!("{primal_module:?}");
     }
+
+    #[test]
+    fn primal_module_embedded_slice() {
+        // cargo test primal_module_embedded_slice -- --nocapture
+        const N: usize = 100;
+        const VN: usize = 20;
+        let mut memory = [0u8; 8192];
+        let mut arena = Arena::new(&mut memory);
+        let mut slice_module = PrimalModuleEmbeddedSlice::new_in(&mut arena, N, VN).unwrap();
+        assert_eq!(slice_module.nodes.capacity(), N);
+        assert_eq!(slice_module.nodes.blossom_begin, N / 2);
+        assert_eq!(slice_module.layer_fusion.capacity(), VN);
+        assert!(PrimalModuleEmbeddedSlice::new_in(&mut arena, 10 * N, VN).is_none());
+        let mut primal_module: PrimalModuleEmbedded<N, VN> = PrimalModuleEmbedded::new();
+        for node_index in [3, 1, 4] {
+            primal_module.nodes.check_node_index(ni!(node_index));
+            slice_module.nodes.check_node_index(ni!(node_index));
+        }
+        assert_eq!(format!("{primal_module:?}"), format!("{slice_module:?}"));
+        primal_module.reset();
+        slice_module.reset();
+        assert_eq!(format!("{primal_module:?}"), format!("{slice_module:?}"));
+    }
+
+    #[test]
+    fn primal_module_embedded_break_alternating_trees() {
+        // cargo test primal_module_embedded_break_alternating_trees -- --nocapture
+        use crate::benchmark::dual_module_counter::*;
+        use crate::dual_module_stackless::*;
+        let mut dual_module = DualModuleStackless::new(DualModuleCounterDriver::new());
+        let mut primal_module: PrimalModuleEmbedded<16> = PrimalModuleEmbedded::new();
+        // 0 - 1 matched, then 2 touches 1 to form a tree 2 -> 1 -> 0
+        for (node_1, node_2) in [(0, 1), (2, 1)] {
+            let obstacle = CompactObstacle::Conflict {
+                node_1: ni!(node_1).option(),
+                node_2: ni!(node_2).option(),
+                touch_1: ni!(node_1).option(),
+                touch_2: ni!(node_2).option(),
+                vertex_1: ni!(node_1),
+                vertex_2: ni!(node_2),
+            };
+            primal_module.resolve(&mut dual_module, obstacle);
+        }
+        assert!(primal_module.nodes.get_node(ni!(2)).in_alternating_tree());
+        assert_eq!(primal_module.break_alternating_trees(&mut dual_module), 1);
+        primal_module.check_invariants().unwrap();
+        assert!(primal_module.nodes.get_node(ni!(2)).is_free());
+        let mut matchings = vec![];
+        primal_module.iterate_perfect_matching(|_, node_index, match_target, _| {
+            matchings.push((node_index, match_target));
+        });
+        assert_eq!(matchings, [(ni!(0), CompactMatchTarget::Peer(ni!(1)))]);
+    }
 }
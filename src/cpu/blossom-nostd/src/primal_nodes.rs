@@ -6,17 +6,18 @@
 //!
 
 use crate::interface::*;
+use crate::storage::*;
 use crate::util::*;
 use core::iter::Chain;
 use core::ops::Range;
 
-pub struct PrimalNodes<const N: usize> {
+pub struct PrimalNodesBase<S: Storage> {
     /// defect nodes starting from 0, blossom nodes starting from `blossom_begin`
-    pub buffer: [Option<PrimalNode>; N],
+    pub buffer: S::Nodes,
     /// the index which blossom begins, should not change once the program starts
     pub blossom_begin: usize,
     /// the first child within a blossom
-    pub first_blossom_child: [OptionCompactNodeIndex; N],
+    pub first_blossom_child: S::NodeIndices,
     /// the number of defect nodes reported by the dual module, not necessarily all the defect nodes
     pub count_defects: usize,
     /// the number of allocated blossoms
@@ -48,7 +49,10 @@ pub struct PrimalNode {
     pub link: TouchingLink,
 }
 
-impl<const N: usize> PrimalNodes<N> {
+pub type PrimalNodes<const N: usize> = PrimalNodesBase<ArrayStorage<N>>;
+pub type PrimalNodesSlice<'a> = PrimalNodesBase<SliceStorage<'a>>;
+
+impl<const N: usize, const VN: usize> PrimalNodesBase<ArrayStorage<N, VN>> {
     pub const fn new() -> Self {
         Self {
             buffer: [None; N],
@@ -58,6 +62,36 @@ impl<const N: usize> PrimalNodes<N> {
             count_blossoms: 0,
         }
     }
+}
+
+impl<'a> PrimalNodesSlice<'a> {
+    /// allocate the nodes of the given capacity in the arena
+    pub fn new_in(arena: &mut Arena<'a>, capacity: usize) -> Option<Self> {
+        Some(Self::from_buffers(
+            arena.alloc_slice(capacity, None)?,
+            arena.alloc_slice(capacity, OptionCompactNodeIndex::NONE)?,
+        ))
+    }
+}
+
+impl<S: Storage> PrimalNodesBase<S> {
+    /// the two buffers must have the same capacity; the content of the buffers is ignored
+    pub fn from_buffers(buffer: S::Nodes, first_blossom_child: S::NodeIndices) -> Self {
+        let capacity = buffer.as_ref().len();
+        assert_eq!(capacity, first_blossom_child.as_ref().len());
+        Self {
+            buffer,
+            blossom_begin: capacity / 2, // by default half defects half blossom
+            first_blossom_child,
+            count_defects: 0,
+            count_blossoms: 0,
+        }
+    }
+
+    /// the maximum number of nodes, including both defects and blossoms
+    pub fn capacity(&self) -> usize {
+        self.buffer.as_ref().len()
+    }
 
     pub fn clear(&mut self) {
         self.count_defects = 0;
@@ -68,7 +102,7 @@ impl<const N: usize> PrimalNodes<N> {
         debug_assert!((defect_index.get() as usize) < self.blossom_begin);
         if defect_index.get() as usize >= self.count_defects {
             for index in self.count_defects..=defect_index.get() as usize {
-                set!(self.buffer, index as usize, None);
+                set!(self.buffer.as_mut(), index as usize, None);
             }
             self.count_defects = defect_index.get() as usize + 1;
         }
@@ -86,7 +120,7 @@ impl<const N: usize> PrimalNodes<N> {
             );
         } else {
             self.prepare_defects_up_to(node_index);
-            if get!(self.buffer, node_index.get() as usize).is_none() {
+            if get!(self.buffer.as_ref(), node_index.get() as usize).is_none() {
                 // Bambu HLS cannot handle this, error message:
                 // opt-12: ../../../etc/clang_plugin/dumpGimple.cpp:2935:
                 // int64_t llvm::DumpGimpleRaw::TREE_INT_CST_LOW(const void *): Assertion `val.getNumWords() == 1' failed.
@@ -94,7 +128,7 @@ impl<const N: usize> PrimalNodes<N> {
                     if #[cfg(feature="hls")] {
                         unimplemented_or_loop!()
                     } else {
-                        set!(self.buffer, node_index.get() as usize, Some(PrimalNode::new()))
+                        set!(self.buffer.as_mut(), node_index.get() as usize, Some(PrimalNode::new()))
                     }
                 }
             }
@@ -102,7 +136,10 @@ impl<const N: usize> PrimalNodes<N> {
     }
 
     pub fn is_blossom(&self, node_index: CompactNodeIndex) -> bool {
-        debug_assert!((node_index.get() as usize) < N, "node index too large, leading to overflow");
+        debug_assert!(
+            (node_index.get() as usize) < self.capacity(),
+            "node index too large, leading to overflow"
+        );
         if node_index.get() < self.blossom_begin as CompactNodeNum {
             false
         } else {
@@ -119,26 +156,35 @@ impl<const N: usize> PrimalNodes<N> {
     }
 
     pub fn has_node(&self, node_index: CompactNodeIndex) -> bool {
-        debug_assert!((node_index.get() as usize) < N, "node index too large, leading to overflow");
-        get!(self.buffer, node_index.get() as usize).is_some()
+        debug_assert!(
+            (node_index.get() as usize) < self.capacity(),
+            "node index too large, leading to overflow"
+        );
+        get!(self.buffer.as_ref(), node_index.get() as usize).is_some()
     }
 
     #[allow(unused_unsafe)]
     pub fn get_node(&self, node_index: CompactNodeIndex) -> &PrimalNode {
-        debug_assert!((node_index.get() as usize) < N, "node index too large, leading to overflow");
-        usu!(get!(self.buffer, node_index.get() as usize).as_ref())
+        debug_assert!(
+            (node_index.get() as usize) < self.capacity(),
+            "node index too large, leading to overflow"
+        );
+        usu!(get!(self.buffer.as_ref(), node_index.get() as usize).as_ref())
     }
 
     #[allow(unused_unsafe)]
     pub fn get_node_mut(&mut self, node_index: CompactNodeIndex) -> &mut PrimalNode {
-        debug_assert!((node_index.get() as usize) < N, "node index too large, leading to overflow");
-        usu!(get_mut!(self.buffer, node_index.get() as usize).as_mut())
+        debug_assert!(
+            (node_index.get() as usize) < self.capacity(),
+            "node index too large, leading to overflow"
+        );
+        usu!(get_mut!(self.buffer.as_mut(), node_index.get() as usize).as_mut())
     }
 
     #[allow(unused_unsafe)]
     pub fn get_first_blossom_child(&self, blossom_index: CompactNodeIndex) -> CompactNodeIndex {
         debug_assert!(self.is_blossom(blossom_index) && self.has_node(blossom_index));
-        usu!(get!(self.first_blossom_child, blossom_index.get() as usize))
+        usu!(get!(self.first_blossom_child.as_ref(), blossom_index.get() as usize))
     }
 
     #[inline]
@@ -373,10 +419,10 @@ impl<const N: usize> PrimalNodes<N> {
 
     /// allocate a blank blossom
     pub fn allocate_blossom(&mut self, first_blossom_child: CompactNodeIndex) -> CompactNodeIndex {
-        debug_assert!(self.blossom_begin + self.count_blossoms < N, "blossom overflow");
+        debug_assert!(self.blossom_begin + self.count_blossoms < self.capacity(), "blossom overflow");
         let blossom_index = self.blossom_begin + self.count_blossoms;
-        set!(self.buffer, blossom_index, Some(PrimalNode::new()));
-        set!(self.first_blossom_child, blossom_index, first_blossom_child.option());
+        set!(self.buffer.as_mut(), blossom_index, Some(PrimalNode::new()));
+        set!(self.first_blossom_child.as_mut(), blossom_index, first_blossom_child.option());
         self.count_blossoms += 1;
         ni!(blossom_index)
     }
@@ -385,8 +431,8 @@ impl<const N: usize> PrimalNodes<N> {
     pub fn dispose_blossom(&mut self, blossom_index: CompactNodeIndex) {
         debug_assert!(self.is_blossom(blossom_index), "do not dispose a defect vertex");
         debug_assert!(self.has_node(blossom_index), "do not dispose twice");
-        set!(self.buffer, blossom_index.get() as usize, None);
-        set!(self.first_blossom_child, blossom_index.get() as usize, None.into());
+        set!(self.buffer.as_mut(), blossom_index.get() as usize, None);
+        set!(self.first_blossom_child.as_mut(), blossom_index.get() as usize, None.into());
     }
 
     /// create an iterator containing all the existing node indices
@@ -484,14 +530,14 @@ impl TouchingLink {
 }
 
 #[cfg(any(test, feature = "std"))]
-impl<const N: usize> std::fmt::Debug for PrimalNodes<N> {
+impl<S: Storage> std::fmt::Debug for PrimalNodesBase<S> {
     fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
         formatter
             .debug_struct("Nodes")
             .field(
                 "defects",
                 &(0..self.count_defects as usize)
-                    .map(|index| (index, &self.buffer[index]))
+                    .map(|index| (index, &self.buffer.as_ref()[index]))
                     .collect::<std::collections::BTreeMap<_, _>>(),
             )
             .field(
@@ -499,8 +545,11 @@ impl<const N: usize> std::fmt::Debug for PrimalNodes<N> {
                 &(0..self.count_blossoms as usize)
                     .map(|index| {
                         (
-                            N + index,
-                            (&self.buffer[self.blossom_begin + index], self.first_blossom_child[index]),
+                            self.capacity() + index,
+                            (
+                                &self.buffer.as_ref()[self.blossom_begin + index],
+                                self.first_blossom_child.as_ref()[index],
+                            ),
                         )
                     })
                     .collect::<std::collections::BTreeMap<_, _>>(),
@@ -0,0 +1,123 @@
+//! Storage
+//!
+//! The primal module and the blossom tracker keep their states in fixed-capacity tables. The tables are either arrays
+//! sized by const generics ([`ArrayStorage`]), which always reserve the worst case, or slices provided by the caller
+//! ([`SliceStorage`]) whose capacity is chosen at initialization, e.g., from `MicroBlossomHardwareInfo`, so that one
+//! binary can serve graphs of different sizes. The slices can be carved from a static byte array using [`Arena`].
+//!
+
+use crate::blossom_tracker::*;
+use crate::primal_nodes::*;
+use crate::util::*;
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+
+/// a fixed-capacity table, either an array or a mutable slice
+pub trait Buffer<T>: AsRef<[T]> + AsMut<[T]> {}
+
+impl<T, B: AsRef<[T]> + AsMut<[T]>> Buffer<T> for B {}
+
+/// the buffer types of all the fixed-capacity tables
+pub trait Storage {
+    /// primal nodes, including both defects and blossoms
+    type Nodes: Buffer<Option<PrimalNode>>;
+    /// the first child of each blossom
+    type NodeIndices: Buffer<OptionCompactNodeIndex>;
+    /// the layer id of each vertex, only used by layer fusion
+    type LayerIds: Buffer<OptionCompactLayerId>;
+    /// the pending breaks of layer fusion, at most one per vertex
+    type PendingBreaks: Buffer<CompactNodeIndex>;
+    /// the priority queue of the blossom tracker
+    type HitZeroEvents: Buffer<HitZeroEvent>;
+    /// the dual variable checkpoints of the blossom tracker
+    type Checkpoints: Buffer<(CompactTimestamp, CompactWeight)>;
+    /// the grow states of the blossom tracker
+    type GrowStates: Buffer<CompactGrowState>;
+}
+
+/// arrays of `N` nodes and `VN` vertices; `VN` is only needed for layer fusion
+#[cfg_attr(any(test, feature = "std"), derive(Debug))]
+pub struct ArrayStorage<const N: usize, const VN: usize = 0>;
+
+impl<const N: usize, const VN: usize> Storage for ArrayStorage<N, VN> {
+    type Nodes = [Option<PrimalNode>; N];
+    type NodeIndices = [OptionCompactNodeIndex; N];
+    type LayerIds = [OptionCompactLayerId; VN];
+    type PendingBreaks = [CompactNodeIndex; VN];
+    type HitZeroEvents = [HitZeroEvent; N];
+    type Checkpoints = [(CompactTimestamp, CompactWeight); N];
+    type GrowStates = [CompactGrowState; N];
+}
+
+/// slices provided by the caller, with the capacity chosen at runtime
+#[cfg_attr(any(test, feature = "std"), derive(Debug))]
+pub struct SliceStorage<'a>(PhantomData<&'a mut ()>);
+
+impl<'a> Storage for SliceStorage<'a> {
+    type Nodes = &'a mut [Option<PrimalNode>];
+    type NodeIndices = &'a mut [OptionCompactNodeIndex];
+    type LayerIds = &'a mut [OptionCompactLayerId];
+    type PendingBreaks = &'a mut [CompactNodeIndex];
+    type HitZeroEvents = &'a mut [HitZeroEvent];
+    type Checkpoints = &'a mut [(CompactTimestamp, CompactWeight)];
+    type GrowStates = &'a mut [CompactGrowState];
+}
+
+/// a bump allocator over a caller-provided memory, e.g., a static byte array; the memory is never freed
+pub struct Arena<'a> {
+    memory: &'a mut [u8],
+}
+
+impl<'a> Arena<'a> {
+    pub fn new(memory: &'a mut [u8]) -> Self {
+        Self { memory }
+    }
+
+    /// the number of remaining bytes, some of which may be used for alignment
+    pub fn remaining(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// allocate a slice filled with `value`, or `None` if the memory is not enough
+    pub fn alloc_slice<T: Copy>(&mut self, length: usize, value: T) -> Option<&'a mut [T]> {
+        let offset = self.memory.as_ptr().align_offset(align_of::<T>());
+        let bytes = length.checked_mul(size_of::<T>())?;
+        if offset.checked_add(bytes)? > self.memory.len() {
+            return None;
+        }
+        let memory = core::mem::take(&mut self.memory);
+        let (allocated, remaining) = memory[offset..].split_at_mut(bytes);
+        self.memory = remaining;
+        let pointer = allocated.as_mut_ptr() as *mut T;
+        // safety: the memory is aligned, large enough and exclusively borrowed for `'a`
+        unsafe {
+            for index in 0..length {
+                pointer.add(index).write(value);
+            }
+            Some(core::slice::from_raw_parts_mut(pointer, length))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storage_arena_alignment() {
+        // cargo test storage_arena_alignment -- --nocapture
+        let mut memory = [0u8; 64];
+        let mut arena = Arena::new(&mut memory);
+        let bytes = arena.alloc_slice(3, 7u8).unwrap();
+        assert_eq!(bytes, &[7, 7, 7]);
+        let words = arena.alloc_slice(4, u32::MAX).unwrap();
+        assert_eq!(words.as_ptr() as usize % align_of::<u32>(), 0);
+        assert_eq!(words, &[u32::MAX; 4]);
+        assert!(arena.remaining() <= 64 - 3 - 16);
+        assert!(arena.alloc_slice(100, 0u8).is_none());
+        // a failed allocation does not consume memory
+        let remaining = arena.remaining();
+        assert!(arena.alloc_slice(remaining, 0u8).is_some());
+        assert_eq!(arena.remaining(), 0);
+    }
+}
@@ -243,9 +243,16 @@ pub mod c_printer {
         pub fn print_char(c: c_char);
     }
 
+    /// with the `std` feature, e.g. in the simulators, print to the standard output instead of the C function
     pub fn print_string(s: &str) {
-        for c in s.chars() {
-            unsafe { print_char(c as c_char) };
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "std")] {
+                std::print!("{}", s);
+            } else {
+                for c in s.chars() {
+                    unsafe { print_char(c as c_char) };
+                }
+            }
         }
     }
 
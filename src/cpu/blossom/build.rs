@@ -1,3 +1,43 @@
+use std::env;
+
+// https://github.com/rust-lang/cargo/issues/9661#issuecomment-1722358176
+fn get_cargo_target_dir() -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
+    let profile = std::env::var("PROFILE")?;
+    let mut target_dir = None;
+    let mut sub_path = out_dir.as_path();
+    while let Some(parent) = sub_path.parent() {
+        if parent.ends_with(&profile) {
+            target_dir = Some(parent);
+            break;
+        }
+        sub_path = parent;
+    }
+    let target_dir = target_dir.ok_or("not found")?;
+    Ok(target_dir.to_path_buf())
+}
+
 fn main() {
     println!("cargo:rerun-if-env-changed=MAX_NODE_NUM");
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/binding.rs");
+
+    // create c bindgen for the `cdylib`, see `src/binding.rs`
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let mut cbindgen_config: cbindgen::Config = Default::default();
+    // only the functions and the types they use, not the constants of the whole crate
+    cbindgen_config.export.item_types = vec![
+        cbindgen::ItemType::Functions,
+        cbindgen::ItemType::Structs,
+        cbindgen::ItemType::OpaqueItems,
+    ];
+    cbindgen::Builder::new()
+        .with_crate(crate_dir)
+        .with_config(cbindgen_config)
+        .with_header("/* DO NOT MODIFY: automatically generated by cbindgen */")
+        .with_include_guard("MICRO_BLOSSOM_H")
+        .with_language(cbindgen::Language::C)
+        .generate()
+        .expect("Unable to generate bindings")
+        .write_to_file(get_cargo_target_dir().unwrap().parent().unwrap().join("micro_blossom.h"));
 }
@@ -0,0 +1,113 @@
+//! Batch Decoder
+//!
+//! Decode many syndrome patterns in parallel for offline analysis. Each rayon worker owns one solver that is
+//! constructed only once, so that the large allocations (e.g. the `stacker::grow` in `SolverEmbeddedBoxed::new`) are
+//! not repeated for every shot. The solver is cleared and its profiler is reset before each shot, so the results do
+//! not depend on which worker decodes which shot and are returned in the input order.
+//!
+
+use crate::cli::*;
+use crate::resources::*;
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::util::*;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchDecodeResult {
+    pub subgraph: Vec<EdgeIndex>,
+    pub profiler_report: serde_json::Value,
+}
+
+pub struct BatchDecoder {
+    thread_pool: rayon::ThreadPool,
+    /// one solver per worker, indexed by [`rayon::current_thread_index`]
+    solvers: Vec<Mutex<Box<dyn PrimalDualSolver + Send>>>,
+}
+
+impl BatchDecoder {
+    /// create a decoder with `num_threads` workers (0 for the number of CPUs), each constructing a solver by `factory`
+    pub fn new(num_threads: usize, factory: impl Fn() -> Box<dyn PrimalDualSolver + Send>) -> Self {
+        let thread_pool = rayon::ThreadPoolBuilder::new().num_threads(num_threads).build().unwrap();
+        let solvers = (0..thread_pool.current_num_threads())
+            .map(|_| Mutex::new(factory()))
+            .collect();
+        Self { thread_pool, solvers }
+    }
+
+    pub fn from_type(
+        primal_dual_type: PrimalDualType,
+        graph: &MicroBlossomSingle,
+        primal_dual_config: serde_json::Value,
+        num_threads: usize,
+    ) -> Self {
+        assert!(
+            primal_dual_type != PrimalDualType::ErrorPatternLogger,
+            "error-pattern-logger is not a decoder"
+        );
+        Self::new(num_threads, || {
+            primal_dual_type.build_from_graph(graph.clone(), primal_dual_config.clone())
+        })
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.solvers.len()
+    }
+
+    /// decode the syndrome patterns and return the results in the same order
+    pub fn decode(&self, syndrome_patterns: &[SyndromePattern]) -> Vec<BatchDecodeResult> {
+        self.thread_pool.install(|| {
+            syndrome_patterns
+                .par_iter()
+                .map(|syndrome_pattern| {
+                    let worker_index = rayon::current_thread_index().unwrap();
+                    let mut solver = self.solvers[worker_index].lock().unwrap();
+                    solver.clear();
+                    solver.reset_profiler();
+                    solver.solve(syndrome_pattern);
+                    BatchDecodeResult {
+                        subgraph: solver.subgraph(),
+                        profiler_report: solver.generate_profiler_report(),
+                    }
+                })
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn batch_decoder_matches_sequential() {
+        // cargo test batch_decoder_matches_sequential -- --nocapture
+        let mut code = PhenomenologicalPlanarCode::new(5, 3, 0.02, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let syndrome_patterns: Vec<SyndromePattern> = (0..100).map(|seed| code.generate_random_errors(seed)).collect();
+        for primal_dual_type in [PrimalDualType::Serial, PrimalDualType::EmbeddedComb] {
+            let mut solver = primal_dual_type.build_from_graph(graph.clone(), json!({}));
+            let expected: Vec<Vec<EdgeIndex>> = syndrome_patterns
+                .iter()
+                .map(|syndrome_pattern| {
+                    solver.solve(syndrome_pattern);
+                    let subgraph = solver.subgraph();
+                    solver.clear();
+                    subgraph
+                })
+                .collect();
+            let batch_decoder = BatchDecoder::from_type(primal_dual_type, &graph, json!({}), 4);
+            assert_eq!(batch_decoder.num_threads(), 4);
+            // decoding twice gives the same results regardless of the state of the solvers
+            for _ in 0..2 {
+                let results = batch_decoder.decode(&syndrome_patterns);
+                assert_eq!(results.len(), syndrome_patterns.len());
+                for (result, expected) in results.iter().zip(expected.iter()) {
+                    assert_eq!(&result.subgraph, expected);
+                }
+            }
+        }
+    }
+}
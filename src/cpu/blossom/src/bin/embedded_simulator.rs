@@ -17,6 +17,9 @@
 //! EMBEDDED_BLOSSOM_MAIN=benchmark_primal_simple_match cargo run --features=compact --release --bin embedded_simulator -- ../../../resources/graphs/example_code_capacity_planar_d3.json
 //!
 //! EMBEDDED_BLOSSOM_MAIN=test_micro_blossom cargo run --features=compact --release --bin embedded_simulator -- ../../../resources/graphs/example_code_capacity_d3.json
+//!
+//! # without SBT or Verilator: use the native Rust host (no waveform)
+//! NATIVE_HOST=1 EMBEDDED_BLOSSOM_MAIN=test_micro_blossom cargo run --features=compact --release --bin embedded_simulator -- ../../../resources/graphs/example_code_capacity_d3.json
 //! ```
 //!
 //! For more use cases and details, see https://docs.google.com/document/d/1HA6VL_ywSoCpS7PODIA8HeTbg_VIbbpyqtazdunSRvc/edit?usp=sharing
@@ -199,7 +202,7 @@ extern "C" fn reset_context(context_id: u16) {
     let mut simulator = SIMULATOR_DRIVER.lock();
     let driver = simulator.as_mut().unwrap();
     driver.context_id = context_id;
-    driver.reset();
+    driver.try_reset().unwrap();
 }
 
 #[no_mangle]
@@ -0,0 +1,351 @@
+//! C Binding
+//!
+//! A C API of the `cdylib` so that a control stack written in C/C++ can run the decoders in this crate. The header
+//! `target/micro_blossom.h` is generated by cbindgen in `build.rs`; see `tests/c_api_test.c` for an example.
+//!
+//! All the functions catch panics; a failing function returns `NULL` or a negative value and the reason can be
+//! queried by [`micro_blossom_last_error`].
+//!
+
+use crate::cli::*;
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use clap::ValueEnum;
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::util::*;
+use micro_blossom_nostd::interface::MicroBlossomError;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// a decoder of the C API; the embedded solvers record a failed shot instead of panicking
+trait BindingSolver: PrimalDualSolver {
+    fn error(&self) -> Option<MicroBlossomError> {
+        None
+    }
+}
+
+impl BindingSolver for SolverSerial {}
+impl BindingSolver for SolverPrimalEmbedded {}
+impl BindingSolver for SolverDualComb {}
+impl<Dual: SolverTrackedDual> BindingSolver for SolverEmbeddedBoxed<Dual> {
+    fn error(&self) -> Option<MicroBlossomError> {
+        SolverEmbeddedBoxed::error(self)
+    }
+}
+
+/// the same as [`PrimalDualType::build_from_graph`], keeping access to the error of the last shot
+fn build_binding_solver(
+    primal_dual_type: PrimalDualType,
+    graph: MicroBlossomSingle,
+    primal_dual_config: serde_json::Value,
+) -> Result<Box<dyn BindingSolver>, String> {
+    let initializer = &graph.get_initializer();
+    let check_empty_config = || {
+        if primal_dual_config != json!({}) {
+            return Err(format!("{primal_dual_type:?} does not take primal_dual_config"));
+        }
+        Ok(())
+    };
+    Ok(match primal_dual_type {
+        PrimalDualType::PrimalEmbedded => {
+            check_empty_config()?;
+            Box::new(SolverPrimalEmbedded::new(initializer))
+        }
+        PrimalDualType::DualComb => {
+            check_empty_config()?;
+            Box::new(SolverDualComb::new(initializer))
+        }
+        PrimalDualType::EmbeddedComb => Box::new(SolverEmbeddedComb::new(graph, primal_dual_config)),
+        PrimalDualType::EmbeddedScala => Box::new(SolverEmbeddedScala::new(graph, primal_dual_config)),
+        PrimalDualType::EmbeddedLooper => Box::new(SolverEmbeddedLooper::new(graph, primal_dual_config)),
+        PrimalDualType::EmbeddedAxi4 => Box::new(SolverEmbeddedAxi4::new(graph, primal_dual_config)),
+        PrimalDualType::Serial => {
+            check_empty_config()?;
+            Box::new(SolverSerial::new(initializer))
+        }
+        PrimalDualType::ErrorPatternLogger => return Err("error-pattern-logger is not a decoder".to_string()),
+    })
+}
+
+/// a solver together with the results of the latest decoding
+pub struct MicroBlossomSolver {
+    graph: MicroBlossomSingle,
+    solver: Box<dyn BindingSolver>,
+    matchings: Vec<MicroBlossomMatching>,
+    subgraph: Vec<u32>,
+}
+
+/// a defect vertex matched to either another defect vertex or a virtual vertex
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct MicroBlossomMatching {
+    pub vertex_1: u32,
+    pub vertex_2: u32,
+    /// whether `vertex_2` is a virtual vertex
+    pub is_virtual: bool,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error(error: String) {
+    let error = CString::new(error.replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = error);
+}
+
+/// run the function and record the error or panic message, if any
+fn catch_error<T>(function: impl FnOnce() -> Result<T, String>) -> Option<T> {
+    let result = catch_unwind(AssertUnwindSafe(function)).unwrap_or_else(|panic| {
+        Err(if let Some(message) = panic.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = panic.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "unknown panic".to_string()
+        })
+    });
+    result.map_err(set_last_error).ok()
+}
+
+unsafe fn read_str<'a>(pointer: *const c_char, name: &str) -> Result<&'a str, String> {
+    if pointer.is_null() {
+        return Err(format!("{name} is NULL"));
+    }
+    CStr::from_ptr(pointer)
+        .to_str()
+        .map_err(|error| format!("{name} is not valid UTF-8: {error}"))
+}
+
+/// copy as many items as the capacity allows and return the total number of items
+unsafe fn copy_out<T: Copy>(items: &[T], output: *mut T, capacity: usize) -> usize {
+    if !output.is_null() {
+        std::ptr::copy_nonoverlapping(items.as_ptr(), output, items.len().min(capacity));
+    }
+    items.len()
+}
+
+/// the message of the latest error in this thread; the string is valid until the next failing call in this thread
+#[no_mangle]
+pub extern "C" fn micro_blossom_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| last_error.borrow().as_ptr())
+}
+
+/// create a solver from the JSON of a `MicroBlossomSingle` graph configuration. `primal_dual_type` is the name used
+/// in the CLI, e.g. "embedded-comb", and `primal_dual_config` is a JSON object or `NULL`. Returns `NULL` on error.
+///
+/// # Safety
+///
+/// the strings must be valid null-terminated C strings (or `NULL` for `primal_dual_config`)
+#[no_mangle]
+pub unsafe extern "C" fn micro_blossom_solver_new(
+    graph_json: *const c_char,
+    primal_dual_type: *const c_char,
+    primal_dual_config: *const c_char,
+) -> *mut MicroBlossomSolver {
+    catch_error(|| {
+        let graph: MicroBlossomSingle = serde_json::from_str(read_str(graph_json, "graph_json")?)
+            .map_err(|error| format!("invalid graph configuration: {error}"))?;
+        let primal_dual_type_str = read_str(primal_dual_type, "primal_dual_type")?;
+        let primal_dual_type = PrimalDualType::from_str(primal_dual_type_str, true)?;
+        let primal_dual_config: serde_json::Value = if primal_dual_config.is_null() {
+            json!({})
+        } else {
+            serde_json::from_str(read_str(primal_dual_config, "primal_dual_config")?)
+                .map_err(|error| format!("invalid primal_dual_config: {error}"))?
+        };
+        let solver = build_binding_solver(primal_dual_type, graph.clone(), primal_dual_config)?;
+        Ok(Box::into_raw(Box::new(MicroBlossomSolver {
+            graph,
+            solver,
+            matchings: vec![],
+            subgraph: vec![],
+        })))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// # Safety
+///
+/// the solver must be created by [`micro_blossom_solver_new`] and not freed yet; `NULL` is ignored
+#[no_mangle]
+pub unsafe extern "C" fn micro_blossom_solver_free(solver: *mut MicroBlossomSolver) {
+    if !solver.is_null() {
+        drop(Box::from_raw(solver));
+    }
+}
+
+/// decode the defect vertices and keep the matchings and the subgraph until the next call; returns 0 on success
+/// and -1 on error, including a shot that the solver fails to decode, e.g. because of a hardware error
+///
+/// # Safety
+///
+/// the solver must be valid and `defects` must point to `defect_num` vertex indices (or be `NULL` if it is 0)
+#[no_mangle]
+pub unsafe extern "C" fn micro_blossom_solve(
+    solver: *mut MicroBlossomSolver,
+    defects: *const u32,
+    defect_num: usize,
+) -> i32 {
+    catch_error(|| {
+        let solver = solver.as_mut().ok_or("solver is NULL".to_string())?;
+        let defect_vertices: Vec<VertexIndex> = if defect_num == 0 {
+            vec![]
+        } else if defects.is_null() {
+            return Err("defects is NULL".to_string());
+        } else {
+            std::slice::from_raw_parts(defects, defect_num)
+                .iter()
+                .map(|&vertex_index| vertex_index as VertexIndex)
+                .collect()
+        };
+        for &vertex_index in defect_vertices.iter() {
+            if vertex_index >= solver.graph.vertex_num || solver.graph.virtual_vertices.contains(&vertex_index) {
+                return Err(format!("invalid defect vertex {vertex_index}"));
+            }
+        }
+        solver.matchings.clear();
+        solver.subgraph.clear();
+        solver.solver.clear();
+        solver.solver.solve(&SyndromePattern::new_vertices(defect_vertices));
+        if let Some(error) = solver.solver.error() {
+            return Err(format!("decoding failed: {error}"));
+        }
+        let perfect_matching = solver.solver.perfect_matching();
+        for (node_1, node_2) in perfect_matching.peer_matchings.iter() {
+            solver.matchings.push(MicroBlossomMatching {
+                vertex_1: node_1.get_representative_vertex() as u32,
+                vertex_2: node_2.get_representative_vertex() as u32,
+                is_virtual: false,
+            });
+        }
+        for (node, virtual_vertex) in perfect_matching.virtual_matchings.iter() {
+            solver.matchings.push(MicroBlossomMatching {
+                vertex_1: node.get_representative_vertex() as u32,
+                vertex_2: *virtual_vertex as u32,
+                is_virtual: true,
+            });
+        }
+        solver.subgraph = solver.solver.subgraph().iter().map(|&edge_index| edge_index as u32).collect();
+        Ok(0)
+    })
+    .unwrap_or(-1)
+}
+
+/// copy the matchings of the latest decoding into `matchings` and return the total number of matchings, which
+/// may be larger than `capacity`; pass `NULL` to only query the number. Returns -1 on error.
+///
+/// # Safety
+///
+/// the solver must be valid and `matchings` must have space for `capacity` items
+#[no_mangle]
+pub unsafe extern "C" fn micro_blossom_get_matchings(
+    solver: *const MicroBlossomSolver,
+    matchings: *mut MicroBlossomMatching,
+    capacity: usize,
+) -> isize {
+    catch_error(|| {
+        let solver = solver.as_ref().ok_or("solver is NULL".to_string())?;
+        Ok(copy_out(&solver.matchings, matchings, capacity) as isize)
+    })
+    .unwrap_or(-1)
+}
+
+/// copy the edge indices of the subgraph of the latest decoding, in the same way as [`micro_blossom_get_matchings`]
+///
+/// # Safety
+///
+/// the solver must be valid and `edges` must have space for `capacity` items
+#[no_mangle]
+pub unsafe extern "C" fn micro_blossom_get_subgraph(
+    solver: *const MicroBlossomSolver,
+    edges: *mut u32,
+    capacity: usize,
+) -> isize {
+    catch_error(|| {
+        let solver = solver.as_ref().ok_or("solver is NULL".to_string())?;
+        Ok(copy_out(&solver.subgraph, edges, capacity) as isize)
+    })
+    .unwrap_or(-1)
+}
+
+/// the profiler report in JSON; the string must be freed by [`micro_blossom_string_free`]. Returns `NULL` on error.
+///
+/// # Safety
+///
+/// the solver must be valid
+#[no_mangle]
+pub unsafe extern "C" fn micro_blossom_profiler_json(solver: *const MicroBlossomSolver) -> *mut c_char {
+    catch_error(|| {
+        let solver = solver.as_ref().ok_or("solver is NULL".to_string())?;
+        let report = solver.solver.generate_profiler_report().to_string();
+        Ok(CString::new(report).unwrap().into_raw())
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// # Safety
+///
+/// the string must be returned by this library and not freed yet; `NULL` is ignored
+#[no_mangle]
+pub unsafe extern "C" fn micro_blossom_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    #[test]
+    fn binding_c_api_test() {
+        // cargo test binding_c_api_test -- --nocapture
+        if Command::new("cc").arg("--version").output().is_err() {
+            println!("[warning] skipped because no C compiler is found");
+            return;
+        }
+        // the test binary is in `target/<profile>/deps`, next to which are the `cdylib` and the generated header
+        let profile_dir = std::env::current_exe()
+            .unwrap()
+            .parent()
+            .unwrap()
+            .parent()
+            .unwrap()
+            .to_path_buf();
+        let target_dir = profile_dir.parent().unwrap();
+        assert!(target_dir.join("micro_blossom.h").exists(), "header not generated");
+        assert!(profile_dir.join("libmicro_blossom.so").exists(), "cdylib not built");
+        let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let executable = profile_dir.join("c_api_test");
+        let status = Command::new("cc")
+            .arg(manifest_dir.join("tests/c_api_test.c"))
+            .arg("-I")
+            .arg(target_dir)
+            .arg("-L")
+            .arg(&profile_dir)
+            .arg("-lmicro_blossom")
+            .arg("-o")
+            .arg(&executable)
+            .status()
+            .expect("cannot run the C compiler");
+        assert!(status.success(), "failed to compile the C test program");
+        let code = CodeCapacityPlanarCode::new(3, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let graph_file = profile_dir.join("c_api_test.json");
+        std::fs::write(&graph_file, serde_json::to_string(&graph).unwrap()).unwrap();
+        let output = Command::new(&executable)
+            .arg(&graph_file)
+            .env("LD_LIBRARY_PATH", &profile_dir)
+            .output()
+            .unwrap();
+        println!("{}", String::from_utf8_lossy(&output.stdout));
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+        assert!(output.status.success());
+    }
+}
@@ -1,17 +1,30 @@
+use crate::detector_error_model::*;
+use crate::embedded_report::*;
+use crate::fuzz::*;
+use crate::graph_export::*;
+use crate::graph_validator::*;
+use crate::logical_observable::*;
+use crate::multi_context::*;
 use crate::mwpm_solver::*;
+use crate::resource_estimator::*;
 use crate::resources::*;
+use crate::simulation_tcp_client::*;
+use crate::sweep::*;
 use crate::transform_syndromes::*;
 use crate::util::*;
-use byteorder::{LittleEndian, WriteBytesExt};
+use crate::weight_quantization::*;
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use fusion_blossom::cli::{ExampleCodeType, RunnableBenchmarkParameters, Verifier};
 use fusion_blossom::mwpm_solver::*;
 use fusion_blossom::util::*;
 use fusion_blossom::visualize::VisualizePosition;
+use micro_blossom_nostd::instruction::Instruction32;
 use serde::Serialize;
 use serde_json::json;
 use std::convert::AsRef;
 use std::env;
+use std::io::{Read, Write};
 use strum_macros::AsRefStr;
 
 cfg_if::cfg_if! {
@@ -55,6 +68,550 @@ enum Commands {
         #[clap(subcommand)]
         transform_type: TransformSyndromesType,
     },
+    /// import a Stim detector error model as the graph configuration of Micro Blossom
+    ImportDem(ImportDemParameters),
+    /// estimate the logical error rate by tracking the logical observable of each edge
+    LogicalErrorRate(LogicalErrorRateParameters),
+    /// run a grid of benchmark configurations in parallel and write one row per configuration
+    Sweep(SweepParameters),
+    /// attach a user-defined layer fusion plan to an existing graph configuration
+    FusionPlan(FusionPlanParameters),
+    /// check a graph configuration and print a report; exit with a non-zero code if any check fails
+    Validate(ValidateParameters),
+    /// export a graph configuration to Graphviz DOT or GraphML for inspection
+    Export(ExportParameters),
+    /// estimate the register bits and flip-flops of the hardware generated from a graph configuration
+    Resources(ResourcesParameters),
+    /// quantize the edge probabilities to integer weights under a bit budget and report the rounding error
+    Quantize(QuantizeParameters),
+    /// decode random graphs and syndromes with every solver backend and shrink the failing cases
+    Fuzz(FuzzParameters),
+    /// convert the output of the embedded `benchmark_decoding` into the report of `--benchmark-profiler-output`
+    EmbeddedReport(EmbeddedReportParameters),
+    /// disassemble or assemble `Instruction32` programs
+    Isa {
+        #[clap(subcommand)]
+        command: IsaCommands,
+    },
+}
+
+#[derive(Parser, Clone)]
+pub struct ImportDemParameters {
+    /// detector error model file, e.g. from `circuit.detector_error_model(decompose_errors=True)` in Stim
+    #[clap(value_parser)]
+    dem_file: String,
+    /// output graph configuration
+    #[clap(value_parser)]
+    graph_file: String,
+    /// the largest half weight after quantization
+    #[clap(long, default_value_t = DEM_DEFAULT_MAX_HALF_WEIGHT)]
+    max_half_weight: usize,
+}
+
+#[derive(Parser, Clone)]
+#[clap(group(clap::ArgGroup::new("plan").required(true)))]
+pub struct FusionPlanParameters {
+    /// input graph configuration
+    #[clap(value_parser)]
+    graph_file: String,
+    /// output graph configuration; overwrite the input if not provided
+    #[clap(value_parser)]
+    output_file: Option<String>,
+    /// fusion plan file in the format of `{"layers":[[0,1,2],[3,4,5]]}`, listing the non-virtual vertices of each layer
+    #[clap(long, group = "plan")]
+    plan_file: Option<String>,
+    /// fuse several measurement rounds at once
+    #[clap(long, group = "plan", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    rounds_per_layer: Option<usize>,
+    /// fuse spatial strips of a wide code, each covering this number of columns
+    #[clap(long, group = "plan", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    columns_per_strip: Option<usize>,
+}
+
+impl FusionPlanParameters {
+    pub fn run(self) {
+        let graph_str = std::fs::read_to_string(&self.graph_file).unwrap();
+        let mut graph: MicroBlossomSingle = serde_json::from_str(&graph_str).unwrap();
+        let plan = if let Some(plan_file) = self.plan_file {
+            let plan_str = std::fs::read_to_string(plan_file).unwrap();
+            serde_json::from_str(&plan_str).unwrap()
+        } else if let Some(rounds_per_layer) = self.rounds_per_layer {
+            LayerFusionPlan::time_windows(&graph, rounds_per_layer)
+                .unwrap_or_else(|error| panic!("invalid fusion plan: {error}"))
+        } else {
+            LayerFusionPlan::spatial_strips(&graph, self.columns_per_strip.unwrap())
+                .unwrap_or_else(|error| panic!("invalid fusion plan: {error}"))
+        };
+        graph
+            .set_layer_fusion_plan(&plan)
+            .unwrap_or_else(|error| panic!("invalid fusion plan: {error}"));
+        let json_str = serde_json::to_string(&graph).unwrap();
+        std::fs::write(self.output_file.unwrap_or(self.graph_file), json_str).unwrap();
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct FuzzParameters {
+    /// the number of random cases
+    #[clap(long, default_value_t = 1000)]
+    cases: u64,
+    /// the seed of the first case; case `i` uses seed `seed + i`
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
+    /// the fuzzer configuration, see `FuzzConfig`
+    #[clap(long, default_value_t = ("{}").to_string())]
+    fuzz_config: String,
+    /// save the shrunk failing cases as regression files in this folder, e.g. `resources/fuzz_regressions`
+    #[clap(long)]
+    regression_folder: Option<String>,
+}
+
+impl FuzzParameters {
+    /// return whether all the cases pass
+    pub fn run(self) -> bool {
+        let config: FuzzConfig = serde_json::from_str(&self.fuzz_config).unwrap();
+        let failures = fuzz(&config, self.seed..self.seed + self.cases);
+        for failure in failures.iter() {
+            println!("{}", serde_json::to_string(failure).unwrap());
+            if let Some(regression_folder) = self.regression_folder.as_ref() {
+                let path = failure.save_regression(regression_folder).unwrap();
+                println!("saved to {}", path.display());
+            }
+        }
+        println!("{} of {} cases failed", failures.len(), self.cases);
+        failures.is_empty()
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct EmbeddedReportParameters {
+    /// graph configuration that the embedded program runs on
+    #[clap(value_parser)]
+    graph_file: String,
+    /// the captured outputs of `embedded_simulator` or the UART, merged if multiple are given
+    #[clap(value_parser, required = true)]
+    output_files: Vec<String>,
+    /// the benchmark profile output file path, in the same schema as `benchmark --benchmark-profiler-output`
+    #[clap(long)]
+    benchmark_profiler_output: Option<String>,
+    /// write the merged latency histograms as JSON
+    #[clap(long)]
+    histogram_output: Option<String>,
+    /// the number of noisy measurement rounds of the decoded syndromes, recorded in the report
+    #[clap(short = 'n', long, default_value_t = 0)]
+    noisy_measurements: VertexNum,
+}
+
+impl EmbeddedReportParameters {
+    pub fn run(self) -> Result<(), String> {
+        let graph_str =
+            std::fs::read_to_string(&self.graph_file).map_err(|error| format!("{}: {error}", self.graph_file))?;
+        let graph: MicroBlossomSingle =
+            serde_json::from_str(&graph_str).map_err(|error| format!("{}: invalid graph: {error}", self.graph_file))?;
+        let mut report = EmbeddedOutput::default();
+        for output_file in self.output_files.iter() {
+            let output = std::fs::read_to_string(output_file).map_err(|error| format!("{output_file}: {error}"))?;
+            let parsed = EmbeddedOutput::parse(&output).map_err(|error| format!("{output_file}: {error}"))?;
+            report.merge(parsed).map_err(|error| format!("{output_file}: {error}"))?;
+        }
+        report.print_statistics();
+        if let Some(benchmark_profiler_output) = self.benchmark_profiler_output.as_ref() {
+            report.write_profile(
+                benchmark_profiler_output,
+                graph.vertex_num as VertexNum,
+                self.noisy_measurements,
+            )?;
+        }
+        if let Some(histogram_output) = self.histogram_output.as_ref() {
+            std::fs::write(histogram_output, report.histograms_json().to_string())
+                .map_err(|error| format!("{histogram_output}: {error}"))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct ExportParameters {
+    /// input graph configuration
+    #[clap(value_parser)]
+    graph_file: String,
+    /// output file; print to stdout if not provided
+    #[clap(value_parser)]
+    output_file: Option<String>,
+    #[clap(long, value_enum, default_value_t = GraphExportFormat::Dot)]
+    format: GraphExportFormat,
+}
+
+impl ExportParameters {
+    pub fn run(self) -> Result<(), String> {
+        let graph_str = std::fs::read_to_string(&self.graph_file).unwrap();
+        let graph: MicroBlossomSingle = serde_json::from_str(&graph_str).unwrap();
+        let exported = graph.export(self.format)?;
+        match self.output_file {
+            Some(output_file) => std::fs::write(output_file, exported).unwrap(),
+            None => print!("{exported}"),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct ResourcesParameters {
+    /// graph configuration
+    #[clap(value_parser)]
+    graph_file: String,
+    /// the hardware options in the same format as the `sim_config` of the Axi4 dual module
+    #[clap(long, default_value_t = json!({}).to_string())]
+    sim_config: String,
+    /// the number of flip-flops available on the target FPGA, used to report the utilization
+    #[clap(long)]
+    fpga_flip_flops: Option<usize>,
+}
+
+impl ResourcesParameters {
+    pub fn run(self) -> Result<ResourceEstimate, String> {
+        let graph_str = std::fs::read_to_string(&self.graph_file).unwrap();
+        let graph: MicroBlossomSingle = serde_json::from_str(&graph_str).unwrap();
+        let sim_config: SimulationConfig = serde_json::from_str(&self.sim_config).unwrap();
+        let estimate = ResourceEstimate::new(&graph, &sim_config)?;
+        Ok(match self.fpga_flip_flops {
+            Some(fpga_flip_flops) => estimate.with_available_flip_flops(fpga_flip_flops),
+            None => estimate,
+        })
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct QuantizeParameters {
+    /// detector error model file; if not provided, the probabilities are taken from the generated example code
+    #[clap(long)]
+    dem_file: Option<String>,
+    /// code distance
+    #[clap(short = 'd', long, default_value_t = 5)]
+    d: VertexNum,
+    /// physical error rate of the example code
+    #[clap(short = 'p', long, default_value_t = 0.01)]
+    p: f64,
+    /// rounds of noisy measurement, valid only when multiple rounds
+    #[clap(short = 'n', long, default_value_t = 0)]
+    noisy_measurements: VertexNum,
+    /// example code type
+    #[clap(short = 'c', long, value_enum, default_value_t = ExampleCodeType::CodeCapacityPlanarCode)]
+    code_type: ExampleCodeType,
+    /// the configuration of the code builder
+    #[clap(long, default_value_t = ("{}").to_string())]
+    code_config: String,
+    /// the bits of a signed weight, e.g. 16 for `i16_weight`
+    #[clap(long, default_value_t = 16)]
+    weight_bits: usize,
+    /// the bits reserved for the dual variables to grow beyond the largest edge weight
+    #[clap(long, default_value_t = 0)]
+    headroom_bits: usize,
+    /// use this maximum half weight instead of the one derived from the bit budget
+    #[clap(long)]
+    max_half_weight: Option<Weight>,
+    /// the number of samples to compare the logical error rate against the reference weights; 0 to skip
+    #[clap(short = 'r', long, default_value_t = 0)]
+    total_rounds: usize,
+    /// the seed of the random error generator
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
+    /// the confidence level of the reported interval
+    #[clap(long, default_value_t = 0.95)]
+    confidence: f64,
+    /// write the graph configuration with the quantized weights to this file
+    #[clap(long)]
+    output_file: Option<String>,
+}
+
+impl QuantizeParameters {
+    pub fn run(self) -> serde_json::Value {
+        let (graph, probabilities) = if let Some(dem_file) = self.dem_file.as_ref() {
+            let text = std::fs::read_to_string(dem_file).unwrap();
+            let graph = MicroBlossomSingle::from_dem(&text, DEM_DEFAULT_MAX_HALF_WEIGHT)
+                .unwrap_or_else(|error| panic!("invalid detector error model: {error}"));
+            (graph, dem_edge_probabilities(&text).unwrap())
+        } else {
+            let code_config: serde_json::Value = serde_json::from_str(&self.code_config).unwrap();
+            let code = self
+                .code_type
+                .build(self.d, self.p, self.noisy_measurements, 500, code_config);
+            let mut graph = MicroBlossomSingle::new_code(code.as_ref());
+            graph.set_boundary_observable(0);
+            let (_, edges) = code.immutable_vertices_edges();
+            (graph, edges.iter().map(|edge| edge.p).collect())
+        };
+        let quantized = match self.max_half_weight {
+            Some(max_half_weight) => QuantizedWeights::new(&probabilities, max_half_weight),
+            None => QuantizedWeights::with_bits(&probabilities, self.weight_bits, self.headroom_bits),
+        }
+        .unwrap_or_else(|error| panic!("cannot quantize the weights: {error}"));
+        if let Some(output_file) = self.output_file.as_ref() {
+            let json_str = serde_json::to_string(&graph.with_weights(&quantized.weights)).unwrap();
+            std::fs::write(output_file, json_str).unwrap();
+        }
+        let comparison = (self.total_rounds > 0).then(|| {
+            compare_logical_error_rate(
+                &graph,
+                &probabilities,
+                &quantized,
+                self.total_rounds,
+                self.seed,
+                self.confidence,
+            )
+            .unwrap()
+        });
+        json!({
+            "max_half_weight": quantized.max_half_weight,
+            "scale": quantized.scale,
+            "max_relative_error": quantized.max_relative_error,
+            "mean_relative_error": quantized.mean_relative_error,
+            "relative_error_bound": quantized.relative_error_bound,
+            "comparison": comparison,
+        })
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct ValidateParameters {
+    /// graph configuration
+    #[clap(value_parser)]
+    graph_file: String,
+    /// check the bit widths of the embedded primal module compiled with `u16_index`
+    #[clap(long, action)]
+    u16_index: bool,
+    /// check the bit widths of the embedded primal module compiled with `i16_weight`
+    #[clap(long, action)]
+    i16_weight: bool,
+    /// check the bit widths of the embedded primal module compiled with `u8_layer_id`
+    #[clap(long, action)]
+    u8_layer_id: bool,
+}
+
+impl ValidateParameters {
+    /// return whether the graph passes all the checks
+    pub fn run(self) -> bool {
+        let graph_str = std::fs::read_to_string(&self.graph_file).unwrap();
+        let graph: MicroBlossomSingle = match serde_json::from_str(&graph_str) {
+            Ok(graph) => graph,
+            Err(error) => {
+                println!("[FAIL] {} is not a valid graph configuration: {error}", self.graph_file);
+                return false;
+            }
+        };
+        let config = GraphValidatorConfig {
+            u16_index: self.u16_index,
+            i16_weight: self.i16_weight,
+            u8_layer_id: self.u8_layer_id,
+        };
+        println!(
+            "validating {}: {} vertices, {} edges",
+            self.graph_file,
+            graph.vertex_num,
+            graph.weighted_edges.len()
+        );
+        let report = validate_graph(&graph, &config);
+        println!("{report}");
+        report.is_valid()
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct LogicalErrorRateParameters {
+    /// code distance
+    #[clap(value_parser)]
+    d: VertexNum,
+    /// physical error rate: the probability of each edge to have an error
+    #[clap(value_parser)]
+    p: f64,
+    /// erasure probability of each edge
+    #[clap(short = 'e', long, default_value_t = 0.)]
+    pe: f64,
+    /// rounds of noisy measurement, valid only when multiple rounds
+    #[clap(short = 'n', long, default_value_t = 0)]
+    noisy_measurements: VertexNum,
+    /// maximum half weight of edges
+    #[clap(long, default_value_t = 500)]
+    max_half_weight: Weight,
+    /// example code type; the errors are sampled on the edges so the code must be generated rather than read from file
+    #[clap(short = 'c', long, value_enum, default_value_t = ExampleCodeType::CodeCapacityPlanarCode)]
+    code_type: ExampleCodeType,
+    /// the configuration of the code builder
+    #[clap(long, default_value_t = ("{}").to_string())]
+    code_config: String,
+    /// primal-dual solver type
+    #[clap(short = 'p', long, value_enum, default_value_t = PrimalDualType::EmbeddedComb)]
+    primal_dual_type: PrimalDualType,
+    /// primal-dual solver config
+    #[clap(long, default_value_t = ("{}").to_string())]
+    primal_dual_config: String,
+    /// the number of iterations to run
+    #[clap(short = 'r', long, default_value_t = 1000)]
+    total_rounds: usize,
+    /// the seed of the random error generator
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
+    /// the confidence level of the reported interval
+    #[clap(long, default_value_t = 0.95)]
+    confidence: f64,
+}
+
+impl LogicalErrorRateParameters {
+    pub fn run(self) -> LogicalErrorRate {
+        assert!(
+            !matches!(
+                self.code_type,
+                ExampleCodeType::ErrorPatternReader | ExampleCodeType::QECPlaygroundCode
+            ),
+            "the code type does not provide the ground truth of the errors on each edge"
+        );
+        assert!(
+            self.confidence > 0. && self.confidence < 1.,
+            "confidence must be within (0, 1)"
+        );
+        let code_config: serde_json::Value = serde_json::from_str(&self.code_config).unwrap();
+        let primal_dual_config: serde_json::Value = serde_json::from_str(&self.primal_dual_config).unwrap();
+        let mut code = self
+            .code_type
+            .build(self.d, self.p, self.noisy_measurements, self.max_half_weight, code_config);
+        if self.pe > 0. {
+            code.set_erasure_probability(self.pe);
+        }
+        let mut graph = MicroBlossomSingle::new_code(code.as_ref());
+        graph.set_boundary_observable(0);
+        let initializer = code.get_initializer();
+        let positions = code.get_positions();
+        assert!(
+            self.primal_dual_type != PrimalDualType::ErrorPatternLogger,
+            "error pattern logger does not decode"
+        );
+        let mut solver = self.primal_dual_type.build(&initializer, &positions, primal_dual_config);
+        let mut sampler = LogicalErrorSampler::new(code.as_ref(), &graph, self.seed);
+        estimate_logical_error_rate(solver.as_mut(), &graph, &mut sampler, self.total_rounds, self.confidence)
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct SweepParameters {
+    /// output file; configurations already in this file are skipped so that an interrupted sweep can resume
+    #[clap(value_parser)]
+    output_file: String,
+    /// code distances
+    #[clap(short = 'd', long, value_delimiter = ',', required = true)]
+    d: Vec<VertexNum>,
+    /// physical error rates
+    #[clap(short = 'p', long, value_delimiter = ',', required = true)]
+    p: Vec<f64>,
+    /// erasure probabilities
+    #[clap(short = 'e', long, value_delimiter = ',', default_value = "0")]
+    pe: Vec<f64>,
+    /// rounds of noisy measurement, valid only when multiple rounds
+    #[clap(short = 'n', long, value_delimiter = ',', default_value = "0")]
+    noisy_measurements: Vec<VertexNum>,
+    /// example code types
+    #[clap(
+        short = 'c',
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "code-capacity-planar-code"
+    )]
+    code_type: Vec<ExampleCodeType>,
+    /// primal-dual solver types
+    #[clap(long, value_enum, value_delimiter = ',', default_value = "embedded-comb")]
+    primal_dual_type: Vec<PrimalDualType>,
+    /// maximum half weight of edges
+    #[clap(long, default_value_t = 500)]
+    max_half_weight: Weight,
+    /// the configuration of the code builder
+    #[clap(long, default_value_t = ("{}").to_string())]
+    code_config: String,
+    /// primal-dual solver config
+    #[clap(long, default_value_t = ("{}").to_string())]
+    primal_dual_config: String,
+    /// the number of iterations to run for each configuration
+    #[clap(short = 'r', long, default_value_t = 1000)]
+    total_rounds: usize,
+    /// the seed of the random error generator
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
+    /// also estimate the logical error rate of each configuration
+    #[clap(long, action)]
+    logical_error_rate: bool,
+    /// the format of the output file
+    #[clap(long, value_enum, default_value_t = SweepFormat::Csv)]
+    format: SweepFormat,
+    /// the number of threads; use all the cores by default
+    #[clap(long)]
+    threads: Option<usize>,
+}
+
+impl SweepParameters {
+    pub fn run(self) -> Result<(), String> {
+        if self.primal_dual_type.contains(&PrimalDualType::ErrorPatternLogger) {
+            return Err("error pattern logger does not decode".to_string());
+        }
+        if self.logical_error_rate
+            && self.code_type.iter().any(|code_type| {
+                matches!(
+                    code_type,
+                    ExampleCodeType::ErrorPatternReader | ExampleCodeType::QECPlaygroundCode
+                )
+            })
+        {
+            return Err("the code type does not provide the ground truth of the errors on each edge".to_string());
+        }
+        let configurations = SweepConfiguration::grid(
+            &self.d,
+            &self.p,
+            &self.pe,
+            &self.noisy_measurements,
+            &self.code_type,
+            &self.primal_dual_type,
+        );
+        let options = SweepOptions {
+            max_half_weight: self.max_half_weight,
+            code_config: serde_json::from_str(&self.code_config).map_err(|error| format!("code config: {error}"))?,
+            primal_dual_config: serde_json::from_str(&self.primal_dual_config)
+                .map_err(|error| format!("primal-dual config: {error}"))?,
+            total_rounds: self.total_rounds,
+            seed: self.seed,
+            logical_error_rate: self.logical_error_rate,
+        };
+        let mut thread_pool_builder = rayon::ThreadPoolBuilder::new();
+        if let Some(threads) = self.threads {
+            thread_pool_builder = thread_pool_builder.num_threads(threads);
+        }
+        let thread_pool = thread_pool_builder.build().unwrap();
+        let executed = thread_pool.install(|| run_sweep(&configurations, &options, &self.output_file, self.format))?;
+        println!(
+            "executed {executed} of {} configurations, results in {}",
+            configurations.len(),
+            self.output_file
+        );
+        Ok(())
+    }
+}
+
+#[derive(Subcommand, Clone)]
+enum IsaCommands {
+    /// print the textual form of hexadecimal instruction words, e.g. from the hex dumps or the ILA captures
+    Disasm(IsaParameters),
+    /// assemble the textual form into hexadecimal instruction words
+    Asm(IsaParameters),
+}
+
+#[derive(Parser, Clone)]
+pub struct IsaParameters {
+    /// input file; read from stdin if not provided
+    #[clap(value_parser)]
+    input_file: Option<String>,
+    /// output file; print to stdout if not provided
+    #[clap(short = 'o', long)]
+    output_file: Option<String>,
+    /// use the binary format of little-endian u32 words instead of the hexadecimal text
+    #[clap(long, action)]
+    binary: bool,
 }
 
 #[derive(Parser, Clone)]
@@ -117,6 +674,35 @@ pub struct BenchmarkParameters {
     /// and the u32 array binary syndrome defects for embedding into the memory {name}.defects
     #[clap(long, action)]
     parse_micro_blossom_files: bool,
+    /// when `--primal-dual-type embedded-axi4`, also decode the shots by interleaving this number of hardware
+    /// contexts and print the throughput compared to a single context
+    #[clap(long)]
+    context_depth: Option<usize>,
+}
+
+impl BenchmarkParameters {
+    pub fn run_contexts(&self, context_depth: usize) -> MultiContextBenchmark {
+        assert!(context_depth >= 1, "at least one context is required");
+        assert_eq!(
+            self.primal_dual_type,
+            PrimalDualType::EmbeddedAxi4,
+            "multiple contexts are only supported by the Axi4 dual module"
+        );
+        let code_config: serde_json::Value = serde_json::from_str(&self.code_config).unwrap();
+        let mut primal_dual_config: serde_json::Value = serde_json::from_str(&self.primal_dual_config).unwrap();
+        primal_dual_config["dual"]["sim_config"]["context_depth"] = json!(context_depth);
+        let mut code = self
+            .code_type
+            .build(self.d, self.p, self.noisy_measurements, self.max_half_weight, code_config);
+        if self.pe > 0. {
+            code.set_erasure_probability(self.pe);
+        }
+        let syndrome_patterns: Vec<_> = (self.starting_iteration..self.total_rounds)
+            .map(|round| code.generate_random_errors(round as u64))
+            .collect();
+        let mut solver = SolverMultiContextAxi4::new(MicroBlossomSingle::new_code(code.as_ref()), primal_dual_config);
+        solver.benchmark(&syndrome_patterns)
+    }
 }
 
 #[derive(Parser, Clone)]
@@ -430,8 +1016,15 @@ impl Cli {
             Commands::Benchmark(benchmark_parameters) => {
                 let parse_micro_blossom_files = benchmark_parameters.parse_micro_blossom_files;
                 let primal_dual_config = benchmark_parameters.primal_dual_config.clone();
+                let contexts_parameters = benchmark_parameters
+                    .context_depth
+                    .map(|context_depth| (benchmark_parameters.clone(), context_depth));
                 let runnable = RunnableBenchmarkParameters::from(benchmark_parameters);
                 runnable.run();
+                if let Some((parameters, context_depth)) = contexts_parameters {
+                    let benchmark = parameters.run_contexts(context_depth);
+                    println!("{}", serde_json::to_string(&benchmark).unwrap());
+                }
                 if parse_micro_blossom_files {
                     let config: serde_json::Map<String, serde_json::Value> =
                         serde_json::from_str(primal_dual_config.as_str()).unwrap();
@@ -466,7 +1059,13 @@ impl Cli {
                     let mut micro_blossom = MicroBlossomSingle::new_code(&code);
                     if let Some(transform_type) = parameters.transform_type {
                         let original = micro_blossom.clone();
-                        micro_blossom = transform_type.parse(micro_blossom);
+                        micro_blossom = match transform_type.parse(micro_blossom) {
+                            Ok(micro_blossom) => micro_blossom,
+                            Err(error) => {
+                                println!("[error] {error}");
+                                std::process::exit(1);
+                            }
+                        };
                         // sanity check: should not modify these fields because otherwise the
                         // defects file will contain invalid or misaligned indices
                         assert_eq!(original.vertex_num, micro_blossom.vertex_num);
@@ -494,11 +1093,151 @@ impl Cli {
                 transform_type,
                 input_file,
                 output_file,
-            } => transform_type.run(input_file, output_file),
+            } => {
+                if let Err(error) = transform_type.run(input_file, output_file) {
+                    println!("[error] {error}");
+                    std::process::exit(1);
+                }
+            }
+            Commands::ImportDem(parameters) => {
+                let text = std::fs::read_to_string(parameters.dem_file).unwrap();
+                let micro_blossom = MicroBlossomSingle::from_dem(&text, parameters.max_half_weight)
+                    .unwrap_or_else(|error| panic!("invalid detector error model: {error}"));
+                let json_str = serde_json::to_string(&micro_blossom).unwrap();
+                std::fs::write(parameters.graph_file, json_str).unwrap();
+            }
+            Commands::LogicalErrorRate(parameters) => {
+                let result = parameters.run();
+                println!("{}", serde_json::to_string(&result).unwrap());
+            }
+            Commands::Sweep(parameters) => {
+                if let Err(error) = parameters.run() {
+                    println!("[error] {error}");
+                    std::process::exit(1);
+                }
+            }
+            Commands::FusionPlan(parameters) => parameters.run(),
+            Commands::Validate(parameters) => {
+                if !parameters.run() {
+                    std::process::exit(1);
+                }
+            }
+            Commands::Export(parameters) => {
+                if let Err(error) = parameters.run() {
+                    println!("[error] {error}");
+                    std::process::exit(1);
+                }
+            }
+            Commands::Resources(parameters) => match parameters.run() {
+                Ok(estimate) => println!("{}", serde_json::to_string(&estimate).unwrap()),
+                Err(error) => {
+                    println!("[error] {error}");
+                    std::process::exit(1);
+                }
+            },
+            Commands::Quantize(parameters) => {
+                let result = parameters.run();
+                println!("{}", serde_json::to_string(&result).unwrap());
+            }
+            Commands::Fuzz(parameters) => {
+                if !parameters.run() {
+                    std::process::exit(1);
+                }
+            }
+            Commands::EmbeddedReport(parameters) => {
+                if let Err(error) = parameters.run() {
+                    println!("[error] {error}");
+                    std::process::exit(1);
+                }
+            }
+            Commands::Isa { command } => command.run(),
         }
     }
 }
 
+impl IsaCommands {
+    pub fn run(&self) {
+        let (Self::Disasm(parameters) | Self::Asm(parameters)) = self;
+        let input = match parameters.input_file.as_ref() {
+            Some(input_file) => std::fs::read(input_file).unwrap(),
+            None => {
+                let mut input = vec![];
+                std::io::stdin().read_to_end(&mut input).unwrap();
+                input
+            }
+        };
+        let output: Vec<u8> = match self {
+            Self::Disasm(_) => {
+                let words = if parameters.binary {
+                    assert!(input.len() % 4 == 0, "binary input must be a multiple of 4 bytes");
+                    input.chunks(4).map(LittleEndian::read_u32).collect()
+                } else {
+                    isa_parse_hex_words(std::str::from_utf8(&input).unwrap()).unwrap_or_else(|error| panic!("{error}"))
+                };
+                let mut output = String::new();
+                for word in words {
+                    output += &format!("{:<48} // {:#010X}\n", Instruction32(word).to_string(), word);
+                }
+                output.into_bytes()
+            }
+            Self::Asm(_) => {
+                let instructions =
+                    isa_assemble(std::str::from_utf8(&input).unwrap()).unwrap_or_else(|error| panic!("{error}"));
+                let mut output = vec![];
+                for instruction in instructions {
+                    if parameters.binary {
+                        output.write_u32::<LittleEndian>(instruction.0).unwrap();
+                    } else {
+                        output.extend(format!("{:#010X}\n", instruction.0).into_bytes());
+                    }
+                }
+                output
+            }
+        };
+        match parameters.output_file.as_ref() {
+            Some(output_file) => std::fs::write(output_file, output).unwrap(),
+            None => std::io::stdout().write_all(&output).unwrap(),
+        }
+    }
+}
+
+fn isa_strip_comment(line: &str) -> &str {
+    line.split("//").next().unwrap().split('#').next().unwrap().trim()
+}
+
+/// parse hexadecimal words separated by whitespaces or commas, with or without the `0x` prefix
+pub fn isa_parse_hex_words(text: &str) -> Result<Vec<u32>, String> {
+    let mut words = vec![];
+    for (line_index, line) in text.lines().enumerate() {
+        for token in isa_strip_comment(line).split(|c: char| c.is_whitespace() || c == ',') {
+            if token.is_empty() {
+                continue;
+            }
+            let digits = token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")).unwrap_or(token);
+            let word = u32::from_str_radix(&digits.replace('_', ""), 16)
+                .map_err(|error| format!("line {}: invalid word `{token}`: {error}", line_index + 1))?;
+            words.push(word);
+        }
+    }
+    Ok(words)
+}
+
+/// assemble one instruction per line; comments start with `//` or `#`
+pub fn isa_assemble(text: &str) -> Result<Vec<Instruction32>, String> {
+    let mut instructions = vec![];
+    for (line_index, line) in text.lines().enumerate() {
+        let line = isa_strip_comment(line);
+        if line.is_empty() {
+            continue;
+        }
+        let instruction = line
+            .parse::<Instruction32>()
+            .map_err(|error| format!("line {}: {error}", line_index + 1))?;
+        instructions.push(instruction);
+    }
+    Ok(instructions)
+}
+
 pub fn execute_in_cli<I, T>(iter: I, print_command: bool)
 where
     I: IntoIterator<Item = T> + Clone,
@@ -525,9 +1264,19 @@ impl PrimalDualType {
         initializer: &SolverInitializer,
         positions: &Vec<VisualizePosition>,
         primal_dual_config: serde_json::Value,
-    ) -> Box<dyn PrimalDualSolver> {
+    ) -> Box<dyn PrimalDualSolver + Send> {
         // create micro blossom single graph configuration
         let graph = MicroBlossomSingle::new(initializer, positions);
+        self.build_from_graph(graph, primal_dual_config)
+    }
+
+    /// build the solver from a given graph configuration, e.g. one loaded from a JSON file with customized offloading
+    pub fn build_from_graph(
+        &self,
+        graph: MicroBlossomSingle,
+        primal_dual_config: serde_json::Value,
+    ) -> Box<dyn PrimalDualSolver + Send> {
+        let initializer = &graph.get_initializer();
         match self {
             Self::PrimalEmbedded => {
                 assert_eq!(primal_dual_config, json!({}));
@@ -541,7 +1290,11 @@ impl PrimalDualType {
             Self::EmbeddedScala => Box::new(SolverEmbeddedScala::new(graph, primal_dual_config)),
             Self::EmbeddedLooper => Box::new(SolverEmbeddedLooper::new(graph, primal_dual_config)),
             Self::EmbeddedAxi4 => Box::new(SolverEmbeddedAxi4::new(graph, primal_dual_config)),
-            Self::Serial | Self::ErrorPatternLogger => {
+            Self::Serial => {
+                assert_eq!(primal_dual_config, json!({}));
+                Box::new(SolverSerial::new(initializer))
+            }
+            Self::ErrorPatternLogger => {
                 unreachable!()
             }
         }
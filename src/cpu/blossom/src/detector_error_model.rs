@@ -0,0 +1,430 @@
+//! Detector Error Model
+//!
+//! Import a Stim detector error model (DEM) as the decoding graph of Micro Blossom.
+//! Each detector becomes a vertex with the same index, so that the detection events sampled by Stim can be used as
+//! defect vertices directly. Every detector that is connected to the boundary gets its own virtual vertex, appended
+//! after the detectors and placed next to it, which keeps the boundary edges local on the hardware.
+//! The detector coordinates are interpreted as `(x, y, t)`, or `(x, t)` when there are only two of them, which is
+//! the convention of the circuits generated by Stim.
+//!
+
+use crate::resources::*;
+use crate::weight_quantization::*;
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+use std::collections::BTreeMap;
+
+/// the default maximum half weight, same as the one we use when generating the FPGA graphs from QEC-Playground
+pub const DEM_DEFAULT_MAX_HALF_WEIGHT: usize = 7;
+/// the observables are stored as a bit mask per edge
+pub const DEM_MAX_OBSERVABLES: usize = 64;
+
+/// an edge of the detector error model before quantization
+#[derive(Debug, Clone)]
+struct DemEdge {
+    /// the right detector is `None` if the edge connects to the boundary
+    detectors: (usize, Option<usize>),
+    probability: f64,
+    observables: u64,
+}
+
+#[derive(Debug, Default)]
+struct DemParser {
+    detector_offset: usize,
+    coordinate_offset: Vec<f64>,
+    detector_num: usize,
+    coordinates: BTreeMap<usize, Vec<f64>>,
+    edges: Vec<DemEdge>,
+    edge_indices: BTreeMap<(usize, Option<usize>), usize>,
+}
+
+impl DemParser {
+    /// parse a block of lines, `lines[index]` being the first line of the block; return the index after the block
+    fn parse_block(&mut self, lines: &[(usize, &str)], mut index: usize, is_nested: bool) -> Result<usize, String> {
+        while index < lines.len() {
+            let (line_number, line) = lines[index];
+            let error = |message: String| format!("line {line_number}: {message}");
+            index += 1;
+            if line == "}" {
+                if !is_nested {
+                    return Err(error("unexpected `}`".to_string()));
+                }
+                return Ok(index);
+            }
+            let (instruction, arguments, targets) = split_instruction(line).map_err(error)?;
+            match instruction {
+                "error" => {
+                    let probability = parse_single_argument(arguments).map_err(error)?;
+                    self.add_error(probability, targets).map_err(error)?;
+                }
+                "detector" => {
+                    let arguments = parse_arguments(arguments).map_err(error)?;
+                    for target in targets.split_whitespace() {
+                        let detector = self.parse_detector(target).map_err(error)?;
+                        let coordinates = arguments
+                            .iter()
+                            .enumerate()
+                            .map(|(i, value)| value + self.coordinate_offset.get(i).unwrap_or(&0.))
+                            .collect();
+                        self.coordinates.insert(detector, coordinates);
+                    }
+                }
+                "shift_detectors" => {
+                    let arguments = parse_arguments(arguments).map_err(error)?;
+                    for (i, value) in arguments.iter().enumerate() {
+                        if i >= self.coordinate_offset.len() {
+                            self.coordinate_offset.push(0.);
+                        }
+                        self.coordinate_offset[i] += value;
+                    }
+                    if !targets.is_empty() {
+                        let shift: usize = targets
+                            .parse()
+                            .map_err(|_| error(format!("invalid detector shift `{targets}`")))?;
+                        self.detector_offset += shift;
+                    }
+                }
+                "repeat" => {
+                    let repetitions: usize = targets
+                        .strip_suffix('{')
+                        .map(str::trim)
+                        .and_then(|count| count.parse().ok())
+                        .ok_or_else(|| error("expect `repeat N {`".to_string()))?;
+                    let mut block_end = index;
+                    for _ in 0..repetitions {
+                        block_end = self.parse_block(lines, index, true)?;
+                    }
+                    if repetitions == 0 {
+                        // still need to skip the block
+                        let mut depth = 1;
+                        while depth > 0 {
+                            let (_, line) = lines.get(block_end).ok_or_else(|| error("unclosed `repeat`".to_string()))?;
+                            depth += line.ends_with('{') as usize;
+                            depth -= (*line == "}") as usize;
+                            block_end += 1;
+                        }
+                    }
+                    index = block_end;
+                }
+                "logical_observable" => {
+                    for target in targets.split_whitespace() {
+                        parse_observable(target).map_err(error)?;
+                    }
+                }
+                _ => return Err(error(format!("unsupported instruction `{instruction}`"))),
+            }
+        }
+        if is_nested {
+            return Err("unclosed `repeat` block".to_string());
+        }
+        Ok(index)
+    }
+
+    fn parse_detector(&mut self, target: &str) -> Result<usize, String> {
+        let detector = target
+            .strip_prefix('D')
+            .and_then(|index| index.parse::<usize>().ok())
+            .ok_or_else(|| format!("invalid detector `{target}`"))?
+            + self.detector_offset;
+        self.detector_num = std::cmp::max(self.detector_num, detector + 1);
+        Ok(detector)
+    }
+
+    /// a suggested decomposition `D0 D1 ^ D2` is added as independent edges with the same probability
+    fn add_error(&mut self, probability: f64, targets: &str) -> Result<(), String> {
+        if !(0. ..=0.5).contains(&probability) {
+            return Err(format!("error probability {probability} is not in [0, 0.5]"));
+        }
+        for component in targets.split('^') {
+            let mut detectors = vec![];
+            let mut observables = 0u64;
+            for target in component.split_whitespace() {
+                if target.starts_with('D') {
+                    let detector = self.parse_detector(target)?;
+                    if let Some(position) = detectors.iter().position(|&existing| existing == detector) {
+                        detectors.remove(position);
+                    } else {
+                        detectors.push(detector);
+                    }
+                } else {
+                    observables ^= 1 << parse_observable(target)?;
+                }
+            }
+            detectors.sort();
+            let key = match detectors[..] {
+                [] => continue, // undetectable error cannot be corrected anyway
+                [detector] => (detector, None),
+                [left, right] => (left, Some(right)),
+                _ => {
+                    return Err(format!(
+                        "error `{component}` is not graphlike, export the model with `decompose_errors=True`"
+                    ))
+                }
+            };
+            self.add_edge(key, probability, observables);
+        }
+        Ok(())
+    }
+
+    fn add_edge(&mut self, detectors: (usize, Option<usize>), probability: f64, observables: u64) {
+        if probability == 0. {
+            return;
+        }
+        if let Some(&edge_index) = self.edge_indices.get(&detectors) {
+            // independent errors on the same edge: the edge flips if an odd number of them happen;
+            // the observables are taken from the more likely error if they disagree
+            let edge = &mut self.edges[edge_index];
+            if probability > edge.probability {
+                edge.observables = observables;
+            }
+            edge.probability = edge.probability * (1. - probability) + probability * (1. - edge.probability);
+        } else {
+            self.edge_indices.insert(detectors, self.edges.len());
+            self.edges.push(DemEdge {
+                detectors,
+                probability,
+                observables,
+            });
+        }
+    }
+}
+
+/// split `name(arguments) targets` into its parts; tags like `error[tag](0.1)` are ignored
+fn split_instruction(line: &str) -> Result<(&str, &str, &str), String> {
+    let name_end = line
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(line.len());
+    let (name, mut rest) = line.split_at(name_end);
+    if rest.starts_with('[') {
+        let tag_end = rest.find(']').ok_or_else(|| format!("unclosed tag in `{line}`"))?;
+        rest = &rest[tag_end + 1..];
+    }
+    let mut arguments = "";
+    if let Some(after_parenthesis) = rest.strip_prefix('(') {
+        let end = after_parenthesis
+            .find(')')
+            .ok_or_else(|| format!("unclosed parenthesis in `{line}`"))?;
+        arguments = &after_parenthesis[..end];
+        rest = &after_parenthesis[end + 1..];
+    }
+    Ok((name, arguments, rest.trim()))
+}
+
+fn parse_arguments(arguments: &str) -> Result<Vec<f64>, String> {
+    arguments
+        .split(',')
+        .map(str::trim)
+        .filter(|argument| !argument.is_empty())
+        .map(|argument| argument.parse().map_err(|_| format!("invalid argument `{argument}`")))
+        .collect()
+}
+
+fn parse_single_argument(arguments: &str) -> Result<f64, String> {
+    match parse_arguments(arguments)?[..] {
+        [value] => Ok(value),
+        _ => Err(format!("expect a single argument, found `({arguments})`")),
+    }
+}
+
+fn parse_observable(target: &str) -> Result<usize, String> {
+    let observable = target
+        .strip_prefix('L')
+        .and_then(|index| index.parse::<usize>().ok())
+        .ok_or_else(|| format!("invalid target `{target}`"))?;
+    if observable >= DEM_MAX_OBSERVABLES {
+        return Err(format!("at most {DEM_MAX_OBSERVABLES} logical observables are supported"));
+    }
+    Ok(observable)
+}
+
+impl DemParser {
+    fn parse(text: &str) -> Result<Self, String> {
+        let lines: Vec<(usize, &str)> = text
+            .lines()
+            .enumerate()
+            .map(|(line_index, line)| (line_index + 1, line.split('#').next().unwrap().trim()))
+            .filter(|(_, line)| !line.is_empty())
+            .collect();
+        let mut parser = DemParser::default();
+        parser.parse_block(&lines, 0, false)?;
+        Ok(parser)
+    }
+}
+
+/// the error probability of each edge, in the same order as the edges of [`MicroBlossomSingle::from_dem`]
+pub fn dem_edge_probabilities(text: &str) -> Result<Vec<f64>, String> {
+    Ok(DemParser::parse(text)?.edges.iter().map(|edge| edge.probability).collect())
+}
+
+impl MicroBlossomSingle {
+    /// construct the decoding graph from the text of a Stim detector error model; the weights are quantized by
+    /// [`QuantizedWeights`] such that the largest half weight is `max_half_weight`
+    pub fn from_dem(text: &str, max_half_weight: usize) -> Result<Self, String> {
+        let parser = DemParser::parse(text)?;
+        // create a virtual vertex for each detector with a boundary edge
+        let mut virtual_vertices = BTreeMap::<usize, usize>::new(); // detector: virtual vertex
+        for edge in parser.edges.iter() {
+            if edge.detectors.1.is_none() {
+                let virtual_vertex = parser.detector_num + virtual_vertices.len();
+                virtual_vertices.entry(edge.detectors.0).or_insert(virtual_vertex);
+            }
+        }
+        let vertex_num = parser.detector_num + virtual_vertices.len();
+        let mut positions: Vec<VisualizePosition> = (0..parser.detector_num)
+            .map(|detector| {
+                let coordinates = parser.coordinates.get(&detector).map(|c| &c[..]).unwrap_or(&[]);
+                let coordinate = |i: usize| coordinates.get(i).cloned().unwrap_or(0.);
+                match coordinates.len() {
+                    0 | 1 => VisualizePosition::new(0., coordinate(0), 0.),
+                    2 => VisualizePosition::new(0., coordinate(0), coordinate(1)),
+                    _ => VisualizePosition::new(coordinate(1), coordinate(0), coordinate(2)),
+                }
+            })
+            .collect();
+        let mut virtual_positions = vec![VisualizePosition::new(0., 0., 0.); virtual_vertices.len()];
+        for (&detector, &virtual_vertex) in virtual_vertices.iter() {
+            let position = &positions[detector];
+            virtual_positions[virtual_vertex - parser.detector_num] =
+                VisualizePosition::new(position.i + 0.5, position.j, position.t);
+        }
+        positions.extend(virtual_positions);
+        // quantize the weights
+        let probabilities: Vec<f64> = parser.edges.iter().map(|edge| edge.probability).collect();
+        let quantized = QuantizedWeights::new(&probabilities, max_half_weight as Weight)?;
+        let weighted_edges = parser
+            .edges
+            .iter()
+            .zip(quantized.weights.iter())
+            .map(|(edge, &weight)| {
+                let (left, right) = edge.detectors;
+                let right = right.unwrap_or_else(|| virtual_vertices[&left]);
+                (left, right, weight)
+            })
+            .collect();
+        // the virtual vertices are numbered by the first boundary edge, not by the detector
+        let mut virtual_vertex_indices: Vec<usize> = virtual_vertices.values().cloned().collect();
+        virtual_vertex_indices.sort_unstable();
+        let initializer = SolverInitializer::new(vertex_num, weighted_edges, virtual_vertex_indices);
+        let mut graph = Self::new(&initializer, &positions);
+        for (weighted_edge, edge) in graph.weighted_edges.iter_mut().zip(parser.edges.iter()) {
+            weighted_edge.observables = edge.observables;
+        }
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `stim.Circuit.generated("repetition_code:memory", distance=3, rounds=2, after_clifford_depolarization=0.01)`
+    const REPETITION_CODE_DEM: &str = "
+        error(0.00667) D0
+        error(0.00667) D0 D1
+        error(0.00667) D0 D2
+        error(0.00667) D1 D3
+        error(0.00667) D1 L0
+        error(0.00667) D2 D3
+        error(0.00133) D2 D4 ^ D3 D5  # decomposed
+        error(0.00667) D2 D4
+        error(0.00667) D3 D5
+        error(0.00667) D4 D5
+        error(0.00667) D5 L0
+        detector(1, 0) D0
+        detector(3, 0) D1
+        shift_detectors(0, 1) 0
+        detector(1, 0) D2
+        detector(3, 0) D3
+        detector(1, 1) D4
+        detector(3, 1) D5
+    ";
+
+    #[test]
+    fn detector_error_model_repetition_code() {
+        // cargo test detector_error_model_repetition_code -- --nocapture
+        let graph = MicroBlossomSingle::from_dem(REPETITION_CODE_DEM, 500).unwrap();
+        println!("{graph:?}");
+        assert_eq!(graph.vertex_num, 6 + 3);
+        assert_eq!(graph.virtual_vertices, vec![6, 7, 8]);
+        assert_eq!(graph.weighted_edges.len(), 10);
+        assert_eq!((graph.weighted_edges[0].l, graph.weighted_edges[0].r), (0, 6));
+        assert_eq!((graph.weighted_edges[4].l, graph.weighted_edges[4].r), (1, 7));
+        assert_eq!(graph.weighted_edges[4].observables, 1);
+        assert_eq!(graph.weighted_edges[1].observables, 0);
+        // the decomposed error is merged into the existing edges and thus more likely
+        let d2_d4 = graph.weighted_edges.iter().find(|edge| (edge.l, edge.r) == (2, 4)).unwrap();
+        assert!(d2_d4.w < graph.weighted_edges[0].w);
+        assert_eq!(graph.weighted_edges[0].w, 1000);
+        assert_eq!((graph.positions[2].j, graph.positions[2].t), (1., 1.));
+        assert_eq!((graph.positions[5].j, graph.positions[5].t), (3., 2.));
+        assert_eq!(graph.layer_fusion.as_ref().unwrap().num_layers, 3);
+    }
+
+    #[test]
+    fn detector_error_model_repeat_block() {
+        // cargo test detector_error_model_repeat_block -- --nocapture
+        let text = "
+            error(0.1) D0
+            error(0.1) D0 D1
+            detector(0, 0) D0
+            detector(1, 0) D1
+            repeat 2 {
+                error(0.1) D0 D2
+                error(0.1) D1 D3 L0
+                error(0.1) D2 D3
+                shift_detectors(0, 1) 2
+                detector(0, 0) D0
+                detector(1, 0) D1
+            }
+            repeat 0 {
+                error(0.1) D100
+            }
+            error[boundary](0.1) D1 L0
+        ";
+        let graph = MicroBlossomSingle::from_dem(text, 100).unwrap();
+        assert_eq!(graph.vertex_num, 6 + 2);
+        assert_eq!(graph.virtual_vertices, vec![6, 7]);
+        // the virtual vertices are sorted even if the boundary edges are not in the order of the detectors
+        let reversed = MicroBlossomSingle::from_dem("error(0.1) D1\nerror(0.1) D0\nerror(0.1) D0 D1", 100).unwrap();
+        assert_eq!(reversed.virtual_vertices, vec![2, 3]);
+        assert_eq!((reversed.weighted_edges[0].l, reversed.weighted_edges[0].r), (1, 2));
+        let edges: Vec<_> = graph
+            .weighted_edges
+            .iter()
+            .map(|edge| (edge.l, edge.r, edge.observables))
+            .collect();
+        assert_eq!(
+            edges,
+            [
+                (0, 6, 0),
+                (0, 1, 0),
+                (0, 2, 0),
+                (1, 3, 1),
+                (2, 3, 0),
+                (2, 4, 0),
+                (3, 5, 1),
+                (4, 5, 0),
+                (5, 7, 1)
+            ]
+        );
+        assert_eq!(graph.positions[5].t, 2.);
+    }
+
+    #[test]
+    fn detector_error_model_invalid() {
+        // cargo test detector_error_model_invalid -- --nocapture
+        for text in [
+            "error(0.1) D0 D1 D2",
+            "error(0.7) D0",
+            "error(0.1) D0 L64",
+            "error(0.1) X0",
+            "repeat 2 {\nerror(0.1) D0",
+            "}",
+            "detector(0, a) D0",
+            "unknown D0",
+        ] {
+            let error = MicroBlossomSingle::from_dem(text, 100).unwrap_err();
+            println!("{text:?}: {error}");
+        }
+    }
+}
@@ -0,0 +1,593 @@
+//! Dual Driver Trace
+//!
+//! Record every call to a dual driver together with the returned obstacles as a compact binary trace of
+//! `Instruction32` words, and replay the trace on another driver to find the first response that differs.
+//! This is useful for differential testing between the software model and the hardware simulation, e.g.
+//! `DualModuleCombDriver` vs `DualModuleAxi4Driver`.
+//!
+//! Each entry starts with the instruction word that the call corresponds to. A few words carry extra information
+//! that the hardware ignores:
+//! - bit 3 of `SetSpeed` records the `is_blossom` argument
+//! - bit 6 of `FindObstacle` indicates a `find_conflict` call, followed by a word of the maximum growth
+//! - `LoadWeightsExternal` is followed by the number of dynamic weights and then (edge index, weight) pairs
+//! - bit 6 of `LoadWeightsExternal` indicates an erasure instead, followed by a word of the edge index; erasures
+//!   have no instruction in the hardware
+//!
+//! `FindObstacle` entries are then followed by [`TRACE_RESPONSE_WORDS`] words of the response.
+//!
+
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use fusion_blossom::dual_module::*;
+use fusion_blossom::primal_module::*;
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+use micro_blossom_nostd::dual_driver_tracked::*;
+use micro_blossom_nostd::dual_module_stackless::*;
+use micro_blossom_nostd::instruction::*;
+use micro_blossom_nostd::interface::*;
+use micro_blossom_nostd::util::*;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+
+/// "MBT1" in little endian, written at the beginning of a trace file
+pub const TRACE_FILE_MAGIC: u32 = u32::from_le_bytes(*b"MBT1");
+pub const TRACE_RESPONSE_WORDS: usize = 5;
+const TRACE_SET_SPEED_IS_BLOSSOM: u32 = 1 << 3;
+const TRACE_FIND_CONFLICT: u32 = 1 << 6;
+const TRACE_SET_ERASURE: u32 = 1 << 6;
+const TRACE_INDEX_NONE: u32 = 0xFFFF;
+/// the layer id of `FuseLayer` is encoded in the 15-bit field of `LoadSyndromeExternal`
+const TRACE_MAX_LAYER_ID: usize = (1 << 15) - 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceEntry {
+    Reset,
+    SetSpeed {
+        is_blossom: bool,
+        node: CompactNodeIndex,
+        speed: CompactGrowState,
+    },
+    SetBlossom {
+        node: CompactNodeIndex,
+        blossom: CompactNodeIndex,
+    },
+    AddDefect {
+        vertex: CompactVertexIndex,
+        node: CompactNodeIndex,
+    },
+    FindObstacle {
+        response: (CompactObstacle, CompactWeight),
+    },
+    FindConflict {
+        maximum_growth: CompactWeight,
+        response: (CompactObstacle, CompactWeight),
+    },
+    FuseLayer {
+        layer_id: usize,
+    },
+    SetErasure {
+        edge_index: EdgeIndex,
+    },
+    LoadDynamicWeights {
+        dynamic_weights: Vec<(EdgeIndex, Weight)>,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DualTrace {
+    pub words: Vec<u32>,
+}
+
+/// the first response that differs when replaying a trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceDivergence {
+    /// the index of the entry in the trace
+    pub entry_index: usize,
+    pub entry: TraceEntry,
+    pub actual: (CompactObstacle, CompactWeight),
+}
+
+impl std::fmt::Display for TraceDivergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (call, expected) = match &self.entry {
+            TraceEntry::FindObstacle { response } => ("find_obstacle()".to_string(), response),
+            TraceEntry::FindConflict {
+                maximum_growth,
+                response,
+            } => (format!("find_conflict({maximum_growth})"), response),
+            _ => unreachable!("only responses can diverge"),
+        };
+        write!(
+            f,
+            "entry [{}] {call}: expected {:?}, got {:?}",
+            self.entry_index, expected, self.actual
+        )
+    }
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn encode_index(index: OptionCompactNodeIndex) -> u32 {
+    index.option().map(|index| index.get() as u32).unwrap_or(TRACE_INDEX_NONE)
+}
+
+fn decode_index(word: u32) -> OptionCompactNodeIndex {
+    if word == TRACE_INDEX_NONE {
+        None.into()
+    } else {
+        Some(ni!(word)).into()
+    }
+}
+
+impl DualTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.words.clear();
+    }
+
+    /// append an entry; fails if the layer id of `FuseLayer` does not fit into the instruction
+    pub fn push(&mut self, entry: &TraceEntry) -> Result<(), String> {
+        match entry {
+            TraceEntry::Reset => self.words.push(Instruction32::reset().0),
+            &TraceEntry::SetSpeed { is_blossom, node, speed } => {
+                let flag = if is_blossom { TRACE_SET_SPEED_IS_BLOSSOM } else { 0 };
+                self.words.push(Instruction32::set_speed(node, speed).0 | flag)
+            }
+            &TraceEntry::SetBlossom { node, blossom } => self.words.push(Instruction32::set_blossom(node, blossom).0),
+            &TraceEntry::AddDefect { vertex, node } => self.words.push(Instruction32::add_defect_vertex(vertex, node).0),
+            TraceEntry::FindObstacle { response } => {
+                self.words.push(Instruction32::find_obstacle().0);
+                self.push_response(response);
+            }
+            TraceEntry::FindConflict {
+                maximum_growth,
+                response,
+            } => {
+                self.words.push(Instruction32::find_obstacle().0 | TRACE_FIND_CONFLICT);
+                self.words.push(*maximum_growth as u32);
+                self.push_response(response);
+            }
+            &TraceEntry::FuseLayer { layer_id } => {
+                if layer_id > TRACE_MAX_LAYER_ID {
+                    return Err(format!(
+                        "layer id {layer_id} exceeds the maximum {TRACE_MAX_LAYER_ID} of the trace"
+                    ));
+                }
+                self.words.push(Instruction32::load_syndrome_external(ni!(layer_id)).0)
+            }
+            &TraceEntry::SetErasure { edge_index } => {
+                self.words.push(Instruction32::load_weights_external().0 | TRACE_SET_ERASURE);
+                self.words.push(edge_index as u32);
+            }
+            TraceEntry::LoadDynamicWeights { dynamic_weights } => {
+                self.words.push(Instruction32::load_weights_external().0);
+                self.words.push(dynamic_weights.len() as u32);
+                for &(edge_index, weight) in dynamic_weights.iter() {
+                    self.words.push(edge_index as u32);
+                    self.words.push(weight as u32);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    fn push_response(&mut self, (obstacle, grown): &(CompactObstacle, CompactWeight)) {
+        let mut words = [*grown as u32, 0, 0, 0, 0];
+        match *obstacle {
+            CompactObstacle::None => {}
+            CompactObstacle::GrowLength { length } => {
+                words[1] = 1;
+                words[2] = length as u32;
+            }
+            CompactObstacle::Conflict {
+                node_1,
+                node_2,
+                touch_1,
+                touch_2,
+                vertex_1,
+                vertex_2,
+            } => {
+                assert!(
+                    vertex_1.get() < TRACE_INDEX_NONE as _ && vertex_2.get() < TRACE_INDEX_NONE as _,
+                    "vertex index too large for the trace"
+                );
+                words[1] = 2;
+                words[2] = encode_index(node_1) | (encode_index(node_2) << 16);
+                words[3] = encode_index(touch_1) | (encode_index(touch_2) << 16);
+                words[4] = vertex_1.get() as u32 | ((vertex_2.get() as u32) << 16);
+            }
+            CompactObstacle::BlossomNeedExpand { blossom } => {
+                words[1] = 3;
+                words[2] = blossom.get() as u32;
+            }
+        }
+        self.words.extend(words);
+    }
+
+    fn decode_response(words: &[u32]) -> Result<(CompactObstacle, CompactWeight), String> {
+        let obstacle = match words[1] {
+            0 => CompactObstacle::None,
+            1 => CompactObstacle::GrowLength {
+                length: words[2] as CompactWeight,
+            },
+            2 => CompactObstacle::Conflict {
+                node_1: decode_index(words[2] & 0xFFFF),
+                node_2: decode_index(words[2] >> 16),
+                touch_1: decode_index(words[3] & 0xFFFF),
+                touch_2: decode_index(words[3] >> 16),
+                vertex_1: ni!(words[4] & 0xFFFF),
+                vertex_2: ni!(words[4] >> 16),
+            },
+            3 => CompactObstacle::BlossomNeedExpand { blossom: ni!(words[2]) },
+            kind => return Err(format!("unknown obstacle kind {kind}")),
+        };
+        Ok((obstacle, words[0] as CompactWeight))
+    }
+
+    /// decode the words into entries
+    pub fn entries(&self) -> Result<Vec<TraceEntry>, String> {
+        let mut entries = vec![];
+        let words = &self.words;
+        let mut index = 0;
+        let take = |index: &mut usize, length: usize| -> Result<&[u32], String> {
+            if *index + length > words.len() {
+                return Err(format!("trace truncated at word {}", *index));
+            }
+            *index += length;
+            Ok(&words[*index - length..*index])
+        };
+        while index < words.len() {
+            let instruction = Instruction32(take(&mut index, 1)?[0]);
+            let entry = if instruction.is_set_speed() {
+                TraceEntry::SetSpeed {
+                    is_blossom: instruction.0 & TRACE_SET_SPEED_IS_BLOSSOM != 0,
+                    node: ni!(instruction.field1()),
+                    speed: instruction.get_speed(),
+                }
+            } else if instruction.is_set_blossom() {
+                TraceEntry::SetBlossom {
+                    node: ni!(instruction.field1()),
+                    blossom: ni!(instruction.field2()),
+                }
+            } else if instruction.op_code() == OP_CODE_ADD_DEFECT_VERTEX {
+                TraceEntry::AddDefect {
+                    vertex: ni!(instruction.field1()),
+                    node: ni!(instruction.field2()),
+                }
+            } else if instruction.is_extended() {
+                match instruction.extended_op_code() {
+                    EXTENDED_OP_CODE_RESET => TraceEntry::Reset,
+                    EXTENDED_OP_CODE_FIND_OBSTACLE if instruction.0 & TRACE_FIND_CONFLICT != 0 => {
+                        let maximum_growth = take(&mut index, 1)?[0] as CompactWeight;
+                        let response = Self::decode_response(take(&mut index, TRACE_RESPONSE_WORDS)?)?;
+                        TraceEntry::FindConflict {
+                            maximum_growth,
+                            response,
+                        }
+                    }
+                    EXTENDED_OP_CODE_FIND_OBSTACLE => TraceEntry::FindObstacle {
+                        response: Self::decode_response(take(&mut index, TRACE_RESPONSE_WORDS)?)?,
+                    },
+                    EXTENDED_OP_CODE_LOAD_DEFECTS_EXTERNAL => TraceEntry::FuseLayer {
+                        layer_id: instruction.field1() as usize,
+                    },
+                    EXTENDED_OP_CODE_LOAD_WEIGHTS_EXTERNAL if instruction.0 & TRACE_SET_ERASURE != 0 => {
+                        TraceEntry::SetErasure {
+                            edge_index: take(&mut index, 1)?[0] as EdgeIndex,
+                        }
+                    }
+                    EXTENDED_OP_CODE_LOAD_WEIGHTS_EXTERNAL => {
+                        let count = take(&mut index, 1)?[0] as usize;
+                        let pairs = take(&mut index, 2 * count)?;
+                        TraceEntry::LoadDynamicWeights {
+                            dynamic_weights: pairs
+                                .chunks(2)
+                                .map(|pair| (pair[0] as EdgeIndex, pair[1] as i32 as Weight))
+                                .collect(),
+                        }
+                    }
+                    _ => return Err(format!("unexpected instruction {:#010X} in trace", instruction.0)),
+                }
+            } else {
+                return Err(format!("unexpected instruction {:#010X} in trace", instruction.0));
+            };
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    pub fn save(&self, filename: &str) -> std::io::Result<()> {
+        let mut file = File::create(filename)?;
+        file.write_all(&TRACE_FILE_MAGIC.to_le_bytes())?;
+        for word in self.words.iter() {
+            file.write_all(&word.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn load(filename: &str) -> std::io::Result<Self> {
+        let mut bytes = vec![];
+        File::open(filename)?.read_to_end(&mut bytes)?;
+        let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string());
+        if bytes.len() % 4 != 0 {
+            return Err(invalid("trace file length is not a multiple of 4"));
+        }
+        let mut words = bytes.chunks(4).map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()));
+        if words.next() != Some(TRACE_FILE_MAGIC) {
+            return Err(invalid("not a trace file"));
+        }
+        Ok(Self { words: words.collect() })
+    }
+
+    /// feed the trace to another driver and return the first response that differs
+    pub fn replay(&self, driver: &mut impl SolverTrackedDual) -> Result<Result<(), TraceDivergence>, String> {
+        for (entry_index, entry) in self.entries()?.into_iter().enumerate() {
+            let (actual, expected) = match &entry {
+                TraceEntry::Reset => {
+                    driver.try_reset().map_err(|error| error.to_string())?;
+                    continue;
+                }
+                &TraceEntry::SetSpeed { is_blossom, node, speed } => {
+                    driver
+                        .try_set_speed(is_blossom, node, speed)
+                        .map_err(|error| error.to_string())?;
+                    continue;
+                }
+                &TraceEntry::SetBlossom { node, blossom } => {
+                    driver.try_set_blossom(node, blossom).map_err(|error| error.to_string())?;
+                    continue;
+                }
+                &TraceEntry::AddDefect { vertex, node } => {
+                    driver.try_add_defect(vertex, node).map_err(|error| error.to_string())?;
+                    continue;
+                }
+                &TraceEntry::FuseLayer { layer_id } => {
+                    driver.fuse_layer(layer_id);
+                    continue;
+                }
+                &TraceEntry::SetErasure { edge_index } => {
+                    driver.load_erasures(&[edge_index]).map_err(|error| error.to_string())?;
+                    continue;
+                }
+                TraceEntry::LoadDynamicWeights { dynamic_weights } => {
+                    driver
+                        .load_dynamic_weights(dynamic_weights)
+                        .map_err(|error| error.to_string())?;
+                    continue;
+                }
+                TraceEntry::FindObstacle { response } => {
+                    (driver.try_find_obstacle().map_err(|error| error.to_string())?, response)
+                }
+                TraceEntry::FindConflict {
+                    maximum_growth,
+                    response,
+                } => (
+                    driver.try_find_conflict(*maximum_growth).map_err(|error| error.to_string())?,
+                    response,
+                ),
+            };
+            if &actual != expected {
+                return Ok(Err(TraceDivergence {
+                    entry_index,
+                    entry,
+                    actual,
+                }));
+            }
+        }
+        Ok(Ok(()))
+    }
+}
+
+/// a wrapper of any dual driver that records all the calls and responses into a [`DualTrace`]
+pub struct DualDriverRecorder<D: FallibleDualStacklessDriver + FallibleDualTrackedDriver> {
+    pub driver: D,
+    pub trace: DualTrace,
+}
+
+impl<D: FallibleDualStacklessDriver + FallibleDualTrackedDriver> DualDriverRecorder<D> {
+    pub fn new(driver: D) -> Self {
+        Self {
+            driver,
+            trace: DualTrace::new(),
+        }
+    }
+
+    /// only the layer id of `FuseLayer` may not fit into the trace, and `fuse_layer` has no way to report it
+    fn record(&mut self, entry: &TraceEntry) {
+        if let Err(error) = self.trace.push(entry) {
+            panic!("cannot record {entry:?}: {error}");
+        }
+    }
+}
+
+impl<D: FallibleDualStacklessDriver + FallibleDualTrackedDriver> FallibleDualStacklessDriver for DualDriverRecorder<D> {
+    fn try_reset(&mut self) -> Result<(), MicroBlossomError> {
+        self.record(&TraceEntry::Reset);
+        self.driver.try_reset()
+    }
+    fn try_set_speed(
+        &mut self,
+        is_blossom: bool,
+        node: CompactNodeIndex,
+        speed: CompactGrowState,
+    ) -> Result<(), MicroBlossomError> {
+        self.record(&TraceEntry::SetSpeed { is_blossom, node, speed });
+        self.driver.try_set_speed(is_blossom, node, speed)
+    }
+    fn try_set_blossom(&mut self, node: CompactNodeIndex, blossom: CompactNodeIndex) -> Result<(), MicroBlossomError> {
+        self.record(&TraceEntry::SetBlossom { node, blossom });
+        self.driver.try_set_blossom(node, blossom)
+    }
+    fn try_find_obstacle(&mut self) -> Result<(CompactObstacle, CompactWeight), MicroBlossomError> {
+        let response = self.driver.try_find_obstacle()?;
+        self.record(&TraceEntry::FindObstacle {
+            response: response.clone(),
+        });
+        Ok(response)
+    }
+    fn try_add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) -> Result<(), MicroBlossomError> {
+        self.record(&TraceEntry::AddDefect { vertex, node });
+        self.driver.try_add_defect(vertex, node)
+    }
+    fn on_blossom_created(&mut self, blossom: CompactNodeIndex) {
+        self.driver.on_blossom_created(blossom);
+    }
+    fn on_blossom_expanded(&mut self, blossom: CompactNodeIndex) {
+        self.driver.on_blossom_expanded(blossom);
+    }
+    fn on_blossom_absorbed_into_blossom(&mut self, child: CompactNodeIndex) {
+        self.driver.on_blossom_absorbed_into_blossom(child);
+    }
+}
+
+impl<D: FallibleDualStacklessDriver + FallibleDualTrackedDriver> FallibleDualTrackedDriver for DualDriverRecorder<D> {
+    fn try_find_conflict(
+        &mut self,
+        maximum_growth: CompactWeight,
+    ) -> Result<(CompactObstacle, CompactWeight), MicroBlossomError> {
+        let response = self.driver.try_find_conflict(maximum_growth)?;
+        self.record(&TraceEntry::FindConflict {
+            maximum_growth,
+            response: response.clone(),
+        });
+        Ok(response)
+    }
+}
+
+impl<D: SolverTrackedDual> FusionVisualizer for DualDriverRecorder<D> {
+    fn snapshot(&self, abbrev: bool) -> serde_json::Value {
+        self.driver.snapshot(abbrev)
+    }
+}
+
+impl<D: SolverTrackedDual> SolverTrackedDual for DualDriverRecorder<D> {
+    fn new_from_graph_config(graph: MicroBlossomSingle, config: serde_json::Value) -> Self {
+        Self::new(D::new_from_graph_config(graph, config))
+    }
+    fn reset_profiler(&mut self) {
+        self.driver.reset_profiler()
+    }
+    fn generate_profiler_report(&self) -> serde_json::Value {
+        self.driver.generate_profiler_report()
+    }
+    fn fuse_layer(&mut self, layer_id: usize) {
+        self.record(&TraceEntry::FuseLayer { layer_id });
+        self.driver.fuse_layer(layer_id)
+    }
+    fn load_erasures(&mut self, erasures: &[EdgeIndex]) -> Result<(), MicroBlossomError> {
+        for &edge_index in erasures.iter() {
+            self.record(&TraceEntry::SetErasure { edge_index });
+        }
+        self.driver.load_erasures(erasures)
+    }
+    fn load_dynamic_weights(&mut self, dynamic_weights: &[(EdgeIndex, Weight)]) -> Result<(), MicroBlossomError> {
+        self.record(&TraceEntry::LoadDynamicWeights {
+            dynamic_weights: dynamic_weights.to_vec(),
+        });
+        self.driver.load_dynamic_weights(dynamic_weights)
+    }
+    fn get_pre_matchings(&self, belonging: DualModuleInterfaceWeak) -> PerfectMatching {
+        self.driver.get_pre_matchings(belonging)
+    }
+    fn offloaded_by_type(&self) -> BTreeMap<&'static str, usize> {
+        self.driver.offloaded_by_type()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dual_module_adaptor::tests::*;
+    use crate::dual_module_axi4::*;
+    use crate::dual_module_comb::*;
+    use fusion_blossom::example_codes::*;
+    use serde_json::json;
+
+    type SolverEmbeddedRecorded = SolverEmbeddedBoxed<DualDriverRecorder<DualModuleCombDriver>>;
+
+    fn dual_driver_trace_record(d: VertexNum, defect_vertices: Vec<VertexIndex>) -> DualTrace {
+        let solver = dual_module_standard_optional_viz(d, None, defect_vertices, |initializer, positions| {
+            SolverEmbeddedRecorded::new(MicroBlossomSingle::new(initializer, positions), json!({}))
+        });
+        solver.dual_module.driver.driver.trace.clone()
+    }
+
+    fn dual_driver_trace_graph(d: VertexNum, half_weight: Weight) -> MicroBlossomSingle {
+        let code = CodeCapacityPlanarCode::new(d, 0.1, half_weight);
+        MicroBlossomSingle::new(&code.get_initializer(), &code.get_positions())
+    }
+
+    #[test]
+    fn dual_driver_trace_replay_comb() {
+        // cargo test dual_driver_trace_replay_comb -- --nocapture
+        let trace = dual_driver_trace_record(5, vec![3, 12, 13, 14, 20]);
+        let entries = trace.entries().unwrap();
+        assert!(entries.iter().any(|entry| matches!(entry, TraceEntry::FindConflict { .. })));
+        assert!(entries.iter().any(|entry| matches!(entry, TraceEntry::AddDefect { .. })));
+        // round trip through the binary file
+        let filename = std::env::temp_dir().join("dual_driver_trace_replay_comb.trace");
+        trace.save(filename.to_str().unwrap()).unwrap();
+        let loaded = DualTrace::load(filename.to_str().unwrap()).unwrap();
+        assert_eq!(loaded, trace);
+        assert_eq!(loaded.entries().unwrap(), entries);
+        // replay on a fresh driver
+        let mut driver = DualModuleCombDriver::new_from_graph_config(dual_driver_trace_graph(5, 500), json!({}));
+        assert_eq!(trace.replay(&mut driver), Ok(Ok(())));
+        // erasures are recorded with a trace-only flag instead of a hardware instruction
+        let mut erasure_trace = DualTrace::new();
+        let erasure_entries = [
+            TraceEntry::SetErasure { edge_index: 1 << 16 },
+            TraceEntry::LoadDynamicWeights {
+                dynamic_weights: vec![(3, 4)],
+            },
+        ];
+        for entry in erasure_entries.iter() {
+            erasure_trace.push(entry).unwrap();
+        }
+        assert_eq!(erasure_trace.entries().unwrap(), erasure_entries);
+        // the layer id must fit into the 15-bit field of the instruction
+        let mut layer_trace = DualTrace::new();
+        layer_trace.push(&TraceEntry::FuseLayer { layer_id: (1 << 15) - 1 }).unwrap();
+        assert!(layer_trace.push(&TraceEntry::FuseLayer { layer_id: 1 << 15 }).is_err());
+        assert_eq!(
+            layer_trace.entries().unwrap(),
+            [TraceEntry::FuseLayer { layer_id: (1 << 15) - 1 }]
+        );
+    }
+
+    #[test]
+    fn dual_driver_trace_replay_divergence() {
+        // cargo test dual_driver_trace_replay_divergence -- --nocapture
+        let trace = dual_driver_trace_record(5, vec![13, 14]);
+        let mut driver = DualModuleCombDriver::new_from_graph_config(dual_driver_trace_graph(5, 300), json!({}));
+        let divergence = trace.replay(&mut driver).unwrap().unwrap_err();
+        println!("{divergence}");
+        assert!(matches!(divergence.entry, TraceEntry::FindConflict { .. }));
+        // a truncated trace is reported as an error instead of a divergence
+        let mut truncated = trace.clone();
+        truncated.words.pop();
+        assert!(truncated.replay(&mut driver).is_err());
+    }
+
+    #[test]
+    fn dual_driver_trace_replay_axi4() {
+        // cargo test dual_driver_trace_replay_axi4 -- --nocapture
+        for seed in 0..10 {
+            let mut code = CodeCapacityPlanarCode::new(5, 0.1, 500);
+            let defect_vertices = code.generate_random_errors(seed).defect_vertices;
+            let trace = dual_driver_trace_record(5, defect_vertices);
+            let mut driver = DualModuleAxi4Driver::new_from_graph_config(
+                dual_driver_trace_graph(5, 500),
+                json!({ "name": format!("dual_driver_trace_replay_axi4_{seed}"), "sim_config": { "native_host": true } }),
+            );
+            if let Err(divergence) = trace.replay(&mut driver).unwrap() {
+                panic!("{divergence}");
+            }
+        }
+    }
+}
@@ -199,7 +199,17 @@ pub mod tests {
         defect_vertices: Vec<VertexIndex>,
         constructor: impl FnOnce(&SolverInitializer, &Vec<VisualizePosition>) -> Solver,
     ) -> Solver {
-        println!("{defect_vertices:?}");
+        dual_module_erasure_optional_viz(d, visualize_filename, defect_vertices, vec![], constructor)
+    }
+
+    pub fn dual_module_erasure_optional_viz<Solver: PrimalDualSolver + Sized>(
+        d: VertexNum,
+        visualize_filename: Option<String>,
+        defect_vertices: Vec<VertexIndex>,
+        erasures: Vec<EdgeIndex>,
+        constructor: impl FnOnce(&SolverInitializer, &Vec<VisualizePosition>) -> Solver,
+    ) -> Solver {
+        println!("{defect_vertices:?}, erasures: {erasures:?}");
         let half_weight = 500;
         let mut code = CodeCapacityPlanarCode::new(d, 0.1, half_weight);
         let mut visualizer = match visualize_filename.as_ref() {
@@ -218,6 +228,7 @@ pub mod tests {
         // create dual module
         let initializer = code.get_initializer();
         code.set_defect_vertices(&defect_vertices);
+        code.set_erasures(&erasures);
         let syndrome = code.get_syndrome();
         let mut solver = constructor(&initializer, &code.get_positions());
         solver.solve_visualizer(&syndrome, visualizer.as_mut());
@@ -231,6 +242,7 @@ pub mod tests {
         standard_solver.solve_visualizer(&syndrome, None);
         let standard_subgraph = standard_solver.subgraph_visualizer(None);
         let mut subgraph_builder = SubGraphBuilder::new(&initializer);
+        subgraph_builder.load_erasures(&erasures);
         subgraph_builder.load_subgraph(&subgraph);
         let total_weight = subgraph_builder.total_weight();
         subgraph_builder.load_subgraph(&standard_subgraph);
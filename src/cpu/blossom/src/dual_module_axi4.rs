@@ -11,6 +11,7 @@ use crate::util::*;
 use embedded_blossom::extern_c::*;
 use fusion_blossom::dual_module::*;
 use fusion_blossom::primal_module::*;
+use fusion_blossom::util::*;
 use fusion_blossom::visualize::*;
 use micro_blossom_nostd::dual_driver_tracked::*;
 use micro_blossom_nostd::dual_module_stackless::*;
@@ -23,6 +24,8 @@ use serde::*;
 pub struct DualModuleAxi4Driver {
     pub client: SimulationTcpClient,
     pub context_id: u16,
+    /// the original edge weights, used to fill the external weights buffer when loading dynamic weights
+    pub default_weights: Vec<Weight>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,6 +47,16 @@ impl SolverTrackedDual for DualModuleAxi4Driver {
         self.execute_instruction(Instruction32::load_syndrome_external(ni!(layer_id)))
             .unwrap();
     }
+    /// only supported when the weights are not hard-coded, so that the edges have weight registers
+    fn load_dynamic_weights(&mut self, dynamic_weights: &[(EdgeIndex, Weight)]) -> Result<(), MicroBlossomError> {
+        if self.client.sim_config.hard_code_weights {
+            return Err(MicroBlossomError::Unsupported {
+                feature: UnsupportedFeature::DynamicWeights,
+            });
+        }
+        let weights = apply_dynamic_weights(self.default_weights.iter().cloned(), dynamic_weights)?;
+        self.load_weights_external(&weights)
+    }
     fn get_pre_matchings(&self, belonging: DualModuleInterfaceWeak) -> PerfectMatching {
         self.client.get_pre_matchings(belonging)
     }
@@ -51,11 +64,14 @@ impl SolverTrackedDual for DualModuleAxi4Driver {
 
 impl DualModuleAxi4Driver {
     pub fn new(micro_blossom: MicroBlossomSingle, config: DualAxi4Config) -> std::io::Result<Self> {
+        let default_weights = micro_blossom.weighted_edges.iter().map(|edge| edge.w as Weight).collect();
         let mut value = Self {
             client: SimulationTcpClient::new("MicroBlossomHost", micro_blossom, config.name, config.sim_config)?,
             context_id: 0,
+            default_weights,
         };
-        value.reset();
+        value.execute_instruction(Instruction32::reset())?;
+        value.get_single_readout()?;
         Ok(value)
     }
 
@@ -154,6 +170,7 @@ impl DualModuleAxi4Driver {
         self.memory_read_16(base)
     }
 
+    /// the error counter is global instead of per context
     pub fn clear_error_counter(&mut self) -> std::io::Result<()> {
         self.memory_write_32(48, 0)
     }
@@ -203,34 +220,65 @@ impl DualModuleAxi4Driver {
         let base = Self::READOUT_BASE + 128 * self.context_id as usize;
         self.memory_read_64(base + 8)
     }
+
+    pub const EXTERNAL_WEIGHTS_BASE: usize = 256 * 1024;
+
+    /// write the weights of all edges to the external weights buffer (16 bits each) and then issue a
+    /// `LoadWeightsExternal` instruction to copy them into the edge registers of the current context; the native
+    /// host implements this buffer, while a bus without it counts the writes as errors and the shot then fails
+    /// with [`MicroBlossomError::HardwareError`]
+    pub fn load_weights_external(&mut self, weights: &[Weight]) -> Result<(), MicroBlossomError> {
+        for (edge_index, &weight) in weights.iter().enumerate() {
+            let weight = u16::try_from(weight).map_err(|_| MicroBlossomError::InvalidDynamicWeight { edge_index })?;
+            self.memory_write_16(Self::EXTERNAL_WEIGHTS_BASE + 2 * edge_index, weight)?;
+        }
+        self.execute_instruction(Instruction32::load_weights_external())?;
+        Ok(())
+    }
 }
 
-impl DualStacklessDriver for DualModuleAxi4Driver {
-    fn reset(&mut self) {
-        self.execute_instruction(Instruction32::reset()).unwrap();
+impl FallibleDualStacklessDriver for DualModuleAxi4Driver {
+    fn try_reset(&mut self) -> Result<(), MicroBlossomError> {
+        self.execute_instruction(Instruction32::reset())?;
         // find obstacle to make sure the reset instruction is flushed
-        self.get_single_readout().unwrap();
-    }
-    fn set_speed(&mut self, _is_blossom: bool, node: CompactNodeIndex, speed: CompactGrowState) {
-        self.execute_instruction(Instruction32::set_speed(node, speed)).unwrap();
-    }
-    fn set_blossom(&mut self, node: CompactNodeIndex, blossom: CompactNodeIndex) {
-        self.execute_instruction(Instruction32::set_blossom(node, blossom)).unwrap();
+        self.get_single_readout()?;
+        Ok(())
     }
-    fn find_obstacle(&mut self) -> (CompactObstacle, CompactWeight) {
-        let readout = self.get_single_readout().unwrap();
+    fn try_set_speed(
+        &mut self,
+        _is_blossom: bool,
+        node: CompactNodeIndex,
+        speed: CompactGrowState,
+    ) -> Result<(), MicroBlossomError> {
+        self.execute_instruction(Instruction32::set_speed(node, speed))
+            .map_err(MicroBlossomError::from)
+    }
+    fn try_set_blossom(&mut self, node: CompactNodeIndex, blossom: CompactNodeIndex) -> Result<(), MicroBlossomError> {
+        self.execute_instruction(Instruction32::set_blossom(node, blossom))
+            .map_err(MicroBlossomError::from)
+    }
+    fn try_find_obstacle(&mut self) -> Result<(CompactObstacle, CompactWeight), MicroBlossomError> {
+        let readout = self.get_single_readout()?;
         // check again
         let grown = readout.accumulated_grown as CompactWeight;
         let growable = readout.max_growable;
         if growable == u8::MAX {
-            (CompactObstacle::None, grown)
+            // the shot is finished: check whether the hardware encountered any error in the meantime; the error
+            // counter is shared by all the contexts, so with multiple contexts the error is reported by whichever
+            // shot finishes next, not necessarily the one that caused it
+            let error_counter = self.get_error_counter()?;
+            if error_counter > 0 {
+                self.clear_error_counter()?;
+                return Err(MicroBlossomError::HardwareError { error_counter });
+            }
+            Ok((CompactObstacle::None, grown))
         } else if growable != 0 {
-            (
+            Ok((
                 CompactObstacle::GrowLength {
                     length: growable as CompactWeight,
                 },
                 grown,
-            )
+            ))
         } else if readout.conflict_valid != 0 {
             let conflict = CompactObstacle::Conflict {
                 node_1: ni!(readout.node_1).option(),
@@ -248,23 +296,26 @@ impl DualStacklessDriver for DualModuleAxi4Driver {
                 vertex_1: ni!(readout.vertex_1),
                 vertex_2: ni!(readout.vertex_2),
             };
-            (conflict, grown)
+            Ok((conflict, grown))
         } else {
             // when this happens, the DualDriverTracked should check for BlossomNeedExpand event
             // this is usually triggered by reaching maximum growth set by the DualDriverTracked
-            (CompactObstacle::GrowLength { length: 0 }, grown)
+            Ok((CompactObstacle::GrowLength { length: 0 }, grown))
         }
     }
-    fn add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) {
+    fn try_add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) -> Result<(), MicroBlossomError> {
         self.execute_instruction(Instruction32::add_defect_vertex(vertex, node))
-            .unwrap();
+            .map_err(MicroBlossomError::from)
     }
 }
 
-impl DualTrackedDriver for DualModuleAxi4Driver {
-    fn find_conflict(&mut self, maximum_growth: CompactWeight) -> (CompactObstacle, CompactWeight) {
-        self.set_maximum_growth(maximum_growth as u16).unwrap();
-        self.find_obstacle()
+impl FallibleDualTrackedDriver for DualModuleAxi4Driver {
+    fn try_find_conflict(
+        &mut self,
+        maximum_growth: CompactWeight,
+    ) -> Result<(CompactObstacle, CompactWeight), MicroBlossomError> {
+        self.set_maximum_growth(maximum_growth as u16)?;
+        self.try_find_obstacle()
     }
 }
 
@@ -287,7 +338,6 @@ mod tests {
     use crate::dual_module_adaptor::tests::*;
     use crate::dual_module_comb::tests::*;
     use fusion_blossom::example_codes::*;
-    use fusion_blossom::util::*;
     use serde_json::json;
     use std::collections::BTreeSet;
 
@@ -362,16 +412,16 @@ mod tests {
         // driver.context_id = 0;
         driver.context_id = (config.sim_config.context_depth - 1) as u16;
         // test manual growth
-        driver.reset();
+        driver.try_reset().unwrap();
         driver.set_maximum_growth(0).unwrap(); // disable spontaneous growth
         check(driver);
-        let (obstacle, grown) = driver.find_obstacle();
+        let (obstacle, grown) = driver.try_find_obstacle().unwrap();
         assert_eq!(obstacle, CompactObstacle::None);
         assert_eq!(grown, 0);
         check(driver);
-        driver.add_defect(vertex, node);
+        driver.try_add_defect(vertex, node).unwrap();
         check(driver);
-        let (obstacle, grown) = driver.find_obstacle();
+        let (obstacle, grown) = driver.try_find_obstacle().unwrap();
         check(driver);
         assert_eq!(grown, 0); // because spontaneous growth is disabled
         let count = |e: &WeightedEdge| e.l == example_vertex || e.r == example_vertex;
@@ -380,32 +430,32 @@ mod tests {
         assert_eq!(obstacle, CompactObstacle::GrowLength { length });
         // grow
         driver.execute_instruction(Instruction32::grow(1)).unwrap();
-        let (obstacle, grown) = driver.find_obstacle();
+        let (obstacle, grown) = driver.try_find_obstacle().unwrap();
         check(driver);
         assert_eq!(grown, 0);
         assert_eq!(obstacle, CompactObstacle::GrowLength { length: length - 1 });
         // test spontaneous growth
-        driver.reset();
-        driver.add_defect(vertex, node);
+        driver.try_reset().unwrap();
+        driver.try_add_defect(vertex, node).unwrap();
         driver.set_maximum_growth(u16::try_from(length).unwrap()).unwrap();
         check(driver);
-        let (_obstacle, grown) = driver.find_obstacle();
+        let (_obstacle, grown) = driver.try_find_obstacle().unwrap();
         check(driver);
         assert_eq!(grown, length);
-        let (_obstacle, grown) = driver.find_obstacle();
+        let (_obstacle, grown) = driver.try_find_obstacle().unwrap();
         check(driver);
         assert_eq!(grown, 0);
         // test accumulated spontaneous growth: first set maximum growth to 1, do not grow it, and then
         assert!(length >= 2, "edge weight should be even number");
-        driver.reset();
-        driver.add_defect(vertex, node);
+        driver.try_reset().unwrap();
+        driver.try_add_defect(vertex, node).unwrap();
         driver.set_maximum_growth(1).unwrap();
         check(driver);
         driver.pre_fetch_conflicts().unwrap(); // prefetch will grow it to 1
         check(driver);
         driver.set_maximum_growth(2).unwrap();
         check(driver);
-        let (obstacle, grown) = driver.find_obstacle();
+        let (obstacle, grown) = driver.try_find_obstacle().unwrap();
         check(driver);
         assert_eq!(grown, 2);
         if length >= 2 {
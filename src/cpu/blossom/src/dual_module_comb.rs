@@ -26,7 +26,7 @@ use micro_blossom_nostd::interface::*;
 use micro_blossom_nostd::util::*;
 use serde::*;
 use serde_json::json;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
 pub struct DualModuleCombDriver {
     pub initializer: SolverInitializer,
@@ -38,6 +38,8 @@ pub struct DualModuleCombDriver {
     pub(crate) instruction: Instruction,
     pub config: DualCombConfig,
     pub graph: MicroBlossomSingle,
+    /// the per-shot edge weights in the external memory, copied to the edge registers by `LoadWeightsExternal`
+    pub external_weights: Vec<Weight>,
     /// only enabled when `config.log_instructions` is true
     pub profiler_instruction_history: Vec<Instruction>,
     pub profiler_response_history: Vec<(CompactObstacle, CompactWeight)>,
@@ -99,6 +101,18 @@ impl SolverTrackedDual for DualModuleCombDriver {
             channel: 0,
         });
     }
+    fn load_erasures(&mut self, erasures: &[EdgeIndex]) -> Result<(), MicroBlossomError> {
+        for &edge in erasures.iter() {
+            self.execute_instruction(Instruction::SetErasure { edge });
+        }
+        Ok(())
+    }
+    fn load_dynamic_weights(&mut self, dynamic_weights: &[(EdgeIndex, Weight)]) -> Result<(), MicroBlossomError> {
+        let default_weights = self.edges.iter().map(|edge| edge.default_weight);
+        self.external_weights = apply_dynamic_weights(default_weights, dynamic_weights)?;
+        self.execute_instruction(Instruction::LoadWeightsExternal);
+        Ok(())
+    }
     fn get_pre_matchings(&self, belonging: DualModuleInterfaceWeak) -> PerfectMatching {
         let edges = self.pre_matching_edges();
         let mut perfect_matching = PerfectMatching::default();
@@ -158,6 +172,26 @@ impl SolverTrackedDual for DualModuleCombDriver {
         }
         perfect_matching
     }
+    fn offloaded_by_type(&self) -> BTreeMap<&'static str, usize> {
+        let mut offloaded = BTreeMap::new();
+        // a defect stalled by multiple offloaders is counted once, for the first of them
+        let mut counted = BTreeSet::new();
+        for offloading in self.offloading_units.iter() {
+            let signals = offloading.get_signals(self);
+            if signals.condition {
+                let defects = signals
+                    .vertex_stalls
+                    .iter()
+                    .filter(|&&vertex_index| self.vertices[vertex_index].registers.is_defect)
+                    .filter(|&&vertex_index| counted.insert(vertex_index))
+                    .count();
+                if defects > 0 {
+                    *offloaded.entry(offloading.offloading_type.name()).or_insert(0) += defects;
+                }
+            }
+        }
+        offloaded
+    }
 }
 
 impl DualModuleCombDriver {
@@ -186,6 +220,7 @@ impl DualModuleCombDriver {
                 .enumerate()
                 .map(|(edge_index, &(i, j, weight))| Edge::new(edge_index, i, j, weight))
                 .collect(),
+            external_weights: initializer.weighted_edges.iter().map(|&(_, _, weight)| weight).collect(),
             maximum_growth: CompactWeight::MAX,
             offloading_units: vec![],
             instruction: Instruction::FindObstacle,
@@ -283,7 +318,17 @@ impl DualModuleCombDriver {
         }
     }
 
-    fn execute_instruction(&mut self, instruction: Instruction) -> CompactObstacle {
+    pub fn execute_instruction(&mut self, instruction: Instruction) -> CompactObstacle {
+        self.execute_instruction_channels(instruction, 0).0
+    }
+
+    /// execute an instruction and additionally collect up to `conflict_channels` distinct conflicts, like the
+    /// hardware with multiple conflict channels does; the first conflict is always the reduced response
+    pub fn execute_instruction_channels(
+        &mut self,
+        instruction: Instruction,
+        conflict_channels: usize,
+    ) -> (CompactObstacle, Vec<CompactObstacle>) {
         if self.config.log_instructions {
             self.profiler_instruction_history.push(instruction.clone());
         }
@@ -295,8 +340,21 @@ impl DualModuleCombDriver {
             .chain(self.edges.iter().map(|edge| edge.get_response(self).clone()))
             .reduce(CompactObstacle::reduce)
             .unwrap();
+        let mut conflicts = vec![];
+        if conflict_channels > 0 && matches!(response, CompactObstacle::Conflict { .. }) {
+            conflicts.push(response.clone());
+            for edge in self.edges.iter() {
+                if conflicts.len() >= conflict_channels {
+                    break;
+                }
+                let conflict = edge.get_response(self).clone();
+                if matches!(conflict, CompactObstacle::Conflict { .. }) && !conflicts.contains(&conflict) {
+                    conflicts.push(conflict);
+                }
+            }
+        }
         self.update_registers();
-        response
+        (response, conflicts)
     }
 
     /// get all the edges that are pre-matched in the graph
@@ -472,6 +530,8 @@ pub enum Instruction {
     FindObstacle,
     Grow { length: Weight },
     LoadDefectsExternal { time: usize, channel: usize },
+    SetErasure { edge: EdgeIndex },
+    LoadWeightsExternal,
 }
 
 pub const VIRTUAL_NODE_INDEX: NodeIndex = NodeIndex::MAX;
@@ -588,6 +648,79 @@ pub mod tests {
         }
     }
 
+    /// a defect pair next to the boundary: the boundary vertex touches the boundary at the same time as its peer
+    #[test]
+    fn dual_module_comb_pre_matching_boundary_defect() {
+        // cargo test dual_module_comb_pre_matching_boundary_defect -- --nocapture
+        use fusion_blossom::mwpm_solver::*;
+        let initializer = SolverInitializer::new(5, vec![(0, 1, 2), (1, 2, 4), (2, 3, 100), (3, 4, 100)], vec![0, 4]);
+        let syndrome = SyndromePattern::new_vertices(vec![1, 2]);
+        for second_order in [false, true] {
+            let mut graph = MicroBlossomSingle::new_initializer_only(&initializer);
+            if second_order {
+                graph.add_second_order_offloading();
+            }
+            assert_eq!(graph.offloading.count_by_type().get("bm").is_some(), second_order);
+            let mut solver =
+                SolverEmbeddedComb::new(graph, json!({ "dual": { "sim_config": { "support_offloading": true } } }));
+            solver.solve(&syndrome);
+            assert_eq!(solver.subgraph(), vec![1]);
+            if second_order {
+                assert_eq!(solver.offloaded, 2, "both defects should be offloaded");
+                assert_eq!(solver.offloaded_by_type.get("bm"), Some(&2));
+            } else {
+                assert_eq!(solver.offloaded, 0);
+                assert!(solver.offloaded_by_type.is_empty());
+            }
+        }
+    }
+
+    /// a defect pair in a chain: the middle defect also touches a non-defect vertex at the same time as its peer
+    #[test]
+    fn dual_module_comb_pre_matching_chain_defect() {
+        // cargo test dual_module_comb_pre_matching_chain_defect -- --nocapture
+        use fusion_blossom::mwpm_solver::*;
+        let initializer = SolverInitializer::new(5, vec![(0, 1, 100), (1, 2, 4), (2, 3, 2), (3, 4, 100)], vec![0, 4]);
+        let syndrome = SyndromePattern::new_vertices(vec![1, 2]);
+        for second_order in [false, true] {
+            let mut graph = MicroBlossomSingle::new_initializer_only(&initializer);
+            if second_order {
+                graph.add_second_order_offloading();
+            }
+            assert_eq!(graph.offloading.count_by_type().get("cm").is_some(), second_order);
+            let mut solver =
+                SolverEmbeddedComb::new(graph, json!({ "dual": { "sim_config": { "support_offloading": true } } }));
+            solver.solve(&syndrome);
+            assert_eq!(solver.subgraph(), vec![1]);
+            if second_order {
+                assert_eq!(solver.offloaded, 2, "both defects should be offloaded");
+                assert_eq!(solver.offloaded_by_type.get("cm"), Some(&2));
+                assert_eq!(solver.offloaded_by_type.len(), 1);
+            } else {
+                assert_eq!(solver.offloaded, 0);
+            }
+        }
+    }
+
+    /// random small weights to create many simultaneous tight edges, where the second-order offloaders take effect
+    #[test]
+    fn dual_module_comb_second_order_random() {
+        // cargo test dual_module_comb_second_order_random -- --nocapture
+        use rand::Rng;
+        use rand_xoshiro::rand_core::SeedableRng;
+        let offloaded_by_type = dual_module_comb_random_syndrome_compare(7, true, true, |code, seed| {
+            let mut rng = rand_xoshiro::Xoroshiro128StarStar::seed_from_u64(seed);
+            let mut syndrome = code.generate_random_errors(seed);
+            for edge_index in 0..code.get_initializer().weighted_edges.len() {
+                syndrome.dynamic_weights.push((edge_index, 2 * rng.gen_range(1..=3)));
+            }
+            syndrome
+        });
+        println!("{offloaded_by_type:?}");
+        assert!(offloaded_by_type.get("bm").cloned().unwrap_or(0) > 0);
+        assert!(offloaded_by_type.get("cm").cloned().unwrap_or(0) > 0);
+    }
+
     /// test layer fusion without any offloading
     #[test]
     fn dual_module_comb_layer_fusion_1() {
@@ -747,6 +880,214 @@ pub mod tests {
         }
     }
 
+    /// erased edges have zero weight, so that the defects can be matched through them at no cost
+    #[test]
+    fn dual_module_comb_erasure_1() {
+        // cargo test dual_module_comb_erasure_1 -- --nocapture
+        let visualize_filename = "dual_module_comb_erasure_1.json".to_string();
+        let defect_vertices = vec![18, 26, 34];
+        let erasures = vec![35, 53];
+        dual_module_comb_erasure_standard_syndrome(7, visualize_filename, defect_vertices, erasures, false);
+    }
+
+    /// erasure with pre matching: a zero-weight edge is immediately tight
+    #[test]
+    fn dual_module_comb_erasure_pre_matching_1() {
+        // cargo test dual_module_comb_erasure_pre_matching_1 -- --nocapture
+        let visualize_filename = "dual_module_comb_erasure_pre_matching_1.json".to_string();
+        let defect_vertices = vec![13, 14, 20];
+        let erasures = vec![19, 28];
+        dual_module_comb_erasure_standard_syndrome(5, visualize_filename, defect_vertices, erasures, true);
+    }
+
+    /// randomly generated erasures, reusing the same solver to make sure the weights are recovered between shots
+    #[test]
+    fn dual_module_comb_erasure_random() {
+        // cargo test dual_module_comb_erasure_random -- --nocapture
+        for support_offloading in [false, true] {
+            dual_module_comb_random_syndrome_compare(7, support_offloading, false, |code, seed| {
+                code.set_erasure_probability(0.1);
+                code.generate_random_errors(seed)
+            });
+        }
+    }
+
+    /// randomly generated dynamic weights as if they come from the soft information of the readout
+    #[test]
+    fn dual_module_comb_dynamic_weights_random() {
+        // cargo test dual_module_comb_dynamic_weights_random -- --nocapture
+        use rand::Rng;
+        use rand_xoshiro::rand_core::SeedableRng;
+        for support_offloading in [false, true] {
+            dual_module_comb_random_syndrome_compare(7, support_offloading, false, |code, seed| {
+                let mut rng = rand_xoshiro::Xoroshiro128StarStar::seed_from_u64(seed);
+                let mut syndrome = code.generate_random_errors(seed);
+                for edge_index in 0..code.get_initializer().weighted_edges.len() {
+                    if rng.gen_bool(0.3) {
+                        syndrome.dynamic_weights.push((edge_index, 2 * rng.gen_range(0..=1000)));
+                    }
+                }
+                syndrome
+            });
+        }
+    }
+
+    /// push the defects round by round and compare the final matching with the serial solver
+    #[test]
+    fn dual_module_comb_streaming_random() {
+        // cargo test dual_module_comb_streaming_random -- --nocapture
+        use fusion_blossom::mwpm_solver::*;
+        let window = 1;
+        let mut code = PhenomenologicalPlanarCode::new(5, 4, 0.02, 500);
+        let initializer = code.get_initializer();
+        let graph = MicroBlossomSingle::new(&initializer, &code.get_positions());
+        let layer_fusion = graph.layer_fusion.clone().unwrap();
+        assert_eq!(layer_fusion.num_layers, 5);
+        for support_offloading in [false, true] {
+            let mut solver = SolverEmbeddedComb::new(
+                graph.clone(),
+                json!({ "dual": { "sim_config": {
+                    "support_offloading": support_offloading,
+                    "support_layer_fusion": true,
+                } } }),
+            );
+            let mut standard_solver = SolverSerial::new(&initializer);
+            for seed in 0..50 {
+                let syndrome = code.generate_random_errors(seed);
+                for round in 0..layer_fusion.num_layers {
+                    let defect_vertices: Vec<_> = syndrome
+                        .defect_vertices
+                        .iter()
+                        .filter(|vertex_index| layer_fusion.vertex_layer_id[vertex_index] == round)
+                        .cloned()
+                        .collect();
+                    solver.push_round(&defect_vertices).unwrap();
+                    assert_eq!(solver.pushed_rounds(), round + 1);
+                    // the tentative matchings only involve the rounds that are old enough
+                    let tentative = solver.tentative_matching(window);
+                    let mut vertices: Vec<_> = tentative.virtual_matchings.iter().map(|(_, v)| *v).collect();
+                    let mut nodes: Vec<_> = tentative.virtual_matchings.iter().map(|(node, _)| node).collect();
+                    for (node_1, node_2) in tentative.peer_matchings.iter() {
+                        nodes.extend([node_1, node_2]);
+                    }
+                    for node in nodes {
+                        if let DualNodeClass::DefectVertex { defect_index } = node.read_recursive().class {
+                            vertices.push(defect_index);
+                        }
+                    }
+                    for vertex_index in vertices {
+                        if let Some(&layer_id) = layer_fusion.vertex_layer_id.get(&vertex_index) {
+                            assert!(
+                                layer_id + window <= round,
+                                "seed {seed}: vertex {vertex_index} is not old enough"
+                            );
+                        }
+                    }
+                }
+                solver.finish_rounds(None).unwrap();
+                let subgraph = solver.subgraph();
+                assert_eq!(
+                    initializer.syndrome_of(&subgraph),
+                    syndrome.defect_vertices.iter().cloned().collect()
+                );
+                standard_solver.solve(&syndrome);
+                let standard_subgraph = standard_solver.subgraph();
+                let mut subgraph_builder = fusion_blossom::primal_module::SubGraphBuilder::new(&initializer);
+                subgraph_builder.load_subgraph(&subgraph);
+                let total_weight = subgraph_builder.total_weight();
+                subgraph_builder.load_subgraph(&standard_subgraph);
+                let standard_total_weight = subgraph_builder.total_weight();
+                assert_eq!(total_weight, standard_total_weight, "seed {seed}: {syndrome:?}");
+                // after all the rounds, the tentative matching with no window is the final matching
+                let tentative = solver.tentative_matching(0);
+                let perfect_matching = solver.perfect_matching();
+                assert_eq!(tentative.peer_matchings.len(), perfect_matching.peer_matchings.len());
+                assert_eq!(tentative.virtual_matchings.len(), perfect_matching.virtual_matchings.len());
+                solver.clear();
+                standard_solver.clear();
+            }
+        }
+    }
+
+    /// compare the total weight with the serial solver, reusing the same solver for all the shots;
+    /// return the total number of defects pre-matched by each type of offloader
+    pub fn dual_module_comb_random_syndrome_compare(
+        d: VertexNum,
+        support_offloading: bool,
+        second_order: bool,
+        mut generate_syndrome: impl FnMut(&mut CodeCapacityPlanarCode, u64) -> SyndromePattern,
+    ) -> BTreeMap<&'static str, usize> {
+        use fusion_blossom::mwpm_solver::*;
+        let mut code = CodeCapacityPlanarCode::new(d, 0.05, 500);
+        let initializer = code.get_initializer();
+        let mut graph = MicroBlossomSingle::new(&initializer, &code.get_positions());
+        if second_order {
+            graph.add_second_order_offloading();
+        }
+        let mut solver = SolverEmbeddedComb::new(
+            graph,
+            json!({ "dual": { "sim_config": { "support_offloading": support_offloading } } }),
+        );
+        let mut standard_solver = SolverSerial::new(&initializer);
+        let mut offloaded_by_type = BTreeMap::new();
+        for seed in 0..100 {
+            let syndrome = generate_syndrome(&mut code, seed);
+            solver.solve(&syndrome);
+            let subgraph = solver.subgraph();
+            for (&name, &count) in solver.offloaded_by_type.iter() {
+                *offloaded_by_type.entry(name).or_insert(0) += count;
+            }
+            standard_solver.solve(&syndrome);
+            let standard_subgraph = standard_solver.subgraph();
+            assert_eq!(
+                initializer.syndrome_of(&subgraph),
+                syndrome.defect_vertices.iter().cloned().collect()
+            );
+            let mut subgraph_builder = fusion_blossom::primal_module::SubGraphBuilder::new(&initializer);
+            if !syndrome.erasures.is_empty() {
+                subgraph_builder.load_erasures(&syndrome.erasures);
+            }
+            if !syndrome.dynamic_weights.is_empty() {
+                subgraph_builder.load_dynamic_weights(&syndrome.dynamic_weights);
+            }
+            subgraph_builder.load_subgraph(&subgraph);
+            let total_weight = subgraph_builder.total_weight();
+            subgraph_builder.load_subgraph(&standard_subgraph);
+            let standard_total_weight = subgraph_builder.total_weight();
+            assert_eq!(total_weight, standard_total_weight, "seed {seed}: {syndrome:?}");
+            solver.clear();
+            standard_solver.clear();
+        }
+        offloaded_by_type
+    }
+
+    pub fn dual_module_comb_erasure_standard_syndrome(
+        d: VertexNum,
+        visualize_filename: String,
+        defect_vertices: Vec<VertexIndex>,
+        erasures: Vec<EdgeIndex>,
+        support_offloading: bool,
+    ) -> SolverEmbeddedComb {
+        dual_module_erasure_optional_viz(
+            d,
+            Some(visualize_filename.clone()),
+            defect_vertices,
+            erasures,
+            |initializer, positions| {
+                SolverEmbeddedComb::new(
+                    MicroBlossomSingle::new(initializer, positions),
+                    json!({
+                        "dual": {
+                            "sim_config": {
+                                "support_offloading": support_offloading,
+                            }
+                        }
+                    }),
+                )
+            },
+        )
+    }
+
     pub fn dual_module_comb_basic_standard_syndrome(
         d: VertexNum,
         visualize_filename: String,
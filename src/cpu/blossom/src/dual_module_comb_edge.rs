@@ -140,10 +140,21 @@ impl Edge {
         .clone()
     }
 
-    pub fn get_post_execute_state(&self, _dual_module: &DualModuleCombDriver) -> Ref<'_, EdgeRegisters> {
+    pub fn get_post_execute_state(&self, dual_module: &DualModuleCombDriver) -> Ref<'_, EdgeRegisters> {
         referenced_signal!(self.signals.post_execute_state, || {
-            let state = self.registers.clone();
-            // TODO: dynamically update edge weights
+            let mut state = self.registers.clone();
+            match &dual_module.instruction {
+                Instruction::SetErasure { edge } => {
+                    if self.edge_index == *edge {
+                        // an erasure error means p=0.5, so the weight is ln((1-p)/p) = 0 until the next reset
+                        state.weight = 0;
+                    }
+                }
+                Instruction::LoadWeightsExternal => {
+                    state.weight = dual_module.external_weights[self.edge_index];
+                }
+                _ => {}
+            }
             state
         })
     }
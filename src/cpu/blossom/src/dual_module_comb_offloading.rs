@@ -70,6 +70,41 @@ impl Offloading {
                 affecting_vertices.insert(left_index);
                 affecting_vertices.insert(right_index);
             }
+            OffloadingType::BoundaryDefectMatch {
+                edge_index,
+                boundary_edge_index,
+            } => {
+                affecting_edges.insert(edge_index);
+                let (left_index, right_index, _) = initializer.weighted_edges[edge_index];
+                let (boundary_left, boundary_right, _) = initializer.weighted_edges[boundary_edge_index];
+                assert!(
+                    [left_index, right_index].contains(&boundary_left)
+                        || [left_index, right_index].contains(&boundary_right),
+                    "the boundary edge must be incident to the matched edge"
+                );
+                affecting_vertices.insert(left_index);
+                affecting_vertices.insert(right_index);
+            }
+            OffloadingType::ChainDefectMatch {
+                edge_index,
+                chain_edge_index,
+            } => {
+                affecting_edges.insert(edge_index);
+                let (left_index, right_index, _) = initializer.weighted_edges[edge_index];
+                let (chain_left, chain_right, _) = initializer.weighted_edges[chain_edge_index];
+                let chain_end = if [left_index, right_index].contains(&chain_left) {
+                    chain_right
+                } else {
+                    assert!(
+                        [left_index, right_index].contains(&chain_right),
+                        "the chain edge must be incident to the matched edge"
+                    );
+                    chain_left
+                };
+                affecting_vertices.insert(left_index);
+                affecting_vertices.insert(right_index);
+                affecting_vertices.insert(chain_end);
+            }
         }
         Self {
             offloading_type,
@@ -128,8 +163,11 @@ impl Offloading {
                         let neighbor_edge = &dual_module.edges[neighbor_edge_index];
                         let neighbor_vertex_index = neighbor_edge.get_peer(regular_index);
                         let neighbor_vertex = &dual_module.vertices[neighbor_vertex_index];
+                        // another tight virtual neighbor would be pre-matched by its own offloader at the same time
                         condition &= !neighbor_edge.get_post_fetch_is_tight(dual_module)
-                            || (neighbor_vertex.get_is_unique_tight(dual_module) && !neighbor_vertex.registers.is_defect);
+                            || (neighbor_vertex.get_is_unique_tight(dual_module)
+                                && !neighbor_vertex.registers.is_defect
+                                && !neighbor_vertex.registers.is_virtual);
                     }
                     if condition {
                         vertex_stalls.insert(regular_index);
@@ -166,6 +204,80 @@ impl Offloading {
                     }
                     condition
                 }
+                OffloadingType::BoundaryDefectMatch {
+                    edge_index,
+                    boundary_edge_index,
+                } => {
+                    // the boundary vertex has exactly two tight edges: one to the peer defect and one to the boundary;
+                    // since the peer defect can only match the boundary vertex, matching them is what the primal
+                    // module would do, regardless of whether it first matches the boundary vertex to the boundary
+                    let edge = &dual_module.edges[edge_index];
+                    let boundary_edge = &dual_module.edges[boundary_edge_index];
+                    let boundary_index =
+                        if boundary_edge.left_index == edge.left_index || boundary_edge.left_index == edge.right_index {
+                            boundary_edge.left_index
+                        } else {
+                            boundary_edge.right_index
+                        };
+                    let boundary_vertex = &dual_module.vertices[boundary_index];
+                    let peer_index = edge.get_peer(boundary_index);
+                    let peer_vertex = &dual_module.vertices[peer_index];
+                    let virtual_vertex = &dual_module.vertices[boundary_edge.get_peer(boundary_index)];
+                    let condition = edge.get_post_fetch_is_tight(dual_module)
+                        && boundary_edge.get_post_fetch_count_tight(dual_module)
+                        && virtual_vertex.registers.is_virtual
+                        && boundary_vertex.registers.is_defect
+                        && boundary_vertex.registers.speed == CompactGrowState::Grow
+                        && boundary_vertex.get_tight_count(dual_module) == 2
+                        && peer_vertex.registers.is_defect
+                        && peer_vertex.registers.speed == CompactGrowState::Grow
+                        && peer_vertex.get_is_unique_tight(dual_module);
+                    if condition {
+                        vertex_stalls.insert(boundary_index);
+                        vertex_stalls.insert(peer_index);
+                        edge_stalls.insert(edge_index);
+                    }
+                    condition
+                }
+                OffloadingType::ChainDefectMatch {
+                    edge_index,
+                    chain_edge_index,
+                } => {
+                    // the chain vertex has exactly two tight edges: one to the peer defect and one to a non-defect
+                    // vertex that touches nothing else, i.e. the end of the chain only belongs to the chain vertex;
+                    // the peer defect can only match the chain vertex, so the primal module would match them
+                    let edge = &dual_module.edges[edge_index];
+                    let chain_edge = &dual_module.edges[chain_edge_index];
+                    let chain_index =
+                        if chain_edge.left_index == edge.left_index || chain_edge.left_index == edge.right_index {
+                            chain_edge.left_index
+                        } else {
+                            chain_edge.right_index
+                        };
+                    let chain_vertex = &dual_module.vertices[chain_index];
+                    let peer_index = edge.get_peer(chain_index);
+                    let peer_vertex = &dual_module.vertices[peer_index];
+                    let end_index = chain_edge.get_peer(chain_index);
+                    let end_vertex = &dual_module.vertices[end_index];
+                    let condition = edge.get_post_fetch_is_tight(dual_module)
+                        && chain_edge.get_post_fetch_count_tight(dual_module)
+                        && chain_vertex.registers.is_defect
+                        && chain_vertex.registers.speed == CompactGrowState::Grow
+                        && chain_vertex.get_tight_count(dual_module) == 2
+                        && peer_vertex.registers.is_defect
+                        && peer_vertex.registers.speed == CompactGrowState::Grow
+                        && peer_vertex.get_is_unique_tight(dual_module)
+                        && !end_vertex.registers.is_defect
+                        && !end_vertex.registers.is_virtual
+                        && end_vertex.get_is_unique_tight(dual_module);
+                    if condition {
+                        vertex_stalls.insert(chain_index);
+                        vertex_stalls.insert(peer_index);
+                        vertex_stalls.insert(end_index);
+                        edge_stalls.insert(edge_index);
+                    }
+                    condition
+                }
             };
             OffloadingSignals {
                 condition,
@@ -0,0 +1,362 @@
+//! Embedded Report
+//!
+//! Parse the output of the embedded `benchmark_decoding` main, captured from `embedded_simulator` or the UART of
+//! the FPGA board, and convert it into the same report schema as `benchmark --benchmark-profiler-output`: the
+//! partition config, the benchmark config and then one JSON line per shot. The latency histograms are parsed from
+//! either the JSON form or the legacy `<lower>...` form and merged across multiple outputs.
+//!
+
+use fusion_blossom::util::*;
+use micro_blossom_nostd::latency_benchmarker::*;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::Write;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmbeddedShot {
+    /// the index of the shot in the defects file, starting from 1
+    pub index: usize,
+    /// the latency measured by the hardware, in seconds
+    pub latency: f64,
+    /// the wall time measured by the CPU, in seconds
+    pub cpu_wall: f64,
+    pub instruction_counter: usize,
+    /// the number of defects, not printed by older firmware
+    pub defect_num: Option<usize>,
+    /// whether the deadline expired and the matching is non-optimal
+    pub non_optimal: bool,
+}
+
+impl EmbeddedShot {
+    /// parse a line like `[1] time: 1.234us, counter: 20, wall: 5.678us, defects: 4, non-optimal`
+    pub fn parse(line: &str) -> Option<Self> {
+        let (index, rest) = line.strip_prefix('[')?.split_once("] time: ")?;
+        let mut fields = rest.split(", ");
+        let parse_us = |value: &str| -> Option<f64> { Some(value.strip_suffix("us")?.parse::<f64>().ok()? * 1e-6) };
+        let latency = parse_us(fields.next()?)?;
+        let instruction_counter = fields.next()?.strip_prefix("counter: ")?.parse().ok()?;
+        let cpu_wall = parse_us(fields.next()?.strip_prefix("wall: ")?)?;
+        let mut shot = Self {
+            index: index.parse().ok()?,
+            latency,
+            cpu_wall,
+            instruction_counter,
+            defect_num: None,
+            non_optimal: false,
+        };
+        for field in fields {
+            if let Some(defect_num) = field.strip_prefix("defects: ") {
+                shot.defect_num = Some(defect_num.parse().ok()?);
+            } else if field == "non-optimal" {
+                shot.non_optimal = true;
+            } else {
+                return None;
+            }
+        }
+        Some(shot)
+    }
+
+    /// an entry of the benchmark profiler, where the hardware latency is reported as the decoding time
+    pub fn profile_entry(&self) -> serde_json::Value {
+        let mut entry = json!({
+            "round_time": self.latency,
+            "events": { "decoded": self.latency },
+            "solver_profile": {
+                "cpu_wall": self.cpu_wall,
+                "instruction_counter": self.instruction_counter,
+                "non_optimal": self.non_optimal,
+            },
+        });
+        if let Some(defect_num) = self.defect_num {
+            entry
+                .as_object_mut()
+                .unwrap()
+                .insert("defect_num".to_string(), json!(defect_num));
+        }
+        entry
+    }
+}
+
+/// the JSON form printed by [`LatencyBenchmarker::println_json`]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LatencyBenchmarkerJson {
+    lower: f64,
+    upper: f64,
+    resolution: usize,
+    counter: Vec<(usize, usize)>,
+    underflow: usize,
+    overflow: usize,
+    sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+fn new_latency_benchmarker(lower: f64, upper: f64, resolution: usize) -> Result<Box<LatencyBenchmarker>, String> {
+    if !(lower > 0. && upper > lower && resolution > 0 && resolution <= 2000) {
+        return Err(format!(
+            "invalid histogram: lower {lower}, upper {upper}, resolution {resolution}"
+        ));
+    }
+    Ok(Box::new(LatencyBenchmarker::new_with_resolution(lower, upper, resolution)))
+}
+
+fn add_bucket(benchmarker: &mut LatencyBenchmarker, index: usize, count: usize) -> Result<(), String> {
+    if index >= benchmarker.resolution {
+        return Err(format!("bucket {index} out of resolution {}", benchmarker.resolution));
+    }
+    benchmarker.counter[index] += count;
+    Ok(())
+}
+
+pub fn latency_benchmarker_from_json(json: &str) -> Result<Box<LatencyBenchmarker>, String> {
+    let value: LatencyBenchmarkerJson = serde_json::from_str(json).map_err(|error| error.to_string())?;
+    let mut benchmarker = new_latency_benchmarker(value.lower, value.upper, value.resolution)?;
+    for (index, count) in value.counter {
+        add_bucket(&mut benchmarker, index, count)?;
+    }
+    benchmarker.underflow_count = value.underflow;
+    benchmarker.overflow_count = value.overflow;
+    benchmarker.sum_latency = value.sum;
+    benchmarker.min_latency = value.min.unwrap_or(f64::INFINITY);
+    benchmarker.max_latency = value.max.unwrap_or(f64::NEG_INFINITY);
+    Ok(benchmarker)
+}
+
+/// parse the legacy form printed by [`LatencyBenchmarker::println`], which has neither the exact sum nor min/max
+pub fn latency_benchmarker_from_line(line: &str) -> Result<Box<LatencyBenchmarker>, String> {
+    let invalid = || format!("invalid histogram line: {line}");
+    let rest = line.strip_prefix("<lower>").ok_or_else(invalid)?;
+    let (lower, rest) = rest.split_once("<upper>").ok_or_else(invalid)?;
+    let (upper, rest) = rest.split_once("<N>").ok_or_else(invalid)?;
+    let (resolution, rest) = rest.split_once('[').ok_or_else(invalid)?;
+    let parse_f64 = |value: &str| value.parse::<f64>().map_err(|_| invalid());
+    let parse_usize = |value: &str| value.parse::<usize>().map_err(|_| invalid());
+    let mut benchmarker = new_latency_benchmarker(parse_f64(lower)?, parse_f64(upper)?, parse_usize(resolution)?)?;
+    for bucket in rest.split('[') {
+        let (index, count) = bucket.split_once(']').ok_or_else(invalid)?;
+        let count = parse_usize(count)?;
+        match index {
+            "underflow" => benchmarker.underflow_count = count,
+            "overflow" => benchmarker.overflow_count = count,
+            _ => add_bucket(&mut benchmarker, parse_usize(index)?, count)?,
+        }
+    }
+    Ok(benchmarker)
+}
+
+pub fn latency_benchmarker_to_json(benchmarker: &LatencyBenchmarker) -> serde_json::Value {
+    let mut json = String::new();
+    benchmarker.write_json(&mut json).unwrap();
+    serde_json::from_str(&json).unwrap()
+}
+
+fn legacy_histogram<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    line.strip_prefix(name).filter(|histogram| histogram.starts_with("<lower>"))
+}
+
+#[derive(Default)]
+pub struct EmbeddedOutput {
+    pub shots: Vec<EmbeddedShot>,
+    /// the latency measured by the hardware
+    pub latency: Option<Box<LatencyBenchmarker>>,
+    /// the wall time measured by the CPU
+    pub cpu_wall: Option<Box<LatencyBenchmarker>>,
+    /// the number of shots whose deadline expired, only printed when the deadline is enabled; unlike the
+    /// `non-optimal` flag of each shot, it is also printed with `DISABLE_DETAIL_PRINT`
+    pub non_optimal_count: Option<usize>,
+}
+
+impl EmbeddedOutput {
+    pub fn parse(output: &str) -> Result<Self, String> {
+        let mut result = Self::default();
+        let (mut latency_line, mut cpu_wall_line) = (None, None);
+        for line in output.lines() {
+            let line = line.trim();
+            if let Some(json) = line.strip_prefix("latency_benchmarker_json") {
+                result.latency = Some(latency_benchmarker_from_json(json)?);
+            } else if let Some(json) = line.strip_prefix("cpu_wall_benchmarker_json") {
+                result.cpu_wall = Some(latency_benchmarker_from_json(json)?);
+            } else if let Some(histogram) = legacy_histogram(line, "latency_benchmarker") {
+                latency_line = Some(histogram);
+            } else if let Some(histogram) = legacy_histogram(line, "cpu_wall_benchmarker") {
+                cpu_wall_line = Some(histogram);
+            } else if let Some(rest) = line.strip_prefix("non-optimal count: ") {
+                let count = rest.split_once(' ').map_or(rest, |(count, _)| count);
+                let count = count.parse().map_err(|_| format!("invalid non-optimal count: {line}"))?;
+                result.non_optimal_count = Some(count);
+            } else if let Some(shot) = EmbeddedShot::parse(line) {
+                result.shots.push(shot);
+            }
+        }
+        // the legacy form is only used when the exact JSON form is not printed
+        if result.latency.is_none() {
+            result.latency = latency_line.map(latency_benchmarker_from_line).transpose()?;
+        }
+        if result.cpu_wall.is_none() {
+            result.cpu_wall = cpu_wall_line.map(latency_benchmarker_from_line).transpose()?;
+        }
+        Ok(result)
+    }
+
+    /// merge the output of another run, e.g., on another context or board
+    pub fn merge(&mut self, other: Self) -> Result<(), String> {
+        if self.non_optimal_count.is_some() || other.non_optimal_count.is_some() {
+            self.non_optimal_count = Some(self.non_optimal_count() + other.non_optimal_count());
+        }
+        self.shots.extend(other.shots);
+        for (mine, theirs) in [(&mut self.latency, other.latency), (&mut self.cpu_wall, other.cpu_wall)] {
+            match (mine.as_mut(), theirs) {
+                (Some(mine), Some(theirs)) => mine.merge(&theirs).map_err(|error| error.to_string())?,
+                (None, theirs) => *mine = theirs,
+                (Some(_), None) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// write the report in the schema of `benchmark --benchmark-profiler-output`
+    pub fn write_profile(&self, filename: &str, vertex_num: VertexNum, noisy_measurements: VertexNum) -> Result<(), String> {
+        if self.shots.is_empty() {
+            return Err("no shot is printed, build the firmware without `DISABLE_DETAIL_PRINT`".to_string());
+        }
+        let mut file = File::create(filename).map_err(|error| format!("{filename}: {error}"))?;
+        let mut lines = vec![
+            serde_json::to_string(&PartitionConfig::new(vertex_num)).unwrap(),
+            serde_json::to_string(&json!({
+                "noisy_measurements": noisy_measurements,
+                "non_optimal_count": self.non_optimal_count(),
+            }))
+            .unwrap(),
+        ];
+        lines.extend(
+            self.shots
+                .iter()
+                .map(|shot| serde_json::to_string(&shot.profile_entry()).unwrap()),
+        );
+        for line in lines.iter() {
+            writeln!(file, "{line}").map_err(|error| format!("{filename}: {error}"))?;
+        }
+        Ok(())
+    }
+
+    /// the number of shots whose deadline expired, counted from the shots if the firmware does not print it
+    pub fn non_optimal_count(&self) -> usize {
+        self.non_optimal_count
+            .unwrap_or_else(|| self.shots.iter().filter(|shot| shot.non_optimal).count())
+    }
+
+    pub fn histograms_json(&self) -> serde_json::Value {
+        json!({
+            "latency": self.latency.as_deref().map(latency_benchmarker_to_json),
+            "cpu_wall": self.cpu_wall.as_deref().map(latency_benchmarker_to_json),
+            "non_optimal_count": self.non_optimal_count(),
+        })
+    }
+
+    pub fn print_statistics(&self) {
+        println!("shots: {}", self.shots.len());
+        let non_optimal_count = self.non_optimal_count();
+        if non_optimal_count > 0 {
+            println!("non-optimal shots: {non_optimal_count}");
+        }
+        for (name, benchmarker) in [("latency", &self.latency), ("cpu_wall", &self.cpu_wall)] {
+            let Some(benchmarker) = benchmarker else {
+                continue;
+            };
+            println!("{name}: {} records", benchmarker.count_all_records());
+            if benchmarker.is_min_max_tracked() {
+                println!("    average: {:.3e}s", benchmarker.exact_average_latency());
+            } else {
+                println!("    average: {:.3e}s (bucket centers)", benchmarker.average_latency());
+            }
+            for quantile in [0.5, 0.9, 0.99, 0.999, 1.] {
+                println!("    {quantile} quantile: {:.3e}s", benchmarker.quantile(quantile));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_report_parse() {
+        // cargo test embedded_report_parse -- --nocapture
+        let mut latency: LatencyBenchmarker = LatencyBenchmarker::new_default();
+        latency.record(1.5e-6).unwrap();
+        latency.record(2.5e-6).unwrap();
+        let mut latency_json = String::new();
+        latency.write_json(&mut latency_json).unwrap();
+        let output = format!(
+            "------- start of build parameters -------\n\
+            [1] time: 1.500us, counter: 12, wall: 3.000us, defects: 2\r\n\
+            [info] have run 1 samples\n\
+            [2] time: 2.500us, counter: 20, wall: 4.000us, defects: 4, non-optimal\n\
+            cpu_wall_benchmarker<lower>1.000e-9<upper>1.000e0<N>2000[1093]1[1203]1[underflow]0[overflow]0\n\
+            latency_benchmarker<lower>1.000e-9<upper>1.000e0<N>2000[1000]2[underflow]0[overflow]0\n\
+            latency_benchmarker_json{latency_json}\n\
+            latency_benchmarker statistics:\n\
+            non-optimal count: 1 (deadline expired)\n"
+        );
+        let mut parsed = EmbeddedOutput::parse(&output).unwrap();
+        assert_eq!(parsed.shots.len(), 2);
+        assert_eq!(parsed.shots[0].index, 1);
+        assert_eq!(parsed.shots[0].defect_num, Some(2));
+        assert!(!parsed.shots[0].non_optimal);
+        assert!(parsed.shots[1].non_optimal);
+        assert_eq!(parsed.non_optimal_count, Some(1));
+        assert_eq!(parsed.shots[1].instruction_counter, 20);
+        assert!((parsed.shots[1].cpu_wall - 4e-6).abs() < 1e-15);
+        // the JSON form takes precedence over the legacy form
+        let parsed_latency = parsed.latency.as_ref().unwrap();
+        assert_eq!(parsed_latency.counter, latency.counter);
+        assert_eq!(parsed_latency.sum_latency, latency.sum_latency);
+        assert_eq!(parsed_latency.quantile(1.), 2.5e-6);
+        let parsed_cpu_wall = parsed.cpu_wall.as_ref().unwrap();
+        assert!(!parsed_cpu_wall.is_min_max_tracked());
+        assert_eq!(parsed_cpu_wall.count_all_records(), 2);
+        // merge another run
+        let other = EmbeddedOutput::parse(&output).unwrap();
+        parsed.merge(other).unwrap();
+        assert_eq!(parsed.shots.len(), 4);
+        assert_eq!(parsed.non_optimal_count(), 2);
+        assert_eq!(parsed.latency.as_ref().unwrap().count_all_records(), 4);
+        assert_eq!(parsed.cpu_wall.as_ref().unwrap().count_all_records(), 4);
+        let entry = parsed.shots[1].profile_entry();
+        assert_eq!(entry["defect_num"], json!(4));
+        assert!((entry["events"]["decoded"].as_f64().unwrap() - 2.5e-6).abs() < 1e-15);
+        assert_eq!(entry["solver_profile"]["non_optimal"], json!(true));
+        parsed.print_statistics();
+        println!("{}", parsed.histograms_json());
+        assert_eq!(parsed.histograms_json()["non_optimal_count"], json!(2));
+        // without the count, e.g. from older firmware, the flags of the shots are counted
+        parsed.non_optimal_count = None;
+        assert_eq!(parsed.non_optimal_count(), 2);
+    }
+
+    #[test]
+    fn embedded_report_invalid_histogram() {
+        // cargo test embedded_report_invalid_histogram -- --nocapture
+        assert!(
+            latency_benchmarker_from_line("<lower>1.000e-9<upper>1.000e0<N>2000[2000]1[underflow]0[overflow]0").is_err()
+        );
+        assert!(latency_benchmarker_from_line("<lower>1.000e-9<upper>1.000e0<N>4000[underflow]0[overflow]0").is_err());
+        assert!(latency_benchmarker_from_line("<lower>1.000e-9<upper>1.000e0<N>2000[underflow]0").is_ok());
+        assert!(latency_benchmarker_from_json("{\"lower\":1e-9}").is_err());
+        let mut different: LatencyBenchmarker = LatencyBenchmarker::new(1e-9, 10.);
+        different.record(1.).unwrap();
+        let mut output = EmbeddedOutput {
+            latency: Some(
+                latency_benchmarker_from_line("<lower>1.000e-9<upper>1.000e0<N>2000[underflow]0[overflow]0").unwrap(),
+            ),
+            ..Default::default()
+        };
+        let other = EmbeddedOutput {
+            latency: Some(Box::new(different)),
+            ..Default::default()
+        };
+        assert!(output.merge(other).is_err());
+    }
+}
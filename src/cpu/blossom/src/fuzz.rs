@@ -0,0 +1,622 @@
+//! Differential Fuzzer
+//!
+//! Generate random decoding graphs (random weights, virtual vertices and layer fusion plans) and random defect sets,
+//! decode them with [`SolverEmbeddedComb`] under every combination of pre-matching and layer fusion, and compare the
+//! results against the serial solver of fusion-blossom and a reference minimum-weight perfect matching computed by the
+//! `mwmatching` crate. A failing case is shrunk to a minimal graph and syndrome, which can be saved to
+//! [`REGRESSION_FOLDER`] so that it is replayed by `cargo test fuzz_regressions`.
+//!
+//! Enable the `check_invariants` feature to also check the primal invariants after every `resolve`.
+//!
+
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::primal_module::SubGraphBuilder;
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+use mwmatching::Matching;
+use petgraph::{algo::floyd_warshall, graph::UnGraph};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_xoshiro::rand_core::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+/// the shrunk failing cases, replayed by the `fuzz_regressions` test
+pub const REGRESSION_FOLDER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../../resources/fuzz_regressions");
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FuzzConfig {
+    /// minimum number of vertices, including virtual vertices
+    #[serde(default = "fuzz_config_default::min_vertex_num")]
+    pub min_vertex_num: usize,
+    /// maximum number of vertices, including virtual vertices
+    #[serde(default = "fuzz_config_default::max_vertex_num")]
+    pub max_vertex_num: usize,
+    /// maximum number of virtual vertices; there is always at least one
+    #[serde(default = "fuzz_config_default::max_virtual_num")]
+    pub max_virtual_num: usize,
+    /// the probability of adding each edge besides the random spanning tree
+    #[serde(default = "fuzz_config_default::edge_probability")]
+    pub edge_probability: f64,
+    /// the edge weights are even numbers in `[2, 2 * max_half_weight]`
+    #[serde(default = "fuzz_config_default::max_half_weight")]
+    pub max_half_weight: isize,
+    /// maximum number of layers in the layer fusion plan
+    #[serde(default = "fuzz_config_default::max_layer_num")]
+    pub max_layer_num: usize,
+    /// the probability of each non-virtual vertex being a defect
+    #[serde(default = "fuzz_config_default::defect_probability")]
+    pub defect_probability: f64,
+    /// allow a vertex to have more than one virtual neighbor, which is common at the corners of the surface codes
+    #[serde(default = "fuzz_config_default::allow_multiple_virtual_neighbors")]
+    pub allow_multiple_virtual_neighbors: bool,
+}
+
+impl Default for FuzzConfig {
+    fn default() -> Self {
+        serde_json::from_value(json!({})).unwrap()
+    }
+}
+
+pub mod fuzz_config_default {
+    pub fn min_vertex_num() -> usize {
+        2
+    }
+    pub fn max_vertex_num() -> usize {
+        12
+    }
+    pub fn max_virtual_num() -> usize {
+        3
+    }
+    pub fn edge_probability() -> f64 {
+        0.3
+    }
+    pub fn max_half_weight() -> isize {
+        4
+    }
+    pub fn max_layer_num() -> usize {
+        4
+    }
+    pub fn defect_probability() -> f64 {
+        0.5
+    }
+    pub fn allow_multiple_virtual_neighbors() -> bool {
+        true
+    }
+}
+
+/// a decoding graph together with a syndrome; this is also the format of the regression files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FuzzCase {
+    pub vertex_num: usize,
+    /// (left, right, weight) of each edge
+    pub weighted_edges: Vec<(VertexIndex, VertexIndex, Weight)>,
+    pub virtual_vertices: Vec<VertexIndex>,
+    /// the non-virtual vertices of each layer, in the order of fusion
+    pub layers: Vec<Vec<VertexIndex>>,
+    pub defect_vertices: Vec<VertexIndex>,
+}
+
+impl FuzzCase {
+    /// generate a connected graph with a random spanning tree of the non-virtual vertices, each virtual vertex
+    /// attached to a random non-virtual vertex and some more random edges; unless
+    /// [`FuzzConfig::allow_multiple_virtual_neighbors`], each virtual vertex is attached to a distinct vertex
+    pub fn random(config: &FuzzConfig, seed: u64) -> Self {
+        assert!(config.min_vertex_num >= 2 && config.min_vertex_num <= config.max_vertex_num);
+        assert!(config.max_virtual_num >= 1 && config.max_half_weight >= 1 && config.max_layer_num >= 1);
+        let mut rng = rand_xoshiro::Xoroshiro128StarStar::seed_from_u64(seed);
+        let vertex_num = rng.gen_range(config.min_vertex_num..=config.max_vertex_num);
+        let max_virtual_num = if config.allow_multiple_virtual_neighbors {
+            vertex_num - 1
+        } else {
+            vertex_num / 2
+        };
+        let virtual_num = rng.gen_range(1..=std::cmp::min(config.max_virtual_num, max_virtual_num));
+        let mut vertices: Vec<VertexIndex> = (0..vertex_num).collect();
+        vertices.shuffle(&mut rng);
+        let (virtual_vertices, real_vertices) = vertices.split_at(virtual_num);
+        let mut edges = BTreeSet::new();
+        for index in 1..real_vertices.len() {
+            let peer = real_vertices[rng.gen_range(0..index)];
+            edges.insert(sorted_pair(real_vertices[index], peer));
+        }
+        let mut is_virtual = vec![false; vertex_num];
+        let mut has_virtual_neighbor = vec![false; vertex_num];
+        for (index, &virtual_vertex) in virtual_vertices.iter().enumerate() {
+            let peer = if config.allow_multiple_virtual_neighbors {
+                *real_vertices.choose(&mut rng).unwrap()
+            } else {
+                real_vertices[index] // the real vertices are already shuffled
+            };
+            edges.insert(sorted_pair(virtual_vertex, peer));
+            is_virtual[virtual_vertex] = true;
+            has_virtual_neighbor[peer] = true;
+        }
+        for left in 0..vertex_num {
+            for right in left + 1..vertex_num {
+                if is_virtual[left] && is_virtual[right] || edges.contains(&(left, right)) {
+                    continue;
+                }
+                if !config.allow_multiple_virtual_neighbors
+                    && (is_virtual[left] && has_virtual_neighbor[right] || is_virtual[right] && has_virtual_neighbor[left])
+                {
+                    continue;
+                }
+                if rng.gen_bool(config.edge_probability) {
+                    edges.insert((left, right));
+                    has_virtual_neighbor[left] |= is_virtual[right];
+                    has_virtual_neighbor[right] |= is_virtual[left];
+                }
+            }
+        }
+        let weighted_edges = edges
+            .into_iter()
+            .map(|(left, right)| (left, right, 2 * rng.gen_range(1..=config.max_half_weight)))
+            .collect();
+        let layer_num = rng.gen_range(1..=std::cmp::min(config.max_layer_num, real_vertices.len()));
+        let mut layers = vec![vec![]; layer_num];
+        for &vertex_index in real_vertices.iter() {
+            layers[rng.gen_range(0..layer_num)].push(vertex_index);
+        }
+        layers.retain(|layer| !layer.is_empty());
+        layers.iter_mut().for_each(|layer| layer.sort());
+        let mut defect_vertices: Vec<VertexIndex> = real_vertices
+            .iter()
+            .cloned()
+            .filter(|_| rng.gen_bool(config.defect_probability))
+            .collect();
+        defect_vertices.sort();
+        let mut virtual_vertices = virtual_vertices.to_vec();
+        virtual_vertices.sort();
+        let case = Self {
+            vertex_num,
+            weighted_edges,
+            virtual_vertices,
+            layers,
+            defect_vertices,
+        };
+        debug_assert_eq!(case.sanity_check(), Ok(()));
+        case
+    }
+
+    /// a valid case has a connected graph with at least one virtual vertex, positive even weights, no edge between
+    /// virtual vertices, every non-virtual vertex in exactly one non-empty layer and distinct non-virtual defects
+    pub fn sanity_check(&self) -> Result<(), String> {
+        let mut is_virtual = vec![false; self.vertex_num];
+        for &vertex_index in self.virtual_vertices.iter() {
+            if vertex_index >= self.vertex_num || is_virtual[vertex_index] {
+                return Err(format!("invalid virtual vertex {vertex_index}"));
+            }
+            is_virtual[vertex_index] = true;
+        }
+        if self.virtual_vertices.is_empty() || self.virtual_vertices.len() == self.vertex_num {
+            return Err("requires both virtual and non-virtual vertices".to_string());
+        }
+        let mut edges = BTreeSet::new();
+        for &(left, right, weight) in self.weighted_edges.iter() {
+            if left >= self.vertex_num || right >= self.vertex_num || left == right {
+                return Err(format!("invalid edge ({left}, {right})"));
+            }
+            if is_virtual[left] && is_virtual[right] {
+                return Err(format!("edge ({left}, {right}) between virtual vertices"));
+            }
+            if weight <= 0 || weight % 2 != 0 {
+                return Err(format!("edge ({left}, {right}) has invalid weight {weight}"));
+            }
+            if !edges.insert(sorted_pair(left, right)) {
+                return Err(format!("duplicate edge ({left}, {right})"));
+            }
+        }
+        let mut layer_of = vec![None; self.vertex_num];
+        for (layer_id, layer) in self.layers.iter().enumerate() {
+            if layer.is_empty() {
+                return Err(format!("layer {layer_id} is empty"));
+            }
+            for &vertex_index in layer.iter() {
+                if vertex_index >= self.vertex_num || is_virtual[vertex_index] || layer_of[vertex_index].is_some() {
+                    return Err(format!("invalid vertex {vertex_index} in layer {layer_id}"));
+                }
+                layer_of[vertex_index] = Some(layer_id);
+            }
+        }
+        if let Some(vertex_index) = (0..self.vertex_num).find(|&i| !is_virtual[i] && layer_of[i].is_none()) {
+            return Err(format!("vertex {vertex_index} is not assigned to any layer"));
+        }
+        let mut is_defect = vec![false; self.vertex_num];
+        for &vertex_index in self.defect_vertices.iter() {
+            if vertex_index >= self.vertex_num || is_virtual[vertex_index] || is_defect[vertex_index] {
+                return Err(format!("invalid defect vertex {vertex_index}"));
+            }
+            is_defect[vertex_index] = true;
+        }
+        // connectivity by union-find
+        let mut parent: Vec<usize> = (0..self.vertex_num).collect();
+        fn find(parent: &mut [usize], mut index: usize) -> usize {
+            while parent[index] != index {
+                parent[index] = parent[parent[index]];
+                index = parent[index];
+            }
+            index
+        }
+        for &(left, right, _) in self.weighted_edges.iter() {
+            let (left, right) = (find(&mut parent, left), find(&mut parent, right));
+            parent[left] = right;
+        }
+        let root = find(&mut parent, 0);
+        if (0..self.vertex_num).any(|i| find(&mut parent, i) != root) {
+            return Err("the graph is not connected".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn get_initializer(&self) -> SolverInitializer {
+        SolverInitializer::new(self.vertex_num, self.weighted_edges.clone(), self.virtual_vertices.clone())
+    }
+
+    /// the graph configuration with the layer fusion plan of this case
+    pub fn get_graph(&self) -> MicroBlossomSingle {
+        let positions: Vec<_> = (0..self.vertex_num)
+            .map(|vertex_index| VisualizePosition::new(vertex_index as f64, 0., 0.))
+            .collect();
+        let mut graph = MicroBlossomSingle::new(&self.get_initializer(), &positions);
+        graph
+            .set_layer_fusion_plan(&LayerFusionPlan {
+                layers: self.layers.clone(),
+            })
+            .unwrap();
+        graph
+    }
+
+    pub fn get_syndrome(&self) -> SyndromePattern {
+        SyndromePattern::new_vertices(self.defect_vertices.clone())
+    }
+
+    /// the minimum weight of a perfect matching of the defects, where each defect can also be matched to its nearest
+    /// virtual vertex, computed by the `mwmatching` crate on the complete graph of shortest paths
+    pub fn reference_weight(&self) -> Weight {
+        let mut graph = UnGraph::<usize, Weight>::new_undirected();
+        let node_indices: Vec<_> = (0..self.vertex_num)
+            .map(|vertex_index| graph.add_node(vertex_index))
+            .collect();
+        for &(left, right, weight) in self.weighted_edges.iter() {
+            graph.add_edge(node_indices[left], node_indices[right], weight);
+        }
+        let distance = floyd_warshall(&graph, |edge| *edge.weight()).unwrap();
+        let distance = |i: VertexIndex, j: VertexIndex| *distance.get(&(node_indices[i], node_indices[j])).unwrap();
+        // defect `i` has a boundary copy `defect_num + i`; the boundary copies can be matched with each other freely
+        let defect_num = self.defect_vertices.len();
+        let boundary: Vec<Weight> = self
+            .defect_vertices
+            .iter()
+            .map(|&defect| self.virtual_vertices.iter().map(|&v| distance(defect, v)).min().unwrap())
+            .collect();
+        let mut edges = vec![];
+        for (i, &defect_i) in self.defect_vertices.iter().enumerate() {
+            for (j, &defect_j) in self.defect_vertices.iter().enumerate().skip(i + 1) {
+                edges.push((i, j, -distance(defect_i, defect_j) as i32));
+                edges.push((defect_num + i, defect_num + j, 0));
+            }
+            edges.push((i, defect_num + i, -boundary[i] as i32));
+        }
+        let mates = Matching::new(edges).max_cardinality().solve();
+        let mut total_weight = 0;
+        for (i, &j) in mates.iter().enumerate().take(defect_num) {
+            assert_ne!(j, usize::MAX, "the reference matching is not perfect");
+            if j == defect_num + i {
+                total_weight += boundary[i];
+            } else if i < j {
+                total_weight += distance(self.defect_vertices[i], self.defect_vertices[j]);
+            }
+        }
+        total_weight
+    }
+
+    /// the case without a vertex; the remaining vertices are re-indexed
+    fn remove_vertex(&self, removed: VertexIndex) -> Self {
+        let map = |vertex_index: VertexIndex| {
+            if vertex_index > removed {
+                vertex_index - 1
+            } else {
+                vertex_index
+            }
+        };
+        let map_vertices = |vertices: &[VertexIndex]| -> Vec<VertexIndex> {
+            vertices.iter().filter(|&&i| i != removed).map(|&i| map(i)).collect()
+        };
+        Self {
+            vertex_num: self.vertex_num - 1,
+            weighted_edges: self
+                .weighted_edges
+                .iter()
+                .filter(|&&(left, right, _)| left != removed && right != removed)
+                .map(|&(left, right, weight)| (map(left), map(right), weight))
+                .collect(),
+            virtual_vertices: map_vertices(&self.virtual_vertices),
+            layers: self
+                .layers
+                .iter()
+                .map(|layer| map_vertices(layer))
+                .filter(|layer| !layer.is_empty())
+                .collect(),
+            defect_vertices: map_vertices(&self.defect_vertices),
+        }
+    }
+
+    /// the case with a non-virtual vertex turned into a virtual one, dropping its edges to other virtual vertices
+    fn make_virtual(&self, vertex_index: VertexIndex) -> Option<Self> {
+        if self.virtual_vertices.contains(&vertex_index) || self.defect_vertices.contains(&vertex_index) {
+            return None;
+        }
+        let mut candidate = self.clone();
+        let is_virtual = |i: VertexIndex| self.virtual_vertices.contains(&i);
+        candidate.weighted_edges.retain(|&(left, right, _)| {
+            !(left == vertex_index && is_virtual(right) || right == vertex_index && is_virtual(left))
+        });
+        candidate.virtual_vertices.push(vertex_index);
+        candidate.virtual_vertices.sort();
+        for layer in candidate.layers.iter_mut() {
+            layer.retain(|&i| i != vertex_index);
+        }
+        candidate.layers.retain(|layer| !layer.is_empty());
+        Some(candidate)
+    }
+
+    /// the smaller cases to try during shrinking, roughly from the largest reduction to the smallest
+    fn shrink_candidates(&self) -> Vec<Self> {
+        let mut candidates = vec![];
+        for vertex_index in 0..self.vertex_num {
+            candidates.push(self.remove_vertex(vertex_index));
+        }
+        for vertex_index in 0..self.vertex_num {
+            if let Some(candidate) = self.make_virtual(vertex_index) {
+                candidates.push(candidate);
+            }
+        }
+        for edge_index in 0..self.weighted_edges.len() {
+            let mut candidate = self.clone();
+            candidate.weighted_edges.remove(edge_index);
+            candidates.push(candidate);
+        }
+        for defect_index in 0..self.defect_vertices.len() {
+            let mut candidate = self.clone();
+            candidate.defect_vertices.remove(defect_index);
+            candidates.push(candidate);
+        }
+        for layer_id in 1..self.layers.len() {
+            let mut candidate = self.clone();
+            let layer = candidate.layers.remove(layer_id);
+            candidate.layers[layer_id - 1].extend(layer);
+            candidate.layers[layer_id - 1].sort();
+            candidates.push(candidate);
+        }
+        for edge_index in 0..self.weighted_edges.len() {
+            let weight = self.weighted_edges[edge_index].2;
+            let smaller_weights: BTreeSet<Weight> = [2, weight / 4 * 2, weight - 2].into_iter().collect();
+            for smaller_weight in smaller_weights {
+                if smaller_weight >= 2 && smaller_weight < weight {
+                    let mut candidate = self.clone();
+                    candidate.weighted_edges[edge_index].2 = smaller_weight;
+                    candidates.push(candidate);
+                }
+            }
+        }
+        candidates
+    }
+}
+
+fn sorted_pair(a: VertexIndex, b: VertexIndex) -> (VertexIndex, VertexIndex) {
+    (std::cmp::min(a, b), std::cmp::max(a, b))
+}
+
+/// the name and the (`support_offloading`, `support_layer_fusion`) of every tested combination, as in `cli::TestCommands`
+pub const EMBEDDED_COMB_COMBINATIONS: [(&str, bool, bool); 4] = [
+    ("embedded-comb", false, false),
+    ("embedded-comb-pre-matching", true, false),
+    ("embedded-comb-layer-fusion", false, true),
+    ("embedded-comb-pre-matching-layer-fusion", true, true),
+];
+
+/// check that every backend corrects the syndrome with the minimum weight; a panic is also reported as a failure
+pub fn check_case(case: &FuzzCase) -> Result<(), String> {
+    case.sanity_check().map_err(|error| format!("invalid case: {error}"))?;
+    match catch_unwind(AssertUnwindSafe(|| check_case_inner(case))) {
+        Ok(result) => result,
+        Err(payload) => Err(if let Some(message) = payload.downcast_ref::<&str>() {
+            format!("panic: {message}")
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            format!("panic: {message}")
+        } else {
+            "panic".to_string()
+        }),
+    }
+}
+
+fn check_case_inner(case: &FuzzCase) -> Result<(), String> {
+    let initializer = case.get_initializer();
+    let syndrome = case.get_syndrome();
+    let expected_defects: BTreeSet<VertexIndex> = case.defect_vertices.iter().cloned().collect();
+    let expected_weight = case.reference_weight();
+    let mut subgraph_builder = SubGraphBuilder::new(&initializer);
+    let mut check = |name: &str, subgraph: &[EdgeIndex], sum_dual_variables: Weight| -> Result<(), String> {
+        if initializer.syndrome_of(subgraph) != expected_defects {
+            return Err(format!("{name}: the subgraph {subgraph:?} does not correct the syndrome"));
+        }
+        subgraph_builder.clear();
+        subgraph_builder.load_subgraph(subgraph);
+        let total_weight = subgraph_builder.total_weight();
+        if total_weight != expected_weight {
+            return Err(format!(
+                "{name}: matching weight {total_weight} != reference {expected_weight}"
+            ));
+        }
+        if sum_dual_variables != expected_weight {
+            return Err(format!(
+                "{name}: sum of dual variables {sum_dual_variables} != reference {expected_weight}"
+            ));
+        }
+        Ok(())
+    };
+    let mut serial = SolverSerial::new(&initializer);
+    serial.solve(&syndrome);
+    let subgraph = serial.subgraph();
+    check("serial", &subgraph, serial.sum_dual_variables())?;
+    let graph = case.get_graph();
+    for (name, support_offloading, support_layer_fusion) in EMBEDDED_COMB_COMBINATIONS {
+        let mut solver = SolverEmbeddedComb::new(
+            graph.clone(),
+            json!({
+                "dual": {
+                    "sim_config": {
+                        "support_offloading": support_offloading,
+                        "support_layer_fusion": support_layer_fusion,
+                    }
+                }
+            }),
+        );
+        solver.try_solve(&syndrome).map_err(|error| format!("{name}: {error}"))?;
+        let subgraph = solver.subgraph();
+        check(name, &subgraph, solver.sum_dual_variables())?;
+    }
+    Ok(())
+}
+
+/// greedily shrink a case while it keeps failing, until none of the smaller valid cases fails
+pub fn shrink_case(mut case: FuzzCase, mut is_failing: impl FnMut(&FuzzCase) -> bool) -> FuzzCase {
+    'shrink: loop {
+        for candidate in case.shrink_candidates() {
+            if candidate.sanity_check().is_ok() && is_failing(&candidate) {
+                case = candidate;
+                continue 'shrink;
+            }
+        }
+        return case;
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FuzzFailure {
+    pub seed: u64,
+    /// the failure of the shrunk case
+    pub reason: String,
+    pub case: FuzzCase,
+}
+
+impl FuzzFailure {
+    /// save the shrunk case as a regression file in the folder, returning the file path
+    pub fn save_regression(&self, folder: impl AsRef<Path>) -> std::io::Result<PathBuf> {
+        std::fs::create_dir_all(folder.as_ref())?;
+        let path = folder.as_ref().join(format!("seed_{}.json", self.seed));
+        std::fs::write(&path, serde_json::to_string(&self.case).unwrap())?;
+        Ok(path)
+    }
+}
+
+/// run the cases generated from the seeds and shrink every failing case
+pub fn fuzz(config: &FuzzConfig, seeds: impl IntoIterator<Item = u64>) -> Vec<FuzzFailure> {
+    let mut failures = vec![];
+    for seed in seeds {
+        let case = FuzzCase::random(config, seed);
+        if check_case(&case).is_ok() {
+            continue;
+        }
+        let case = shrink_case(case, |candidate| check_case(candidate).is_err());
+        let reason = check_case(&case).unwrap_err();
+        failures.push(FuzzFailure { seed, reason, case });
+    }
+    failures
+}
+
+/// load all the regression cases in the folder, sorted by the file name; empty if the folder does not exist
+pub fn load_regressions(folder: impl AsRef<Path>) -> Vec<(PathBuf, FuzzCase)> {
+    let Ok(entries) = std::fs::read_dir(folder) else {
+        return vec![];
+    };
+    let mut paths: Vec<_> = entries
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let case = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            (path, case)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzz_reference_weight() {
+        // cargo test fuzz_reference_weight -- --nocapture
+        // 0 - 1 - 2 - 3 with virtual vertex 0 and 3
+        let case = FuzzCase {
+            vertex_num: 4,
+            weighted_edges: vec![(0, 1, 2), (1, 2, 10), (2, 3, 4)],
+            virtual_vertices: vec![0, 3],
+            layers: vec![vec![1, 2]],
+            defect_vertices: vec![1, 2],
+        };
+        assert_eq!(case.sanity_check(), Ok(()));
+        assert_eq!(case.reference_weight(), 6);
+        assert_eq!(check_case(&case), Ok(()));
+    }
+
+    #[test]
+    fn fuzz_random_cases() {
+        // cargo test fuzz_random_cases -- --nocapture
+        let config = FuzzConfig::default();
+        for seed in 0..20 {
+            let case = FuzzCase::random(&config, seed);
+            assert_eq!(case.sanity_check(), Ok(()));
+        }
+        let failures = fuzz(&config, 0..20);
+        for failure in failures.iter() {
+            println!("{}", serde_json::to_string(failure).unwrap());
+        }
+        assert!(failures.is_empty());
+    }
+
+    #[test]
+    fn fuzz_shrink_case() {
+        // cargo test fuzz_shrink_case -- --nocapture
+        let config = FuzzConfig {
+            min_vertex_num: 10,
+            ..Default::default()
+        };
+        let case = FuzzCase::random(&config, 0);
+        // an artificial failure: some defect is adjacent to a heavy edge
+        let is_failing = |case: &FuzzCase| {
+            case.weighted_edges.iter().any(|&(left, right, weight)| {
+                weight >= 4 && (case.defect_vertices.contains(&left) || case.defect_vertices.contains(&right))
+            })
+        };
+        assert!(is_failing(&case), "choose another seed");
+        let shrunk = shrink_case(case, is_failing);
+        println!("{}", serde_json::to_string(&shrunk).unwrap());
+        assert_eq!(shrunk.sanity_check(), Ok(()));
+        assert!(is_failing(&shrunk));
+        assert_eq!(shrunk.vertex_num, 2);
+        assert_eq!(shrunk.weighted_edges.len(), 1);
+        assert_eq!(shrunk.weighted_edges[0].2, 4);
+        assert_eq!(shrunk.defect_vertices.len(), 1);
+    }
+
+    #[test]
+    fn fuzz_regressions() {
+        // cargo test fuzz_regressions -- --nocapture
+        for (path, case) in load_regressions(REGRESSION_FOLDER) {
+            println!("{}", path.display());
+            assert_eq!(check_case(&case), Ok(()), "{}", path.display());
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Graph Export
+//!
+//! Export a [`MicroBlossomSingle`] graph to Graphviz DOT or GraphML, so that it can be inspected with standard
+//! graph tools. Virtual vertices, offloading edges and fusion edges are annotated.
+//!
+
+use crate::resources::*;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+pub enum GraphExportFormat {
+    Dot,
+    #[value(name = "graphml")]
+    GraphML,
+}
+
+/// the annotations of each edge collected from the offloading and layer fusion settings
+struct EdgeAnnotation {
+    /// the short names of the offloading types on this edge, e.g. "dm", "vm", "fm", "bm"
+    offloading: Vec<&'static str>,
+    /// the conditioned vertex if this edge crosses the fusion boundary
+    conditioned_vertex: Option<usize>,
+}
+
+/// fails if an offloader or a fusion edge refers to an edge that does not exist
+fn edge_annotations(graph: &MicroBlossomSingle) -> Result<Vec<EdgeAnnotation>, String> {
+    let mut annotations: Vec<_> = (0..graph.weighted_edges.len())
+        .map(|_| EdgeAnnotation {
+            offloading: vec![],
+            conditioned_vertex: None,
+        })
+        .collect();
+    let edge_num = annotations.len();
+    for offloader in graph.offloading.0.iter() {
+        let edge_index = offloader.edge_index();
+        let annotation = annotations
+            .get_mut(edge_index)
+            .ok_or_else(|| format!("offloader {} on edge {edge_index} of {edge_num} edges", offloader.name()))?;
+        annotation.offloading.push(offloader.name());
+    }
+    if let Some(layer_fusion) = graph.layer_fusion.as_ref() {
+        for (&edge_index, &conditioned_vertex) in layer_fusion.fusion_edges.iter() {
+            let annotation = annotations
+                .get_mut(edge_index)
+                .ok_or_else(|| format!("fusion edge {edge_index} of {edge_num} edges"))?;
+            annotation.conditioned_vertex = Some(conditioned_vertex);
+        }
+    }
+    Ok(annotations)
+}
+
+fn vertex_layer_ids(graph: &MicroBlossomSingle) -> BTreeMap<usize, usize> {
+    graph
+        .layer_fusion
+        .as_ref()
+        .map(|layer_fusion| layer_fusion.vertex_layer_id.clone())
+        .unwrap_or_default()
+}
+
+impl MicroBlossomSingle {
+    /// the graph does not need to pass the validator, but its offloaders and fusion edges must refer to existing edges
+    pub fn export(&self, format: GraphExportFormat) -> Result<String, String> {
+        match format {
+            GraphExportFormat::Dot => self.to_dot(),
+            GraphExportFormat::GraphML => self.to_graphml(),
+        }
+    }
+
+    /// virtual vertices are boxes; offloading edges are bold and fusion edges are dashed with an arrow towards the
+    /// conditioned vertex; the positions are kept for the `neato` layout engine
+    pub fn to_dot(&self) -> Result<String, String> {
+        let vertex_layer_id = vertex_layer_ids(self);
+        let mut dot = String::new();
+        writeln!(dot, "graph micro_blossom {{").unwrap();
+        writeln!(dot, "    node [shape=circle];").unwrap();
+        for (vertex_index, position) in self.positions.iter().enumerate() {
+            let mut attributes = vec![format!("pos=\"{},{}!\"", position.j, 0. - position.i)]; // flip i downwards without printing "-0"
+            if self.virtual_vertices.contains(&vertex_index) {
+                attributes.push("shape=box".to_string());
+                attributes.push("style=filled".to_string());
+                attributes.push("fillcolor=lightgrey".to_string());
+            }
+            if let Some(layer_id) = vertex_layer_id.get(&vertex_index) {
+                attributes.push(format!("tooltip=\"layer {layer_id}\""));
+            }
+            writeln!(dot, "    {vertex_index} [{}];", attributes.join(", ")).unwrap();
+        }
+        for (edge, annotation) in self.weighted_edges.iter().zip(edge_annotations(self)?) {
+            let mut label = edge.w.to_string();
+            if !annotation.offloading.is_empty() {
+                label = format!("{label} ({})", annotation.offloading.join(","));
+            }
+            let mut attributes = vec![format!("label=\"{label}\"")];
+            let mut styles = vec![];
+            if !annotation.offloading.is_empty() {
+                styles.push("bold");
+            }
+            if annotation.conditioned_vertex.is_some() {
+                styles.push("dashed");
+            }
+            if !styles.is_empty() {
+                attributes.push(format!("style=\"{}\"", styles.join(",")));
+            }
+            if let Some(conditioned_vertex) = annotation.conditioned_vertex {
+                attributes.push("color=blue".to_string());
+                attributes.push(if conditioned_vertex == edge.r {
+                    "dir=forward".to_string()
+                } else {
+                    "dir=back".to_string()
+                });
+            }
+            writeln!(dot, "    {} -- {} [{}];", edge.l, edge.r, attributes.join(", ")).unwrap();
+        }
+        writeln!(dot, "}}").unwrap();
+        Ok(dot)
+    }
+
+    pub fn to_graphml(&self) -> Result<String, String> {
+        let vertex_layer_id = vertex_layer_ids(self);
+        let annotations = edge_annotations(self)?;
+        let mut xml = String::new();
+        writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+        writeln!(xml, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#).unwrap();
+        for (id, domain, name, data_type) in [
+            ("i", "node", "i", "double"),
+            ("j", "node", "j", "double"),
+            ("t", "node", "t", "double"),
+            ("virtual", "node", "virtual", "boolean"),
+            ("layer", "node", "layer", "int"),
+            ("weight", "edge", "weight", "long"),
+            ("offloading", "edge", "offloading", "string"),
+            ("conditioned", "edge", "conditioned_vertex", "int"),
+        ] {
+            writeln!(
+                xml,
+                r#"  <key id="{id}" for="{domain}" attr.name="{name}" attr.type="{data_type}"/>"#
+            )
+            .unwrap();
+        }
+        writeln!(xml, r#"  <graph id="micro_blossom" edgedefault="undirected">"#).unwrap();
+        for (vertex_index, position) in self.positions.iter().enumerate() {
+            writeln!(xml, r#"    <node id="v{vertex_index}">"#).unwrap();
+            writeln!(xml, r#"      <data key="i">{}</data>"#, position.i).unwrap();
+            writeln!(xml, r#"      <data key="j">{}</data>"#, position.j).unwrap();
+            writeln!(xml, r#"      <data key="t">{}</data>"#, position.t).unwrap();
+            let is_virtual = self.virtual_vertices.contains(&vertex_index);
+            writeln!(xml, r#"      <data key="virtual">{is_virtual}</data>"#).unwrap();
+            if let Some(layer_id) = vertex_layer_id.get(&vertex_index) {
+                writeln!(xml, r#"      <data key="layer">{layer_id}</data>"#).unwrap();
+            }
+            writeln!(xml, "    </node>").unwrap();
+        }
+        for (edge_index, (edge, annotation)) in self.weighted_edges.iter().zip(annotations).enumerate() {
+            writeln!(
+                xml,
+                r#"    <edge id="e{edge_index}" source="v{}" target="v{}">"#,
+                edge.l, edge.r
+            )
+            .unwrap();
+            writeln!(xml, r#"      <data key="weight">{}</data>"#, edge.w).unwrap();
+            if !annotation.offloading.is_empty() {
+                writeln!(
+                    xml,
+                    r#"      <data key="offloading">{}</data>"#,
+                    annotation.offloading.join(",")
+                )
+                .unwrap();
+            }
+            if let Some(conditioned_vertex) = annotation.conditioned_vertex {
+                writeln!(xml, r#"      <data key="conditioned">{conditioned_vertex}</data>"#).unwrap();
+            }
+            writeln!(xml, "    </edge>").unwrap();
+        }
+        writeln!(xml, "  </graph>").unwrap();
+        writeln!(xml, "</graphml>").unwrap();
+        Ok(xml)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn graph_export_annotations() {
+        // cargo test graph_export_annotations -- --nocapture
+        let code = PhenomenologicalPlanarCode::new(3, 1, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let layer_fusion = graph.layer_fusion.as_ref().unwrap();
+        let dot = graph.to_dot().unwrap();
+        println!("{dot}");
+        assert!(dot.starts_with("graph micro_blossom {"));
+        assert_eq!(dot.matches("shape=box").count(), graph.virtual_vertices.len());
+        assert_eq!(dot.matches(" -- ").count(), graph.weighted_edges.len());
+        assert_eq!(dot.matches("dashed\"").count(), layer_fusion.fusion_edges.len());
+        assert!(dot.lines().all(|line| line.matches("style=").count() <= 1));
+        assert!(dot.contains("(dm)") && dot.contains("(vm)"));
+        let graphml = graph.export(GraphExportFormat::GraphML).unwrap();
+        assert_eq!(graphml.matches("<node ").count(), graph.vertex_num);
+        assert_eq!(graphml.matches("<edge ").count(), graph.weighted_edges.len());
+        assert_eq!(
+            graphml.matches(r#"<data key="virtual">true</data>"#).count(),
+            graph.virtual_vertices.len()
+        );
+        assert_eq!(
+            graphml.matches(r#"<data key="conditioned">"#).count(),
+            layer_fusion.fusion_edges.len()
+        );
+        // a fusion edge that does not exist is reported instead of panicking
+        let mut broken = graph.clone();
+        broken.weighted_edges.pop();
+        assert!(broken.to_dot().is_err());
+        assert!(broken.to_graphml().is_err());
+    }
+}
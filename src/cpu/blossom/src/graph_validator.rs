@@ -0,0 +1,479 @@
+//! Graph Validator
+//!
+//! Check a [`MicroBlossomSingle`] graph before it goes into the hardware generator, so that a broken graph
+//! file is reported with a clear reason instead of failing deep inside the Scala build or as wrong matchings.
+//!
+
+use crate::resources::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap};
+use std::fmt;
+
+/// the compile-time options of the embedded primal module that limit the bit widths
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GraphValidatorConfig {
+    /// vertex and node indices are 16 bits
+    pub u16_index: bool,
+    /// weights are 16 bits signed integers
+    pub i16_weight: bool,
+    /// layer ids are 8 bits
+    pub u8_layer_id: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationCheck {
+    pub name: String,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationReport {
+    pub checks: Vec<ValidationCheck>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.checks.iter().all(|check| check.errors.is_empty())
+    }
+
+    fn add(&mut self, name: &str, errors: Vec<String>) {
+        self.checks.push(ValidationCheck {
+            name: name.to_string(),
+            errors,
+        });
+    }
+}
+
+/// print at most this number of errors for each check
+const REPORT_MAX_ERRORS: usize = 10;
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in self.checks.iter() {
+            if check.errors.is_empty() {
+                writeln!(f, "[ok]   {}", check.name)?;
+                continue;
+            }
+            writeln!(f, "[FAIL] {}: {} error(s)", check.name, check.errors.len())?;
+            for error in check.errors.iter().take(REPORT_MAX_ERRORS) {
+                writeln!(f, "       {error}")?;
+            }
+            if check.errors.len() > REPORT_MAX_ERRORS {
+                writeln!(f, "       ... and {} more", check.errors.len() - REPORT_MAX_ERRORS)?;
+            }
+        }
+        let failed = self.checks.iter().filter(|check| !check.errors.is_empty()).count();
+        if failed == 0 {
+            write!(f, "all {} checks passed", self.checks.len())
+        } else {
+            write!(f, "{failed} of {} checks failed", self.checks.len())
+        }
+    }
+}
+
+/// run all the checks; the checks that depend on a valid edge list are skipped if the edge list is broken
+pub fn validate_graph(graph: &MicroBlossomSingle, config: &GraphValidatorConfig) -> ValidationReport {
+    let mut report = ValidationReport { checks: vec![] };
+    report.add("vertices and edges", check_vertices_edges(graph));
+    if !report.is_valid() {
+        return report;
+    }
+    let edge_num = graph.weighted_edges.len();
+    report.add("vertex binary tree", graph.vertex_binary_tree.validate(graph.vertex_num));
+    report.add("edge binary tree", graph.edge_binary_tree.validate(edge_num));
+    report.add(
+        "vertex edge binary tree",
+        graph.vertex_edge_binary_tree.validate(graph.vertex_num + edge_num),
+    );
+    report.add("weights", check_weights(graph, config));
+    report.add("vertex max growth", check_vertex_max_growth(graph));
+    report.add("index bit widths", check_index_bit_widths(graph, config));
+    report.add("offloading", check_offloading(graph));
+    report.add("layer fusion", check_layer_fusion(graph, config));
+    report.add("parity reporters", check_parity_reporters(graph));
+    report
+}
+
+fn check_vertices_edges(graph: &MicroBlossomSingle) -> Vec<String> {
+    let mut errors = vec![];
+    if graph.positions.len() != graph.vertex_num {
+        errors.push(format!(
+            "{} positions for {} vertices",
+            graph.positions.len(),
+            graph.vertex_num
+        ));
+    }
+    for &vertex_index in graph.virtual_vertices.iter() {
+        if vertex_index >= graph.vertex_num {
+            errors.push(format!("virtual vertex {vertex_index} does not exist"));
+        }
+    }
+    for (edge_index, edge) in graph.weighted_edges.iter().enumerate() {
+        if edge.l >= graph.vertex_num || edge.r >= graph.vertex_num {
+            errors.push(format!("edge {edge_index} ({}, {}) is out of range", edge.l, edge.r));
+        } else if edge.l == edge.r {
+            errors.push(format!("edge {edge_index} is a self loop on vertex {}", edge.l));
+        }
+    }
+    errors
+}
+
+fn check_weights(graph: &MicroBlossomSingle, config: &GraphValidatorConfig) -> Vec<String> {
+    let max_weight = if config.i16_weight {
+        i16::MAX as isize
+    } else {
+        i32::MAX as isize
+    };
+    let mut errors = vec![];
+    for (edge_index, edge) in graph.weighted_edges.iter().enumerate() {
+        if edge.w < 0 || edge.w % 2 != 0 {
+            errors.push(format!(
+                "edge {edge_index} has weight {}, expected a non-negative even number",
+                edge.w
+            ));
+        } else if edge.w > max_weight {
+            errors.push(format!("edge {edge_index} has weight {} larger than {max_weight}", edge.w));
+        }
+    }
+    for (vertex_index, &max_growth) in graph.vertex_max_growth.iter().enumerate() {
+        if max_growth > max_weight {
+            errors.push(format!(
+                "vertex {vertex_index} has maximum growth {max_growth} larger than {max_weight}"
+            ));
+        }
+    }
+    errors
+}
+
+/// the distance from every vertex to the nearest virtual vertex, `None` if not reachable
+pub fn nearest_virtual_distance(graph: &MicroBlossomSingle) -> Vec<Option<isize>> {
+    let mut adjacency = vec![vec![]; graph.vertex_num];
+    for edge in graph.weighted_edges.iter() {
+        adjacency[edge.l].push((edge.r, edge.w));
+        adjacency[edge.r].push((edge.l, edge.w));
+    }
+    let mut distance = vec![None; graph.vertex_num];
+    let mut heap = BinaryHeap::new();
+    for &vertex_index in graph.virtual_vertices.iter() {
+        heap.push(Reverse((0, vertex_index)));
+    }
+    while let Some(Reverse((current, vertex_index))) = heap.pop() {
+        if distance[vertex_index].is_some() {
+            continue;
+        }
+        distance[vertex_index] = Some(current);
+        for &(peer, weight) in adjacency[vertex_index].iter() {
+            if distance[peer].is_none() {
+                heap.push(Reverse((current + weight, peer)));
+            }
+        }
+    }
+    distance
+}
+
+fn check_vertex_max_growth(graph: &MicroBlossomSingle) -> Vec<String> {
+    if graph.vertex_max_growth.len() != graph.vertex_num {
+        return vec![format!(
+            "{} values for {} vertices",
+            graph.vertex_max_growth.len(),
+            graph.vertex_num
+        )];
+    }
+    let mut errors = vec![];
+    for (vertex_index, distance) in nearest_virtual_distance(graph).into_iter().enumerate() {
+        let max_growth = graph.vertex_max_growth[vertex_index];
+        match distance {
+            // a smaller value stops the growth before the vertex is able to match with a virtual vertex
+            Some(distance) if max_growth < distance => errors.push(format!(
+                "vertex {vertex_index} has maximum growth {max_growth} but the nearest virtual vertex is {distance} away"
+            )),
+            _ if max_growth < 0 => errors.push(format!("vertex {vertex_index} has negative maximum growth")),
+            _ => {}
+        }
+    }
+    errors
+}
+
+fn check_index_bit_widths(graph: &MicroBlossomSingle, config: &GraphValidatorConfig) -> Vec<String> {
+    let mut errors = vec![];
+    // the maximum value is reserved for `None`
+    let max_index = if config.u16_index {
+        u16::MAX as usize - 1
+    } else {
+        u32::MAX as usize - 1
+    };
+    if graph.vertex_num > max_index {
+        errors.push(format!(
+            "{} vertices do not fit in indices up to {max_index}",
+            graph.vertex_num
+        ));
+    }
+    if graph.weighted_edges.len() > u32::MAX as usize {
+        errors.push(format!("{} edges do not fit in 32 bits", graph.weighted_edges.len()));
+    }
+    errors
+}
+
+fn check_offloading(graph: &MicroBlossomSingle) -> Vec<String> {
+    let virtual_vertices: BTreeSet<usize> = graph.virtual_vertices.iter().cloned().collect();
+    let edge_num = graph.weighted_edges.len();
+    let mut errors = vec![];
+    for (offloader_index, offloader) in graph.offloading.0.iter().enumerate() {
+        let edge_index = offloader.edge_index();
+        if edge_index >= edge_num {
+            errors.push(format!(
+                "offloader {offloader_index} refers to edge {edge_index} out of range"
+            ));
+            continue;
+        }
+        if !offloader.is_hardware_supported() {
+            errors.push(format!(
+                "offloader {offloader_index} of type {} is not supported by the hardware",
+                offloader.name()
+            ));
+        }
+        let edge = &graph.weighted_edges[edge_index];
+        let is_virtual = |vertex_index: usize| virtual_vertices.contains(&vertex_index);
+        match offloader {
+            OffloadingType::DefectMatch { .. } => {
+                if is_virtual(edge.l) || is_virtual(edge.r) {
+                    errors.push(format!(
+                        "defect match {offloader_index} on edge {edge_index} touches a virtual vertex"
+                    ));
+                }
+            }
+            &OffloadingType::VirtualMatch { virtual_vertex, .. } => {
+                let peer = if edge.l == virtual_vertex { edge.r } else { edge.l };
+                if (edge.l != virtual_vertex && edge.r != virtual_vertex) || !is_virtual(virtual_vertex) || is_virtual(peer)
+                {
+                    errors.push(format!(
+                        "virtual match {offloader_index} on edge {edge_index} does not connect a regular vertex to virtual vertex {virtual_vertex}"
+                    ));
+                }
+            }
+            &OffloadingType::FusionMatch { conditioned_vertex, .. } => {
+                if edge.l != conditioned_vertex && edge.r != conditioned_vertex {
+                    errors.push(format!(
+                        "fusion match {offloader_index} on edge {edge_index} is conditioned on vertex {conditioned_vertex} outside the edge"
+                    ));
+                }
+            }
+            &OffloadingType::BoundaryDefectMatch { boundary_edge_index, .. } => {
+                let Some(boundary_edge) = graph.weighted_edges.get(boundary_edge_index) else {
+                    errors.push(format!(
+                        "boundary defect match {offloader_index} refers to boundary edge {boundary_edge_index} out of range"
+                    ));
+                    continue;
+                };
+                let shared = [boundary_edge.l, boundary_edge.r]
+                    .into_iter()
+                    .find(|&vertex_index| vertex_index == edge.l || vertex_index == edge.r);
+                let is_valid = !is_virtual(edge.l)
+                    && !is_virtual(edge.r)
+                    && shared.is_some_and(|shared| {
+                        let peer = if boundary_edge.l == shared {
+                            boundary_edge.r
+                        } else {
+                            boundary_edge.l
+                        };
+                        is_virtual(peer)
+                    });
+                if !is_valid {
+                    errors.push(format!(
+                        "boundary defect match {offloader_index} on edge {edge_index} is not next to boundary edge {boundary_edge_index}"
+                    ));
+                }
+            }
+            &OffloadingType::ChainDefectMatch { chain_edge_index, .. } => {
+                let Some(chain_edge) = graph.weighted_edges.get(chain_edge_index) else {
+                    errors.push(format!(
+                        "chain defect match {offloader_index} refers to chain edge {chain_edge_index} out of range"
+                    ));
+                    continue;
+                };
+                let shared_count = [chain_edge.l, chain_edge.r]
+                    .into_iter()
+                    .filter(|&vertex_index| vertex_index == edge.l || vertex_index == edge.r)
+                    .count();
+                let is_valid = [edge.l, edge.r, chain_edge.l, chain_edge.r]
+                    .into_iter()
+                    .all(|vertex_index| !is_virtual(vertex_index))
+                    && shared_count == 1;
+                if !is_valid {
+                    errors.push(format!(
+                        "chain defect match {offloader_index} on edge {edge_index} does not continue with chain edge {chain_edge_index}"
+                    ));
+                }
+            }
+        }
+    }
+    errors
+}
+
+fn check_layer_fusion(graph: &MicroBlossomSingle, config: &GraphValidatorConfig) -> Vec<String> {
+    let Some(layer_fusion) = graph.layer_fusion.as_ref() else {
+        return vec![];
+    };
+    let mut errors = vec![];
+    if layer_fusion.num_layers != layer_fusion.layers.len() {
+        errors.push(format!(
+            "num_layers = {} but there are {} layers",
+            layer_fusion.num_layers,
+            layer_fusion.layers.len()
+        ));
+    }
+    if config.u8_layer_id && layer_fusion.num_layers >= u8::MAX as usize {
+        errors.push(format!("{} layers do not fit in 8 bits", layer_fusion.num_layers));
+    }
+    // the layers must form a valid plan, and the maps must be the ones derived from it
+    let plan = LayerFusionPlan {
+        layers: layer_fusion.layers.clone(),
+    };
+    match LayerFusion::from_plan(graph, &plan) {
+        Err(error) => errors.push(error),
+        Ok(derived) => {
+            if derived.vertex_layer_id != layer_fusion.vertex_layer_id {
+                errors.push("vertex_layer_id does not match the layers".to_string());
+            }
+            for (edge_index, conditioned_vertex) in layer_fusion.fusion_edges.iter() {
+                if derived.fusion_edges.get(edge_index) != Some(conditioned_vertex) {
+                    errors.push(format!(
+                        "fusion edge {edge_index} conditioned on vertex {conditioned_vertex} is inconsistent with the layers"
+                    ));
+                }
+            }
+            for edge_index in derived.fusion_edges.keys() {
+                if !layer_fusion.fusion_edges.contains_key(edge_index) {
+                    errors.push(format!("edge {edge_index} crosses layers but is not a fusion edge"));
+                }
+            }
+            if derived.unique_tight_conditions != layer_fusion.unique_tight_conditions {
+                errors.push("unique_tight_conditions does not match the fusion edges".to_string());
+            }
+        }
+    }
+    errors
+}
+
+fn check_parity_reporters(graph: &MicroBlossomSingle) -> Vec<String> {
+    let Some(parity_reporters) = graph.parity_reporters.as_ref() else {
+        return vec![];
+    };
+    let mut errors = vec![];
+    for (reporter_index, offloaders) in parity_reporters.reporters.iter().enumerate() {
+        for &offloader_index in offloaders.iter() {
+            if offloader_index >= graph.offloading.0.len() {
+                errors.push(format!(
+                    "parity reporter {reporter_index} refers to offloader {offloader_index} out of range"
+                ));
+            }
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn graph_validator_valid_graphs() {
+        // cargo test graph_validator_valid_graphs -- --nocapture
+        let graphs = [
+            MicroBlossomSingle::new_code(&CodeCapacityPlanarCode::new(5, 0.1, 500)),
+            MicroBlossomSingle::new_code(&PhenomenologicalPlanarCode::new(3, 3, 0.1, 500)),
+            MicroBlossomSingle::new_code(&CodeCapacityRepetitionCode::new(5, 0.1, 500)),
+        ];
+        for graph in graphs.iter() {
+            let report = validate_graph(graph, &GraphValidatorConfig::default());
+            println!("{report}");
+            assert!(report.is_valid());
+        }
+    }
+
+    #[test]
+    fn graph_validator_broken_graphs() {
+        // cargo test graph_validator_broken_graphs -- --nocapture
+        let code = PhenomenologicalPlanarCode::new(3, 3, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let failed_checks = |graph: &MicroBlossomSingle, config: &GraphValidatorConfig| -> Vec<String> {
+            let report = validate_graph(graph, config);
+            println!("{report}");
+            report
+                .checks
+                .into_iter()
+                .filter(|check| !check.errors.is_empty())
+                .map(|check| check.name)
+                .collect()
+        };
+        let default_config = GraphValidatorConfig::default();
+        // odd weight, which also exceeds 16 bits
+        let mut broken = graph.clone();
+        broken.weighted_edges[0].w = 40001;
+        assert_eq!(failed_checks(&broken, &default_config), ["weights"]);
+        broken.weighted_edges[0].w = 40000;
+        assert!(failed_checks(&broken, &default_config).is_empty());
+        let config_16 = GraphValidatorConfig {
+            i16_weight: true,
+            ..Default::default()
+        };
+        assert_eq!(failed_checks(&broken, &config_16), ["weights"]);
+        // maximum growth too small
+        let mut broken = graph.clone();
+        let regular_vertex = (0..graph.vertex_num)
+            .find(|vertex_index| !graph.virtual_vertices.contains(vertex_index))
+            .unwrap();
+        broken.vertex_max_growth[regular_vertex] = 2;
+        assert_eq!(failed_checks(&broken, &default_config), ["vertex max growth"]);
+        // tree inconsistency
+        let mut broken: MicroBlossomSingle = serde_json::from_value({
+            let mut value = serde_json::to_value(&graph).unwrap();
+            value["vertex_binary_tree"]["nodes"][0]["p"] = serde_json::json!(0);
+            value
+        })
+        .unwrap();
+        assert_eq!(failed_checks(&broken, &default_config), ["vertex binary tree"]);
+        // offloading out of range
+        broken = graph.clone();
+        broken.offloading.0.push(OffloadingType::DefectMatch {
+            edge_index: graph.weighted_edges.len(),
+        });
+        assert_eq!(failed_checks(&broken, &default_config), ["offloading"]);
+        // second-order offloaders are only simulated
+        broken = MicroBlossomSingle::new_code(&CodeCapacityPlanarCode::new(5, 0.1, 500));
+        broken.add_second_order_offloading();
+        assert_eq!(failed_checks(&broken, &default_config), ["offloading"]);
+        // boundary defect match on two unrelated edges
+        broken = graph.clone();
+        let regular_edge_index = broken.offloading.0.iter().find_map(|offloader| match offloader {
+            OffloadingType::DefectMatch { edge_index } => Some(*edge_index),
+            _ => None,
+        });
+        broken.offloading.0.push(OffloadingType::BoundaryDefectMatch {
+            edge_index: regular_edge_index.unwrap(),
+            boundary_edge_index: regular_edge_index.unwrap(),
+        });
+        assert_eq!(failed_checks(&broken, &default_config), ["offloading"]);
+        // chain defect match on the same edge twice
+        broken = graph.clone();
+        broken.offloading.0.push(OffloadingType::ChainDefectMatch {
+            edge_index: regular_edge_index.unwrap(),
+            chain_edge_index: regular_edge_index.unwrap(),
+        });
+        assert_eq!(failed_checks(&broken, &default_config), ["offloading"]);
+        assert_eq!(check_offloading(&broken).len(), 2, "not in hardware and not a chain");
+        // layer fusion maps inconsistent with the layers
+        broken = graph.clone();
+        let layer_fusion = broken.layer_fusion.as_mut().unwrap();
+        let (&edge_index, _) = layer_fusion.fusion_edges.iter().next().unwrap();
+        layer_fusion.fusion_edges.remove(&edge_index);
+        assert_eq!(failed_checks(&broken, &default_config), ["layer fusion"]);
+        // edges out of range skip the remaining checks
+        broken = graph.clone();
+        broken.weighted_edges[0].r = graph.vertex_num;
+        assert_eq!(failed_checks(&broken, &default_config), ["vertices and edges"]);
+        assert_eq!(validate_graph(&broken, &default_config).checks.len(), 1);
+    }
+}
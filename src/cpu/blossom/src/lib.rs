@@ -1,7 +1,11 @@
 #[macro_use]
 extern crate serde_json;
 
+pub mod batch_decoder;
+pub mod binding;
 pub mod cli;
+pub mod detector_error_model;
+pub mod dual_driver_trace;
 pub mod dual_module_adaptor;
 pub mod dual_module_axi4;
 pub mod dual_module_comb;
@@ -10,13 +14,23 @@ pub mod dual_module_comb_offloading;
 pub mod dual_module_comb_vertex;
 pub mod dual_module_looper;
 pub mod dual_module_scala;
+pub mod embedded_report;
 pub mod example_codes;
+pub mod fuzz;
+pub mod graph_export;
+pub mod graph_validator;
+pub mod logical_observable;
+pub mod multi_context;
 pub mod mwpm_solver;
 pub mod primal_module_embedded_adaptor;
+pub mod resource_estimator;
 pub mod resources;
+pub mod simulation_native_host;
 pub mod simulation_tcp_client;
+pub mod sweep;
 pub mod transform_syndromes;
 pub mod util;
+pub mod weight_quantization;
 
 use lazy_static::lazy_static;
 use std::sync::Mutex;
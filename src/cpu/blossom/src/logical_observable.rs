@@ -0,0 +1,252 @@
+//! Logical Observable
+//!
+//! Estimate the logical error rate of a decoder. The errors are sampled on the edges of the decoding graph so that
+//! the ground truth of the observable flips is known; a logical error happens when the observables flipped by the
+//! decoded subgraph differ from the ground truth.
+//!
+
+use crate::resources::*;
+use fusion_blossom::example_codes::*;
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::util::*;
+use rand::Rng;
+use rand_xoshiro::rand_core::SeedableRng;
+use serde::Serialize;
+
+/// sample random errors on the edges of an example code and record the observables they flip
+pub struct LogicalErrorSampler {
+    /// (left, right, p, pe) of each edge
+    edges: Vec<(VertexIndex, VertexIndex, f64, f64)>,
+    is_virtual: Vec<bool>,
+    observables: Vec<u64>,
+    rng: rand_xoshiro::Xoroshiro128StarStar,
+}
+
+impl LogicalErrorSampler {
+    /// the graph must be generated from the same code, with the observables set on its edges
+    pub fn new(code: &dyn ExampleCode, graph: &MicroBlossomSingle, seed: u64) -> Self {
+        let (vertices, edges) = code.immutable_vertices_edges();
+        assert_eq!(edges.len(), graph.weighted_edges.len(), "graph does not match the code");
+        Self {
+            edges: edges
+                .iter()
+                .map(|edge| (edge.vertices.0, edge.vertices.1, edge.p, edge.pe))
+                .collect(),
+            is_virtual: vertices.iter().map(|vertex| vertex.is_virtual).collect(),
+            observables: graph.weighted_edges.iter().map(|edge| edge.observables).collect(),
+            rng: rand_xoshiro::Xoroshiro128StarStar::seed_from_u64(seed),
+        }
+    }
+
+    /// sample the errors from the given probability of each edge, e.g. from a detector error model; no erasure
+    pub fn from_probabilities(graph: &MicroBlossomSingle, probabilities: &[f64], seed: u64) -> Self {
+        assert_eq!(probabilities.len(), graph.weighted_edges.len(), "one probability per edge");
+        let mut is_virtual = vec![false; graph.vertex_num];
+        for &vertex_index in graph.virtual_vertices.iter() {
+            is_virtual[vertex_index] = true;
+        }
+        Self {
+            edges: graph
+                .weighted_edges
+                .iter()
+                .zip(probabilities.iter())
+                .map(|(edge, &p)| (edge.l as VertexIndex, edge.r as VertexIndex, p, 0.))
+                .collect(),
+            is_virtual,
+            observables: graph.weighted_edges.iter().map(|edge| edge.observables).collect(),
+            rng: rand_xoshiro::Xoroshiro128StarStar::seed_from_u64(seed),
+        }
+    }
+
+    /// generate a syndrome and the observables flipped by the actual errors
+    pub fn generate_random_errors(&mut self) -> (SyndromePattern, u64) {
+        let mut is_defect = vec![false; self.is_virtual.len()];
+        let mut erasures = vec![];
+        let mut observables = 0;
+        for (edge_index, &(left, right, p, pe)) in self.edges.iter().enumerate() {
+            let p = if pe > 0. && self.rng.gen::<f64>() < pe {
+                erasures.push(edge_index);
+                0.5 // when erasure happens, there are 50% chance of error
+            } else {
+                p
+            };
+            if self.rng.gen::<f64>() < p {
+                is_defect[left] ^= true;
+                is_defect[right] ^= true;
+                observables ^= self.observables[edge_index];
+            }
+        }
+        let defect_vertices = (0..is_defect.len())
+            .filter(|&vertex_index| is_defect[vertex_index] && !self.is_virtual[vertex_index])
+            .collect();
+        (SyndromePattern::new(defect_vertices, erasures), observables)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogicalErrorRate {
+    pub total_rounds: usize,
+    pub logical_errors: usize,
+    pub logical_error_rate: f64,
+    /// the confidence level of the interval, e.g. 0.95
+    pub confidence: f64,
+    /// Wilson score interval of the logical error rate
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl LogicalErrorRate {
+    pub fn new(logical_errors: usize, total_rounds: usize, confidence: f64) -> Self {
+        let (lower, upper) = wilson_interval(logical_errors, total_rounds, confidence);
+        Self {
+            total_rounds,
+            logical_errors,
+            logical_error_rate: if total_rounds == 0 {
+                0.
+            } else {
+                logical_errors as f64 / total_rounds as f64
+            },
+            confidence,
+            lower,
+            upper,
+        }
+    }
+}
+
+/// decode `total_rounds` random syndromes and count the logical errors
+pub fn estimate_logical_error_rate(
+    solver: &mut dyn PrimalDualSolver,
+    graph: &MicroBlossomSingle,
+    sampler: &mut LogicalErrorSampler,
+    total_rounds: usize,
+    confidence: f64,
+) -> LogicalErrorRate {
+    let mut logical_errors = 0;
+    for _ in 0..total_rounds {
+        let (syndrome_pattern, actual_observables) = sampler.generate_random_errors();
+        solver.solve(&syndrome_pattern);
+        let predicted_observables = graph.subgraph_observables(&solver.subgraph());
+        if predicted_observables != actual_observables {
+            logical_errors += 1;
+        }
+        solver.clear();
+    }
+    LogicalErrorRate::new(logical_errors, total_rounds, confidence)
+}
+
+/// Wilson score interval of a binomial proportion
+pub fn wilson_interval(successes: usize, total: usize, confidence: f64) -> (f64, f64) {
+    if total == 0 {
+        return (0., 1.);
+    }
+    let z = normal_quantile(1. - (1. - confidence) / 2.);
+    let n = total as f64;
+    let p = successes as f64 / n;
+    let denominator = 1. + z * z / n;
+    let center = (p + z * z / (2. * n)) / denominator;
+    let half_width = z * (p * (1. - p) / n + z * z / (4. * n * n)).sqrt() / denominator;
+    ((center - half_width).max(0.), (center + half_width).min(1.))
+}
+
+/// the quantile function of the standard normal distribution, using the rational approximation by Peter Acklam
+/// with a relative error below 1.15e-9
+pub fn normal_quantile(p: f64) -> f64 {
+    assert!(p > 0. && p < 1., "quantile is only defined in (0, 1)");
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.383_577_518_672_69e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.)
+    };
+    if p < 0.02425 {
+        tail((-2. * p.ln()).sqrt())
+    } else if p > 1. - 0.02425 {
+        -tail((-2. * (1. - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwpm_solver::*;
+    use serde_json::json;
+
+    #[test]
+    fn logical_observable_wilson_interval() {
+        // cargo test logical_observable_wilson_interval -- --nocapture
+        assert!((normal_quantile(0.975) - 1.959964).abs() < 1e-6);
+        assert!((normal_quantile(0.005) + 2.575829).abs() < 1e-6);
+        let (lower, upper) = wilson_interval(10, 100, 0.95);
+        println!("[{lower}, {upper}]");
+        assert!((lower - 0.05523).abs() < 1e-4 && (upper - 0.17437).abs() < 1e-4);
+        let (lower, upper) = wilson_interval(0, 1000, 0.95);
+        assert!(lower.abs() < 1e-12 && (upper - 0.003827).abs() < 1e-5);
+    }
+
+    #[test]
+    fn logical_observable_planar_code() {
+        // cargo test logical_observable_planar_code -- --nocapture
+        let d = 5;
+        let code = CodeCapacityPlanarCode::new(d, 0.05, 500);
+        let mut graph = MicroBlossomSingle::new_code(&code);
+        graph.set_boundary_observable(0);
+        assert_eq!(graph.observable_num(), 1);
+        // one edge on each row connects to the left boundary
+        let observable_edges = graph.weighted_edges.iter().filter(|edge| edge.observables != 0).count();
+        assert_eq!(observable_edges, d as usize);
+        // a chain of errors across the code is a logical error
+        let row_edges: Vec<_> = (0..graph.weighted_edges.len())
+            .filter(|&edge_index| {
+                let edge = &graph.weighted_edges[edge_index];
+                graph.positions[edge.l].i == 0. && graph.positions[edge.r].i == 0.
+            })
+            .collect();
+        assert_eq!(row_edges.len(), d as usize);
+        assert_eq!(graph.subgraph_observables(&row_edges), 1);
+        // the decoder should have a low logical error rate
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), json!({}));
+        let mut sampler = LogicalErrorSampler::new(&code, &graph, 0);
+        let result = estimate_logical_error_rate(&mut solver, &graph, &mut sampler, 200, 0.95);
+        println!("{result:?}");
+        assert!(result.logical_error_rate < 0.1);
+        assert!(result.lower <= result.logical_error_rate && result.logical_error_rate <= result.upper);
+        // the parity reporters cover the same edges
+        let parity_reporters = ParityReporters::from_observables(&graph);
+        assert_eq!(parity_reporters.reporters.len(), 1);
+        assert_eq!(parity_reporters.reporters[0].len(), d as usize);
+        assert_eq!(graph.observables_with_parity_reports(&[], &[true]), 1);
+        assert_eq!(graph.observables_with_parity_reports(&row_edges, &[true]), 0);
+    }
+}
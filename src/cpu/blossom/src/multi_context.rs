@@ -0,0 +1,486 @@
+//! Multi-Context Scheduler
+//!
+//! The accelerator holds multiple independent decoding contexts that share the same pipeline.
+//! This scheduler keeps one primal module per context and interleaves their `find_obstacle`/`resolve` steps,
+//! so that the CPU resolves the obstacle of one shot while the hardware is growing another shot.
+//! Before switching to the next context, it prefetches the conflicts of the current one so that the
+//! `FindObstacle` instruction is already in the pipeline when the scheduler comes back.
+//!
+//! A failure of the driver, including switching the context and prefetching, only fails the shot of that context.
+//! Note that the hardware error counter is shared by all the contexts, so a hardware error is attributed to the
+//! next shot that finishes rather than the shot that caused it.
+//!
+
+use crate::dual_module_axi4::*;
+use crate::mwpm_solver::*;
+use crate::primal_module_embedded_adaptor::*;
+use crate::resources::*;
+use crate::simulation_tcp_client::SimulationConfig;
+use crate::util::*;
+use fusion_blossom::primal_module::*;
+use fusion_blossom::util::*;
+use micro_blossom_nostd::dual_driver_tracked::*;
+use micro_blossom_nostd::dual_module_stackless::*;
+use micro_blossom_nostd::interface::*;
+
+use micro_blossom_nostd::util::*;
+use serde::*;
+use serde_json::json;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// a dual driver whose accelerator provides multiple decoding contexts
+pub trait DualContextDriver: SolverTrackedDual {
+    fn context_depth(&self) -> usize;
+    /// the following instructions and readouts apply to this context
+    fn set_context_id(&mut self, context_id: usize) -> Result<(), MicroBlossomError>;
+    /// issue a `FindObstacle` instruction with the given maximum growth without waiting for the result
+    fn prefetch_conflict(&mut self, maximum_growth: CompactWeight) -> Result<(), MicroBlossomError>;
+    /// the timer of the accelerator in clock cycles, if available
+    fn get_cycle(&mut self) -> Result<Option<u64>, MicroBlossomError> {
+        Ok(None)
+    }
+}
+
+impl DualContextDriver for DualModuleAxi4Driver {
+    fn context_depth(&self) -> usize {
+        self.client.sim_config.context_depth
+    }
+    fn set_context_id(&mut self, context_id: usize) -> Result<(), MicroBlossomError> {
+        if context_id >= self.context_depth() {
+            return Err(MicroBlossomError::ContextOverflow { context_id });
+        }
+        self.context_id = context_id as u16;
+        Ok(())
+    }
+    fn prefetch_conflict(&mut self, maximum_growth: CompactWeight) -> Result<(), MicroBlossomError> {
+        // the same value as in `find_conflict`, otherwise updating the maximum growth invalidates the prefetch
+        self.set_maximum_growth(maximum_growth as u16)?;
+        self.pre_fetch_conflicts()?;
+        Ok(())
+    }
+    fn get_cycle(&mut self) -> Result<Option<u64>, MicroBlossomError> {
+        Ok(Some(self.memory_read_64(0)?))
+    }
+}
+
+/// one context of a shared dual driver; every call first switches the driver to this context
+pub struct DualContextHandle<Dual: DualContextDriver> {
+    pub driver: Rc<RefCell<Dual>>,
+    pub context_id: usize,
+}
+
+impl<Dual: DualContextDriver> DualContextHandle<Dual> {
+    pub fn with_driver<R>(
+        &self,
+        func: impl FnOnce(&mut Dual) -> Result<R, MicroBlossomError>,
+    ) -> Result<R, MicroBlossomError> {
+        let mut driver = self.driver.borrow_mut();
+        driver.set_context_id(self.context_id)?;
+        func(&mut driver)
+    }
+}
+
+impl<Dual: DualContextDriver> FallibleDualStacklessDriver for DualContextHandle<Dual> {
+    fn try_reset(&mut self) -> Result<(), MicroBlossomError> {
+        self.with_driver(|driver| driver.try_reset())
+    }
+    fn try_set_speed(
+        &mut self,
+        is_blossom: bool,
+        node: CompactNodeIndex,
+        speed: CompactGrowState,
+    ) -> Result<(), MicroBlossomError> {
+        self.with_driver(|driver| driver.try_set_speed(is_blossom, node, speed))
+    }
+    fn try_set_blossom(&mut self, node: CompactNodeIndex, blossom: CompactNodeIndex) -> Result<(), MicroBlossomError> {
+        self.with_driver(|driver| driver.try_set_blossom(node, blossom))
+    }
+    fn try_find_obstacle(&mut self) -> Result<(CompactObstacle, CompactWeight), MicroBlossomError> {
+        self.with_driver(|driver| driver.try_find_obstacle())
+    }
+    fn try_add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) -> Result<(), MicroBlossomError> {
+        self.with_driver(|driver| driver.try_add_defect(vertex, node))
+    }
+}
+
+impl<Dual: DualContextDriver> FallibleDualTrackedDriver for DualContextHandle<Dual> {
+    fn try_find_conflict(
+        &mut self,
+        maximum_growth: CompactWeight,
+    ) -> Result<(CompactObstacle, CompactWeight), MicroBlossomError> {
+        self.with_driver(|driver| driver.try_find_conflict(maximum_growth))
+    }
+}
+
+pub struct MultiContextSlot<Dual: DualContextDriver> {
+    pub dual_module: Box<DualModuleStackless<DualDriverTracked<DualContextHandle<Dual>, MAX_NODE_NUM>>>,
+    pub primal_module: Box<PrimalModuleEmbedded<MAX_NODE_NUM>>,
+    defect_nodes: Vec<VertexIndex>,
+    /// the shot being decoded in this context
+    shot_index: Option<usize>,
+}
+
+impl<Dual: DualContextDriver> MultiContextSlot<Dual> {
+    /// prefetch the conflicts using the same maximum growth as [`DualDriverTracked`] will use
+    fn prefetch(&mut self) -> Result<(), MicroBlossomError> {
+        let maximum_growth = match self.dual_module.driver.blossom_tracker.get_maximum_growth() {
+            Some((0, _)) => return Ok(()), // a blossom needs to be expanded in software
+            Some((length, _)) => length,
+            None => CompactWeight::MAX,
+        };
+        let handle = &self.dual_module.driver.driver;
+        handle.with_driver(|driver| driver.prefetch_conflict(maximum_growth))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MultiContextStatistics {
+    pub context_depth: usize,
+    pub shots: usize,
+    /// the number of shots that failed, whose subgraphs are empty
+    pub failed: usize,
+    /// the number of accelerator clock cycles to decode all the shots, if the timer is available and readable
+    pub cycles: Option<u64>,
+    /// wall-clock time in seconds, including the simulation overhead
+    pub elapsed: f64,
+}
+
+impl MultiContextStatistics {
+    /// shots per million clock cycles
+    pub fn throughput(&self) -> Option<f64> {
+        self.cycles.map(|cycles| self.shots as f64 / cycles.max(1) as f64 * 1e6)
+    }
+}
+
+pub struct SolverMultiContext<Dual: DualContextDriver> {
+    pub driver: Rc<RefCell<Dual>>,
+    pub slots: Vec<MultiContextSlot<Dual>>,
+    subgraph_builder: SubGraphBuilder,
+    /// the error of each shot in the last batch, whose subgraph is then empty
+    errors: Vec<Option<MicroBlossomError>>,
+}
+
+impl<Dual: DualContextDriver> SolverMultiContext<Dual> {
+    /// use all the contexts of the accelerator, configured by `sim_config.context_depth` of the dual config
+    pub fn new(graph: MicroBlossomSingle, primal_dual_config: serde_json::Value) -> Self {
+        assert!(graph.vertex_num <= MAX_NODE_NUM, "potential overflow");
+        let config: SolverEmbeddedBoxedConfig = serde_json::from_value(primal_dual_config).unwrap();
+        let dual_config = config.dual.clone().unwrap_or(json!({}));
+        let sim_config: SimulationConfig = dual_config
+            .get("sim_config")
+            .map(|sim_config| serde_json::from_value(sim_config.clone()).unwrap())
+            .unwrap_or_default();
+        assert!(
+            !sim_config.support_layer_fusion,
+            "layer fusion is not supported by the multi-context scheduler"
+        );
+        assert!(
+            !sim_config.support_offloading || sim_config.context_depth == 1,
+            "the pre-matchings can only be read from context 0"
+        );
+        let initializer = graph.get_initializer();
+        let driver = Rc::new(RefCell::new(Dual::new_from_graph_config(graph.clone(), dual_config)));
+        let context_depth = driver.borrow().context_depth();
+        let slots = (0..context_depth)
+            .map(|context_id| {
+                let handle = DualContextHandle {
+                    driver: driver.clone(),
+                    context_id,
+                };
+                let mut dual_module = stacker::grow(MAX_NODE_NUM * 256, || {
+                    Box::new(DualModuleStackless::new(DualDriverTracked::new(handle)))
+                });
+                dual_module.reset();
+                let mut primal_module = stacker::grow(MAX_NODE_NUM * 256, || Box::new(PrimalModuleEmbedded::new()));
+                primal_module.nodes.blossom_begin = graph.vertex_num;
+                MultiContextSlot {
+                    dual_module,
+                    primal_module,
+                    defect_nodes: vec![],
+                    shot_index: None,
+                }
+            })
+            .collect();
+        Self {
+            driver,
+            slots,
+            subgraph_builder: SubGraphBuilder::new(&initializer),
+            errors: vec![],
+        }
+    }
+
+    pub fn context_depth(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// the error of each shot in the last batch, like [`SolverEmbeddedBoxed::error`]
+    pub fn errors(&self) -> &[Option<MicroBlossomError>] {
+        &self.errors
+    }
+
+    fn start_shot(
+        &mut self,
+        context_id: usize,
+        shot_index: usize,
+        syndrome_pattern: &SyndromePattern,
+    ) -> Result<(), MicroBlossomError> {
+        let slot = &mut self.slots[context_id];
+        slot.primal_module.reset();
+        slot.dual_module.reset();
+        slot.defect_nodes.clear();
+        let handle = &slot.dual_module.driver.driver;
+        if !syndrome_pattern.erasures.is_empty() && !syndrome_pattern.dynamic_weights.is_empty() {
+            return Err(MicroBlossomError::Unsupported {
+                feature: UnsupportedFeature::ErasuresWithDynamicWeights,
+            });
+        }
+        if !syndrome_pattern.erasures.is_empty() {
+            handle.with_driver(|driver| driver.load_erasures(&syndrome_pattern.erasures))?;
+        }
+        if !syndrome_pattern.dynamic_weights.is_empty() {
+            handle.with_driver(|driver| driver.load_dynamic_weights(&syndrome_pattern.dynamic_weights))?;
+        }
+        for (node_index, &defect_index) in syndrome_pattern.defect_vertices.iter().enumerate() {
+            slot.dual_module.add_defect(ni!(defect_index), ni!(node_index));
+            slot.defect_nodes.push(defect_index);
+        }
+        if let Some(error) = slot.dual_module.take_error() {
+            return Err(error);
+        }
+        slot.prefetch()?;
+        slot.shot_index = Some(shot_index);
+        Ok(())
+    }
+
+    /// start the next shot in this context; the shots that fail to start are skipped
+    fn start_next_shot(&mut self, context_id: usize, next_shot: &mut usize, syndrome_patterns: &[SyndromePattern]) {
+        while *next_shot < syndrome_patterns.len() {
+            let shot_index = *next_shot;
+            *next_shot += 1;
+            match self.start_shot(context_id, shot_index, &syndrome_patterns[shot_index]) {
+                Ok(()) => return,
+                Err(error) => self.errors[shot_index] = Some(error),
+            }
+        }
+    }
+
+    fn finish_shot(&mut self, context_id: usize, syndrome_pattern: &SyndromePattern) -> Vec<EdgeIndex> {
+        let slot = &mut self.slots[context_id];
+        slot.shot_index = None;
+        let (perfect_matching, _) = perfect_matching_from_embedded_primal(slot.primal_module.as_mut(), &slot.defect_nodes);
+        self.subgraph_builder.clear();
+        if !syndrome_pattern.erasures.is_empty() {
+            self.subgraph_builder.load_erasures(&syndrome_pattern.erasures);
+        }
+        if !syndrome_pattern.dynamic_weights.is_empty() {
+            self.subgraph_builder.load_dynamic_weights(&syndrome_pattern.dynamic_weights);
+        }
+        self.subgraph_builder.load_perfect_matching(&perfect_matching);
+        self.subgraph_builder.get_subgraph()
+    }
+
+    /// decode all the shots, using at most `context_depth` contexts at the same time; returns the subgraphs
+    pub fn solve_batch(&mut self, syndrome_patterns: &[SyndromePattern]) -> (Vec<Vec<EdgeIndex>>, MultiContextStatistics) {
+        self.solve_batch_contexts(syndrome_patterns, self.context_depth())
+    }
+
+    pub fn solve_batch_contexts(
+        &mut self,
+        syndrome_patterns: &[SyndromePattern],
+        context_depth: usize,
+    ) -> (Vec<Vec<EdgeIndex>>, MultiContextStatistics) {
+        assert!(context_depth >= 1 && context_depth <= self.context_depth());
+        let begin = Instant::now();
+        let begin_cycle = self.driver.borrow_mut().get_cycle();
+        let mut subgraphs = vec![vec![]; syndrome_patterns.len()];
+        self.errors = vec![None; syndrome_patterns.len()];
+        let mut next_shot = 0;
+        for context_id in 0..context_depth {
+            self.start_next_shot(context_id, &mut next_shot, syndrome_patterns);
+        }
+        let mut context_id = 0;
+        while self.slots[..context_depth].iter().any(|slot| slot.shot_index.is_some()) {
+            let slot = &mut self.slots[context_id];
+            if let Some(shot_index) = slot.shot_index {
+                let (obstacle, _) = slot.dual_module.find_obstacle();
+                let result = match slot.dual_module.take_error() {
+                    Some(error) => Err(error),
+                    None if obstacle.is_none() => Ok(true),
+                    None => {
+                        debug_assert!(
+                            obstacle.is_obstacle(),
+                            "dual module should spontaneously process all finite growth"
+                        );
+                        slot.primal_module
+                            .try_resolve(slot.dual_module.as_mut(), obstacle)
+                            .and_then(|_| slot.prefetch())
+                            .map(|_| false)
+                    }
+                };
+                match result {
+                    Ok(false) => {}
+                    Ok(true) => {
+                        subgraphs[shot_index] = self.finish_shot(context_id, &syndrome_patterns[shot_index]);
+                        self.start_next_shot(context_id, &mut next_shot, syndrome_patterns);
+                    }
+                    Err(error) => {
+                        // the context is reset when the next shot starts
+                        slot.shot_index = None;
+                        self.errors[shot_index] = Some(error);
+                        self.start_next_shot(context_id, &mut next_shot, syndrome_patterns);
+                    }
+                }
+            }
+            context_id = (context_id + 1) % context_depth;
+        }
+        let end_cycle = self.driver.borrow_mut().get_cycle();
+        let statistics = MultiContextStatistics {
+            context_depth,
+            shots: syndrome_patterns.len(),
+            failed: self.errors.iter().filter(|error| error.is_some()).count(),
+            cycles: match (begin_cycle, end_cycle) {
+                (Ok(Some(begin)), Ok(Some(end))) => Some(end - begin),
+                _ => None,
+            },
+            elapsed: begin.elapsed().as_secs_f64(),
+        };
+        (subgraphs, statistics)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MultiContextBenchmark {
+    pub single_context: MultiContextStatistics,
+    pub multi_context: MultiContextStatistics,
+    /// the ratio of the throughput using all the contexts over using a single context
+    pub speedup: Option<f64>,
+}
+
+impl<Dual: DualContextDriver> SolverMultiContext<Dual> {
+    /// decode the same shots first with a single context and then with all the contexts; the failed shots are
+    /// counted in the statistics and excluded from the comparison
+    pub fn benchmark(&mut self, syndrome_patterns: &[SyndromePattern]) -> MultiContextBenchmark {
+        let (single_subgraphs, single_context) = self.solve_batch_contexts(syndrome_patterns, 1);
+        let single_errors = self.errors.clone();
+        let (multi_subgraphs, multi_context) = self.solve_batch(syndrome_patterns);
+        for (shot_index, (single, multi)) in single_subgraphs.iter().zip(multi_subgraphs.iter()).enumerate() {
+            if single_errors[shot_index].is_some() || self.errors[shot_index].is_some() {
+                continue;
+            }
+            self.subgraph_builder.clear();
+            self.subgraph_builder.load_subgraph(single);
+            let single_weight = self.subgraph_builder.total_weight();
+            self.subgraph_builder.load_subgraph(multi);
+            assert_eq!(
+                single_weight,
+                self.subgraph_builder.total_weight(),
+                "shot {shot_index}: decoding results differ between contexts"
+            );
+        }
+        let speedup = single_context
+            .throughput()
+            .zip(multi_context.throughput())
+            .map(|(single, multi)| multi / single);
+        MultiContextBenchmark {
+            single_context,
+            multi_context,
+            speedup,
+        }
+    }
+}
+
+pub type SolverMultiContextAxi4 = SolverMultiContext<DualModuleAxi4Driver>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::*;
+
+    #[test]
+    fn multi_context_axi4_random() {
+        // cargo test multi_context_axi4_random -- --nocapture
+        let d = 5;
+        let mut code = CodeCapacityPlanarCode::new(d, 0.05, 500);
+        let initializer = code.get_initializer();
+        let syndrome_patterns: Vec<_> = (0..20).map(|seed| code.generate_random_errors(seed)).collect();
+        let mut solver = SolverMultiContextAxi4::new(
+            MicroBlossomSingle::new(&initializer, &code.get_positions()),
+            json!({ "dual": {
+                "name": "multi_context_axi4_random",
+                "sim_config": { "context_depth": 4, "native_host": true },
+            } }),
+        );
+        assert_eq!(solver.context_depth(), 4);
+        let (subgraphs, statistics) = solver.solve_batch(&syndrome_patterns);
+        println!("{statistics:?}");
+        let mut standard_solver = SolverSerial::new(&initializer);
+        for (syndrome, subgraph) in syndrome_patterns.iter().zip(subgraphs.iter()) {
+            assert_eq!(
+                initializer.syndrome_of(subgraph),
+                syndrome.defect_vertices.iter().cloned().collect()
+            );
+            standard_solver.solve(syndrome);
+            let standard_subgraph = standard_solver.subgraph();
+            let mut subgraph_builder = SubGraphBuilder::new(&initializer);
+            subgraph_builder.load_subgraph(subgraph);
+            let total_weight = subgraph_builder.total_weight();
+            subgraph_builder.load_subgraph(&standard_subgraph);
+            assert_eq!(total_weight, subgraph_builder.total_weight(), "{syndrome:?}");
+            standard_solver.clear();
+        }
+        assert_eq!(statistics.failed, 0);
+        // the benchmark checks that both runs produce the same decoding results
+        let benchmark = solver.benchmark(&syndrome_patterns);
+        println!("{}", serde_json::to_string(&benchmark).unwrap());
+        assert_eq!(benchmark.single_context.failed + benchmark.multi_context.failed, 0);
+    }
+
+    #[test]
+    fn multi_context_axi4_error_per_shot() {
+        // cargo test multi_context_axi4_error_per_shot -- --nocapture
+        let code = CodeCapacityPlanarCode::new(5, 0.05, 500);
+        let mut syndrome_patterns: Vec<_> = [vec![13, 14], vec![15, 16], vec![13, 14], vec![8, 9]]
+            .into_iter()
+            .map(SyndromePattern::new_vertices)
+            .collect();
+        // the accelerator does not support erasures, so this shot fails without affecting the others
+        syndrome_patterns[1].erasures = vec![0];
+        let mut solver = SolverMultiContextAxi4::new(
+            MicroBlossomSingle::new_code(&code),
+            json!({ "dual": {
+                "name": "multi_context_axi4_error_per_shot",
+                "sim_config": { "context_depth": 2, "native_host": true },
+            } }),
+        );
+        let (subgraphs, statistics) = solver.solve_batch(&syndrome_patterns);
+        assert_eq!(statistics.failed, 1);
+        assert_eq!(
+            solver.errors(),
+            &[
+                None,
+                Some(MicroBlossomError::Unsupported {
+                    feature: UnsupportedFeature::Erasure
+                }),
+                None,
+                None
+            ]
+        );
+        assert!(subgraphs[1].is_empty());
+        assert_eq!(subgraphs[0], subgraphs[2]);
+        for shot_index in [0, 2, 3] {
+            assert_eq!(subgraphs[shot_index].len(), 1);
+        }
+        // switching to a context beyond the accelerator is reported instead of aborting
+        let handle = DualContextHandle {
+            driver: solver.driver.clone(),
+            context_id: 2,
+        };
+        assert_eq!(
+            handle.with_driver(|driver| driver.try_reset()),
+            Err(MicroBlossomError::ContextOverflow { context_id: 2 })
+        );
+    }
+}
@@ -20,6 +20,8 @@ use micro_blossom_nostd::interface::*;
 use micro_blossom_nostd::util::*;
 use serde::*;
 use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Instant;
 
 pub struct SolverPrimalEmbedded {
     dual_module: DualModuleSerial,
@@ -227,7 +229,23 @@ impl PrimalDualSolver for SolverDualComb {
     }
 }
 
-pub trait SolverTrackedDual: DualStacklessDriver + DualTrackedDriver + FusionVisualizer {
+/// the dynamic weights of a shot, overwriting the given default weights; every weight must be a non-negative even
+/// number so that both vertices can grow by half of it
+pub fn apply_dynamic_weights(
+    default_weights: impl Iterator<Item = Weight>,
+    dynamic_weights: &[(EdgeIndex, Weight)],
+) -> Result<Vec<Weight>, MicroBlossomError> {
+    let mut weights: Vec<Weight> = default_weights.collect();
+    for &(edge_index, weight) in dynamic_weights.iter() {
+        if edge_index >= weights.len() || weight < 0 || weight % 2 != 0 {
+            return Err(MicroBlossomError::InvalidDynamicWeight { edge_index });
+        }
+        weights[edge_index] = weight;
+    }
+    Ok(weights)
+}
+
+pub trait SolverTrackedDual: FallibleDualStacklessDriver + FallibleDualTrackedDriver + FusionVisualizer {
     fn new_from_graph_config(graph: MicroBlossomSingle, config: serde_json::Value) -> Self;
     fn reset_profiler(&mut self) {}
     fn generate_profiler_report(&self) -> serde_json::Value {
@@ -238,9 +256,25 @@ pub trait SolverTrackedDual: DualStacklessDriver + DualTrackedDriver + FusionVis
     fn fuse_layer(&mut self, _layer_id: usize) {
         unimplemented!()
     }
+    /// temporarily set the weights of the erased edges to 0; they are recovered on the next `reset`
+    fn load_erasures(&mut self, _erasures: &[EdgeIndex]) -> Result<(), MicroBlossomError> {
+        Err(MicroBlossomError::Unsupported {
+            feature: UnsupportedFeature::Erasure,
+        })
+    }
+    /// temporarily overwrite the weights of some edges, e.g., from soft information; recovered on the next `reset`
+    fn load_dynamic_weights(&mut self, _dynamic_weights: &[(EdgeIndex, Weight)]) -> Result<(), MicroBlossomError> {
+        Err(MicroBlossomError::Unsupported {
+            feature: UnsupportedFeature::DynamicWeights,
+        })
+    }
     fn get_pre_matchings(&self, _belonging: DualModuleInterfaceWeak) -> PerfectMatching {
         Default::default()
     }
+    /// the number of defect vertices pre-matched by each type of offloader, if the driver can tell them apart
+    fn offloaded_by_type(&self) -> BTreeMap<&'static str, usize> {
+        BTreeMap::new()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -248,9 +282,15 @@ pub trait SolverTrackedDual: DualStacklessDriver + DualTrackedDriver + FusionVis
 pub struct SolverEmbeddedBoxedConfig {
     pub primal: Option<serde_json::Value>,
     pub dual: Option<serde_json::Value>,
-    /// to debug the infinite loop bugs: terminate and save the waveform in the middle
+    /// to debug the infinite loop bugs: terminate and save the waveform in the middle; the result is undefined,
+    /// use `deadline_iterations` instead to bound the decoding time
     #[serde(default = "solver_embedded_boxed_config_default::max_iterations")]
     pub max_iterations: usize,
+    /// stop the primal-dual loop after resolving this number of obstacles in a shot and complete the matching with
+    /// a greedy fallback; the result is then flagged as non-optimal
+    pub deadline_iterations: Option<usize>,
+    /// the same as `deadline_iterations` but in the wall-clock time since the beginning of a shot
+    pub deadline_ns: Option<u64>,
 }
 
 pub mod solver_embedded_boxed_config_default {
@@ -265,7 +305,20 @@ pub struct SolverEmbeddedBoxed<Dual: SolverTrackedDual> {
     subgraph_builder: SubGraphBuilder,
     defect_nodes: Vec<VertexIndex>,
     pub offloaded: usize,
+    /// the defect vertices pre-matched by each type of offloader in the last shot
+    pub offloaded_by_type: BTreeMap<&'static str, usize>,
     layer_id: usize,
+    iteration: usize,
+    /// the error of the last shot, whose solution is then empty
+    error: Option<MicroBlossomError>,
+    /// the beginning of the last shot, for `deadline_ns`
+    shot_start: Instant,
+    /// whether the deadline of the last shot expired, so that the matching is completed by the greedy fallback
+    pub non_optimal: bool,
+    /// the number of defects matched by the greedy fallback in the last shot
+    pub fallback_matched: usize,
+    /// the number of non-optimal shots since the profiler is reset
+    pub count_non_optimal: usize,
     graph: MicroBlossomSingle,
     sim_config: SimulationConfig,
     config: SolverEmbeddedBoxedConfig,
@@ -316,7 +369,14 @@ impl<Dual: SolverTrackedDual> SolverEmbeddedBoxed<Dual> {
             subgraph_builder: SubGraphBuilder::new(&initializer),
             defect_nodes: vec![],
             offloaded: 0,
+            offloaded_by_type: BTreeMap::new(),
             layer_id: 0,
+            iteration: 0,
+            error: None,
+            shot_start: Instant::now(),
+            non_optimal: false,
+            fallback_matched: 0,
+            count_non_optimal: 0,
             graph,
             sim_config,
             config,
@@ -324,66 +384,153 @@ impl<Dual: SolverTrackedDual> SolverEmbeddedBoxed<Dual> {
     }
 }
 
-impl<Dual: SolverTrackedDual> PrimalDualSolver for SolverEmbeddedBoxed<Dual> {
-    fn clear(&mut self) {
-        self.primal_module.reset();
-        self.dual_module.reset();
-        self.subgraph_builder.clear();
-        self.defect_nodes.clear();
-        self.layer_id = 0;
+impl<Dual: SolverTrackedDual> SolverEmbeddedBoxed<Dual> {
+    /// the number of layers to fuse; 0 if layer fusion is not supported
+    pub fn num_layers(&self) -> usize {
+        if self.sim_config.support_layer_fusion {
+            self.graph.layer_fusion.as_ref().unwrap().num_layers
+        } else {
+            0
+        }
     }
-    fn reset_profiler(&mut self) {
-        self.dual_module.driver.driver.reset_profiler();
+
+    /// the error of the last shot, if any; the solution of a failed shot is empty
+    pub fn error(&self) -> Option<MicroBlossomError> {
+        self.error
     }
-    fn solve_visualizer(&mut self, syndrome_pattern: &SyndromePattern, mut visualizer: Option<&mut Visualizer>) {
-        assert!(syndrome_pattern.erasures.is_empty());
-        assert!(syndrome_pattern.dynamic_weights.is_empty());
-        assert!(self.defect_nodes.is_empty(), "must call `clear` between different runs");
-        for (node_index, &defect_index) in syndrome_pattern.defect_vertices.iter().enumerate() {
-            self.dual_module.add_defect(ni!(defect_index), ni!(node_index));
-            self.defect_nodes.push(defect_index);
+
+    /// add a defect to the dual module; the node index must not overlap with the blossom indices
+    fn add_defect(&mut self, defect_index: VertexIndex) -> Result<(), MicroBlossomError> {
+        let node_index = self.defect_nodes.len();
+        if node_index >= self.primal_module.nodes.blossom_begin {
+            return Err(MicroBlossomError::NodeIndexOverflow { node_index });
         }
-        if let Some(visualizer) = visualizer.as_mut() {
-            visualizer.snapshot("syndrome".to_string(), self).unwrap();
+        self.dual_module.add_defect(ni!(defect_index), ni!(node_index));
+        self.defect_nodes.push(defect_index);
+        self.dual_module.take_error().map_or(Ok(()), Err)
+    }
+
+    fn is_deadline_expired(&self) -> bool {
+        self.config
+            .deadline_iterations
+            .map_or(false, |deadline| self.iteration >= deadline)
+            || self
+                .config
+                .deadline_ns
+                .map_or(false, |deadline| self.shot_start.elapsed().as_nanos() >= deadline as u128)
+    }
+
+    /// resolve all the obstacles; return false if the maximum number of iterations is reached or the deadline expires
+    fn resolve_obstacles(&mut self, mut visualizer: Option<&mut Visualizer>) -> Result<bool, MicroBlossomError> {
+        if self.non_optimal {
+            return Ok(false);
         }
-        let mut iteration = 0;
-        loop {
-            let (mut obstacle, _) = self.dual_module.find_obstacle();
-            while !obstacle.is_none() && iteration < self.config.max_iterations {
-                iteration += 1;
-                // println!("obstacle: {obstacle:?}");
-                debug_assert!(
-                    obstacle.is_obstacle(),
-                    "dual module should spontaneously process all finite growth"
-                );
-                if let Some(visualizer) = visualizer.as_mut() {
-                    visualizer.snapshot(format!("{obstacle:?}"), self).unwrap();
-                }
-                self.primal_module.resolve(self.dual_module.as_mut(), obstacle);
-                (obstacle, _) = self.dual_module.find_obstacle();
+        let (mut obstacle, _) = self.dual_module.find_obstacle();
+        while !obstacle.is_none() && self.iteration < self.config.max_iterations {
+            if self.is_deadline_expired() {
+                self.non_optimal = true;
+                return Ok(false);
             }
-            if iteration >= self.config.max_iterations {
-                break;
+            self.iteration += 1;
+            // println!("obstacle: {obstacle:?}");
+            debug_assert!(
+                obstacle.is_obstacle(),
+                "dual module should spontaneously process all finite growth"
+            );
+            if let Some(visualizer) = visualizer.as_mut() {
+                visualizer.snapshot(format!("{obstacle:?}"), self).unwrap();
             }
-            // if there are pending fusion layers, execute them
-            if self.sim_config.support_layer_fusion {
-                let num_layers = self.graph.layer_fusion.as_ref().unwrap().num_layers;
-                if self.layer_id < num_layers {
-                    self.dual_module.driver.driver.fuse_layer(self.layer_id);
-                    self.primal_module.fuse_layer(
-                        self.dual_module.as_mut(),
-                        CompactLayerId::new(self.layer_id as CompactLayerNum).unwrap(),
-                    );
-                    if let Some(visualizer) = visualizer.as_mut() {
-                        visualizer.snapshot(format!("fusion {}", self.layer_id), self).unwrap();
-                    }
-                    self.layer_id += 1;
-                    continue;
-                }
+            self.primal_module.try_resolve(self.dual_module.as_mut(), obstacle)?;
+            (obstacle, _) = self.dual_module.find_obstacle();
+        }
+        if let Some(error) = self.dual_module.take_error() {
+            return Err(error);
+        }
+        Ok(self.iteration < self.config.max_iterations)
+    }
+
+    fn fuse_next_layer(&mut self, visualizer: Option<&mut Visualizer>) -> Result<(), MicroBlossomError> {
+        self.dual_module.driver.driver.fuse_layer(self.layer_id);
+        self.primal_module.fuse_layer(
+            self.dual_module.as_mut(),
+            CompactLayerId::new(self.layer_id as CompactLayerNum).unwrap(),
+        );
+        if let Some(visualizer) = visualizer {
+            visualizer.snapshot(format!("fusion {}", self.layer_id), self).unwrap();
+        }
+        self.layer_id += 1;
+        self.dual_module.take_error().map_or(Ok(()), Err)
+    }
+
+    /// after the deadline expires, fuse all the remaining layers without resolving any obstacle and then match the
+    /// nodes in the alternating trees along the tight edges; the rest are matched in [`Self::perfect_matching`]
+    fn prepare_fallback(&mut self, mut visualizer: Option<&mut Visualizer>) -> Result<(), MicroBlossomError> {
+        if !self.non_optimal {
+            return Ok(());
+        }
+        while self.layer_id < self.num_layers() {
+            self.fuse_next_layer(visualizer.as_deref_mut())?;
+        }
+        self.primal_module.break_alternating_trees(self.dual_module.as_mut());
+        self.count_non_optimal += 1;
+        self.dual_module.take_error().map_or(Ok(()), Err)
+    }
+
+    /// complete a partial matching: drop the pre-matchings that overlap with the primal module, then match the
+    /// remaining defects greedily; the graph weights are used even if the shot has erasures or dynamic weights
+    fn complete_matching_greedily(&mut self, perfect_matching: &mut PerfectMatching, belonging: &DualModuleInterfaceWeak) {
+        let defect_index = |node: &DualNodePtr| match node.read_recursive().class {
+            DualNodeClass::DefectVertex { defect_index } => defect_index,
+            _ => unreachable!("only defect vertices are matched"),
+        };
+        let mut matched = BTreeSet::new();
+        let peer_matchings = std::mem::take(&mut perfect_matching.peer_matchings);
+        for (node_1, node_2) in peer_matchings.into_iter() {
+            let (defect_1, defect_2) = (defect_index(&node_1), defect_index(&node_2));
+            if !matched.contains(&defect_1) && !matched.contains(&defect_2) {
+                matched.insert(defect_1);
+                matched.insert(defect_2);
+                perfect_matching.peer_matchings.push((node_1, node_2));
             }
-            break;
         }
-        if let Some(visualizer) = visualizer.as_mut() {
+        let virtual_matchings = std::mem::take(&mut perfect_matching.virtual_matchings);
+        for (node, virtual_vertex) in virtual_matchings.into_iter() {
+            if matched.insert(defect_index(&node)) {
+                perfect_matching.virtual_matchings.push((node, virtual_vertex));
+            }
+        }
+        let unmatched: Vec<(NodeIndex, VertexIndex)> = self
+            .defect_nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, defect_index)| !matched.contains(*defect_index))
+            .map(|(node_index, &defect_index)| (node_index, defect_index))
+            .collect();
+        let defect_vertices: Vec<VertexIndex> = unmatched.iter().map(|&(_, defect_index)| defect_index).collect();
+        let (peer_matchings, virtual_matchings) = self.graph.greedy_matching(&defect_vertices);
+        let node = |i: usize| {
+            let (node_index, defect_index) = unmatched[i];
+            DualNodePtr::new_value(DualNode {
+                index: node_index,
+                class: DualNodeClass::DefectVertex { defect_index },
+                defect_size: nonzero::nonzero!(1usize),
+                grow_state: DualNodeGrowState::Stay,
+                parent_blossom: None,
+                dual_variable_cache: (0, 0),
+                belonging: belonging.clone(),
+            })
+        };
+        for (i, j) in peer_matchings.into_iter() {
+            perfect_matching.peer_matchings.push((node(i), node(j)));
+        }
+        for (i, virtual_vertex) in virtual_matchings.into_iter() {
+            perfect_matching.virtual_matchings.push((node(i), virtual_vertex));
+        }
+        self.fallback_matched = unmatched.len();
+    }
+
+    fn load_solution(&mut self, visualizer: Option<&mut Visualizer>) {
+        if let Some(visualizer) = visualizer {
             visualizer.snapshot("solved".to_string(), self).unwrap();
         }
         let perfect_matching = self.perfect_matching();
@@ -395,8 +542,189 @@ impl<Dual: SolverTrackedDual> PrimalDualSolver for SolverEmbeddedBoxed<Dual> {
                 self.offloaded += 1;
             }
         }
+        self.offloaded_by_type = self.dual_module.driver.driver.offloaded_by_type();
+    }
+
+    /// the number of measurement rounds (fusion layers) already pushed
+    pub fn pushed_rounds(&self) -> usize {
+        self.layer_id
+    }
+
+    /// streaming decoding: load the defects of the next measurement round and fuse its layer immediately;
+    /// the matching is then updated before the next round arrives
+    pub fn push_round(&mut self, defect_vertices: &[VertexIndex]) -> Result<(), MicroBlossomError> {
+        self.push_round_visualizer(defect_vertices, None)
+    }
+
+    pub fn push_round_visualizer(
+        &mut self,
+        defect_vertices: &[VertexIndex],
+        visualizer: Option<&mut Visualizer>,
+    ) -> Result<(), MicroBlossomError> {
+        let result = self.push_round_inner(defect_vertices, visualizer);
+        self.error = result.err();
+        result
+    }
+
+    fn push_round_inner(
+        &mut self,
+        defect_vertices: &[VertexIndex],
+        mut visualizer: Option<&mut Visualizer>,
+    ) -> Result<(), MicroBlossomError> {
+        assert!(
+            self.sim_config.support_layer_fusion,
+            "streaming decoding requires `support_layer_fusion`"
+        );
+        assert!(self.layer_id < self.num_layers(), "all the rounds have been pushed");
+        if self.layer_id == 0 {
+            // the shot begins with its first round
+            self.shot_start = Instant::now();
+        }
+        let layer_fusion = self.graph.layer_fusion.as_ref().unwrap();
+        for &defect_index in defect_vertices.iter() {
+            assert_eq!(
+                layer_fusion.vertex_layer_id.get(&defect_index),
+                Some(&self.layer_id),
+                "defect vertex {defect_index} does not belong to round {}",
+                self.layer_id
+            );
+        }
+        for &defect_index in defect_vertices.iter() {
+            self.add_defect(defect_index)?;
+        }
+        self.fuse_next_layer(visualizer.as_deref_mut())?;
+        self.resolve_obstacles(visualizer)?;
+        Ok(())
+    }
+
+    /// finish streaming decoding by fusing all the remaining layers; the subgraph is then available as usual
+    pub fn finish_rounds(&mut self, visualizer: Option<&mut Visualizer>) -> Result<(), MicroBlossomError> {
+        let result = self.finish_rounds_inner(visualizer);
+        self.error = result.err();
+        result
+    }
+
+    fn finish_rounds_inner(&mut self, mut visualizer: Option<&mut Visualizer>) -> Result<(), MicroBlossomError> {
+        while self.resolve_obstacles(visualizer.as_deref_mut())? && self.layer_id < self.num_layers() {
+            self.fuse_next_layer(visualizer.as_deref_mut())?;
+        }
+        self.prepare_fallback(visualizer.as_deref_mut())?;
+        self.load_solution(visualizer);
+        Ok(())
+    }
+
+    /// decode one shot and report the error instead of aborting; the failed shot has an empty solution
+    pub fn try_solve(&mut self, syndrome_pattern: &SyndromePattern) -> Result<(), MicroBlossomError> {
+        self.try_solve_visualizer(syndrome_pattern, None)
+    }
+
+    pub fn try_solve_visualizer(
+        &mut self,
+        syndrome_pattern: &SyndromePattern,
+        visualizer: Option<&mut Visualizer>,
+    ) -> Result<(), MicroBlossomError> {
+        let result = self.try_solve_inner(syndrome_pattern, visualizer);
+        self.error = result.err();
+        result
+    }
+
+    fn try_solve_inner(
+        &mut self,
+        syndrome_pattern: &SyndromePattern,
+        mut visualizer: Option<&mut Visualizer>,
+    ) -> Result<(), MicroBlossomError> {
+        assert!(self.defect_nodes.is_empty(), "must call `clear` between different runs");
+        self.shot_start = Instant::now();
+        if !syndrome_pattern.erasures.is_empty() {
+            if !syndrome_pattern.dynamic_weights.is_empty() {
+                return Err(MicroBlossomError::Unsupported {
+                    feature: UnsupportedFeature::ErasuresWithDynamicWeights,
+                });
+            }
+            self.subgraph_builder.load_erasures(&syndrome_pattern.erasures);
+            self.dual_module.driver.driver.load_erasures(&syndrome_pattern.erasures)?;
+        }
+        if !syndrome_pattern.dynamic_weights.is_empty() {
+            // the driver validates the weights before the subgraph builder uses them
+            self.dual_module
+                .driver
+                .driver
+                .load_dynamic_weights(&syndrome_pattern.dynamic_weights)?;
+            self.subgraph_builder.load_dynamic_weights(&syndrome_pattern.dynamic_weights);
+        }
+        for &defect_index in syndrome_pattern.defect_vertices.iter() {
+            self.add_defect(defect_index)?;
+        }
+        if let Some(visualizer) = visualizer.as_mut() {
+            visualizer.snapshot("syndrome".to_string(), self).unwrap();
+        }
+        self.iteration = 0;
+        // if there are pending fusion layers, execute them
+        while self.resolve_obstacles(visualizer.as_deref_mut())? && self.layer_id < self.num_layers() {
+            self.fuse_next_layer(visualizer.as_deref_mut())?;
+        }
+        self.prepare_fallback(visualizer.as_deref_mut())?;
+        self.load_solution(visualizer);
+        Ok(())
+    }
+
+    /// the part of the current matching that involves only the rounds at least `window` rounds older than the
+    /// latest pushed round; it is tentative: the defects of later rounds may still change these matchings, so only
+    /// the matching after [`Self::finish_rounds`] is final
+    pub fn tentative_matching(&mut self, window: usize) -> PerfectMatching {
+        let perfect_matching = self.perfect_matching();
+        let old_layers = self.layer_id.saturating_sub(window);
+        let layer_fusion = self.graph.layer_fusion.as_ref().unwrap();
+        let is_old_enough = |vertex_index: VertexIndex| match layer_fusion.vertex_layer_id.get(&vertex_index) {
+            Some(&layer_id) => layer_id < old_layers,
+            None => true, // virtual vertices do not belong to any round
+        };
+        let defect_index = |node: &DualNodePtr| match node.read_recursive().class {
+            DualNodeClass::DefectVertex { defect_index } => defect_index,
+            _ => unreachable!("the embedded primal module only reports defect vertices"),
+        };
+        let mut tentative = PerfectMatching::new();
+        for (node_1, node_2) in perfect_matching.peer_matchings.into_iter() {
+            if is_old_enough(defect_index(&node_1)) && is_old_enough(defect_index(&node_2)) {
+                tentative.peer_matchings.push((node_1, node_2));
+            }
+        }
+        for (node, virtual_vertex) in perfect_matching.virtual_matchings.into_iter() {
+            if is_old_enough(defect_index(&node)) && is_old_enough(virtual_vertex) {
+                tentative.virtual_matchings.push((node, virtual_vertex));
+            }
+        }
+        tentative
+    }
+}
+
+impl<Dual: SolverTrackedDual> PrimalDualSolver for SolverEmbeddedBoxed<Dual> {
+    fn clear(&mut self) {
+        self.primal_module.reset();
+        self.dual_module.reset();
+        self.subgraph_builder.clear();
+        self.defect_nodes.clear();
+        self.layer_id = 0;
+        self.iteration = 0;
+        self.error = None;
+        self.shot_start = Instant::now();
+        self.non_optimal = false;
+        self.fallback_matched = 0;
+    }
+    fn reset_profiler(&mut self) {
+        self.dual_module.driver.driver.reset_profiler();
+        self.count_non_optimal = 0;
+    }
+    /// the error is kept in [`SolverEmbeddedBoxed::error`] instead of aborting
+    fn solve_visualizer(&mut self, syndrome_pattern: &SyndromePattern, visualizer: Option<&mut Visualizer>) {
+        if let Err(error) = self.try_solve_visualizer(syndrome_pattern, visualizer) {
+            debug_assert_eq!(self.error, Some(error));
+        }
     }
     fn perfect_matching_visualizer(&mut self, visualizer: Option<&mut Visualizer>) -> PerfectMatching {
+        if self.error.is_some() {
+            return PerfectMatching::new();
+        }
         // this perfect matching is not necessarily complete when some of the matchings are inside the dual module
         let (mut perfect_matching, belonging) =
             perfect_matching_from_embedded_primal(&mut self.primal_module, &self.defect_nodes);
@@ -407,6 +735,9 @@ impl<Dual: SolverTrackedDual> PrimalDualSolver for SolverEmbeddedBoxed<Dual> {
         perfect_matching
             .virtual_matchings
             .append(&mut pre_matchings.virtual_matchings);
+        if self.non_optimal {
+            self.complete_matching_greedily(&mut perfect_matching, &belonging);
+        }
         if let Some(visualizer) = visualizer {
             visualizer
                 .snapshot_combined("perfect matching".to_string(), vec![self, &perfect_matching])
@@ -441,7 +772,12 @@ impl<Dual: SolverTrackedDual> PrimalDualSolver for SolverEmbeddedBoxed<Dual> {
             "dual": self.dual_module.driver.driver.generate_profiler_report(),
             "primal": {
                 "offloaded": self.offloaded,
+                "offloaded_by_type": self.offloaded_by_type,
+                "non_optimal": self.non_optimal,
+                "fallback_matched": self.fallback_matched,
+                "count_non_optimal": self.count_non_optimal,
             },
+            "error": self.error.map(|error| error.to_string()),
         })
     }
 }
@@ -450,3 +786,203 @@ pub type SolverEmbeddedComb = SolverEmbeddedBoxed<DualModuleCombDriver>;
 pub type SolverEmbeddedScala = SolverEmbeddedBoxed<DualModuleScalaDriver>;
 pub type SolverEmbeddedLooper = SolverEmbeddedBoxed<DualModuleLooperDriver>;
 pub type SolverEmbeddedAxi4 = SolverEmbeddedBoxed<DualModuleAxi4Driver>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    /// fails every `find_conflict` once the budget of operations is used up, like a dropped connection
+    struct FlakyCombDriver {
+        driver: DualModuleCombDriver,
+        budget: usize,
+    }
+
+    impl FlakyCombDriver {
+        fn consume(&mut self) -> Result<(), MicroBlossomError> {
+            if self.budget == 0 {
+                return Err(MicroBlossomError::Transport {
+                    kind: TransportErrorKind::ConnectionReset,
+                });
+            }
+            self.budget -= 1;
+            Ok(())
+        }
+    }
+
+    impl FallibleDualStacklessDriver for FlakyCombDriver {
+        fn try_reset(&mut self) -> Result<(), MicroBlossomError> {
+            self.driver.try_reset()
+        }
+        fn try_set_speed(
+            &mut self,
+            is_blossom: bool,
+            node: CompactNodeIndex,
+            speed: CompactGrowState,
+        ) -> Result<(), MicroBlossomError> {
+            self.driver.try_set_speed(is_blossom, node, speed)
+        }
+        fn try_set_blossom(&mut self, node: CompactNodeIndex, blossom: CompactNodeIndex) -> Result<(), MicroBlossomError> {
+            self.driver.try_set_blossom(node, blossom)
+        }
+        fn try_find_obstacle(&mut self) -> Result<(CompactObstacle, CompactWeight), MicroBlossomError> {
+            self.consume()?;
+            self.driver.try_find_obstacle()
+        }
+        fn try_add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) -> Result<(), MicroBlossomError> {
+            self.driver.try_add_defect(vertex, node)
+        }
+    }
+
+    impl FallibleDualTrackedDriver for FlakyCombDriver {
+        fn try_find_conflict(
+            &mut self,
+            maximum_growth: CompactWeight,
+        ) -> Result<(CompactObstacle, CompactWeight), MicroBlossomError> {
+            self.consume()?;
+            self.driver.try_find_conflict(maximum_growth)
+        }
+    }
+
+    impl FusionVisualizer for FlakyCombDriver {
+        fn snapshot(&self, abbrev: bool) -> serde_json::Value {
+            self.driver.snapshot(abbrev)
+        }
+    }
+
+    impl SolverTrackedDual for FlakyCombDriver {
+        fn new_from_graph_config(graph: MicroBlossomSingle, config: serde_json::Value) -> Self {
+            Self {
+                driver: DualModuleCombDriver::new_from_graph_config(graph, config),
+                budget: usize::MAX,
+            }
+        }
+    }
+
+    #[test]
+    fn solver_embedded_boxed_error_per_shot() {
+        // cargo test solver_embedded_boxed_error_per_shot -- --nocapture
+        let code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let syndrome_pattern = SyndromePattern::new_vertices(vec![10, 18, 25, 33]);
+        let mut reference = SolverEmbeddedComb::new(graph.clone(), json!({}));
+        reference.solve(&syndrome_pattern);
+        let expected = reference.subgraph();
+        let mut solver: SolverEmbeddedBoxed<FlakyCombDriver> = SolverEmbeddedBoxed::new(graph, json!({}));
+        // the connection drops in the middle of the shot
+        solver.dual_module.driver.driver.budget = 2;
+        assert_eq!(
+            solver.try_solve(&syndrome_pattern),
+            Err(MicroBlossomError::Transport {
+                kind: TransportErrorKind::ConnectionReset,
+            })
+        );
+        assert!(solver.subgraph().is_empty());
+        assert_eq!(
+            solver.generate_profiler_report()["error"],
+            json!("transport failure: connection reset")
+        );
+        // the next shot is not affected once the connection recovers
+        solver.clear();
+        solver.dual_module.driver.driver.budget = usize::MAX;
+        solver.solve(&syndrome_pattern);
+        assert_eq!(solver.error(), None);
+        assert_eq!(solver.subgraph(), expected);
+    }
+
+    #[test]
+    fn solver_embedded_boxed_deadline() {
+        // cargo test solver_embedded_boxed_deadline -- --nocapture
+        let mut code = PhenomenologicalPlanarCode::new(5, 5, 0.05, 500);
+        let initializer = code.get_initializer();
+        let graph = MicroBlossomSingle::new_code(&code);
+        for (support_offloading, support_layer_fusion) in [(false, false), (true, false), (false, true), (true, true)] {
+            let sim_config = json!({
+                "support_offloading": support_offloading,
+                "support_layer_fusion": support_layer_fusion,
+            });
+            let mut optimal = SolverEmbeddedComb::new(graph.clone(), json!({ "dual": { "sim_config": sim_config } }));
+            let mut deadline = SolverEmbeddedComb::new(
+                graph.clone(),
+                json!({ "dual": { "sim_config": sim_config }, "deadline_iterations": 3 }),
+            );
+            let mut count_non_optimal = 0;
+            let mut subgraph_builder = SubGraphBuilder::new(&initializer);
+            let mut total_weight = |subgraph: &[EdgeIndex]| {
+                subgraph_builder.clear();
+                subgraph_builder.load_subgraph(subgraph);
+                subgraph_builder.total_weight()
+            };
+            for seed in 0..20 {
+                let syndrome_pattern = code.generate_random_errors(seed);
+                optimal.solve(&syndrome_pattern);
+                deadline.solve(&syndrome_pattern);
+                let defects: BTreeSet<_> = syndrome_pattern.defect_vertices.iter().cloned().collect();
+                let subgraph = deadline.subgraph();
+                assert_eq!(initializer.syndrome_of(&subgraph), defects, "seed {seed}");
+                assert!(!optimal.non_optimal);
+                if deadline.non_optimal {
+                    count_non_optimal += 1;
+                    assert!(total_weight(&subgraph) >= total_weight(&optimal.subgraph()), "seed {seed}");
+                } else {
+                    assert_eq!(subgraph, optimal.subgraph());
+                }
+                optimal.clear();
+                deadline.clear();
+            }
+            assert!(count_non_optimal > 0);
+            let report = deadline.generate_profiler_report();
+            assert_eq!(report["primal"]["count_non_optimal"], json!(count_non_optimal));
+            deadline.reset_profiler();
+            assert_eq!(deadline.generate_profiler_report()["primal"]["count_non_optimal"], json!(0));
+        }
+    }
+
+    #[test]
+    fn solver_embedded_boxed_streaming_deadline() {
+        // cargo test solver_embedded_boxed_streaming_deadline -- --nocapture
+        let mut code = PhenomenologicalPlanarCode::new(5, 4, 0.05, 500);
+        let initializer = code.get_initializer();
+        let graph = MicroBlossomSingle::new_code(&code);
+        let layer_fusion = graph.layer_fusion.clone().unwrap();
+        let stream = |solver: &mut SolverEmbeddedComb, syndrome_pattern: &SyndromePattern| {
+            for round in 0..layer_fusion.num_layers {
+                let defect_vertices: Vec<_> = (syndrome_pattern.defect_vertices.iter())
+                    .filter(|vertex_index| layer_fusion.vertex_layer_id[vertex_index] == round)
+                    .cloned()
+                    .collect();
+                solver.push_round(&defect_vertices).unwrap();
+            }
+            solver.finish_rounds(None).unwrap();
+        };
+        let sim_config = json!({ "support_layer_fusion": true });
+        // the deadline counts from the first round of each shot, not from the construction or the last shot
+        let mut solver = SolverEmbeddedComb::new(
+            graph.clone(),
+            json!({ "dual": { "sim_config": sim_config }, "deadline_ns": 500_000_000 }),
+        );
+        let syndrome_pattern = SyndromePattern::new_vertices(vec![37, 38]);
+        for _ in 0..2 {
+            std::thread::sleep(std::time::Duration::from_millis(600));
+            stream(&mut solver, &syndrome_pattern);
+            assert!(!solver.non_optimal);
+            solver.clear();
+        }
+        // an expired deadline still produces a valid matching
+        let mut solver = SolverEmbeddedComb::new(graph, json!({ "dual": { "sim_config": sim_config }, "deadline_ns": 0 }));
+        let mut count_non_optimal = 0;
+        for seed in 0..20 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            stream(&mut solver, &syndrome_pattern);
+            let defects: BTreeSet<_> = syndrome_pattern.defect_vertices.iter().cloned().collect();
+            assert_eq!(initializer.syndrome_of(&solver.subgraph()), defects, "seed {seed}");
+            count_non_optimal += solver.non_optimal as usize;
+            solver.clear();
+        }
+        assert!(count_non_optimal > 0);
+        assert_eq!(
+            solver.generate_profiler_report()["primal"]["count_non_optimal"],
+            json!(count_non_optimal)
+        );
+    }
+}
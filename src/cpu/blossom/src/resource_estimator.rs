@@ -0,0 +1,273 @@
+//! Resource Estimator
+//!
+//! Estimate the register usage of the distributed dual module before running synthesis. The bit widths follow
+//! `DualConfig.fitGraph` in the Scala implementation; the flip-flop counts are an approximation that ignores
+//! the combinational logic and the registers that synthesis tools may merge or remove.
+//!
+
+use crate::resources::*;
+use crate::simulation_tcp_client::*;
+use serde::Serialize;
+
+/// the instruction width broadcast to all the vertices and edges
+pub const INSTRUCTION_BITS: usize = 32;
+/// speed (2 bits), is_virtual and is_defect
+const VERTEX_STATE_FLAG_BITS: usize = 4;
+
+pub fn log2_up(value: usize) -> usize {
+    if value <= 1 {
+        0
+    } else {
+        (usize::BITS - (value - 1).leading_zeros()) as usize
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceEstimate {
+    pub vertex_num: usize,
+    pub edge_num: usize,
+    pub offloader_num: usize,
+    pub parity_reporter_num: usize,
+    pub max_degree: usize,
+    pub mean_degree: f64,
+    /// bits of a vertex or node index, see `DualConfig.vertexBits`
+    pub vertex_bits: usize,
+    /// bits of the edge weight, see `DualConfig.weightBits`
+    pub weight_bits: usize,
+    /// the maximum `grownBitsOf(vertex)` among all vertices, derived from `vertex_max_growth`
+    pub max_grown_bits: usize,
+    /// the number of copies of the states along the pipeline, i.e., 1 + the number of injected registers
+    pub pipeline_copies: usize,
+    /// the register bits of each vertex
+    pub vertex_register_bits: Vec<usize>,
+    /// the register bits of a single edge (all the edges have the same width)
+    pub edge_register_bits: usize,
+    pub vertex_flip_flops: usize,
+    pub edge_flip_flops: usize,
+    pub offloader_flip_flops: usize,
+    pub parity_flip_flops: usize,
+    /// broadcast and convergecast delay registers and the output of the conflict channels
+    pub global_flip_flops: usize,
+    pub total_flip_flops: usize,
+    /// when context depth is larger than 1, the states are stored in memories instead of registers
+    pub ram_bits: usize,
+    /// the ratio between the estimated flip-flops and the available flip-flops of the target FPGA
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utilization: Option<f64>,
+}
+
+impl ResourceEstimate {
+    /// fails on an empty graph, an edge out of range or an unsupported configuration
+    pub fn new(graph: &MicroBlossomSingle, sim_config: &SimulationConfig) -> Result<Self, String> {
+        if graph.vertex_num == 0 {
+            return Err("empty graph".to_string());
+        }
+        if sim_config.context_depth == 0 {
+            return Err("context depth must be at least 1".to_string());
+        }
+        if sim_config.conflict_channels == 0 || sim_config.conflict_channels > MAX_CONFLICT_CHANNELS {
+            return Err(format!(
+                "conflict channels must be within [1, {MAX_CONFLICT_CHANNELS}], got {}",
+                sim_config.conflict_channels
+            ));
+        }
+        // bit widths
+        let max_weight = graph.weighted_edges.iter().map(|edge| edge.w).max().unwrap_or(1).max(1) as usize;
+        let weight_bits = log2_up(max_weight + 1);
+        let mut vertex_bits = log2_up(graph.vertex_num * 2);
+        if weight_bits + 4 > vertex_bits * 2 {
+            vertex_bits = (weight_bits + 5) / 2;
+        }
+        vertex_bits = vertex_bits.max(5);
+        let grown_bits: Vec<usize> = (0..graph.vertex_num)
+            .map(|vertex_index| {
+                let max_growth = graph.vertex_max_growth.get(vertex_index).cloned().unwrap_or(0).max(0) as usize;
+                log2_up(max_growth + 1).max(weight_bits)
+            })
+            .collect();
+        // degree
+        let mut degrees = vec![0; graph.vertex_num];
+        for (edge_index, edge) in graph.weighted_edges.iter().enumerate() {
+            if edge.l >= graph.vertex_num || edge.r >= graph.vertex_num {
+                return Err(format!("edge {edge_index} ({}, {}) is out of range", edge.l, edge.r));
+            }
+            degrees[edge.l] += 1;
+            degrees[edge.r] += 1;
+        }
+        let max_degree = degrees.iter().cloned().max().unwrap_or(0);
+        let mean_degree = degrees.iter().sum::<usize>() as f64 / graph.vertex_num as f64;
+        // the hardware modules enabled by the configuration
+        let offloader_num = if sim_config.support_offloading {
+            graph
+                .offloading
+                .0
+                .iter()
+                .filter(|offloader| offloader.is_hardware_supported())
+                .count()
+        } else {
+            0
+        };
+        let parity_reporter_num = match (&graph.parity_reporters, sim_config.support_offloading) {
+            (Some(parity_reporters), true) => parity_reporters.reporters.len(),
+            _ => 0,
+        };
+        // each injected register stage duplicates the state and the signals of the incident edges
+        let injected = sim_config.inject_registers.len();
+        let pipeline_copies = 1 + injected;
+        let context_depth = sim_config.context_depth;
+        let vertex_state_bits: Vec<usize> = grown_bits
+            .iter()
+            .map(|grown_bits| 2 * vertex_bits + VERTEX_STATE_FLAG_BITS + grown_bits)
+            .collect();
+        let edge_state_bits = if sim_config.hard_code_weights { 0 } else { weight_bits };
+        let mut ram_bits = 0;
+        let vertex_register_bits: Vec<usize> = vertex_state_bits
+            .iter()
+            .zip(degrees.iter())
+            .map(|(&state_bits, &degree)| {
+                if context_depth > 1 {
+                    ram_bits += state_bits * context_depth;
+                }
+                state_bits * pipeline_copies + degree * injected
+            })
+            .collect();
+        if context_depth > 1 {
+            ram_bits += edge_state_bits * context_depth * graph.weighted_edges.len();
+        }
+        let edge_register_bits = edge_state_bits * pipeline_copies + injected; // the tight flag along the pipeline
+        let vertex_flip_flops: usize = vertex_register_bits.iter().sum();
+        let edge_flip_flops = edge_register_bits * graph.weighted_edges.len();
+        let offloader_flip_flops = offloader_num * pipeline_copies; // the condition bit
+        let parity_flip_flops = parity_reporter_num;
+        // a conflict carries 6 indices and a valid bit
+        let conflict_bits = 6 * vertex_bits + 1;
+        let convergecast_bits = conflict_bits * sim_config.conflict_channels + weight_bits;
+        let mut global_flip_flops = INSTRUCTION_BITS * sim_config.broadcast_delay
+            + convergecast_bits * sim_config.convergecast_delay
+            + log2_up(context_depth) * (sim_config.broadcast_delay + sim_config.convergecast_delay);
+        if context_depth > 1 {
+            ram_bits += convergecast_bits * context_depth;
+        } else {
+            global_flip_flops += convergecast_bits;
+        }
+        let total_flip_flops =
+            vertex_flip_flops + edge_flip_flops + offloader_flip_flops + parity_flip_flops + global_flip_flops;
+        Ok(Self {
+            vertex_num: graph.vertex_num,
+            edge_num: graph.weighted_edges.len(),
+            offloader_num,
+            parity_reporter_num,
+            max_degree,
+            mean_degree,
+            vertex_bits,
+            weight_bits,
+            max_grown_bits: grown_bits.iter().cloned().max().unwrap_or(0),
+            pipeline_copies,
+            vertex_register_bits,
+            edge_register_bits,
+            vertex_flip_flops,
+            edge_flip_flops,
+            offloader_flip_flops,
+            parity_flip_flops,
+            global_flip_flops,
+            total_flip_flops,
+            ram_bits,
+            utilization: None,
+        })
+    }
+
+    pub fn with_available_flip_flops(mut self, available_flip_flops: usize) -> Self {
+        self.utilization = Some(self.total_flip_flops as f64 / available_flip_flops as f64);
+        self
+    }
+
+    pub fn fits(&self) -> Option<bool> {
+        self.utilization.map(|utilization| utilization <= 1.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn resource_estimator_log2_up() {
+        // cargo test resource_estimator_log2_up -- --nocapture
+        let expected = [
+            (0, 0),
+            (1, 0),
+            (2, 1),
+            (3, 2),
+            (4, 2),
+            (5, 3),
+            (8, 3),
+            (9, 4),
+            (1024, 10),
+            (1025, 11),
+        ];
+        for (value, bits) in expected {
+            assert_eq!(log2_up(value), bits, "log2_up({value})");
+        }
+    }
+
+    #[test]
+    fn resource_estimator_code_capacity() {
+        // cargo test resource_estimator_code_capacity -- --nocapture
+        let code = CodeCapacityPlanarCode::new(3, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let sim_config = SimulationConfig {
+            context_depth: 1,
+            hard_code_weights: false,
+            inject_registers: vec![],
+            ..Default::default()
+        };
+        let estimate = ResourceEstimate::new(&graph, &sim_config).unwrap();
+        println!("{}", serde_json::to_string_pretty(&estimate).unwrap());
+        let max_weight = graph.weighted_edges.iter().map(|edge| edge.w).max().unwrap() as usize;
+        assert_eq!(estimate.weight_bits, log2_up(max_weight + 1));
+        assert!(estimate.vertex_bits >= 5 && estimate.vertex_bits * 2 >= estimate.weight_bits + 4);
+        assert_eq!(estimate.edge_register_bits, estimate.weight_bits);
+        assert_eq!(estimate.ram_bits, 0);
+        assert_eq!(estimate.fits(), None);
+        // the simulation-only offloaders are not built in hardware
+        let mut second_order = graph.clone();
+        second_order.add_second_order_offloading();
+        assert!(second_order.offloading.0.len() > graph.offloading.0.len());
+        let sim_config = SimulationConfig {
+            support_offloading: true,
+            ..sim_config
+        };
+        let simulated = ResourceEstimate::new(&second_order, &sim_config).unwrap();
+        assert_eq!(simulated.offloader_num, graph.offloading.0.len());
+        // more pipeline stages, more contexts and hard-coded weights
+        let sim_config = SimulationConfig {
+            context_depth: 4,
+            hard_code_weights: true,
+            inject_registers: vec!["offloadGet".to_string(), "updateGet".to_string()],
+            ..Default::default()
+        };
+        let pipelined = ResourceEstimate::new(&graph, &sim_config)
+            .unwrap()
+            .with_available_flip_flops(1000);
+        assert_eq!(pipelined.pipeline_copies, 3);
+        assert_eq!(pipelined.edge_register_bits, 2);
+        assert!(pipelined.vertex_flip_flops > 2 * estimate.vertex_flip_flops);
+        assert!(pipelined.ram_bits > 0);
+        assert_eq!(pipelined.fits(), Some(pipelined.total_flip_flops <= 1000));
+        // invalid configurations and graphs are reported as errors
+        let no_context = SimulationConfig {
+            context_depth: 0,
+            ..Default::default()
+        };
+        assert!(ResourceEstimate::new(&graph, &no_context).is_err());
+        let no_channel = SimulationConfig {
+            conflict_channels: 0,
+            ..Default::default()
+        };
+        assert!(ResourceEstimate::new(&graph, &no_channel).is_err());
+        let mut broken = graph.clone();
+        broken.weighted_edges[0].r = graph.vertex_num;
+        assert!(ResourceEstimate::new(&broken, &SimulationConfig::default()).is_err());
+    }
+}
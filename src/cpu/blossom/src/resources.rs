@@ -7,7 +7,8 @@ use mwmatching::Matching;
 use ordered_float::OrderedFloat;
 use petgraph::{algo::floyd_warshall, prelude::*};
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, BTreeSet};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MicroBlossomSingle {
@@ -55,6 +56,14 @@ pub struct WeightedEdge {
     pub r: usize,
     /// weight
     pub w: isize,
+    /// the logical observables flipped by this edge, as a bit mask
+    #[serde(rename = "o")]
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub observables: u64,
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -103,8 +112,59 @@ pub enum OffloadingType {
         #[serde(rename = "c")]
         conditioned_vertex: usize,
     },
+    /// second order: a pair of defects match with each other (regular edge) while one of them also touches the
+    /// boundary (the boundary edge); only modelled by the combinatorial dual module for now
+    #[serde(rename = "bm")]
+    BoundaryDefectMatch {
+        #[serde(rename = "e")]
+        edge_index: usize,
+        #[serde(rename = "b")]
+        boundary_edge_index: usize,
+    },
+    /// second order: a pair of defects match with each other (regular edge) while the chain vertex also touches a
+    /// non-defect vertex through the chain edge, which is only reachable from the chain vertex; only modelled by the
+    /// combinatorial dual module for now
+    #[serde(rename = "cm")]
+    ChainDefectMatch {
+        #[serde(rename = "e")]
+        edge_index: usize,
+        #[serde(rename = "c")]
+        chain_edge_index: usize,
+    },
+}
+
+impl OffloadingType {
+    /// the edge that is matched when the offloader takes effect
+    pub fn edge_index(&self) -> usize {
+        match self {
+            Self::DefectMatch { edge_index }
+            | Self::VirtualMatch { edge_index, .. }
+            | Self::FusionMatch { edge_index, .. }
+            | Self::BoundaryDefectMatch { edge_index, .. }
+            | Self::ChainDefectMatch { edge_index, .. } => *edge_index,
+        }
+    }
+
+    /// the same short name as in the graph configuration
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::DefectMatch { .. } => "dm",
+            Self::VirtualMatch { .. } => "vm",
+            Self::FusionMatch { .. } => "fm",
+            Self::BoundaryDefectMatch { .. } => "bm",
+            Self::ChainDefectMatch { .. } => "cm",
+        }
+    }
+
+    /// whether the Scala offloaders implement this type; the others only run in the combinatorial simulator
+    pub fn is_hardware_supported(&self) -> bool {
+        !matches!(self, Self::BoundaryDefectMatch { .. } | Self::ChainDefectMatch { .. })
+    }
 }
 
+/// (peer matchings, virtual matchings) as indices into the defect vertices
+pub type GreedyMatching = (Vec<(usize, usize)>, Vec<(usize, VertexIndex)>);
+
 impl MicroBlossomSingle {
     pub fn new(initializer: &SolverInitializer, positions: &[VisualizePosition]) -> Self {
         let positions: Vec<_> = positions.iter().map(|p| Position { t: p.t, i: p.i, j: p.j }).collect();
@@ -115,6 +175,7 @@ impl MicroBlossomSingle {
                 l: e.0.try_into().unwrap(),
                 r: e.1.try_into().unwrap(),
                 w: e.2,
+                observables: 0,
             })
             .collect();
         // construct vertex and edge binary tree with geometric distance information
@@ -152,6 +213,18 @@ impl MicroBlossomSingle {
         result
     }
 
+    /// replace the automatically inferred layers with a user-defined fusion plan
+    pub fn set_layer_fusion_plan(&mut self, plan: &LayerFusionPlan) -> Result<(), String> {
+        self.layer_fusion = Some(LayerFusion::from_plan(self, plan)?);
+        Ok(())
+    }
+
+    /// append the second-order offloaders, see [`OffloadingFinder::find_second_order`]
+    pub fn add_second_order_offloading(&mut self) {
+        let initializer = self.get_initializer();
+        self.offloading.find_second_order(&initializer);
+    }
+
     pub fn new_code(code: &dyn ExampleCode) -> Self {
         let initializer = code.get_initializer();
         let positions = code.get_positions();
@@ -176,12 +249,135 @@ impl MicroBlossomSingle {
         )
     }
 
+    /// a best-effort matching of the defect vertices by repeatedly taking the cheapest remaining option, either a
+    /// pair of defects or a defect with its nearest virtual vertex, in shortest path distances;
+    /// return the (peer matchings, virtual matchings) as indices into `defect_vertices`
+    pub fn greedy_matching(&self, defect_vertices: &[VertexIndex]) -> GreedyMatching {
+        let mut neighbors = vec![vec![]; self.vertex_num];
+        for edge in self.weighted_edges.iter() {
+            neighbors[edge.l].push((edge.r, edge.w));
+            neighbors[edge.r].push((edge.l, edge.w));
+        }
+        let shortest_distances = |source: VertexIndex| -> Vec<Weight> {
+            let mut distances = vec![Weight::MAX; self.vertex_num];
+            let mut heap = BinaryHeap::new();
+            distances[source] = 0;
+            heap.push(Reverse((0, source)));
+            while let Some(Reverse((distance, vertex_index))) = heap.pop() {
+                if distance > distances[vertex_index] {
+                    continue;
+                }
+                for &(peer_index, weight) in neighbors[vertex_index].iter() {
+                    if distance + weight < distances[peer_index] {
+                        distances[peer_index] = distance + weight;
+                        heap.push(Reverse((distance + weight, peer_index)));
+                    }
+                }
+            }
+            distances
+        };
+        // (cost, defect, peer defect or virtual vertex)
+        let mut options: Vec<(Weight, usize, Result<usize, VertexIndex>)> = vec![];
+        for (i, &defect_i) in defect_vertices.iter().enumerate() {
+            let distances = shortest_distances(defect_i);
+            if let Some(&virtual_vertex) = self.virtual_vertices.iter().min_by_key(|&&v| distances[v]) {
+                if distances[virtual_vertex] != Weight::MAX {
+                    options.push((distances[virtual_vertex], i, Err(virtual_vertex)));
+                }
+            }
+            for (j, &defect_j) in defect_vertices.iter().enumerate().skip(i + 1) {
+                if distances[defect_j] != Weight::MAX {
+                    options.push((distances[defect_j], i, Ok(j)));
+                }
+            }
+        }
+        options.sort();
+        let mut matched = vec![false; defect_vertices.len()];
+        let (mut peer_matchings, mut virtual_matchings) = (vec![], vec![]);
+        for (_, i, target) in options {
+            match target {
+                Ok(j) if !matched[i] && !matched[j] => {
+                    matched[i] = true;
+                    matched[j] = true;
+                    peer_matchings.push((i, j));
+                }
+                Err(virtual_vertex) if !matched[i] => {
+                    matched[i] = true;
+                    virtual_matchings.push((i, virtual_vertex));
+                }
+                _ => {}
+            }
+        }
+        (peer_matchings, virtual_matchings)
+    }
+
     pub fn get_positions(&self) -> Vec<VisualizePosition> {
         self.positions
             .iter()
             .map(|position| VisualizePosition::new(position.i, position.j, position.t))
             .collect()
     }
+
+    /// for codes with two boundaries (e.g. the example planar codes), define the logical observable `observable`
+    /// as the parity of the edges connecting to one of the boundaries. The virtual vertices are divided into two
+    /// sides along the axis (i or j) where they spread the most.
+    pub fn set_boundary_observable(&mut self, observable: usize) {
+        assert!(observable < 64, "at most 64 logical observables are supported");
+        let range = |axis: fn(&Position) -> f64| {
+            let values = self
+                .virtual_vertices
+                .iter()
+                .map(|&vertex_index| axis(&self.positions[vertex_index]));
+            values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+                (min.min(value), max.max(value))
+            })
+        };
+        let (min_i, max_i) = range(|position| position.i);
+        let (min_j, max_j) = range(|position| position.j);
+        let is_left: BTreeSet<usize> = self
+            .virtual_vertices
+            .iter()
+            .filter(|&&vertex_index| {
+                let position = &self.positions[vertex_index];
+                if max_i - min_i > max_j - min_j {
+                    position.i < (min_i + max_i) / 2.
+                } else {
+                    position.j < (min_j + max_j) / 2.
+                }
+            })
+            .cloned()
+            .collect();
+        for edge in self.weighted_edges.iter_mut() {
+            if is_left.contains(&edge.l) || is_left.contains(&edge.r) {
+                edge.observables ^= 1 << observable;
+            }
+        }
+    }
+
+    /// the logical observables flipped by a set of edges, e.g. a subgraph returned by the decoder
+    pub fn subgraph_observables(&self, subgraph: &[usize]) -> u64 {
+        subgraph.iter().fold(0, |observables, &edge_index| {
+            observables ^ self.weighted_edges[edge_index].observables
+        })
+    }
+
+    /// the number of logical observables, i.e., the highest bit set on any edge plus 1
+    pub fn observable_num(&self) -> usize {
+        let observables = self.weighted_edges.iter().fold(0, |mask, edge| mask | edge.observables);
+        64 - observables.leading_zeros() as usize
+    }
+
+    /// combine the subgraph of the CPU (without the pre-matched edges) with the hardware parity reports,
+    /// assuming the parity reporters are generated by [`ParityReporters::from_observables`]
+    pub fn observables_with_parity_reports(&self, cpu_subgraph: &[usize], parity_reports: &[bool]) -> u64 {
+        let mut observables = self.subgraph_observables(cpu_subgraph);
+        for (observable, &report) in parity_reports.iter().enumerate() {
+            if report {
+                observables ^= 1 << observable;
+            }
+        }
+        observables
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -227,6 +423,73 @@ impl OffloadingFinder {
             }
         }
     }
+
+    /// the second-order offloaders are not generated by default because the hardware does not implement them yet
+    pub fn find_second_order(&mut self, initializer: &SolverInitializer) {
+        self.find_boundary_defect_match(initializer);
+        self.find_chain_defect_match(initializer);
+    }
+
+    pub fn find_boundary_defect_match(&mut self, initializer: &SolverInitializer) {
+        let virtual_vertices: BTreeSet<_> = initializer.virtual_vertices.iter().cloned().collect();
+        let mut boundary_edges: BTreeMap<VertexIndex, Vec<usize>> = BTreeMap::new();
+        for (edge_index, (l, r, _weight)) in initializer.weighted_edges.iter().enumerate() {
+            match (virtual_vertices.contains(l), virtual_vertices.contains(r)) {
+                (false, true) => boundary_edges.entry(*l).or_default().push(edge_index),
+                (true, false) => boundary_edges.entry(*r).or_default().push(edge_index),
+                _ => {}
+            }
+        }
+        for (edge_index, (l, r, _weight)) in initializer.weighted_edges.iter().enumerate() {
+            if virtual_vertices.contains(l) || virtual_vertices.contains(r) {
+                continue;
+            }
+            for vertex_index in [l, r] {
+                for &boundary_edge_index in boundary_edges.get(vertex_index).into_iter().flatten() {
+                    self.0.push(OffloadingType::BoundaryDefectMatch {
+                        edge_index,
+                        boundary_edge_index,
+                    })
+                }
+            }
+        }
+    }
+
+    pub fn find_chain_defect_match(&mut self, initializer: &SolverInitializer) {
+        let virtual_vertices: BTreeSet<_> = initializer.virtual_vertices.iter().cloned().collect();
+        let mut regular_edges: BTreeMap<VertexIndex, Vec<usize>> = BTreeMap::new();
+        for (edge_index, (l, r, _weight)) in initializer.weighted_edges.iter().enumerate() {
+            if !virtual_vertices.contains(l) && !virtual_vertices.contains(r) {
+                regular_edges.entry(*l).or_default().push(edge_index);
+                regular_edges.entry(*r).or_default().push(edge_index);
+            }
+        }
+        for edges in regular_edges.values() {
+            for &edge_index in edges.iter() {
+                for &chain_edge_index in edges.iter() {
+                    let (l, r, _weight) = initializer.weighted_edges[edge_index];
+                    let (chain_l, chain_r, _weight) = initializer.weighted_edges[chain_edge_index];
+                    // the chain edge must lead to a third vertex, which also excludes the matched edge itself
+                    if (chain_l == l && chain_r == r) || (chain_l == r && chain_r == l) {
+                        continue;
+                    }
+                    self.0.push(OffloadingType::ChainDefectMatch {
+                        edge_index,
+                        chain_edge_index,
+                    })
+                }
+            }
+        }
+    }
+
+    /// the number of offloaders of each type
+    pub fn count_by_type(&self) -> BTreeMap<&'static str, usize> {
+        let mut counts = BTreeMap::new();
+        for offloader in self.0.iter() {
+            *counts.entry(offloader.name()).or_insert(0) += 1;
+        }
+        counts
+    }
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
@@ -351,6 +614,63 @@ impl BinaryTree {
         tree
     }
 
+    /// check the parent/child consistency of a tree with `leaf_num` leaves, returning a list of problems
+    pub fn validate(&self, leaf_num: usize) -> Vec<String> {
+        let mut errors = vec![];
+        let expected_num = if leaf_num == 0 { 0 } else { 2 * leaf_num - 1 };
+        if self.nodes.len() != expected_num {
+            errors.push(format!(
+                "{} nodes, expected {expected_num} for {leaf_num} leaves",
+                self.nodes.len()
+            ));
+            return errors;
+        }
+        let node_num = self.nodes.len();
+        let in_range = |index: Option<usize>| index.map_or(true, |index| index < node_num);
+        for (index, node) in self.nodes.iter().enumerate() {
+            if !in_range(node.parent) || !in_range(node.left) || !in_range(node.right) {
+                errors.push(format!("node {index} refers to a node out of range"));
+                continue;
+            }
+            if index < leaf_num {
+                if node.left.is_some() || node.right.is_some() {
+                    errors.push(format!("leaf {index} has children"));
+                }
+            } else {
+                for child in [node.left, node.right] {
+                    match child {
+                        Some(child) if self.nodes[child].parent == Some(index) => {}
+                        Some(child) => errors.push(format!("child {child} of node {index} does not point back")),
+                        None => errors.push(format!("internal node {index} misses a child")),
+                    }
+                }
+            }
+            match node.parent {
+                Some(parent) if !self.nodes[parent].has_child(index) => {
+                    errors.push(format!("parent {parent} of node {index} does not have it as a child"))
+                }
+                None if index + 1 != node_num => errors.push(format!("node {index} is a root but not the last node")),
+                _ => {}
+            }
+        }
+        if errors.is_empty() {
+            // every leaf should reach the root without a cycle
+            for leaf in 0..leaf_num {
+                let mut node_index = leaf;
+                let mut steps = 0;
+                while let Some(parent) = self.nodes[node_index].parent {
+                    node_index = parent;
+                    steps += 1;
+                    if steps > node_num {
+                        errors.push(format!("leaf {leaf} is in a cycle"));
+                        break;
+                    }
+                }
+            }
+        }
+        errors
+    }
+
     fn sanity_check(&self, positions: &[Position]) {
         assert_eq!(self.nodes.len(), positions.len() * 2 - 1);
         if positions.len() > 1 {
@@ -418,8 +738,48 @@ impl LayerFusion {
                 layers.insert(t, vec![vertex_index]);
             }
         }
+        Self::from_layers(graph, layers.into_values().collect())
+    }
+
+    /// load a user-defined fusion plan after checking that every non-virtual vertex belongs to exactly one layer
+    pub fn from_plan(graph: &MicroBlossomSingle, plan: &LayerFusionPlan) -> Result<Self, String> {
+        let virtual_vertices: BTreeSet<usize> = graph.virtual_vertices.iter().cloned().collect();
+        let mut assigned = vec![None; graph.vertex_num];
+        for (layer_id, vertices) in plan.layers.iter().enumerate() {
+            if vertices.is_empty() {
+                return Err(format!("layer {layer_id} is empty"));
+            }
+            for &vertex_index in vertices.iter() {
+                if vertex_index >= graph.vertex_num {
+                    return Err(format!("vertex {vertex_index} in layer {layer_id} does not exist"));
+                }
+                if virtual_vertices.contains(&vertex_index) {
+                    return Err(format!(
+                        "virtual vertex {vertex_index} cannot be assigned to layer {layer_id}"
+                    ));
+                }
+                if let Some(previous_layer_id) = assigned[vertex_index] {
+                    return Err(format!(
+                        "vertex {vertex_index} is assigned to both layer {previous_layer_id} and layer {layer_id}"
+                    ));
+                }
+                assigned[vertex_index] = Some(layer_id);
+            }
+        }
+        if let Some(vertex_index) = (0..graph.vertex_num)
+            .find(|vertex_index| assigned[*vertex_index].is_none() && !virtual_vertices.contains(vertex_index))
+        {
+            return Err(format!("vertex {vertex_index} is not assigned to any layer"));
+        }
+        Ok(Self::from_layers(graph, plan.layers.clone()))
+    }
+
+    /// derive the fusion edges from the non-virtual vertices of each layer, in the order of fusion;
+    /// every non-virtual vertex must belong to a layer, see [`LayerFusion::from_plan`] for a checked version
+    pub fn from_layers(graph: &MicroBlossomSingle, layers: Vec<Vec<usize>>) -> Self {
+        let virtual_vertices: BTreeSet<usize> = graph.virtual_vertices.iter().cloned().collect();
         let mut vertex_layer_id = BTreeMap::<usize, usize>::new(); // vertex_index: layer id
-        for (layer_id, vertices) in layers.values().enumerate() {
+        for (layer_id, vertices) in layers.iter().enumerate() {
             for vertex_index in vertices.iter() {
                 vertex_layer_id.insert(*vertex_index, layer_id);
             }
@@ -450,7 +810,7 @@ impl LayerFusion {
         }
         Self {
             num_layers: layers.len(),
-            layers: layers.values().cloned().collect(),
+            layers,
             vertex_layer_id,
             fusion_edges,
             unique_tight_conditions,
@@ -458,6 +818,48 @@ impl LayerFusion {
     }
 }
 
+/// a user-defined fusion plan, e.g. several measurement rounds per layer or spatial strips of a wide code
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayerFusionPlan {
+    /// `layers[layer_id] = Vec<vertices>`, in the order of fusion; every non-virtual vertex must appear exactly once
+    pub layers: Vec<Vec<usize>>,
+}
+
+impl LayerFusionPlan {
+    /// group the non-virtual vertices by their coordinate, `values_per_layer` distinct values per layer
+    fn group_by(
+        graph: &MicroBlossomSingle,
+        values_per_layer: usize,
+        coordinate: impl Fn(&Position) -> f64,
+    ) -> Result<Self, String> {
+        if values_per_layer == 0 {
+            return Err("each layer must cover at least one coordinate value".to_string());
+        }
+        let mut rounds_map = BTreeMap::<OrderedFloat<f64>, Vec<usize>>::new();
+        let virtual_vertices: BTreeSet<usize> = graph.virtual_vertices.iter().cloned().collect();
+        for (vertex_index, position) in graph.positions.iter().enumerate() {
+            if !virtual_vertices.contains(&vertex_index) {
+                rounds_map.entry(coordinate(position).into()).or_default().push(vertex_index);
+            }
+        }
+        let rounds: Vec<_> = rounds_map.into_values().collect();
+        Ok(Self {
+            layers: rounds.chunks(values_per_layer).map(|chunk| chunk.concat()).collect(),
+        })
+    }
+
+    /// fuse `rounds_per_layer` consecutive measurement rounds (distinct `t` values) at once
+    pub fn time_windows(graph: &MicroBlossomSingle, rounds_per_layer: usize) -> Result<Self, String> {
+        Self::group_by(graph, rounds_per_layer, |position| position.t)
+    }
+
+    /// split a wide code into strips along the `j` axis, each covering `columns_per_strip` distinct `j` values
+    pub fn spatial_strips(graph: &MicroBlossomSingle, columns_per_strip: usize) -> Result<Self, String> {
+        Self::group_by(graph, columns_per_strip, |position| position.j)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ParityReporters {
     /// a reporter the XOR of multiple offloader; there could be multiple reporters
@@ -472,6 +874,32 @@ impl ParityReporters {
     pub fn add_parity_reporter(&mut self, offloaders: Vec<usize>) {
         self.reporters.push(offloaders);
     }
+
+    /// one parity reporter per logical observable, collecting the pre-matchings that flip the observable;
+    /// the temporary fusion matchings are excluded because they are guaranteed to be removed in the end
+    pub fn from_observables(graph: &MicroBlossomSingle) -> Self {
+        let mut parity_reporters = Self::new();
+        for observable in 0..graph.observable_num() {
+            let offloaders = graph
+                .offloading
+                .0
+                .iter()
+                .enumerate()
+                .filter(|(_, offloader)| match offloader {
+                    OffloadingType::DefectMatch { edge_index }
+                    | OffloadingType::VirtualMatch { edge_index, .. }
+                    | OffloadingType::BoundaryDefectMatch { edge_index, .. }
+                    | OffloadingType::ChainDefectMatch { edge_index, .. } => {
+                        graph.weighted_edges[*edge_index].observables & (1 << observable) != 0
+                    }
+                    OffloadingType::FusionMatch { .. } => false,
+                })
+                .map(|(offloader_index, _)| offloader_index)
+                .collect();
+            parity_reporters.add_parity_reporter(offloaders);
+        }
+        parity_reporters
+    }
 }
 
 fn find_max_cardinality_matching_with_minimum_weight(vertices: &Vec<(Coordinate2D, usize)>) -> Vec<(usize, usize)> {
@@ -582,4 +1010,105 @@ mod tests {
         println!("{:?}", micro_blossom.layer_fusion);
         visualize_code(&mut code, visualize_filename);
     }
+
+    #[test]
+    fn resources_layer_fusion_plan_validation() {
+        // cargo test resources_layer_fusion_plan_validation -- --nocapture
+        let code = PhenomenologicalPlanarCode::new(3, 3, 0.1, 500);
+        let mut graph = MicroBlossomSingle::new_code(&code);
+        let inferred = graph.layer_fusion.clone().unwrap();
+        assert_eq!(inferred.num_layers, 4);
+        // one round per layer is the same as the inferred plan
+        graph
+            .set_layer_fusion_plan(&LayerFusionPlan::time_windows(&graph, 1).unwrap())
+            .unwrap();
+        assert_eq!(graph.layer_fusion.as_ref(), Some(&inferred));
+        // two rounds per layer: only the edges between the second and the third rounds are fusion edges
+        graph
+            .set_layer_fusion_plan(&LayerFusionPlan::time_windows(&graph, 2).unwrap())
+            .unwrap();
+        let layer_fusion = graph.layer_fusion.clone().unwrap();
+        assert_eq!(layer_fusion.num_layers, 2);
+        assert_eq!(
+            layer_fusion.layers[0],
+            [inferred.layers[0].clone(), inferred.layers[1].clone()].concat()
+        );
+        for (edge_index, conditioned_vertex) in layer_fusion.fusion_edges.iter() {
+            let edge = &graph.weighted_edges[*edge_index];
+            assert_eq!(inferred.vertex_layer_id[conditioned_vertex], 2);
+            assert!(inferred.vertex_layer_id[&edge.l].min(inferred.vertex_layer_id[&edge.r]) == 1);
+        }
+        // invalid plans are rejected
+        assert!(LayerFusionPlan::time_windows(&graph, 0).is_err());
+        let mut plan = LayerFusionPlan::time_windows(&graph, 2).unwrap();
+        let missing = plan.layers[1].pop().unwrap();
+        assert_eq!(
+            graph.set_layer_fusion_plan(&plan),
+            Err(format!("vertex {missing} is not assigned to any layer"))
+        );
+        let duplicated = plan.layers[0][0];
+        plan.layers[1].push(duplicated);
+        assert!(graph
+            .set_layer_fusion_plan(&plan)
+            .unwrap_err()
+            .contains("both layer 0 and layer 1"));
+        plan.layers[1].pop();
+        plan.layers[1].push(graph.virtual_vertices[0]);
+        assert!(graph.set_layer_fusion_plan(&plan).unwrap_err().starts_with("virtual vertex"));
+        plan.layers[1].pop();
+        plan.layers[1].push(graph.vertex_num);
+        assert!(graph.set_layer_fusion_plan(&plan).unwrap_err().contains("does not exist"));
+        plan.layers[1].pop();
+        plan.layers.push(vec![]);
+        assert_eq!(graph.set_layer_fusion_plan(&plan), Err("layer 2 is empty".to_string()));
+        // a failed attempt does not modify the graph
+        assert_eq!(graph.layer_fusion, Some(layer_fusion));
+    }
+
+    #[test]
+    fn resources_layer_fusion_plan_decoding() {
+        // cargo test resources_layer_fusion_plan_decoding -- --nocapture
+        use crate::mwpm_solver::*;
+        use fusion_blossom::mwpm_solver::*;
+        use fusion_blossom::primal_module::SubGraphBuilder;
+        let mut code = PhenomenologicalPlanarCode::new(5, 5, 0.02, 500);
+        let initializer = code.get_initializer();
+        let mut planar_code = CodeCapacityPlanarCode::new(7, 0.05, 500);
+        let planar_initializer = planar_code.get_initializer();
+        let graph = MicroBlossomSingle::new_code(&code);
+        let planar_graph = MicroBlossomSingle::new_code(&planar_code);
+        let cases = [
+            (
+                &mut code as &mut dyn ExampleCode,
+                &initializer,
+                LayerFusionPlan::time_windows(&graph, 2).unwrap(),
+                graph,
+            ),
+            (
+                &mut planar_code as &mut dyn ExampleCode,
+                &planar_initializer,
+                LayerFusionPlan::spatial_strips(&planar_graph, 2).unwrap(),
+                planar_graph,
+            ),
+        ];
+        for (code, initializer, plan, mut graph) in cases {
+            graph.set_layer_fusion_plan(&plan).unwrap();
+            assert_eq!(graph.layer_fusion.as_ref().unwrap().num_layers, 3);
+            let mut solver =
+                SolverEmbeddedComb::new(graph, json!({ "dual": { "sim_config": { "support_layer_fusion": true } } }));
+            let mut standard_solver = SolverSerial::new(initializer);
+            let mut subgraph_builder = SubGraphBuilder::new(initializer);
+            for seed in 0..30 {
+                let syndrome = code.generate_random_errors(seed);
+                solver.solve(&syndrome);
+                subgraph_builder.load_subgraph(&solver.subgraph());
+                let total_weight = subgraph_builder.total_weight();
+                standard_solver.solve(&syndrome);
+                subgraph_builder.load_subgraph(&standard_solver.subgraph());
+                assert_eq!(total_weight, subgraph_builder.total_weight(), "seed {seed}");
+                solver.clear();
+                standard_solver.clear();
+            }
+        }
+    }
 }
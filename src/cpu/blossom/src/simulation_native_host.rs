@@ -0,0 +1,891 @@
+//! Native Simulation Host
+//!
+//! A pure-Rust replacement of the Scala simulation hosts `LooperHost` and `MicroBlossomHost`.
+//! It speaks the same line-based TCP protocol and implements the same register map as `MicroBlossomBus.scala`,
+//! but every context is backed by a [`DualModuleCombDriver`] instead of a Verilator simulation.
+//!
+//! The timing is cycle-approximate: the `MicroBlossomLooper` pipeline is modeled by its read latency
+//! (broadcast delay + convergecast delay + execute latency), the instruction buffer by its depth and each bus
+//! transaction by a fixed number of cycles. This is enough for the timer and the load/finish timestamps to
+//! behave like the hardware without simulating every register.
+//!
+//! Enable it with `NATIVE_HOST=1` or `"native_host": true` in the simulation config.
+//!
+
+use crate::dual_module_comb::*;
+use crate::dual_module_looper::*;
+use crate::resources::*;
+use crate::simulation_tcp_client::*;
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+use micro_blossom_nostd::dual_module_stackless::*;
+use micro_blossom_nostd::instruction::*;
+use micro_blossom_nostd::interface::*;
+use micro_blossom_nostd::util::*;
+use scan_fmt::*;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::io::prelude::*;
+use std::io::{BufReader, LineWriter};
+use std::net::TcpStream;
+use std::thread::JoinHandle;
+
+/// the same as `DualConfig.version` in Scala
+pub const NATIVE_HOST_VERSION: u32 = 0x240123c0;
+pub const NATIVE_HOST_INSTRUCTION_BUFFER_DEPTH: usize = 4;
+/// clock cycles of a single bus write transaction
+pub const NATIVE_HOST_BUS_WRITE_CYCLES: u64 = 2;
+/// clock cycles of a single bus read transaction, not including the time waiting for the result
+pub const NATIVE_HOST_BUS_READ_CYCLES: u64 = 4;
+/// conflict channels are placed at 32 + 16 * i in the readout of each context, until the load stall emulator at 112
+pub const NATIVE_HOST_MAX_CONFLICT_CHANNELS: usize = 5;
+
+pub const READOUT_BASE: usize = 128 * 1024;
+pub const READOUT_SIZE: usize = 128 * 1024;
+pub const EXTERNAL_WEIGHTS_BASE: usize = 256 * 1024;
+pub const EXTERNAL_WEIGHTS_SIZE: usize = 256 * 1024;
+
+pub struct NativeSimulationHost {
+    pub graph: MicroBlossomSingle,
+    pub sim_config: SimulationConfig,
+    pub contexts: Vec<NativeHostContext>,
+    /// shared by all the contexts, copied to the edge registers by `LoadWeightsExternal`
+    pub external_weights: Vec<Weight>,
+    /// the 64 bits timer, counting the cycles of the bus clock
+    pub cycle: u64,
+    pub instruction_counter: u32,
+    pub readout_counter: u32,
+    pub transaction_counter: u32,
+    pub error_counter: u32,
+    /// the earliest cycle when the pipeline accepts another instruction
+    pipeline_free: u64,
+    /// the issue time of the instructions in the instruction buffer
+    instruction_buffer: VecDeque<u64>,
+}
+
+pub struct NativeHostContext {
+    pub dual_module: DualModuleCombDriver,
+    pub maximum_growth: u16,
+    pub accumulated_grown: u16,
+    /// `CompactWeight::MAX` means no obstacle
+    pub max_growable: CompactWeight,
+    pub conflicts: Vec<CompactObstacle>,
+    pub is_last_find_obstacle: bool,
+    /// the cycle when all the instructions of this context leave the pipeline
+    pub ready_time: u64,
+    /// the cycle when the last FindObstacle instruction of this context leaves the pipeline
+    pub find_obstacle_ready_time: u64,
+    pub load_time: u64,
+    pub finish_time: u64,
+    pub load_stall_start_time: u64,
+    pub load_stall_interval: u32,
+}
+
+impl NativeHostContext {
+    pub fn new(dual_module: DualModuleCombDriver) -> Self {
+        Self {
+            dual_module,
+            maximum_growth: 0,
+            accumulated_grown: 0,
+            max_growable: CompactWeight::MAX,
+            conflicts: vec![],
+            is_last_find_obstacle: false,
+            ready_time: 0,
+            find_obstacle_ready_time: 0,
+            load_time: 0,
+            finish_time: 0,
+            load_stall_start_time: 0,
+            load_stall_interval: 0,
+        }
+    }
+}
+
+impl NativeSimulationHost {
+    pub fn new(graph: MicroBlossomSingle, mut sim_config: SimulationConfig) -> Self {
+        assert!(sim_config.context_depth >= 1, "at least one context is required");
+        assert!(
+            sim_config.conflict_channels >= 1 && sim_config.conflict_channels <= NATIVE_HOST_MAX_CONFLICT_CHANNELS,
+            "the native host supports 1 to {NATIVE_HOST_MAX_CONFLICT_CHANNELS} conflict channels"
+        );
+        assert!(sim_config.clock_divide_by >= 1., "the bus must run at higher frequency");
+        sim_config.native_host = true;
+        let config: DualCombConfig = serde_json::from_value(json!({ "sim_config": sim_config })).unwrap();
+        Self {
+            external_weights: graph.weighted_edges.iter().map(|edge| edge.w as Weight).collect(),
+            contexts: (0..sim_config.context_depth)
+                .map(|_| NativeHostContext::new(DualModuleCombDriver::new(graph.clone(), config.clone())))
+                .collect(),
+            graph,
+            sim_config,
+            cycle: 0,
+            instruction_counter: 0,
+            readout_counter: 0,
+            transaction_counter: 0,
+            error_counter: 0,
+            pipeline_free: 0,
+            instruction_buffer: VecDeque::new(),
+        }
+    }
+
+    /// connect to a [`SimulationTcpClient`] listening on `hostname:port` and serve it in a new thread
+    pub fn spawn(simulation_name: &str, hostname: &str, port: u16) -> std::io::Result<JoinHandle<std::io::Result<()>>> {
+        let simulation_name = simulation_name.to_string();
+        let socket = TcpStream::connect(format!("{hostname}:{port}"))?;
+        std::thread::Builder::new()
+            .name(format!("{simulation_name}:{port}"))
+            .spawn(move || Self::serve(&simulation_name, socket))
+    }
+
+    pub fn serve(simulation_name: &str, socket: TcpStream) -> std::io::Result<()> {
+        assert!(
+            simulation_name == "LooperHost" || simulation_name == "MicroBlossomHost",
+            "native host does not support {simulation_name}"
+        );
+        let mut reader = BufReader::new(socket.try_clone()?);
+        let mut writer = LineWriter::new(socket);
+        writeln!(writer, "{simulation_name} v0.0.1, ask for decoding graph")?;
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let graph: MicroBlossomSingle = serde_json::from_str(&line)?;
+        let sim_config = SimulationConfig::read_from(&mut reader)?;
+        let mut host = Self::new(graph, sim_config);
+        writeln!(writer, "simulation started")?;
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(()); // client disconnected
+            }
+            let command = line.trim_end();
+            if command == "quit" {
+                return Ok(());
+            } else if let Some(json_content) = command.strip_prefix("execute: ") {
+                let input: InputData = serde_json::from_str(json_content)?;
+                let output = host.looper_execute(input)?;
+                writeln!(writer, "{}", serde_json::to_string(&output)?)?;
+            } else if command.starts_with("read(") {
+                let (num_bytes, address) = scan_fmt!(command, "read({d}, {d})", usize, usize).map_err(invalid_data)?;
+                let value = host.bus_read(num_bytes, address);
+                writeln!(writer, "{value}")?;
+            } else if command.starts_with("write(") {
+                let (num_bytes, address, data) =
+                    scan_fmt!(command, "write({d}, {d}, {d})", usize, usize, u64).map_err(invalid_data)?;
+                host.bus_write(num_bytes, address, data);
+            } else if let Some(abbrev) = command.strip_prefix("snapshot(").and_then(|s| s.strip_suffix(')')) {
+                let abbrev: bool = abbrev.parse().map_err(invalid_data)?;
+                writeln!(writer, "{}", host.contexts[0].dual_module.snapshot(abbrev))?;
+            } else if command == "pre_matchings()" {
+                let pre_matchings = host.pre_matchings(0);
+                writeln!(writer, "{}", serde_json::to_string(&pre_matchings)?)?;
+            } else {
+                return Err(invalid_data(format!("unknown command: {command}")));
+            }
+        }
+    }
+
+    /// the number of bus clock cycles from sending an instruction to receiving the obstacle
+    pub fn read_latency(&self) -> u64 {
+        let context_delay = if self.sim_config.context_depth != 1 { 2 } else { 0 };
+        let execute_latency = self.sim_config.inject_registers.len() + context_delay;
+        let latency = self.sim_config.broadcast_delay + self.sim_config.convergecast_delay + execute_latency;
+        self.slow_cycles(std::cmp::max(latency, 1) as u64)
+    }
+
+    fn slow_cycles(&self, cycles: u64) -> u64 {
+        (cycles as f64 * self.sim_config.clock_divide_by).ceil() as u64
+    }
+
+    /// convert an instruction to the combinatorial model; return `None` if not supported by the configuration
+    pub fn decode_instruction(&self, instruction: Instruction32) -> Option<Instruction> {
+        let sim_config = &self.sim_config;
+        if instruction.is_set_speed() {
+            return Some(Instruction::SetSpeed {
+                node: instruction.field1() as NodeIndex,
+                speed: instruction.get_speed(),
+            });
+        }
+        if instruction.is_set_blossom() {
+            return Some(Instruction::SetBlossom {
+                node: instruction.field1() as NodeIndex,
+                blossom: instruction.field2() as NodeIndex,
+            });
+        }
+        if instruction.op_code() == OP_CODE_ADD_DEFECT_VERTEX {
+            return sim_config.support_add_defect_vertex.then(|| Instruction::AddDefectVertex {
+                vertex: instruction.field1() as VertexIndex,
+                node: instruction.field2() as NodeIndex,
+            });
+        }
+        if !instruction.is_extended() {
+            return None; // Match instruction is not used
+        }
+        match instruction.extended_op_code() {
+            EXTENDED_OP_CODE_FIND_OBSTACLE => Some(Instruction::FindObstacle),
+            EXTENDED_OP_CODE_GROW => Some(Instruction::Grow {
+                length: instruction.get_length() as Weight,
+            }),
+            EXTENDED_OP_CODE_LOAD_DEFECTS_EXTERNAL => {
+                sim_config.support_layer_fusion.then(|| Instruction::LoadDefectsExternal {
+                    time: instruction.field1() as usize,
+                    channel: 0,
+                })
+            }
+            EXTENDED_OP_CODE_LOAD_WEIGHTS_EXTERNAL => {
+                (!sim_config.hard_code_weights).then_some(Instruction::LoadWeightsExternal)
+            }
+            _ => None,
+        }
+    }
+
+    /// execute an instruction functionally, including the spontaneous growth up to `maximum_growth` that the
+    /// looper performs by feeding the response back to the pipeline; return the obstacle, the grown length
+    /// and the number of rounds in the pipeline
+    fn execute(
+        &mut self,
+        context_id: usize,
+        instruction: Instruction32,
+        maximum_growth: u16,
+    ) -> (CompactObstacle, u16, u64) {
+        let is_reset = instruction.is_extended() && instruction.extended_op_code() == EXTENDED_OP_CODE_RESET;
+        let instruction = if is_reset {
+            self.contexts[context_id].dual_module.reset();
+            Instruction::FindObstacle
+        } else {
+            self.decode_instruction(instruction).unwrap_or_else(|| {
+                self.error_counter += 1;
+                Instruction::FindObstacle
+            })
+        };
+        let conflict_channels = self.sim_config.conflict_channels;
+        let context = &mut self.contexts[context_id];
+        let dual_module = &mut context.dual_module;
+        if matches!(instruction, Instruction::LoadWeightsExternal) {
+            dual_module.external_weights.clone_from(&self.external_weights);
+        }
+        let (mut obstacle, mut conflicts) = dual_module.execute_instruction_channels(instruction, conflict_channels);
+        let mut grown: u16 = 0;
+        let mut rounds = 1;
+        while let CompactObstacle::GrowLength { length } = obstacle {
+            if length == CompactWeight::MAX || length == 0 || grown >= maximum_growth {
+                break;
+            }
+            let length = std::cmp::min(length as u32, (maximum_growth - grown) as u32) as u16;
+            dual_module.execute_instruction(Instruction::Grow {
+                length: length as Weight,
+            });
+            grown += length;
+            (obstacle, conflicts) = dual_module.execute_instruction_channels(Instruction::FindObstacle, conflict_channels);
+            rounds += 2;
+        }
+        for conflict in conflicts.iter_mut() {
+            conflict.fix_conflict_order();
+        }
+        obstacle.fix_conflict_order();
+        context.max_growable = match obstacle {
+            CompactObstacle::GrowLength { length } => length,
+            _ => 0,
+        };
+        context.conflicts = conflicts;
+        (obstacle, grown, rounds)
+    }
+
+    /// model the timing of pushing an instruction into the pipeline
+    fn issue(&mut self, context_id: usize, rounds: u64) -> u64 {
+        // the bus stalls when the instruction buffer is full
+        while self
+            .instruction_buffer
+            .front()
+            .is_some_and(|&issue_time| issue_time <= self.cycle)
+        {
+            self.instruction_buffer.pop_front();
+        }
+        if self.instruction_buffer.len() >= NATIVE_HOST_INSTRUCTION_BUFFER_DEPTH {
+            self.cycle = self.instruction_buffer.pop_front().unwrap();
+        }
+        let latency = self.read_latency();
+        let slot = self.slow_cycles(1);
+        let context = &mut self.contexts[context_id];
+        // an instruction enters the pipeline only when there is no entry of the same context in the pipeline
+        let issue_time = self.cycle.max(self.pipeline_free).max(context.ready_time);
+        context.ready_time = issue_time + rounds * latency;
+        self.pipeline_free = issue_time + slot;
+        self.instruction_buffer.push_back(issue_time);
+        context.ready_time
+    }
+
+    pub fn looper_execute(&mut self, input: InputData) -> std::io::Result<OutputData> {
+        let context_id = input.context_id as usize;
+        if context_id >= self.contexts.len() {
+            return Err(invalid_data(format!("context {context_id} out of range")));
+        }
+        let (obstacle, grown, rounds) = self.execute(context_id, Instruction32(input.instruction), input.maximum_growth);
+        self.cycle = self.issue(context_id, rounds);
+        let max_growable = match obstacle {
+            CompactObstacle::GrowLength { length } if length != CompactWeight::MAX => {
+                std::cmp::min(length as u32, 65534) as u16
+            }
+            CompactObstacle::Conflict { .. } => 0,
+            _ => u16::MAX,
+        };
+        let conflict = match obstacle {
+            CompactObstacle::Conflict {
+                node_1,
+                node_2,
+                touch_1,
+                touch_2,
+                vertex_1,
+                vertex_2,
+            } => ConvergecastConflict {
+                node1: node_1.option().map(|v| v.get() as u16).unwrap_or(u16::MAX),
+                node2: node_2.option().map(|v| v.get() as u16),
+                touch1: touch_1.option().map(|v| v.get() as u16).unwrap_or(u16::MAX),
+                touch2: touch_2.option().map(|v| v.get() as u16),
+                vertex1: vertex_1.get() as u16,
+                vertex2: vertex_2.get() as u16,
+                valid: true,
+            },
+            _ => ConvergecastConflict {
+                node1: 0,
+                node2: None,
+                touch1: 0,
+                touch2: None,
+                vertex1: 0,
+                vertex2: 0,
+                valid: false,
+            },
+        };
+        Ok(OutputData {
+            context_id: input.context_id,
+            max_growable,
+            conflict,
+            grown,
+        })
+    }
+
+    /// write an instruction through the bus, following `stateWriteInstruction` in `MicroBlossomBus.scala`
+    fn bus_write_instruction(&mut self, context_id: usize, instruction: Instruction32) {
+        self.instruction_counter += 1;
+        if context_id >= self.contexts.len() {
+            self.error_counter += 1;
+            return;
+        }
+        self.push_instruction(context_id, instruction);
+    }
+
+    fn push_instruction(&mut self, context_id: usize, instruction: Instruction32) {
+        let is_find_obstacle = instruction.is_extended() && instruction.extended_op_code() == EXTENDED_OP_CODE_FIND_OBSTACLE;
+        let is_reset = instruction.is_extended() && instruction.extended_op_code() == EXTENDED_OP_CODE_RESET;
+        let is_load_defects =
+            instruction.is_extended() && instruction.extended_op_code() == EXTENDED_OP_CODE_LOAD_DEFECTS_EXTERNAL;
+        let is_changing_syndrome = is_reset || is_load_defects || instruction.op_code() == OP_CODE_ADD_DEFECT_VERTEX;
+        let context = &mut self.contexts[context_id];
+        // only a single FindObstacle instruction is allowed in the pipeline
+        if is_find_obstacle || is_reset {
+            self.cycle = self.cycle.max(context.find_obstacle_ready_time);
+        }
+        if is_load_defects && self.sim_config.support_load_stall_emulator {
+            let layer_id = instruction.field1() as u64;
+            let ready_time = context.load_stall_start_time + context.load_stall_interval as u64 * layer_id;
+            self.cycle = self.cycle.max(ready_time);
+        }
+        if is_changing_syndrome {
+            context.load_time = self.cycle;
+            context.finish_time = u64::MAX;
+        }
+        let maximum_growth = if is_find_obstacle {
+            context.maximum_growth.wrapping_sub(context.accumulated_grown)
+        } else {
+            0
+        };
+        let (_, grown, rounds) = self.execute(context_id, instruction, maximum_growth);
+        let ready_time = self.issue(context_id, rounds);
+        let context = &mut self.contexts[context_id];
+        context.accumulated_grown = context.accumulated_grown.wrapping_add(grown);
+        context.is_last_find_obstacle = is_find_obstacle;
+        if is_find_obstacle {
+            context.find_obstacle_ready_time = ready_time;
+            if context.max_growable == CompactWeight::MAX {
+                context.finish_time = ready_time;
+            }
+        }
+        if is_reset {
+            context.accumulated_grown = 0;
+            context.maximum_growth = 0;
+            context.load_stall_start_time = 0;
+            context.load_stall_interval = 0;
+        }
+    }
+
+    /// the 64 bits obstacle readout of a context, following the layout of `SingleReadout`
+    fn read_obstacle(&mut self, context_id: usize, channel: usize, is_upper: bool) -> u64 {
+        if !self.contexts[context_id].is_last_find_obstacle {
+            // reading the obstacle automatically issues a FindObstacle instruction
+            self.push_instruction(context_id, Instruction32::find_obstacle());
+        }
+        let context = &self.contexts[context_id];
+        self.cycle = self.cycle.max(context.ready_time);
+        let field = |value: OptionCompactNodeIndex| value.option().map(|v| v.get() as u64).unwrap_or(0xFFFF);
+        let (lower, upper) = match context.conflicts.get(channel) {
+            Some(&CompactObstacle::Conflict {
+                node_1,
+                node_2,
+                touch_1,
+                touch_2,
+                vertex_1,
+                vertex_2,
+            }) => (
+                field(node_1) | (field(node_2) << 16) | (field(touch_1) << 32) | (field(touch_2) << 48),
+                vertex_1.get() as u64 | ((vertex_2.get() as u64) << 16) | (1 << 32),
+                // conflict_valid = 1
+            ),
+            _ => (0, 0),
+        };
+        if !is_upper {
+            return lower;
+        }
+        let max_growable: u64 = if context.max_growable == CompactWeight::MAX {
+            0xFF
+        } else {
+            std::cmp::min(context.max_growable as u64, 0xFE)
+        };
+        upper | (max_growable << 40) | ((context.accumulated_grown as u64) << 48)
+    }
+
+    fn hardware_info(&self) -> (u64, u64) {
+        let sim_config = &self.sim_config;
+        let mut vertex_bits = usize::BITS - (2 * self.graph.vertex_num - 1).leading_zeros();
+        let max_weight = self.graph.weighted_edges.iter().map(|edge| edge.w).max().unwrap() as usize;
+        let weight_bits = usize::BITS - max_weight.leading_zeros();
+        if weight_bits + 4 > vertex_bits * 2 {
+            vertex_bits = (weight_bits + 5) / 2;
+        }
+        vertex_bits = std::cmp::max(vertex_bits, 5);
+        let flags = (sim_config.support_add_defect_vertex as u64)
+            | ((sim_config.support_offloading as u64) << 1)
+            | ((sim_config.support_layer_fusion as u64) << 2)
+            | ((sim_config.hard_code_weights as u64) << 3)
+            | (((sim_config.context_depth > 1) as u64) << 4)
+            | ((sim_config.use_64_bus as u64) << 5)
+            | ((sim_config.support_load_stall_emulator as u64) << 6);
+        let num_layers = self.graph.layer_fusion.as_ref().map(|fusion| fusion.num_layers).unwrap_or(0) as u64;
+        (
+            NATIVE_HOST_VERSION as u64 | ((sim_config.context_depth as u64) << 32),
+            sim_config.conflict_channels as u64
+                | ((vertex_bits as u64) << 8)
+                | ((weight_bits as u64) << 16)
+                | ((NATIVE_HOST_INSTRUCTION_BUFFER_DEPTH as u64) << 24)
+                | (flags << 32)
+                | (num_layers << 48),
+        )
+    }
+
+    /// read a 64 bits word at an 8-byte aligned address
+    fn read_word(&mut self, address: usize) -> u64 {
+        match address {
+            0 => self.cycle,
+            8 => self.hardware_info().0,
+            16 => self.hardware_info().1,
+            24 => self.instruction_counter as u64,
+            32 => self.readout_counter as u64,
+            40 => self.transaction_counter as u64,
+            48 => self.error_counter as u64,
+            _ if (READOUT_BASE..READOUT_BASE + READOUT_SIZE).contains(&address) => {
+                self.transaction_counter += 1;
+                let context_id = (address - READOUT_BASE) / 128;
+                let sub_address = (address - READOUT_BASE) % 128;
+                if context_id >= self.contexts.len() {
+                    self.error_counter += 1;
+                    return u64::MAX;
+                }
+                let channels = self.sim_config.conflict_channels;
+                match sub_address {
+                    0 => {
+                        self.cycle = self.cycle.max(self.contexts[context_id].ready_time);
+                        self.contexts[context_id].load_time
+                    }
+                    8 => self.contexts[context_id].finish_time,
+                    16 => {
+                        let context = &self.contexts[context_id];
+                        let max_growable: u64 = if context.max_growable == CompactWeight::MAX {
+                            0xFFFF
+                        } else {
+                            std::cmp::min(context.max_growable as u64, 0xFFFE)
+                        };
+                        context.maximum_growth as u64 | (max_growable << 16)
+                    }
+                    24 => 0, // parity reporters are not modeled
+                    _ if sub_address >= 32 && sub_address < 32 + 16 * channels => {
+                        self.readout_counter += 1;
+                        self.read_obstacle(context_id, (sub_address - 32) / 16, sub_address % 16 == 8)
+                    }
+                    112 if self.sim_config.support_load_stall_emulator => self.contexts[context_id].load_stall_start_time,
+                    120 if self.sim_config.support_load_stall_emulator => {
+                        self.contexts[context_id].load_stall_interval as u64
+                    }
+                    _ => {
+                        self.error_counter += 1;
+                        u64::MAX
+                    }
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    /// an unaligned access is counted in the error counter, as an invalid address of the bus
+    fn is_aligned(num_bytes: usize, address: usize) -> bool {
+        matches!(num_bytes, 1 | 2 | 4 | 8) && address % num_bytes == 0
+    }
+
+    pub fn bus_read(&mut self, num_bytes: usize, address: usize) -> u64 {
+        self.cycle += NATIVE_HOST_BUS_READ_CYCLES;
+        if !Self::is_aligned(num_bytes, address) {
+            self.error_counter += 1;
+            return 0;
+        }
+        let word = self.read_word(address - address % 8);
+        let value = word >> (8 * (address % 8));
+        if num_bytes == 8 {
+            value
+        } else {
+            value & ((1 << (8 * num_bytes)) - 1)
+        }
+    }
+
+    pub fn bus_write(&mut self, num_bytes: usize, address: usize, data: u64) {
+        self.cycle += NATIVE_HOST_BUS_WRITE_CYCLES;
+        if !Self::is_aligned(num_bytes, address) {
+            self.error_counter += 1;
+            return;
+        }
+        match address {
+            24 => self.instruction_counter = data as u32,
+            32 => self.readout_counter = data as u32,
+            40 => self.transaction_counter = data as u32,
+            48 => self.error_counter = data as u32,
+            4096 if self.sim_config.use_64_bus => {
+                self.transaction_counter += 1;
+                self.bus_write_instruction((data >> 32) as usize, Instruction32(data as u32));
+            }
+            _ if !self.sim_config.use_64_bus && (8192..12288).contains(&address) => {
+                self.transaction_counter += 1;
+                self.bus_write_instruction((address - 8192) / 4, Instruction32(data as u32));
+            }
+            _ if (READOUT_BASE..READOUT_BASE + READOUT_SIZE).contains(&address) => {
+                self.transaction_counter += 1;
+                let context_id = (address - READOUT_BASE) / 128;
+                let sub_address = (address - READOUT_BASE) % 128;
+                if context_id >= self.contexts.len() {
+                    self.error_counter += 1;
+                    return;
+                }
+                let support_load_stall_emulator = self.sim_config.support_load_stall_emulator;
+                let context = &mut self.contexts[context_id];
+                match sub_address {
+                    0 => context.accumulated_grown = 0,
+                    16 => {
+                        if data > u16::MAX as u64 {
+                            self.error_counter += 1;
+                        }
+                        if context.maximum_growth != data as u16 {
+                            // updating maximum growth will invalidate the previous readout value
+                            context.is_last_find_obstacle = false;
+                        }
+                        context.maximum_growth = data as u16;
+                    }
+                    112 if support_load_stall_emulator && num_bytes == 8 => context.load_stall_start_time = data,
+                    112 if support_load_stall_emulator => {
+                        context.load_stall_start_time = (context.load_stall_start_time & !0xFFFFFFFF) | data
+                    }
+                    116 if support_load_stall_emulator => {
+                        context.load_stall_start_time = (context.load_stall_start_time & 0xFFFFFFFF) | (data << 32)
+                    }
+                    120 if support_load_stall_emulator => context.load_stall_interval = data as u32,
+                    _ => self.error_counter += 1,
+                }
+            }
+            _ if (EXTERNAL_WEIGHTS_BASE..EXTERNAL_WEIGHTS_BASE + EXTERNAL_WEIGHTS_SIZE).contains(&address) => {
+                let edge_index = (address - EXTERNAL_WEIGHTS_BASE) / 2;
+                if self.sim_config.hard_code_weights || edge_index >= self.external_weights.len() {
+                    self.error_counter += 1;
+                    return;
+                }
+                self.external_weights[edge_index] = data as Weight;
+            }
+            _ => self.error_counter += 1,
+        }
+    }
+
+    pub fn pre_matchings(&self, context_id: usize) -> Vec<PreMatchingData> {
+        let dual_module = &self.contexts[context_id].dual_module;
+        dual_module
+            .pre_matching_edges()
+            .into_iter()
+            .map(|edge_index| {
+                let edge = &dual_module.edges[edge_index];
+                let mut vertex_1 = &dual_module.vertices[edge.left_index];
+                let mut vertex_2 = &dual_module.vertices[edge.right_index];
+                if vertex_1.registers.is_virtual {
+                    std::mem::swap(&mut vertex_1, &mut vertex_2);
+                }
+                let optional_u16 = |index: Option<NodeIndex>| index.map(|index| index as u16);
+                let is_peer_virtual = vertex_2.registers.is_virtual;
+                PreMatchingData {
+                    edge_index,
+                    node1: vertex_1.registers.node_index.unwrap() as u16,
+                    node2: if is_peer_virtual {
+                        None
+                    } else {
+                        optional_u16(vertex_2.registers.node_index)
+                    },
+                    touch1: vertex_1.registers.root_index.unwrap() as u16,
+                    touch2: if is_peer_virtual {
+                        None
+                    } else {
+                        optional_u16(vertex_2.registers.root_index)
+                    },
+                    vertex1: vertex_1.vertex_index as u16,
+                    vertex2: vertex_2.vertex_index as u16,
+                }
+            })
+            .collect()
+    }
+}
+
+fn invalid_data(error: impl ToString) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dual_module_adaptor::tests::*;
+    use crate::mwpm_solver::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::PrimalDualSolver;
+
+    fn native_host_graph(d: VertexNum) -> MicroBlossomSingle {
+        let code = CodeCapacityPlanarCode::new(d, 0.1, 500);
+        MicroBlossomSingle::new(&code.get_initializer(), &code.get_positions())
+    }
+
+    fn native_host_sim_config(sim_config: serde_json::Value) -> SimulationConfig {
+        serde_json::from_value(sim_config).unwrap()
+    }
+
+    #[test]
+    fn simulation_native_host_axi4_random() {
+        // cargo test simulation_native_host_axi4_random -- --nocapture
+        let d = 5;
+        for (index, sim_config) in [
+            json!({ "native_host": true }),
+            json!({ "native_host": true, "use_64_bus": false, "context_depth": 4 }),
+            json!({ "native_host": true, "broadcast_delay": 2, "convergecast_delay": 3, "support_offloading": true }),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            for seed in 0..20 {
+                let mut code = CodeCapacityPlanarCode::new(d, 0.1, 500);
+                let defect_vertices = code.generate_random_errors(seed).defect_vertices;
+                println!("[{index}] seed {seed}: {defect_vertices:?}");
+                let sim_config = sim_config.clone();
+                dual_module_standard_optional_viz(d, None, defect_vertices, |initializer, positions| {
+                    SolverEmbeddedAxi4::new(
+                        MicroBlossomSingle::new(initializer, positions),
+                        json!({ "dual": { "name": format!("native_axi4_{index}_{seed}"), "sim_config": sim_config } }),
+                    )
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn simulation_native_host_looper_basic() {
+        // cargo test simulation_native_host_looper_basic -- --nocapture
+        let defect_vertices = vec![13, 14, 20];
+        dual_module_standard_optional_viz(5, None, defect_vertices, |initializer, positions| {
+            SolverEmbeddedLooper::new(
+                MicroBlossomSingle::new(initializer, positions),
+                json!({ "dual": { "name": "native_looper_basic", "sim_config": {
+                    "native_host": true, "context_depth": 2, "support_offloading": true
+                } } }),
+            )
+        });
+    }
+
+    /// the hardware implements neither erasures nor dynamic weights with hard-coded weights, so the shot fails
+    /// instead of sending an unknown instruction
+    #[test]
+    fn simulation_native_host_axi4_unsupported() {
+        // cargo test simulation_native_host_axi4_unsupported -- --nocapture
+        let code = CodeCapacityPlanarCode::new(5, 0.1, 500);
+        let mut solver = SolverEmbeddedAxi4::new(
+            MicroBlossomSingle::new_code(&code),
+            json!({ "dual": { "name": "native_axi4_unsupported", "sim_config": { "native_host": true } } }),
+        );
+        let mut syndrome_pattern = SyndromePattern::new_vertices(vec![13, 14]);
+        syndrome_pattern.erasures = vec![0];
+        assert_eq!(
+            solver.try_solve(&syndrome_pattern),
+            Err(MicroBlossomError::Unsupported {
+                feature: UnsupportedFeature::Erasure
+            })
+        );
+        assert!(solver.subgraph().is_empty());
+        solver.clear();
+        let mut syndrome_pattern = SyndromePattern::new_vertices(vec![13, 14]);
+        syndrome_pattern.dynamic_weights = vec![(0, 2)];
+        assert_eq!(
+            solver.try_solve(&syndrome_pattern),
+            Err(MicroBlossomError::Unsupported {
+                feature: UnsupportedFeature::DynamicWeights
+            })
+        );
+        // the next shot without erasures or dynamic weights is not affected
+        solver.clear();
+        solver.solve(&SyndromePattern::new_vertices(vec![13, 14]));
+        assert_eq!(solver.error(), None);
+        assert_eq!(solver.subgraph().len(), 1);
+    }
+
+    /// without hard-coded weights, the dynamic weights are loaded through the external weights buffer
+    #[test]
+    fn simulation_native_host_axi4_dynamic_weights() {
+        // cargo test simulation_native_host_axi4_dynamic_weights -- --nocapture
+        use rand::Rng;
+        use rand_xoshiro::rand_core::SeedableRng;
+        let mut code = CodeCapacityPlanarCode::new(5, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let edge_num = graph.weighted_edges.len();
+        let mut solver = SolverEmbeddedAxi4::new(
+            graph.clone(),
+            json!({ "dual": { "name": "native_axi4_dynamic_weights", "sim_config": {
+                "native_host": true, "hard_code_weights": false
+            } } }),
+        );
+        let mut reference = SolverEmbeddedComb::new(graph, json!({}));
+        for seed in 0..20 {
+            let mut rng = rand_xoshiro::Xoroshiro128StarStar::seed_from_u64(seed);
+            let mut syndrome_pattern = code.generate_random_errors(seed);
+            // every other shot uses the default weights, which are recovered by the reset
+            if seed % 2 == 0 {
+                for edge_index in 0..edge_num {
+                    if rng.gen_bool(0.3) {
+                        syndrome_pattern
+                            .dynamic_weights
+                            .push((edge_index, 2 * rng.gen_range(0..=1000)));
+                    }
+                }
+            }
+            solver.solve(&syndrome_pattern);
+            reference.solve(&syndrome_pattern);
+            assert_eq!(solver.error(), None, "seed {seed}");
+            assert_eq!(solver.subgraph(), reference.subgraph(), "seed {seed}");
+            solver.clear();
+            reference.clear();
+        }
+        // invalid weights fail the shot instead of aborting
+        let mut syndrome_pattern = SyndromePattern::new_vertices(vec![13, 14]);
+        for (edge_index, weight) in [(0, 3), (1, -2), (edge_num, 2)] {
+            syndrome_pattern.dynamic_weights = vec![(edge_index, weight)];
+            assert_eq!(
+                solver.try_solve(&syndrome_pattern),
+                Err(MicroBlossomError::InvalidDynamicWeight { edge_index })
+            );
+            solver.clear();
+        }
+        syndrome_pattern.dynamic_weights = vec![(0, 2)];
+        syndrome_pattern.erasures = vec![1];
+        assert_eq!(
+            solver.try_solve(&syndrome_pattern),
+            Err(MicroBlossomError::Unsupported {
+                feature: UnsupportedFeature::ErasuresWithDynamicWeights
+            })
+        );
+    }
+
+    /// the second-order offloaders are not implemented by the hardware and thus rejected before the host starts
+    #[test]
+    fn simulation_native_host_simulation_only_offloading() {
+        // cargo test simulation_native_host_simulation_only_offloading -- --nocapture
+        let mut graph = native_host_graph(5);
+        graph.add_second_order_offloading();
+        let sim_config = native_host_sim_config(json!({ "native_host": true, "support_offloading": true }));
+        let result = SimulationTcpClient::new("MicroBlossomHost", graph, "rejected".to_string(), sim_config);
+        assert_eq!(result.err().unwrap().kind(), std::io::ErrorKind::Unsupported);
+    }
+
+    /// the time between loading the syndrome and finishing should reflect the pipeline latency
+    #[test]
+    fn simulation_native_host_timing() {
+        // cargo test simulation_native_host_timing -- --nocapture
+        let decoding_time = |broadcast_delay: usize, clock_divide_by: f64| {
+            let mut host = NativeSimulationHost::new(
+                native_host_graph(3),
+                native_host_sim_config(json!({
+                    "broadcast_delay": broadcast_delay, "clock_divide_by": clock_divide_by
+                })),
+            );
+            host.bus_write(8, 4096, Instruction32::reset().0 as u64);
+            let readout = host.bus_read(8, READOUT_BASE + 40);
+            assert_eq!((readout >> 40) & 0xFF, 0xFF, "no obstacle after reset");
+            let load_time = host.bus_read(8, READOUT_BASE);
+            let finish_time = host.bus_read(8, READOUT_BASE + 8);
+            assert!(host.bus_read(8, 0) >= finish_time, "the timer should not fall behind");
+            assert!(finish_time > load_time);
+            assert_eq!(host.bus_read(4, 48), 0, "no error");
+            finish_time - load_time
+        };
+        let base = decoding_time(0, 1.);
+        assert!(decoding_time(4, 1.) > base);
+        assert!(decoding_time(0, 2.) > base);
+    }
+
+    #[test]
+    fn simulation_native_host_conflict_channels() {
+        // cargo test simulation_native_host_conflict_channels -- --nocapture
+        let graph = native_host_graph(5);
+        let mut host = NativeSimulationHost::new(graph.clone(), native_host_sim_config(json!({ "conflict_channels": 2 })));
+        let hardware_info = host.bus_read(8, 16);
+        assert_eq!(hardware_info & 0xFF, 2);
+        // put two defects on both ends of two non-overlapping edges so that two conflicts happen at the same time
+        let is_virtual = |vertex: usize| graph.virtual_vertices.contains(&vertex);
+        let mut defect_vertices: Vec<usize> = vec![];
+        for edge in graph.weighted_edges.iter() {
+            if defect_vertices.len() < 4
+                && !is_virtual(edge.l)
+                && !is_virtual(edge.r)
+                && !defect_vertices.contains(&edge.l)
+                && !defect_vertices.contains(&edge.r)
+            {
+                defect_vertices.extend([edge.l, edge.r]);
+            }
+        }
+        host.bus_write(8, 4096, Instruction32::reset().0 as u64);
+        for (node_index, &vertex_index) in defect_vertices.iter().enumerate() {
+            let instruction = Instruction32::add_defect_vertex(ni!(vertex_index), ni!(node_index));
+            host.bus_write(8, 4096, instruction.0 as u64);
+        }
+        host.bus_write(2, READOUT_BASE + 16, 1000);
+        let channel_0 = (host.bus_read(8, READOUT_BASE + 32), host.bus_read(8, READOUT_BASE + 40));
+        let channel_1 = (host.bus_read(8, READOUT_BASE + 48), host.bus_read(8, READOUT_BASE + 56));
+        println!("channel 0: {channel_0:?}, channel 1: {channel_1:?}");
+        assert_eq!((channel_0.1 >> 32) & 0xFF, 1, "conflict valid");
+        assert_eq!((channel_1.1 >> 32) & 0xFF, 1, "conflict valid");
+        assert_ne!(channel_0, channel_1);
+        assert_eq!(host.bus_read(4, 48), 0, "no error");
+        // reading a channel beyond the configuration is an error
+        host.bus_read(8, READOUT_BASE + 64);
+        assert_eq!(host.bus_read(4, 48), 1);
+        // so is the reserved extended opcode, which the hardware only uses for debugging
+        let reserved = Instruction32(EXTENDED_OP_CODE_ENABLE | EXTENDED_OP_CODE_RESERVED);
+        assert!(host.decode_instruction(reserved).is_none());
+        host.bus_write(8, 4096, reserved.0 as u64);
+        assert_eq!(host.bus_read(4, 48), 2);
+        // and the unaligned accesses, which are dropped
+        assert_eq!(host.bus_read(4, 50), 0);
+        host.bus_write(8, 4100, Instruction32::reset().0 as u64);
+        host.bus_write(3, 48, 0);
+        assert_eq!(host.bus_read(4, 48), 5);
+    }
+}
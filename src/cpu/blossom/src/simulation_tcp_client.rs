@@ -1,4 +1,5 @@
 use crate::resources::*;
+use crate::simulation_native_host::*;
 use crate::util::*;
 use derivative::Derivative;
 use fusion_blossom::dual_module::*;
@@ -11,6 +12,7 @@ use std::io::{BufReader, LineWriter};
 use std::net::{TcpListener, TcpStream};
 use std::process::Child;
 use std::sync::Mutex;
+use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 use wait_timeout::ChildExt;
 
@@ -64,6 +66,10 @@ pub struct SimulationConfig {
     #[derivative(Default(value = "simulation_config_default::clock_divide_by()"))]
     #[serde(default = "simulation_config_default::clock_divide_by")]
     pub clock_divide_by: f64,
+    /// run the simulation with the native Rust host instead of the Scala host; this is never sent to the host
+    #[derivative(Default(value = "simulation_config_default::native_host()"))]
+    #[serde(default = "simulation_config_default::native_host")]
+    pub native_host: bool,
 }
 
 pub struct SimulationTcpClient {
@@ -78,12 +84,18 @@ pub struct SimulationTcpClient {
 
 pub struct Link {
     pub port: u16,
-    pub child: Child,
+    pub host: SimulationHostHandle,
     pub reader: BufReader<TcpStream>,
     pub writer: LineWriter<TcpStream>,
     pub wall_time: Duration,
 }
 
+/// the simulation host is either a Scala process (Verilator) or a thread running [`NativeSimulationHost`]
+pub enum SimulationHostHandle {
+    Scala(Child),
+    Native(Option<JoinHandle<std::io::Result<()>>>),
+}
+
 impl SimulationTcpClient {
     pub fn new(
         simulation_name: &str,
@@ -92,16 +104,36 @@ impl SimulationTcpClient {
         sim_config: SimulationConfig,
     ) -> std::io::Result<Self> {
         assert!(sim_config.conflict_channels <= MAX_CONFLICT_CHANNELS);
+        if sim_config.support_offloading {
+            if let Some(offloader) = micro_blossom
+                .offloading
+                .0
+                .iter()
+                .find(|offloader| !offloader.is_hardware_supported())
+            {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    format!(
+                        "offloader type {} is simulation-only, use the combinatorial dual module instead",
+                        offloader.name()
+                    ),
+                ));
+            }
+        }
 
         let hostname = "127.0.0.1";
         let listener = TcpListener::bind(format!("{hostname}:0"))?;
         let port = listener.local_addr()?.port();
-        // start the scala simulator host
-        println!("Starting Scala simulator host... this may take a while (listening on {hostname}:{port})");
-        let child = SCALA_MICRO_BLOSSOM_RUNNER.run(
-            format!("microblossom.{simulation_name}").as_str(),
-            [format!("{hostname}"), format!("{port}"), format!("{name}")],
-        )?;
+        let host = if sim_config.native_host {
+            SimulationHostHandle::Native(Some(NativeSimulationHost::spawn(simulation_name, hostname, port)?))
+        } else {
+            // start the scala simulator host
+            println!("Starting Scala simulator host... this may take a while (listening on {hostname}:{port})");
+            SimulationHostHandle::Scala(SCALA_MICRO_BLOSSOM_RUNNER.run(
+                format!("microblossom.{simulation_name}").as_str(),
+                [hostname.to_string(), port.to_string(), name.to_string()],
+            )?)
+        };
         let (socket, _addr) = listener.accept()?;
         let mut reader = BufReader::new(socket.try_clone()?);
         let mut writer = LineWriter::new(socket.try_clone()?);
@@ -114,7 +146,8 @@ impl SimulationTcpClient {
         );
         write!(writer, "{}\n", serde_json::to_string(&micro_blossom).unwrap())?;
         let compile_wall_time = {
-            let simulation_lock = SCALA_SIMULATION_LOCK.lock();
+            // the native host does not compile anything and thus can run in parallel
+            let simulation_lock = (!sim_config.native_host).then(|| SCALA_SIMULATION_LOCK.lock());
             let compile_begin = Instant::now();
             sim_config.write_to(&mut writer)?;
             line.clear();
@@ -132,7 +165,7 @@ impl SimulationTcpClient {
             compile_wall_time,
             link: Mutex::new(Link {
                 port,
-                child,
+                host,
                 reader,
                 writer,
                 wall_time: Duration::ZERO,
@@ -225,17 +258,31 @@ impl SimulationTcpClient {
 // https://stackoverflow.com/questions/30538004/how-do-i-ensure-that-a-spawned-child-process-is-killed-if-my-app-panics
 impl Drop for SimulationTcpClient {
     fn drop(&mut self) {
+        let link = self.link.get_mut().unwrap();
+        let quit_sent = writeln!(link.writer, "quit").is_ok();
+        let child = match &mut link.host {
+            SimulationHostHandle::Scala(child) => child,
+            SimulationHostHandle::Native(handle) => {
+                // the native host quits on its own and does not leave any build folder
+                match handle.take().map(|handle| handle.join()) {
+                    Some(Ok(Err(e))) => println!("Native host quit with error: {}", e),
+                    Some(Err(_)) => println!("Native host panicked"),
+                    _ => {}
+                }
+                return;
+            }
+        };
         let need_to_kill: bool = (|| {
-            if write!(self.link.lock().unwrap().writer, "quit\n").is_ok() {
+            if quit_sent {
                 let wait_time = std::time::Duration::from_millis(1000);
-                if let Ok(Some(status)) = self.link.lock().unwrap().child.wait_timeout(wait_time) {
+                if let Ok(Some(status)) = child.wait_timeout(wait_time) {
                     return !status.success();
                 }
             }
             true
         })();
         if need_to_kill {
-            match self.link.lock().unwrap().child.kill() {
+            match child.kill() {
                 Err(e) => println!("Could not kill Scala process: {}", e),
                 Ok(_) => println!("Successfully killed Scala process"),
             }
@@ -318,15 +365,40 @@ pub mod simulation_config_default {
     pub fn clock_divide_by() -> f64 {
         env_f64("CLOCK_DIVIDE_BY", 1.0)
     }
+    pub fn native_host() -> bool {
+        env_bool("NATIVE_HOST", "SCALA_HOST", false)
+    }
 }
 
+/// keys that only matter to the client; the Scala host reads the other keys in a fixed order
+pub const SIMULATION_CONFIG_CLIENT_KEYS: [&str; 1] = ["native_host"];
+
 impl SimulationConfig {
     pub fn write_to(&self, writer: &mut impl Write) -> std::io::Result<()> {
         let value = serde_json::to_value(self).unwrap();
         let object = value.as_object().unwrap();
         for (key, value) in object {
+            if SIMULATION_CONFIG_CLIENT_KEYS.contains(&key.as_str()) {
+                continue;
+            }
             write!(writer, "{} = {}\n", key, value)?;
         }
         Ok(())
     }
+
+    /// the reverse of [`SimulationConfig::write_to`], used by the native host
+    pub fn read_from(reader: &mut impl BufRead) -> std::io::Result<Self> {
+        let value = serde_json::to_value(Self::default()).unwrap();
+        let key_num = value.as_object().unwrap().len() - SIMULATION_CONFIG_CLIENT_KEYS.len();
+        let mut object = serde_json::Map::new();
+        for _ in 0..key_num {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            let (key, value) = line.trim_end().split_once(" = ").ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, format!("invalid config line: {line}"))
+            })?;
+            object.insert(key.to_string(), serde_json::from_str(value)?);
+        }
+        Ok(serde_json::from_value(serde_json::Value::Object(object))?)
+    }
 }
@@ -0,0 +1,455 @@
+//! Parameter Sweep
+//!
+//! Run a grid of benchmark configurations in parallel and write one row per configuration. Each row is appended
+//! to the output file as soon as the configuration finishes, so that an interrupted sweep can resume by skipping
+//! the configurations already in the file. A configuration is only skipped if its row was produced with the same
+//! number of rounds, seed and primal-dual config.
+//!
+
+use crate::cli::*;
+use crate::logical_observable::*;
+use crate::resources::*;
+use clap::ValueEnum;
+use fusion_blossom::cli::ExampleCodeType;
+use fusion_blossom::util::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum)]
+pub enum SweepFormat {
+    /// comma separated values with a header line
+    Csv,
+    /// one JSON object per line
+    Json,
+}
+
+#[derive(Clone, Debug)]
+pub struct SweepConfiguration {
+    pub d: VertexNum,
+    pub p: f64,
+    pub pe: f64,
+    pub noisy_measurements: VertexNum,
+    pub code_type: ExampleCodeType,
+    pub primal_dual_type: PrimalDualType,
+}
+
+/// the options shared by all the configurations of a sweep
+#[derive(Clone, Debug)]
+pub struct SweepOptions {
+    pub max_half_weight: Weight,
+    pub code_config: serde_json::Value,
+    pub primal_dual_config: serde_json::Value,
+    pub total_rounds: usize,
+    pub seed: u64,
+    /// also estimate the logical error rate; this requires the errors to be sampled on the edges
+    pub logical_error_rate: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SweepRow {
+    pub d: VertexNum,
+    pub p: f64,
+    pub pe: f64,
+    pub noisy_measurements: VertexNum,
+    pub code_type: String,
+    pub primal_dual_type: String,
+    pub total_rounds: usize,
+    pub seed: u64,
+    pub defects_mean: f64,
+    /// decoding latency in seconds, measured around `solve`
+    pub latency_mean: f64,
+    pub latency_p50: f64,
+    pub latency_p90: f64,
+    pub latency_p99: f64,
+    pub latency_max: f64,
+    /// the average number of defects offloaded from the primal module, if reported by the solver
+    pub offloaded_mean: Option<f64>,
+    pub logical_error_rate: Option<f64>,
+    /// the primal-dual config in compact JSON
+    pub primal_dual_config: String,
+}
+
+pub const SWEEP_CSV_HEADER: &str = "d,p,pe,noisy_measurements,code_type,primal_dual_type,total_rounds,seed,\
+defects_mean,latency_mean,latency_p50,latency_p90,latency_p99,latency_max,offloaded_mean,logical_error_rate,\
+primal_dual_config";
+
+/// the number of leading columns in a row that identify the configuration; the last column (the primal-dual config)
+/// is also part of the identity
+const SWEEP_KEY_COLUMNS: usize = 8;
+
+fn value_enum_name(value: &impl ValueEnum) -> String {
+    value.to_possible_value().unwrap().get_name().to_string()
+}
+
+fn optional_to_string(value: Option<f64>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// quote a CSV field, escaping the double quotes inside
+fn csv_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+impl SweepConfiguration {
+    /// the grid of all configurations, in the order of the parameters
+    pub fn grid(
+        d: &[VertexNum],
+        p: &[f64],
+        pe: &[f64],
+        noisy_measurements: &[VertexNum],
+        code_type: &[ExampleCodeType],
+        primal_dual_type: &[PrimalDualType],
+    ) -> Vec<Self> {
+        let mut configurations = vec![];
+        for &code_type in code_type.iter() {
+            for &primal_dual_type in primal_dual_type.iter() {
+                for &d in d.iter() {
+                    for &noisy_measurements in noisy_measurements.iter() {
+                        for &p in p.iter() {
+                            for &pe in pe.iter() {
+                                configurations.push(Self {
+                                    d,
+                                    p,
+                                    pe,
+                                    noisy_measurements,
+                                    code_type,
+                                    primal_dual_type,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        configurations
+    }
+
+    /// the identity of the configuration under the given options, identical to [`SweepRow::key`] of its row
+    pub fn key(&self, options: &SweepOptions) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{}",
+            self.d,
+            self.p,
+            self.pe,
+            self.noisy_measurements,
+            value_enum_name(&self.code_type),
+            value_enum_name(&self.primal_dual_type),
+            options.total_rounds,
+            options.seed,
+            csv_quote(&options.primal_dual_config.to_string())
+        )
+    }
+
+    pub fn run(&self, options: &SweepOptions) -> Result<SweepRow, String> {
+        let mut code = self.code_type.build(
+            self.d,
+            self.p,
+            self.noisy_measurements,
+            options.max_half_weight,
+            options.code_config.clone(),
+        );
+        if self.pe > 0. {
+            code.set_erasure_probability(self.pe);
+        }
+        if options.logical_error_rate && code.immutable_vertices_edges().1.iter().all(|edge| edge.p == 0.) {
+            return Err(format!(
+                "{}: the code does not provide the error probabilities of its edges, cannot estimate the logical error rate",
+                self.key(options)
+            ));
+        }
+        let initializer = code.get_initializer();
+        let positions = code.get_positions();
+        let mut solver = self
+            .primal_dual_type
+            .build(&initializer, &positions, options.primal_dual_config.clone());
+        let mut observable_sampler = options.logical_error_rate.then(|| {
+            let mut graph = MicroBlossomSingle::new_code(code.as_ref());
+            graph.set_boundary_observable(0);
+            let sampler = LogicalErrorSampler::new(code.as_ref(), &graph, options.seed);
+            (graph, sampler)
+        });
+        let mut latencies = Vec::with_capacity(options.total_rounds);
+        let mut defects = 0;
+        let mut offloaded: Option<usize> = None;
+        let mut logical_errors = 0;
+        for round in 0..options.total_rounds {
+            let (syndrome_pattern, actual_observables) = match observable_sampler.as_mut() {
+                Some((_, sampler)) => sampler.generate_random_errors(),
+                None => (code.generate_random_errors(options.seed + round as u64), 0),
+            };
+            defects += syndrome_pattern.defect_vertices.len();
+            let begin = Instant::now();
+            solver.solve(&syndrome_pattern);
+            latencies.push(begin.elapsed().as_secs_f64());
+            if let Some(value) = solver.generate_profiler_report()["primal"]["offloaded"].as_u64() {
+                *offloaded.get_or_insert(0) += value as usize;
+            }
+            if let Some((graph, _)) = observable_sampler.as_ref() {
+                if graph.subgraph_observables(&solver.subgraph()) != actual_observables {
+                    logical_errors += 1;
+                }
+            }
+            solver.clear();
+        }
+        let rounds = options.total_rounds.max(1) as f64;
+        latencies.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let percentile = |fraction: f64| -> f64 {
+            if latencies.is_empty() {
+                return 0.;
+            }
+            let index = ((latencies.len() as f64 * fraction).ceil() as usize).clamp(1, latencies.len());
+            latencies[index - 1]
+        };
+        Ok(SweepRow {
+            d: self.d,
+            p: self.p,
+            pe: self.pe,
+            noisy_measurements: self.noisy_measurements,
+            code_type: value_enum_name(&self.code_type),
+            primal_dual_type: value_enum_name(&self.primal_dual_type),
+            total_rounds: options.total_rounds,
+            seed: options.seed,
+            defects_mean: defects as f64 / rounds,
+            latency_mean: latencies.iter().sum::<f64>() / rounds,
+            latency_p50: percentile(0.5),
+            latency_p90: percentile(0.9),
+            latency_p99: percentile(0.99),
+            latency_max: percentile(1.),
+            offloaded_mean: offloaded.map(|offloaded| offloaded as f64 / rounds),
+            logical_error_rate: options.logical_error_rate.then_some(logical_errors as f64 / rounds),
+            primal_dual_config: options.primal_dual_config.to_string(),
+        })
+    }
+}
+
+impl SweepRow {
+    pub fn key(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{}",
+            self.d,
+            self.p,
+            self.pe,
+            self.noisy_measurements,
+            self.code_type,
+            self.primal_dual_type,
+            self.total_rounds,
+            self.seed,
+            csv_quote(&self.primal_dual_config)
+        )
+    }
+
+    pub fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.d,
+            self.p,
+            self.pe,
+            self.noisy_measurements,
+            self.code_type,
+            self.primal_dual_type,
+            self.total_rounds,
+            self.seed,
+            self.defects_mean,
+            self.latency_mean,
+            self.latency_p50,
+            self.latency_p90,
+            self.latency_p99,
+            self.latency_max,
+            optional_to_string(self.offloaded_mean),
+            optional_to_string(self.logical_error_rate),
+            csv_quote(&self.primal_dual_config)
+        )
+    }
+
+    pub fn to_line(&self, format: SweepFormat) -> String {
+        match format {
+            SweepFormat::Csv => self.to_csv(),
+            SweepFormat::Json => serde_json::to_string(self).unwrap(),
+        }
+    }
+}
+
+/// the keys of the configurations that are already finished in a (partial) output file
+pub fn sweep_finished_keys(content: &str, format: SweepFormat) -> Result<BTreeSet<String>, String> {
+    let mut keys = BTreeSet::new();
+    for (line_index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match format {
+            SweepFormat::Csv => {
+                if line == SWEEP_CSV_HEADER {
+                    continue;
+                }
+                // the primal-dual config is the last column and may contain commas
+                let columns: Vec<&str> = line.splitn(SWEEP_CSV_HEADER.split(',').count(), ',').collect();
+                let primal_dual_config = columns.last().unwrap();
+                if columns.len() != SWEEP_CSV_HEADER.split(',').count() || !primal_dual_config.starts_with('"') {
+                    return Err(format!("line {}: unexpected number of columns", line_index + 1));
+                }
+                keys.insert(format!("{},{primal_dual_config}", columns[..SWEEP_KEY_COLUMNS].join(",")));
+            }
+            SweepFormat::Json => {
+                let row: SweepRow =
+                    serde_json::from_str(line).map_err(|error| format!("line {}: {error}", line_index + 1))?;
+                keys.insert(row.key());
+            }
+        }
+    }
+    Ok(keys)
+}
+
+/// run the configurations that are not yet in `output_file` and append their rows; returns the number of new rows
+pub fn run_sweep(
+    configurations: &[SweepConfiguration],
+    options: &SweepOptions,
+    output_file: &str,
+    format: SweepFormat,
+) -> Result<usize, String> {
+    let content = std::fs::read_to_string(output_file).unwrap_or_default();
+    // the last line of an interrupted sweep may be incomplete: drop it so that its configuration runs again
+    let content = &content[..content.rfind('\n').map(|index| index + 1).unwrap_or(0)];
+    let finished =
+        sweep_finished_keys(content, format).map_err(|error| format!("cannot resume from {output_file}: {error}"))?;
+    let pending: Vec<&SweepConfiguration> = configurations
+        .iter()
+        .filter(|configuration| !finished.contains(&configuration.key(options)))
+        .collect();
+    let mut file = OpenOptions::new().create(true).append(true).open(output_file).unwrap();
+    file.set_len(content.len() as u64).unwrap();
+    if format == SweepFormat::Csv && !content.lines().any(|line| line.trim() == SWEEP_CSV_HEADER) {
+        writeln!(file, "{SWEEP_CSV_HEADER}").unwrap();
+    }
+    let file = Mutex::new(file);
+    pending.par_iter().try_for_each(|configuration| {
+        let row = configuration.run(options)?;
+        let mut file = file.lock().unwrap();
+        writeln!(file, "{}", row.to_line(format)).unwrap();
+        file.flush().unwrap();
+        Ok::<(), String>(())
+    })?;
+    Ok(pending.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn sweep_resume() {
+        // cargo test sweep_resume -- --nocapture
+        let output_file = std::env::temp_dir().join("micro_blossom_sweep_resume.csv");
+        let output_file = output_file.to_str().unwrap();
+        let _ = std::fs::remove_file(output_file);
+        let configurations = SweepConfiguration::grid(
+            &[3, 5],
+            &[0.01, 0.05],
+            &[0.],
+            &[0],
+            &[ExampleCodeType::CodeCapacityPlanarCode],
+            &[PrimalDualType::EmbeddedComb],
+        );
+        assert_eq!(configurations.len(), 4);
+        let options = SweepOptions {
+            max_half_weight: 500,
+            code_config: json!({}),
+            primal_dual_config: json!({}),
+            total_rounds: 20,
+            seed: 0,
+            logical_error_rate: true,
+        };
+        assert_eq!(
+            run_sweep(&configurations[..3], &options, output_file, SweepFormat::Csv),
+            Ok(3)
+        );
+        // an interrupted sweep only runs the remaining configurations, including the one whose row is incomplete
+        let content = std::fs::read_to_string(output_file).unwrap();
+        std::fs::write(output_file, &content[..content.len() - 10]).unwrap();
+        assert_eq!(run_sweep(&configurations, &options, output_file, SweepFormat::Csv), Ok(2));
+        assert_eq!(run_sweep(&configurations, &options, output_file, SweepFormat::Csv), Ok(0));
+        let content = std::fs::read_to_string(output_file).unwrap();
+        println!("{content}");
+        assert_eq!(content.lines().count(), 5);
+        let keys = sweep_finished_keys(&content, SweepFormat::Csv).unwrap();
+        let expected: BTreeSet<String> = configurations
+            .iter()
+            .map(|configuration| configuration.key(&options))
+            .collect();
+        assert_eq!(keys, expected);
+        // every row reports the offloaded defects and the logical error rate
+        for line in content.lines().skip(1) {
+            assert!(!line.ends_with(','));
+            assert!(!line.contains(",,"));
+        }
+        // the rows of other rounds, seeds or primal-dual configs do not count as finished
+        let mut other_options = options.clone();
+        other_options.seed = 1;
+        assert_eq!(
+            run_sweep(&configurations[..1], &other_options, output_file, SweepFormat::Csv),
+            Ok(1)
+        );
+        other_options.total_rounds = 10;
+        assert_eq!(
+            run_sweep(&configurations[..1], &other_options, output_file, SweepFormat::Csv),
+            Ok(1)
+        );
+        other_options.primal_dual_config = json!({ "max_iterations": 1000000 });
+        assert_eq!(
+            run_sweep(&configurations[..1], &other_options, output_file, SweepFormat::Csv),
+            Ok(1)
+        );
+        assert_eq!(
+            run_sweep(&configurations[..1], &other_options, output_file, SweepFormat::Csv),
+            Ok(0)
+        );
+        let content = std::fs::read_to_string(output_file).unwrap();
+        assert_eq!(sweep_finished_keys(&content, SweepFormat::Csv).unwrap().len(), 7);
+        std::fs::remove_file(output_file).unwrap();
+    }
+
+    #[test]
+    fn sweep_json_row() {
+        // cargo test sweep_json_row -- --nocapture
+        let configuration = SweepConfiguration {
+            d: 3,
+            p: 0.1,
+            pe: 0.,
+            noisy_measurements: 0,
+            code_type: ExampleCodeType::CodeCapacityRepetitionCode,
+            primal_dual_type: PrimalDualType::Serial,
+        };
+        let options = SweepOptions {
+            max_half_weight: 500,
+            code_config: json!({}),
+            primal_dual_config: json!({}),
+            total_rounds: 10,
+            seed: 0,
+            logical_error_rate: false,
+        };
+        let row = configuration.run(&options).unwrap();
+        assert_eq!(row.key(), configuration.key(&options));
+        assert_eq!(row.offloaded_mean, None);
+        assert_eq!(row.logical_error_rate, None);
+        assert!(row.latency_p50 <= row.latency_p90 && row.latency_p90 <= row.latency_max);
+        let line = row.to_line(SweepFormat::Json);
+        println!("{line}");
+        let keys = sweep_finished_keys(&line, SweepFormat::Json).unwrap();
+        assert!(keys.contains(&configuration.key(&options)));
+        assert_eq!(
+            sweep_finished_keys(&row.to_line(SweepFormat::Csv), SweepFormat::Csv).unwrap(),
+            keys
+        );
+        // a code without the error probabilities cannot estimate the logical error rate
+        let mut options = options;
+        options.logical_error_rate = true;
+        let configuration = SweepConfiguration { p: 0., ..configuration };
+        assert!(configuration.run(&options).is_err());
+    }
+}
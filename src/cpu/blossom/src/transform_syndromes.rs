@@ -4,8 +4,10 @@ use fusion_blossom::example_codes::*;
 use fusion_blossom::mwpm_solver::*;
 use fusion_blossom::util::*;
 use fusion_blossom::visualize::*;
+use rand::seq::index::sample;
+use rand_xoshiro::rand_core::SeedableRng;
 use serde_json::json;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
 #[derive(Subcommand, Clone)]
 #[allow(clippy::large_enum_variant)]
@@ -16,41 +18,98 @@ pub enum TransformSyndromesType {
         #[clap(value_parser)]
         d: usize,
     },
+    /// for any code, merge the virtual vertices of each boundary class into a single virtual vertex and keep only
+    /// the lightest of the resulting parallel edges, which must flip the same observables; by default, a boundary
+    /// class is a connected component of the virtual vertices (connected by the edges between virtual vertices)
+    MergeVirtualVertices {
+        /// user-provided grouping of the virtual vertices in the format of `[[0,1,2],[3,4]]`; the virtual vertices
+        /// not in any group are kept as they are
+        #[clap(long)]
+        groups_file: Option<String>,
+        /// also write the graph configuration of the transformed syndrome file
+        #[clap(long)]
+        graph_file: Option<String>,
+        /// the graph configuration of the input syndrome file, whose observables and parity reporters are carried
+        /// over to `graph_file`
+        #[clap(long)]
+        source_graph_file: Option<String>,
+    },
+    /// keep only a window of measurement rounds and shift it to start from the first round; the defects, erasures
+    /// and dynamic weights outside the window are dropped. Keep the first k rounds with `--rounds k`.
+    TimeWindow {
+        /// the first measurement round to keep, counting from 0
+        #[clap(long, default_value_t = 0)]
+        start_round: usize,
+        /// the number of measurement rounds to keep
+        #[clap(long)]
+        rounds: usize,
+        /// also write the graph configuration of the transformed syndrome file
+        #[clap(long)]
+        graph_file: Option<String>,
+        /// the graph configuration of the input syndrome file, whose observables are carried over to `graph_file`
+        #[clap(long)]
+        source_graph_file: Option<String>,
+    },
+    /// keep only the shots whose number of defects is within the range
+    FilterDefects {
+        #[clap(long)]
+        min_defects: Option<usize>,
+        #[clap(long)]
+        max_defects: Option<usize>,
+    },
+    /// randomly choose a number of shots without replacement, keeping their original order
+    Subsample {
+        #[clap(value_parser)]
+        shots: usize,
+        #[clap(long, default_value_t = 0)]
+        seed: u64,
+    },
+    /// append the shots of other syndrome files after those of the input file; all the files must have the same
+    /// initializer and positions
+    Concatenate {
+        #[clap(value_parser, required = true)]
+        other_files: Vec<String>,
+    },
 }
 
 impl TransformSyndromesType {
-    pub fn sanity_check(&self, initializer: &SolverInitializer, positions: &Vec<VisualizePosition>) {
+    pub fn sanity_check(&self, initializer: &SolverInitializer, positions: &[VisualizePosition]) -> Result<(), String> {
         match self {
             Self::QecpRotatedPlanarCode { d } => {
                 let d = *d as isize;
                 // first verify the graph structure is as expected
                 let virtual_vertices: BTreeSet<usize> = initializer.virtual_vertices.iter().cloned().collect();
-                let mut max_t = isize::MIN;
-                let mut min_t = isize::MAX;
                 for (vertex_index, position) in positions.iter().enumerate() {
-                    let t = position.t as isize;
-                    max_t = std::cmp::max(max_t, t);
-                    min_t = std::cmp::min(min_t, t);
-                    let i = position.i as isize;
-                    let j = position.j as isize;
-                    assert_eq!(position.t, t as f64);
-                    assert_eq!(position.i, i as f64);
-                    assert_eq!(position.j, j as f64);
-                    assert_eq!(t % 2, 0);
-                    assert_eq!(i % 2, 1);
-                    assert_eq!(j % 2, 0);
-                    assert!(j - i <= d);
-                    assert!(i - j <= d);
-                    assert!(i + j >= d);
-                    assert!(i + j <= 3 * d);
+                    let (t, i, j) = (position.t as isize, position.i as isize, position.j as isize);
+                    let is_valid = position.t == t as f64
+                        && position.i == i as f64
+                        && position.j == j as f64
+                        && t % 2 == 0
+                        && i % 2 == 1
+                        && j % 2 == 0
+                        && j - i <= d
+                        && i - j <= d
+                        && i + j >= d
+                        && i + j <= 3 * d;
+                    if !is_valid {
+                        return Err(format!("vertex {vertex_index} is not in a rotated planar code of d={d}"));
+                    }
                     let is_virtual = j - i == d || i - j == d;
-                    assert_eq!(virtual_vertices.contains(&vertex_index), is_virtual);
+                    if virtual_vertices.contains(&vertex_index) != is_virtual {
+                        return Err(format!("vertex {vertex_index} has a wrong virtual flag for d={d}"));
+                    }
                 }
+                Ok(())
             }
+            Self::MergeVirtualVertices { .. }
+            | Self::TimeWindow { .. }
+            | Self::FilterDefects { .. }
+            | Self::Subsample { .. }
+            | Self::Concatenate { .. } => Ok(()),
         }
     }
 
-    pub fn run(&self, input_file: String, output_file: String) {
+    pub fn run(&self, input_file: String, output_file: String) -> Result<(), String> {
         let mut reader = ErrorPatternReader::new(json!({
             "filename": input_file,
         }));
@@ -59,7 +118,7 @@ impl TransformSyndromesType {
         match self {
             Self::QecpRotatedPlanarCode { d } => {
                 // first verify the graph structure is as expected
-                self.sanity_check(&initializer, &positions);
+                self.sanity_check(&initializer, &positions)?;
                 let d = *d as isize;
                 let mut max_t = isize::MIN;
                 let mut min_t = isize::MAX;
@@ -74,7 +133,7 @@ impl TransformSyndromesType {
                 let mut new_vertex_indices: Vec<usize> = Vec::with_capacity(initializer.vertex_num);
                 // 0 is left boundary, 1 is right boundary
                 let mut vertex_num = 2;
-                let virtual_t = ((max_t + min_t) / 4 * 2) as f64;  // make sure t % 2 == 0
+                let virtual_t = ((max_t + min_t) / 4 * 2) as f64; // make sure t % 2 == 0
                 let mut new_positions = vec![
                     VisualizePosition::new(d as f64, 0., virtual_t),
                     VisualizePosition::new(d as f64, (2 * d) as f64, virtual_t),
@@ -111,17 +170,105 @@ impl TransformSyndromesType {
                     logger.solve_visualizer(&syndrome_pattern, None);
                 }
             }
+            Self::MergeVirtualVertices {
+                graph_file,
+                source_graph_file,
+                ..
+            } => {
+                let source_graph = read_source_graph(source_graph_file, &initializer, &positions)?;
+                let (graph, merge) = source_graph
+                    .merge_virtual_vertices(&self.virtual_vertex_grouping()?)
+                    .map_err(|error| format!("cannot merge virtual vertices: {error}"))?;
+                let syndrome_patterns = reader
+                    .syndrome_patterns
+                    .iter()
+                    .map(|pattern| merge.transform_syndrome(pattern))
+                    .collect::<Result<Vec<_>, _>>()?;
+                write_syndrome_file(&output_file, &merge.initializer, &merge.positions, syndrome_patterns);
+                if let Some(graph_file) = graph_file {
+                    write_graph_file(graph_file, &graph)?;
+                }
+            }
+            Self::TimeWindow {
+                start_round,
+                rounds,
+                graph_file,
+                source_graph_file,
+            } => {
+                let source_graph = read_source_graph(source_graph_file, &initializer, &positions)?;
+                let (graph, window) = source_graph
+                    .time_window(*start_round, *rounds)
+                    .map_err(|error| format!("cannot slice the time window: {error}"))?;
+                let syndrome_patterns = reader
+                    .syndrome_patterns
+                    .iter()
+                    .map(|pattern| window.transform_syndrome(pattern))
+                    .collect::<Result<Vec<_>, _>>()?;
+                write_syndrome_file(&output_file, &window.initializer, &window.positions, syndrome_patterns);
+                if let Some(graph_file) = graph_file {
+                    write_graph_file(graph_file, &graph)?;
+                }
+            }
+            Self::FilterDefects {
+                min_defects,
+                max_defects,
+            } => {
+                let min_defects = min_defects.unwrap_or(0);
+                let max_defects = max_defects.unwrap_or(usize::MAX);
+                let syndrome_patterns = reader
+                    .syndrome_patterns
+                    .into_iter()
+                    .filter(|pattern| (min_defects..=max_defects).contains(&pattern.defect_vertices.len()));
+                write_syndrome_file(&output_file, &initializer, &positions, syndrome_patterns);
+            }
+            Self::Subsample { shots, seed } => {
+                let syndrome_patterns = subsample_syndromes(&reader.syndrome_patterns, *shots, *seed)
+                    .map_err(|error| format!("cannot subsample: {error}"))?;
+                write_syndrome_file(&output_file, &initializer, &positions, syndrome_patterns);
+            }
+            Self::Concatenate { other_files } => {
+                let mut syndrome_patterns = reader.syndrome_patterns;
+                for other_file in other_files.iter() {
+                    let other = ErrorPatternReader::new(json!({
+                        "filename": other_file,
+                    }));
+                    if json!(other.get_initializer()) != json!(initializer) {
+                        return Err(format!("the initializer of {other_file} does not match"));
+                    }
+                    if json!(other.get_positions()) != json!(positions) {
+                        return Err(format!("the positions of {other_file} do not match"));
+                    }
+                    syndrome_patterns.extend(other.syndrome_patterns);
+                }
+                write_syndrome_file(&output_file, &initializer, &positions, syndrome_patterns);
+            }
+        }
+        Ok(())
+    }
+
+    fn virtual_vertex_grouping(&self) -> Result<VirtualVertexGrouping, String> {
+        match self {
+            Self::MergeVirtualVertices {
+                groups_file: Some(groups_file),
+                ..
+            } => {
+                let groups_str = std::fs::read_to_string(groups_file).map_err(|error| format!("{groups_file}: {error}"))?;
+                let groups = serde_json::from_str(&groups_str).map_err(|error| format!("{groups_file}: {error}"))?;
+                Ok(VirtualVertexGrouping::Groups(groups))
+            }
+            _ => Ok(VirtualVertexGrouping::ConnectedComponents),
         }
     }
 
     /// modify the graph; this must not modify the vertex_num, weighted_edges, etc.
-    /// If you do want to modify them, use `Self::run` on the syndrome file themselves.
-    pub fn parse(&self, mut graph: MicroBlossomSingle) -> MicroBlossomSingle {
+    /// If you do want to modify them, use `Self::run` on the syndrome file themselves; those transforms are
+    /// rejected here because the graph would no longer match the syndrome file.
+    pub fn parse(&self, mut graph: MicroBlossomSingle) -> Result<MicroBlossomSingle, String> {
         match self {
             Self::QecpRotatedPlanarCode { d } => {
                 let initializer = graph.get_initializer();
                 let positions = graph.get_positions();
-                self.sanity_check(&initializer, &positions);
+                self.sanity_check(&initializer, &positions)?;
                 let d = *d as isize;
                 // first identify the edges that connects the left boundary
                 let is_left_boundary = |position: &VisualizePosition| (position.i as isize) - (position.j as isize) == d;
@@ -146,8 +293,612 @@ impl TransformSyndromesType {
                 let mut parity_reporters = ParityReporters::new();
                 parity_reporters.add_parity_reporter(left_offloaders);
                 graph.parity_reporters = Some(parity_reporters);
-                graph
+                Ok(graph)
             }
+            Self::MergeVirtualVertices { .. }
+            | Self::TimeWindow { .. }
+            | Self::FilterDefects { .. }
+            | Self::Subsample { .. }
+            | Self::Concatenate { .. } => Err(
+                "this transform changes the syndrome file; run `transform-syndromes` and then parse its output".to_string(),
+            ),
         }
     }
 }
+
+/// the graph configuration of the input syndrome file if given, otherwise inferred from the syndrome file
+fn read_source_graph(
+    source_graph_file: &Option<String>,
+    initializer: &SolverInitializer,
+    positions: &[VisualizePosition],
+) -> Result<MicroBlossomSingle, String> {
+    let Some(source_graph_file) = source_graph_file else {
+        return Ok(MicroBlossomSingle::new(initializer, positions));
+    };
+    let graph_str = std::fs::read_to_string(source_graph_file).map_err(|error| format!("{source_graph_file}: {error}"))?;
+    let source_graph: MicroBlossomSingle =
+        serde_json::from_str(&graph_str).map_err(|error| format!("{source_graph_file}: {error}"))?;
+    if json!(source_graph.get_initializer()) != json!(initializer) {
+        return Err(format!("the initializer of {source_graph_file} does not match"));
+    }
+    Ok(source_graph)
+}
+
+fn write_graph_file(graph_file: &str, graph: &MicroBlossomSingle) -> Result<(), String> {
+    std::fs::write(graph_file, serde_json::to_string(graph).unwrap()).map_err(|error| format!("{graph_file}: {error}"))
+}
+
+fn write_syndrome_file(
+    output_file: &str,
+    initializer: &SolverInitializer,
+    positions: &Vec<VisualizePosition>,
+    syndrome_patterns: impl IntoIterator<Item = SyndromePattern>,
+) {
+    let mut logger = SolverErrorPatternLogger::new(
+        initializer,
+        positions,
+        json!({
+            "filename": output_file,
+        }),
+    );
+    for syndrome_pattern in syndrome_patterns {
+        logger.solve_visualizer(&syndrome_pattern, None);
+    }
+}
+
+/// randomly choose `shots` syndrome patterns without replacement, keeping their original order
+pub fn subsample_syndromes(
+    syndrome_patterns: &[SyndromePattern],
+    shots: usize,
+    seed: u64,
+) -> Result<Vec<SyndromePattern>, String> {
+    if shots > syndrome_patterns.len() {
+        return Err(format!("cannot choose {shots} shots out of {}", syndrome_patterns.len()));
+    }
+    let mut rng = rand_xoshiro::Xoroshiro128StarStar::seed_from_u64(seed);
+    let mut indices = sample(&mut rng, syndrome_patterns.len(), shots).into_vec();
+    indices.sort();
+    Ok(indices.into_iter().map(|index| syndrome_patterns[index].clone()).collect())
+}
+
+/// the time of each measurement round, i.e., the sorted distinct `t` of the real vertices
+pub fn measurement_rounds(initializer: &SolverInitializer, positions: &[VisualizePosition]) -> Vec<f64> {
+    let is_virtual: BTreeSet<VertexIndex> = initializer.virtual_vertices.iter().cloned().collect();
+    let mut rounds: Vec<f64> = (0..initializer.vertex_num)
+        .filter(|vertex_index| !is_virtual.contains(vertex_index))
+        .map(|vertex_index| positions[vertex_index].t)
+        .collect();
+    rounds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    rounds.dedup();
+    rounds
+}
+
+/// a window of consecutive measurement rounds, with the mappings from the original indices
+#[derive(Debug, Clone)]
+pub struct SyndromeTimeWindow {
+    /// the new index of each original vertex; `None` if the vertex is outside the window
+    pub vertex_map: Vec<Option<VertexIndex>>,
+    /// the new index of each original edge; `None` if any of its vertices is outside the window
+    pub edge_map: Vec<Option<EdgeIndex>>,
+    pub initializer: SolverInitializer,
+    pub positions: Vec<VisualizePosition>,
+}
+
+impl SyndromeTimeWindow {
+    /// the real vertices are kept by their measurement round, while a virtual vertex is kept if it connects to any
+    /// kept real vertex; the time is shifted such that the window starts at the time of the first round
+    pub fn new(
+        initializer: &SolverInitializer,
+        positions: &[VisualizePosition],
+        start_round: usize,
+        rounds: usize,
+    ) -> Result<Self, String> {
+        if positions.len() != initializer.vertex_num {
+            return Err(format!(
+                "{} positions for {} vertices",
+                positions.len(),
+                initializer.vertex_num
+            ));
+        }
+        let round_times = measurement_rounds(initializer, positions);
+        if rounds == 0 || start_round + rounds > round_times.len() {
+            return Err(format!(
+                "cannot keep {rounds} rounds from round {start_round} out of {} rounds",
+                round_times.len()
+            ));
+        }
+        let (first_time, last_time) = (round_times[start_round], round_times[start_round + rounds - 1]);
+        let time_shift = first_time - round_times[0];
+        let is_virtual: BTreeSet<VertexIndex> = initializer.virtual_vertices.iter().cloned().collect();
+        let mut is_kept: Vec<bool> = (0..initializer.vertex_num)
+            .map(|vertex_index| {
+                let t = positions[vertex_index].t;
+                !is_virtual.contains(&vertex_index) && t >= first_time && t <= last_time
+            })
+            .collect();
+        let mut kept_virtual = BTreeSet::new();
+        for &(left, right, _) in initializer.weighted_edges.iter() {
+            if is_virtual.contains(&left) && is_kept[right] {
+                kept_virtual.insert(left);
+            }
+            if is_virtual.contains(&right) && is_kept[left] {
+                kept_virtual.insert(right);
+            }
+        }
+        for &vertex_index in kept_virtual.iter() {
+            is_kept[vertex_index] = true;
+        }
+        let mut vertex_map = vec![None; initializer.vertex_num];
+        let mut new_positions = vec![];
+        let mut virtual_vertices = vec![];
+        for vertex_index in 0..initializer.vertex_num {
+            if is_kept[vertex_index] {
+                if is_virtual.contains(&vertex_index) {
+                    virtual_vertices.push(new_positions.len());
+                }
+                vertex_map[vertex_index] = Some(new_positions.len());
+                let position = &positions[vertex_index];
+                new_positions.push(VisualizePosition::new(position.i, position.j, position.t - time_shift));
+            }
+        }
+        let mut edge_map = vec![None; initializer.weighted_edges.len()];
+        let mut weighted_edges = vec![];
+        for (edge_index, &(left, right, weight)) in initializer.weighted_edges.iter().enumerate() {
+            if let (Some(left), Some(right)) = (vertex_map[left], vertex_map[right]) {
+                edge_map[edge_index] = Some(weighted_edges.len());
+                weighted_edges.push((left, right, weight));
+            }
+        }
+        Ok(Self {
+            vertex_map,
+            edge_map,
+            initializer: SolverInitializer::new(new_positions.len(), weighted_edges, virtual_vertices),
+            positions: new_positions,
+        })
+    }
+
+    pub fn transform_syndrome(&self, syndrome_pattern: &SyndromePattern) -> Result<SyndromePattern, String> {
+        check_syndrome_indices(syndrome_pattern, self.vertex_map.len(), self.edge_map.len())?;
+        let defect_vertices = syndrome_pattern
+            .defect_vertices
+            .iter()
+            .filter_map(|&vertex_index| self.vertex_map[vertex_index])
+            .collect();
+        let erasures = syndrome_pattern
+            .erasures
+            .iter()
+            .filter_map(|&edge_index| self.edge_map[edge_index])
+            .collect();
+        let mut transformed = SyndromePattern::new(defect_vertices, erasures);
+        transformed.dynamic_weights = syndrome_pattern
+            .dynamic_weights
+            .iter()
+            .filter_map(|&(edge_index, weight)| self.edge_map[edge_index].map(|new_edge_index| (new_edge_index, weight)))
+            .collect();
+        Ok(transformed)
+    }
+}
+
+/// the indices of the defects, erasures and dynamic weights must be in the graph
+fn check_syndrome_indices(syndrome_pattern: &SyndromePattern, vertex_num: usize, edge_num: usize) -> Result<(), String> {
+    if let Some(vertex_index) = syndrome_pattern.defect_vertices.iter().find(|&&v| v >= vertex_num) {
+        return Err(format!("defect vertex {vertex_index} out of range"));
+    }
+    let edge_indices = (syndrome_pattern.erasures.iter()).chain(syndrome_pattern.dynamic_weights.iter().map(|(e, _)| e));
+    if let Some(edge_index) = edge_indices.into_iter().find(|&&e| e >= edge_num) {
+        return Err(format!("edge {edge_index} out of range"));
+    }
+    Ok(())
+}
+
+/// how to divide the virtual vertices into boundary classes
+#[derive(Debug, Clone)]
+pub enum VirtualVertexGrouping {
+    /// each group is merged into one virtual vertex; the virtual vertices not in any group are kept
+    Groups(Vec<Vec<VertexIndex>>),
+    /// the virtual vertices connected by edges between them are merged
+    ConnectedComponents,
+}
+
+/// the root of a vertex in the union-find forest of the virtual vertices
+fn find_root(parent: &mut BTreeMap<VertexIndex, VertexIndex>, vertex_index: VertexIndex) -> VertexIndex {
+    let mut root = vertex_index;
+    while parent[&root] != root {
+        root = parent[&root];
+    }
+    parent.insert(vertex_index, root);
+    root
+}
+
+/// the result of merging virtual vertices, with the mappings from the original indices
+#[derive(Debug, Clone)]
+pub struct VirtualVertexMerge {
+    /// the new index of each original vertex
+    pub vertex_map: Vec<VertexIndex>,
+    /// the new index of each original edge; `None` if the edge is removed because it connects the same boundary
+    /// class; parallel edges are mapped to the same new edge
+    pub edge_map: Vec<Option<EdgeIndex>>,
+    /// the original edge that each new edge comes from, i.e., the lightest one among the parallel edges
+    pub kept_edges: Vec<EdgeIndex>,
+    /// the weight of each original edge, used when only some of the parallel edges have a dynamic weight
+    pub original_weights: Vec<Weight>,
+    pub initializer: SolverInitializer,
+    pub positions: Vec<VisualizePosition>,
+}
+
+impl VirtualVertexMerge {
+    pub fn new(
+        initializer: &SolverInitializer,
+        positions: &[VisualizePosition],
+        grouping: &VirtualVertexGrouping,
+    ) -> Result<Self, String> {
+        let vertex_num = initializer.vertex_num;
+        if positions.len() != vertex_num {
+            return Err(format!("{} positions for {vertex_num} vertices", positions.len()));
+        }
+        let is_virtual: BTreeSet<VertexIndex> = initializer.virtual_vertices.iter().cloned().collect();
+        // the representative (smallest index) of the boundary class of each virtual vertex
+        let mut representative: BTreeMap<VertexIndex, VertexIndex> = BTreeMap::new();
+        match grouping {
+            VirtualVertexGrouping::Groups(groups) => {
+                for group in groups.iter() {
+                    let Some(&first) = group.iter().min() else {
+                        return Err("empty group".to_string());
+                    };
+                    for &vertex_index in group.iter() {
+                        if !is_virtual.contains(&vertex_index) {
+                            return Err(format!("vertex {vertex_index} is not a virtual vertex"));
+                        }
+                        if representative.insert(vertex_index, first).is_some() {
+                            return Err(format!("virtual vertex {vertex_index} appears in multiple groups"));
+                        }
+                    }
+                }
+            }
+            VirtualVertexGrouping::ConnectedComponents => {
+                let mut parent: BTreeMap<VertexIndex, VertexIndex> = is_virtual.iter().map(|&v| (v, v)).collect();
+                for &(left, right, _) in initializer.weighted_edges.iter() {
+                    if is_virtual.contains(&left) && is_virtual.contains(&right) {
+                        let (left_root, right_root) = (find_root(&mut parent, left), find_root(&mut parent, right));
+                        parent.insert(left_root.max(right_root), left_root.min(right_root));
+                    }
+                }
+                for &vertex_index in is_virtual.iter() {
+                    let root = find_root(&mut parent, vertex_index);
+                    representative.insert(vertex_index, root);
+                }
+            }
+        }
+        // new vertices keep the original order, with each boundary class at its representative
+        let mut vertex_map = vec![usize::MAX; vertex_num];
+        let mut members: Vec<Vec<VertexIndex>> = vec![];
+        for vertex_index in 0..vertex_num {
+            match representative.get(&vertex_index) {
+                Some(&root) if root != vertex_index => {
+                    let new_index = vertex_map[root];
+                    vertex_map[vertex_index] = new_index;
+                    members[new_index].push(vertex_index);
+                }
+                _ => {
+                    vertex_map[vertex_index] = members.len();
+                    members.push(vec![vertex_index]);
+                }
+            }
+        }
+        let positions: Vec<VisualizePosition> = members
+            .iter()
+            .map(|members| {
+                let mean = |axis: fn(&VisualizePosition) -> f64| {
+                    members.iter().map(|&v| axis(&positions[v])).sum::<f64>() / members.len() as f64
+                };
+                VisualizePosition::new(mean(|p| p.i), mean(|p| p.j), mean(|p| p.t))
+            })
+            .collect();
+        let virtual_vertices: Vec<VertexIndex> = (0..members.len())
+            .filter(|&new_index| is_virtual.contains(&members[new_index][0]))
+            .collect();
+        // deduplicate the parallel edges by keeping the minimum weight
+        let mut edge_map = vec![None; initializer.weighted_edges.len()];
+        let mut kept_edges: Vec<EdgeIndex> = vec![];
+        let mut weighted_edges: Vec<(VertexIndex, VertexIndex, Weight)> = vec![];
+        let mut new_edge_indices: BTreeMap<(VertexIndex, VertexIndex), EdgeIndex> = BTreeMap::new();
+        for (edge_index, &(left, right, weight)) in initializer.weighted_edges.iter().enumerate() {
+            let (left, right) = (vertex_map[left], vertex_map[right]);
+            if left == right {
+                continue;
+            }
+            let key = (left.min(right), left.max(right));
+            if let Some(&new_edge_index) = new_edge_indices.get(&key) {
+                if weight < weighted_edges[new_edge_index].2 {
+                    weighted_edges[new_edge_index].2 = weight;
+                    kept_edges[new_edge_index] = edge_index;
+                }
+                edge_map[edge_index] = Some(new_edge_index);
+            } else {
+                let new_edge_index = weighted_edges.len();
+                new_edge_indices.insert(key, new_edge_index);
+                weighted_edges.push((left, right, weight));
+                kept_edges.push(edge_index);
+                edge_map[edge_index] = Some(new_edge_index);
+            }
+        }
+        Ok(Self {
+            vertex_map,
+            edge_map,
+            kept_edges,
+            original_weights: initializer.weighted_edges.iter().map(|&(_, _, weight)| weight).collect(),
+            initializer: SolverInitializer::new(members.len(), weighted_edges, virtual_vertices),
+            positions,
+        })
+    }
+
+    /// map the defects, erasures and dynamic weights to the merged graph; the weight of a merged edge with any
+    /// dynamic weight is the minimum over its parallel edges, each with its dynamic weight or its default weight
+    pub fn transform_syndrome(&self, syndrome_pattern: &SyndromePattern) -> Result<SyndromePattern, String> {
+        check_syndrome_indices(syndrome_pattern, self.vertex_map.len(), self.edge_map.len())?;
+        let defect_vertices = syndrome_pattern
+            .defect_vertices
+            .iter()
+            .map(|&vertex_index| self.vertex_map[vertex_index])
+            .collect();
+        let erasures: BTreeSet<EdgeIndex> = syndrome_pattern
+            .erasures
+            .iter()
+            .filter_map(|&edge_index| self.edge_map[edge_index])
+            .collect();
+        let original_dynamic_weights: BTreeMap<EdgeIndex, Weight> =
+            syndrome_pattern.dynamic_weights.iter().cloned().collect();
+        let mut dynamic_weights: BTreeMap<EdgeIndex, Weight> = original_dynamic_weights
+            .keys()
+            .filter_map(|&edge_index| self.edge_map[edge_index])
+            .map(|new_edge_index| (new_edge_index, Weight::MAX))
+            .collect();
+        for (edge_index, new_edge_index) in self.edge_map.iter().enumerate() {
+            if let Some(weight) = new_edge_index.and_then(|new_edge_index| dynamic_weights.get_mut(&new_edge_index)) {
+                let original_weight = original_dynamic_weights
+                    .get(&edge_index)
+                    .cloned()
+                    .unwrap_or(self.original_weights[edge_index]);
+                *weight = (*weight).min(original_weight);
+            }
+        }
+        let mut transformed = SyndromePattern::new(defect_vertices, erasures.into_iter().collect());
+        transformed.dynamic_weights = dynamic_weights.into_iter().collect();
+        Ok(transformed)
+    }
+}
+
+impl MicroBlossomSingle {
+    /// merge the virtual vertices of the graph; the observables of each new edge are those of the kept edge,
+    /// while the offloading, layer fusion and parity reporters are inferred again
+    pub fn merge_virtual_vertices(&self, grouping: &VirtualVertexGrouping) -> Result<(Self, VirtualVertexMerge), String> {
+        let merge = VirtualVertexMerge::new(&self.get_initializer(), &self.get_positions(), grouping)?;
+        // the parallel edges are interchangeable only if they flip the same observables
+        for (edge_index, new_edge_index) in merge.edge_map.iter().enumerate() {
+            if let &Some(new_edge_index) = new_edge_index {
+                let kept_edge = merge.kept_edges[new_edge_index];
+                if self.weighted_edges[edge_index].observables != self.weighted_edges[kept_edge].observables {
+                    return Err(format!(
+                        "parallel edges {kept_edge} and {edge_index} have different observables"
+                    ));
+                }
+            }
+        }
+        let mut graph = Self::new(&merge.initializer, &merge.positions);
+        for (edge, &original_index) in graph.weighted_edges.iter_mut().zip(merge.kept_edges.iter()) {
+            edge.observables = self.weighted_edges[original_index].observables;
+        }
+        if self.parity_reporters.is_some() {
+            graph.parity_reporters = Some(ParityReporters::from_observables(&graph));
+        }
+        Ok((graph, merge))
+    }
+
+    /// keep a window of measurement rounds of the graph; the observables of each edge are carried over, while the
+    /// offloading, layer fusion and parity reporters are inferred again
+    pub fn time_window(&self, start_round: usize, rounds: usize) -> Result<(Self, SyndromeTimeWindow), String> {
+        let window = SyndromeTimeWindow::new(&self.get_initializer(), &self.get_positions(), start_round, rounds)?;
+        let mut graph = Self::new(&window.initializer, &window.positions);
+        for (edge_index, new_edge_index) in window.edge_map.iter().enumerate() {
+            if let &Some(new_edge_index) = new_edge_index {
+                graph.weighted_edges[new_edge_index].observables = self.weighted_edges[edge_index].observables;
+            }
+        }
+        if self.parity_reporters.is_some() {
+            graph.parity_reporters = Some(ParityReporters::from_observables(&graph));
+        }
+        Ok((graph, window))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transform_syndromes_merge_virtual_vertices() {
+        // cargo test transform_syndromes_merge_virtual_vertices -- --nocapture
+        // 0 - 1 - 2 with virtual vertices 3, 4 on both sides of 0 and 5 connected to 4
+        let initializer = SolverInitializer::new(
+            6,
+            vec![(0, 1, 2), (1, 2, 2), (0, 3, 6), (0, 4, 4), (3, 4, 0), (2, 5, 8), (4, 5, 2)],
+            vec![3, 4, 5],
+        );
+        let positions: Vec<VisualizePosition> = (0..6).map(|i| VisualizePosition::new(i as f64, 0., 0.)).collect();
+        let merge = VirtualVertexMerge::new(&initializer, &positions, &VirtualVertexGrouping::ConnectedComponents).unwrap();
+        assert_eq!(merge.vertex_map, vec![0, 1, 2, 3, 3, 3]);
+        assert_eq!(merge.initializer.vertex_num, 4);
+        assert_eq!(merge.initializer.virtual_vertices, vec![3]);
+        assert_eq!(
+            merge.initializer.weighted_edges,
+            vec![(0, 1, 2), (1, 2, 2), (0, 3, 4), (2, 3, 8)]
+        );
+        assert_eq!(merge.edge_map, vec![Some(0), Some(1), Some(2), Some(2), None, Some(3), None]);
+        assert_eq!(merge.kept_edges, vec![0, 1, 3, 5]);
+        assert_eq!(merge.positions[3].i, 4.);
+        let mut syndrome_pattern = SyndromePattern::new(vec![0, 2], vec![2, 4]);
+        syndrome_pattern.dynamic_weights = vec![(2, 10), (3, 6)];
+        let transformed = merge.transform_syndrome(&syndrome_pattern).unwrap();
+        assert_eq!(transformed.defect_vertices, vec![0, 2]);
+        assert_eq!(transformed.erasures, vec![2]);
+        assert_eq!(transformed.dynamic_weights, vec![(2, 6)]);
+        // the parallel edges without a dynamic weight keep their default weights
+        syndrome_pattern.dynamic_weights = vec![(2, 10)];
+        assert_eq!(
+            merge.transform_syndrome(&syndrome_pattern).unwrap().dynamic_weights,
+            vec![(2, 4)]
+        );
+        syndrome_pattern.dynamic_weights = vec![(3, 10)];
+        assert_eq!(
+            merge.transform_syndrome(&syndrome_pattern).unwrap().dynamic_weights,
+            vec![(2, 6)]
+        );
+        // the indices out of the graph are rejected
+        assert!(merge.transform_syndrome(&SyndromePattern::new_vertices(vec![6])).is_err());
+        assert!(merge.transform_syndrome(&SyndromePattern::new(vec![], vec![7])).is_err());
+        // the parallel edges 2 and 3 must flip the same observables
+        let mut graph = MicroBlossomSingle::new(&initializer, &positions);
+        graph.weighted_edges[2].observables = 1;
+        let error = graph.merge_virtual_vertices(&VirtualVertexGrouping::ConnectedComponents);
+        assert_eq!(
+            error.err(),
+            Some("parallel edges 3 and 2 have different observables".to_string())
+        );
+        graph.weighted_edges[3].observables = 1;
+        assert!(graph
+            .merge_virtual_vertices(&VirtualVertexGrouping::ConnectedComponents)
+            .is_ok());
+        // user-provided grouping keeps vertex 5 separately
+        let grouping = VirtualVertexGrouping::Groups(vec![vec![3, 4]]);
+        let merge = VirtualVertexMerge::new(&initializer, &positions, &grouping).unwrap();
+        assert_eq!(merge.vertex_map, vec![0, 1, 2, 3, 3, 4]);
+        assert_eq!(merge.initializer.virtual_vertices, vec![3, 4]);
+        // invalid groupings
+        for groups in [vec![vec![0, 3]], vec![vec![3], vec![3, 4]], vec![vec![]]] {
+            let grouping = VirtualVertexGrouping::Groups(groups);
+            assert!(VirtualVertexMerge::new(&initializer, &positions, &grouping).is_err());
+        }
+    }
+
+    #[test]
+    fn transform_syndromes_merge_virtual_vertices_planar_code() {
+        // cargo test transform_syndromes_merge_virtual_vertices_planar_code -- --nocapture
+        let d = 5;
+        let mut code = CodeCapacityPlanarCode::new(d, 0.1, 500);
+        let mut graph = MicroBlossomSingle::new_code(&code);
+        graph.set_boundary_observable(0);
+        let initializer = graph.get_initializer();
+        // group the virtual vertices by the side of the code
+        let middle = graph.get_positions().iter().map(|position| position.j).sum::<f64>() / initializer.vertex_num as f64;
+        let (left, right): (Vec<VertexIndex>, Vec<VertexIndex>) = initializer
+            .virtual_vertices
+            .iter()
+            .partition(|&&vertex_index| graph.positions[vertex_index].j < middle);
+        let grouping = VirtualVertexGrouping::Groups(vec![left, right]);
+        let (merged_graph, merge) = graph.merge_virtual_vertices(&grouping).unwrap();
+        assert_eq!(
+            merged_graph.vertex_num,
+            initializer.vertex_num - initializer.virtual_vertices.len() + 2
+        );
+        assert_eq!(merged_graph.get_initializer().virtual_vertices.len(), 2);
+        assert_eq!(merged_graph.observable_num(), 1);
+        let mut solver = SolverSerial::new(&initializer);
+        let mut merged_solver = SolverSerial::new(&merge.initializer);
+        for seed in 0..100 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            let merged_syndrome = merge.transform_syndrome(&syndrome_pattern).unwrap();
+            solver.solve(&syndrome_pattern);
+            merged_solver.solve(&merged_syndrome);
+            let weight = solver.sum_dual_variables();
+            let merged_weight = merged_solver.sum_dual_variables();
+            assert_eq!(weight, merged_weight, "seed {seed}");
+            let observables = graph.subgraph_observables(&solver.subgraph());
+            let merged_observables = merged_graph.subgraph_observables(&merged_solver.subgraph());
+            assert_eq!(observables, merged_observables, "seed {seed}");
+            solver.clear();
+            merged_solver.clear();
+        }
+        // the merged graph does not match the syndrome file, so the parser rejects it
+        let transform = TransformSyndromesType::MergeVirtualVertices {
+            groups_file: None,
+            graph_file: None,
+            source_graph_file: None,
+        };
+        assert!(transform.parse(merged_graph).is_err());
+    }
+
+    #[test]
+    fn transform_syndromes_time_window() {
+        // cargo test transform_syndromes_time_window -- --nocapture
+        let d = 3;
+        let mut code = PhenomenologicalPlanarCode::new(d, 4, 0.05, 500);
+        let initializer = code.get_initializer();
+        let positions = code.get_positions();
+        let rounds = measurement_rounds(&initializer, &positions);
+        assert_eq!(rounds.len(), 5);
+        // the window has the same graph as the code with fewer rounds
+        let window = SyndromeTimeWindow::new(&initializer, &positions, 1, 2).unwrap();
+        let expected = PhenomenologicalPlanarCode::new(d, 1, 0.05, 500);
+        let expected_initializer = expected.get_initializer();
+        assert_eq!(window.initializer.vertex_num, expected_initializer.vertex_num);
+        assert_eq!(window.initializer.virtual_vertices, expected_initializer.virtual_vertices);
+        assert_eq!(window.initializer.weighted_edges, expected_initializer.weighted_edges);
+        assert_eq!(json!(window.positions), json!(expected.get_positions()));
+        for seed in 0..20 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            let transformed = window.transform_syndrome(&syndrome_pattern).unwrap();
+            let expected_defects: Vec<VisualizePosition> = syndrome_pattern
+                .defect_vertices
+                .iter()
+                .map(|&vertex_index| positions[vertex_index].clone())
+                .filter(|position| position.t >= rounds[1] && position.t <= rounds[2])
+                .map(|position| VisualizePosition::new(position.i, position.j, position.t - rounds[1] + rounds[0]))
+                .collect();
+            let defects: Vec<VisualizePosition> = transformed
+                .defect_vertices
+                .iter()
+                .map(|&vertex_index| window.positions[vertex_index].clone())
+                .collect();
+            assert_eq!(json!(defects), json!(expected_defects));
+        }
+        // keep the first round
+        let window = SyndromeTimeWindow::new(&initializer, &positions, 0, 1).unwrap();
+        assert_eq!(window.initializer.vertex_num, d * (d + 1));
+        assert!(SyndromeTimeWindow::new(&initializer, &positions, 4, 2).is_err());
+        assert!(SyndromeTimeWindow::new(&initializer, &positions, 0, 0).is_err());
+        assert!(window
+            .transform_syndrome(&SyndromePattern::new_vertices(vec![initializer.vertex_num]))
+            .is_err());
+        // the graph of the window carries the observables over
+        let mut graph = MicroBlossomSingle::new_code(&code);
+        graph.set_boundary_observable(0);
+        let (window_graph, window) = graph.time_window(1, 2).unwrap();
+        assert_eq!(window_graph.vertex_num, window.initializer.vertex_num);
+        assert_eq!(window_graph.layer_fusion.as_ref().unwrap().num_layers, 2);
+        for (edge_index, new_edge_index) in window.edge_map.iter().enumerate() {
+            if let &Some(new_edge_index) = new_edge_index {
+                assert_eq!(
+                    window_graph.weighted_edges[new_edge_index].observables,
+                    graph.weighted_edges[edge_index].observables
+                );
+            }
+        }
+        assert!(window_graph.observable_num() > 0);
+    }
+
+    #[test]
+    fn transform_syndromes_subsample() {
+        // cargo test transform_syndromes_subsample -- --nocapture
+        let syndrome_patterns: Vec<SyndromePattern> =
+            (0..20).map(|index| SyndromePattern::new_vertices(vec![index])).collect();
+        let chosen = subsample_syndromes(&syndrome_patterns, 5, 0).unwrap();
+        assert_eq!(chosen.len(), 5);
+        assert!(chosen
+            .windows(2)
+            .all(|pair| pair[0].defect_vertices < pair[1].defect_vertices));
+        let again = subsample_syndromes(&syndrome_patterns, 5, 0).unwrap();
+        assert_eq!(json!(chosen), json!(again));
+        assert_eq!(subsample_syndromes(&syndrome_patterns, 20, 1).unwrap().len(), 20);
+        assert!(subsample_syndromes(&syndrome_patterns, 21, 0).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! Weight Quantization
+//!
+//! Convert the error probability of each edge to an integer weight that fits the bit budget of the hardware and the
+//! embedded primal module (e.g. `i16_weight`). The ideal weight `ln((1-p)/p)` is scaled such that the largest half
+//! weight is `max_half_weight` and then rounded; the rounding error is reported so that the bit budget can be chosen
+//! with confidence. Optionally, a Monte Carlo simulation compares the logical error rate of the quantized weights
+//! against a much finer quantization that approximates the floating-point weights.
+//!
+
+use crate::logical_observable::*;
+use crate::resources::*;
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::util::*;
+use serde::Serialize;
+
+/// the reference weights are quantized with this maximum half weight, because the MWPM solvers only take integers
+pub const REFERENCE_MAX_HALF_WEIGHT: Weight = 1 << 20;
+
+/// the ideal weight of an edge with error probability `p`, which is `ln((1-p)/p)`
+pub fn probability_to_weight(p: f64) -> f64 {
+    ((1. - p) / p).ln()
+}
+
+/// the largest half weight such that every weight fits in a signed integer of `weight_bits` bits while leaving
+/// `headroom_bits` bits for the dual variables, which grow up to the length of the longest matched path
+pub fn max_half_weight_for_bits(weight_bits: usize, headroom_bits: usize) -> Weight {
+    assert!(
+        weight_bits > headroom_bits + 2,
+        "not enough bits to represent a non-zero half weight"
+    );
+    assert!(weight_bits <= 64);
+    let max_weight: u64 = (1 << (weight_bits - 1 - headroom_bits)) - 1;
+    (max_weight / 2) as Weight
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuantizedWeights {
+    /// the integer weights of the edges, always even
+    pub weights: Vec<Weight>,
+    pub max_half_weight: Weight,
+    /// half weight per unit of ideal weight
+    pub scale: f64,
+    /// the maximum of `|quantized - ideal| / ideal` over all edges with a positive ideal weight
+    pub max_relative_error: f64,
+    pub mean_relative_error: f64,
+    /// an upper bound of the relative rounding error that only depends on the smallest ideal weight
+    pub relative_error_bound: f64,
+}
+
+impl QuantizedWeights {
+    /// the probabilities must be within (0, 0.5]; a probability of 0.5 gives a zero weight. To avoid turning
+    /// unlikely-but-possible errors into zero-weight edges, the half weight of the other edges is at least 1.
+    pub fn new(probabilities: &[f64], max_half_weight: Weight) -> Result<Self, String> {
+        if max_half_weight <= 0 {
+            return Err(format!("max_half_weight must be positive, got {max_half_weight}"));
+        }
+        for (edge_index, &p) in probabilities.iter().enumerate() {
+            if !(p > 0. && p <= 0.5) {
+                return Err(format!(
+                    "the probability of edge {edge_index} is {p}, which is not within (0, 0.5]"
+                ));
+            }
+        }
+        let ideal_weights: Vec<f64> = probabilities.iter().cloned().map(probability_to_weight).collect();
+        let maximum_weight = ideal_weights.iter().cloned().fold(0., f64::max);
+        let scale = if maximum_weight > 0. {
+            max_half_weight as f64 / maximum_weight
+        } else {
+            0.
+        };
+        let mut weights = Vec::with_capacity(ideal_weights.len());
+        let mut relative_errors = vec![];
+        let mut minimum_ideal_half_weight = f64::INFINITY;
+        for &ideal_weight in ideal_weights.iter() {
+            let ideal_half_weight = ideal_weight * scale;
+            if ideal_half_weight <= 0. {
+                weights.push(0);
+                continue;
+            }
+            let half_weight = (ideal_half_weight.round() as Weight).clamp(1, max_half_weight);
+            relative_errors.push((half_weight as f64 - ideal_half_weight).abs() / ideal_half_weight);
+            minimum_ideal_half_weight = minimum_ideal_half_weight.min(ideal_half_weight);
+            weights.push(2 * half_weight);
+        }
+        let max_relative_error = relative_errors.iter().cloned().fold(0., f64::max);
+        let mean_relative_error = if relative_errors.is_empty() {
+            0.
+        } else {
+            relative_errors.iter().sum::<f64>() / relative_errors.len() as f64
+        };
+        let relative_error_bound = if minimum_ideal_half_weight.is_finite() {
+            // rounding to the nearest integer, or up to 1 when the ideal half weight is below 0.5
+            (0.5 / minimum_ideal_half_weight).max(1. / minimum_ideal_half_weight - 1.)
+        } else {
+            0.
+        };
+        Ok(Self {
+            weights,
+            max_half_weight,
+            scale,
+            max_relative_error,
+            mean_relative_error,
+            relative_error_bound,
+        })
+    }
+
+    pub fn with_bits(probabilities: &[f64], weight_bits: usize, headroom_bits: usize) -> Result<Self, String> {
+        Self::new(probabilities, max_half_weight_for_bits(weight_bits, headroom_bits))
+    }
+}
+
+impl MicroBlossomSingle {
+    /// construct the same graph with different weights; the observables, the parity reporters and the layer fusion
+    /// are kept, while the vertex max growth and the offloading are inferred again
+    pub fn with_weights(&self, weights: &[Weight]) -> Self {
+        assert_eq!(weights.len(), self.weighted_edges.len(), "one weight per edge");
+        let mut initializer = self.get_initializer();
+        for (weighted_edge, &weight) in initializer.weighted_edges.iter_mut().zip(weights.iter()) {
+            weighted_edge.2 = weight;
+        }
+        let mut graph = Self::new(&initializer, &self.get_positions());
+        for (edge, original) in graph.weighted_edges.iter_mut().zip(self.weighted_edges.iter()) {
+            edge.observables = original.observables;
+        }
+        graph.parity_reporters = self.parity_reporters.clone();
+        graph.layer_fusion = self.layer_fusion.clone();
+        graph
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuantizationComparison {
+    /// the logical error rate with [`REFERENCE_MAX_HALF_WEIGHT`], approximating the floating-point weights
+    pub reference: LogicalErrorRate,
+    pub quantized: LogicalErrorRate,
+    /// the number of samples where the two decoders predict different observables
+    pub disagreements: usize,
+}
+
+/// decode the same random errors with the reference and the quantized weights; the observables must be set on the
+/// edges of `graph` (see [`MicroBlossomSingle::set_boundary_observable`])
+pub fn compare_logical_error_rate(
+    graph: &MicroBlossomSingle,
+    probabilities: &[f64],
+    quantized: &QuantizedWeights,
+    total_rounds: usize,
+    seed: u64,
+    confidence: f64,
+) -> Result<QuantizationComparison, String> {
+    let reference = QuantizedWeights::new(probabilities, REFERENCE_MAX_HALF_WEIGHT)?;
+    let reference_graph = graph.with_weights(&reference.weights);
+    let quantized_graph = graph.with_weights(&quantized.weights);
+    let mut reference_solver = SolverSerial::new(&reference_graph.get_initializer());
+    let mut quantized_solver = SolverSerial::new(&quantized_graph.get_initializer());
+    let mut sampler = LogicalErrorSampler::from_probabilities(graph, probabilities, seed);
+    let mut reference_errors = 0;
+    let mut quantized_errors = 0;
+    let mut disagreements = 0;
+    for _ in 0..total_rounds {
+        let (syndrome_pattern, actual_observables) = sampler.generate_random_errors();
+        reference_solver.solve(&syndrome_pattern);
+        quantized_solver.solve(&syndrome_pattern);
+        let reference_observables = graph.subgraph_observables(&reference_solver.subgraph());
+        let quantized_observables = graph.subgraph_observables(&quantized_solver.subgraph());
+        reference_errors += (reference_observables != actual_observables) as usize;
+        quantized_errors += (quantized_observables != actual_observables) as usize;
+        disagreements += (reference_observables != quantized_observables) as usize;
+        reference_solver.clear();
+        quantized_solver.clear();
+    }
+    Ok(QuantizationComparison {
+        reference: LogicalErrorRate::new(reference_errors, total_rounds, confidence),
+        quantized: LogicalErrorRate::new(quantized_errors, total_rounds, confidence),
+        disagreements,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn weight_quantization_error_bound() {
+        // cargo test weight_quantization_error_bound -- --nocapture
+        assert_eq!(max_half_weight_for_bits(16, 0), 16383);
+        assert_eq!(max_half_weight_for_bits(16, 3), 2047);
+        let probabilities = [0.001, 0.01, 0.03, 0.1, 0.2, 0.5];
+        for max_half_weight in [3, 7, 100, 16383] {
+            let quantized = QuantizedWeights::new(&probabilities, max_half_weight).unwrap();
+            println!("{quantized:?}");
+            assert_eq!(quantized.weights[0], 2 * max_half_weight);
+            assert_eq!(quantized.weights[5], 0);
+            assert!(quantized.weights.iter().all(|weight| weight % 2 == 0));
+            assert!(quantized.weights.windows(2).all(|pair| pair[0] >= pair[1]));
+            assert!(quantized.mean_relative_error <= quantized.max_relative_error);
+            assert!(quantized.max_relative_error <= quantized.relative_error_bound + 1e-12);
+        }
+        // the error decreases with more bits
+        let coarse = QuantizedWeights::with_bits(&probabilities, 8, 0).unwrap();
+        let fine = QuantizedWeights::with_bits(&probabilities, 16, 0).unwrap();
+        assert!(fine.max_relative_error < coarse.max_relative_error);
+        assert!(fine.max_relative_error < 1e-3);
+        // invalid inputs
+        assert!(QuantizedWeights::new(&[0.6], 7).is_err());
+        assert!(QuantizedWeights::new(&[0.], 7).is_err());
+        assert!(QuantizedWeights::new(&[0.1], 0).is_err());
+    }
+
+    #[test]
+    fn weight_quantization_logical_error_rate() {
+        // cargo test weight_quantization_logical_error_rate -- --nocapture
+        let code = CodeCapacityPlanarCode::new(5, 0.05, 500);
+        let mut graph = MicroBlossomSingle::new_code(&code);
+        graph.set_boundary_observable(0);
+        // make the edges non-uniform so that the quantization matters
+        let probabilities: Vec<f64> = (0..graph.weighted_edges.len())
+            .map(|edge_index| 0.02 + 0.01 * (edge_index % 5) as f64)
+            .collect();
+        let quantized = QuantizedWeights::new(&probabilities, 7).unwrap();
+        let quantized_graph = graph.with_weights(&quantized.weights);
+        assert_eq!(quantized_graph.observable_num(), 1);
+        assert_eq!(quantized_graph.weighted_edges[0].w, quantized.weights[0]);
+        let comparison = compare_logical_error_rate(&graph, &probabilities, &quantized, 200, 0, 0.95).unwrap();
+        println!("{comparison:?}");
+        assert_eq!(comparison.reference.total_rounds, 200);
+        assert!(comparison.disagreements <= 200);
+        assert!(comparison.quantized.logical_error_rate < 0.2);
+        // a user-defined fusion plan survives the new weights
+        let code = PhenomenologicalPlanarCode::new(3, 4, 0.05, 500);
+        let mut fused_graph = MicroBlossomSingle::new_code(&code);
+        fused_graph
+            .set_layer_fusion_plan(&LayerFusionPlan::time_windows(&fused_graph, 2).unwrap())
+            .unwrap();
+        let weights = vec![2; fused_graph.weighted_edges.len()];
+        assert_eq!(fused_graph.with_weights(&weights).layer_fusion, fused_graph.layer_fusion);
+        // identical weights never disagree
+        let reference = QuantizedWeights::new(&probabilities, REFERENCE_MAX_HALF_WEIGHT).unwrap();
+        let comparison = compare_logical_error_rate(&graph, &probabilities, &reference, 100, 1, 0.95).unwrap();
+        assert_eq!(comparison.disagreements, 0);
+        assert_eq!(comparison.reference.logical_errors, comparison.quantized.logical_errors);
+    }
+}
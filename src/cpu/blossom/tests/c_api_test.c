@@ -0,0 +1,88 @@
+// an example of using the C API, compiled and run by `cargo test binding_c_api_test`
+// usage: c_api_test <graph.json>
+
+#include <assert.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include "micro_blossom.h"
+
+static char *read_file(const char *filename) {
+    FILE *file = fopen(filename, "rb");
+    assert(file != NULL);
+    fseek(file, 0, SEEK_END);
+    long length = ftell(file);
+    fseek(file, 0, SEEK_SET);
+    char *content = malloc(length + 1);
+    assert(fread(content, 1, length, file) == (size_t)length);
+    content[length] = '\0';
+    fclose(file);
+    return content;
+}
+
+// decode the defects and check that every defect is matched exactly once
+static size_t decode(MicroBlossomSolver *solver, const uint32_t *defects, size_t defect_num) {
+    if (micro_blossom_solve(solver, defects, defect_num) != 0) {
+        fprintf(stderr, "error: %s\n", micro_blossom_last_error());
+        abort();
+    }
+    intptr_t matching_num = micro_blossom_get_matchings(solver, NULL, 0);
+    assert(matching_num >= 0);
+    MicroBlossomMatching *matchings = malloc(sizeof(MicroBlossomMatching) * (matching_num + 1));
+    assert(micro_blossom_get_matchings(solver, matchings, matching_num) == matching_num);
+    for (size_t i = 0; i < defect_num; ++i) {
+        int count = 0;
+        for (intptr_t j = 0; j < matching_num; ++j) {
+            count += matchings[j].vertex_1 == defects[i];
+            count += !matchings[j].is_virtual && matchings[j].vertex_2 == defects[i];
+        }
+        assert(count == 1);
+    }
+    free(matchings);
+    intptr_t edge_num = micro_blossom_get_subgraph(solver, NULL, 0);
+    assert(edge_num >= 0);
+    uint32_t *edges = malloc(sizeof(uint32_t) * (edge_num + 1));
+    assert(micro_blossom_get_subgraph(solver, edges, edge_num) == edge_num);
+    free(edges);
+    return (size_t)edge_num;
+}
+
+int main(int argc, char **argv) {
+    assert(argc == 2);
+    char *graph_json = read_file(argv[1]);
+
+    // errors are reported instead of aborting
+    assert(micro_blossom_solver_new(graph_json, "no-such-type", NULL) == NULL);
+    printf("expected error: %s\n", micro_blossom_last_error());
+    assert(strlen(micro_blossom_last_error()) > 0);
+    assert(micro_blossom_solver_new("{}", "serial", NULL) == NULL);
+
+    MicroBlossomSolver *serial = micro_blossom_solver_new(graph_json, "serial", NULL);
+    MicroBlossomSolver *embedded_comb = micro_blossom_solver_new(graph_json, "embedded-comb", "{}");
+    assert(serial != NULL && embedded_comb != NULL);
+    uint32_t invalid_defects[] = {1000000, 2};  // out of range, virtual
+    for (size_t i = 0; i < 2; ++i) {
+        assert(micro_blossom_solve(serial, invalid_defects + i, 1) == -1);
+        printf("expected error: %s\n", micro_blossom_last_error());
+    }
+
+    uint32_t defects[][2] = {{0, 1}, {4, 9}, {1, 8}};
+    for (size_t i = 0; i < sizeof(defects) / sizeof(defects[0]); ++i) {
+        size_t serial_edges = decode(serial, defects[i], 2);
+        size_t embedded_comb_edges = decode(embedded_comb, defects[i], 2);
+        printf("defects [%u, %u]: %zu edges\n", defects[i][0], defects[i][1], serial_edges);
+        assert(serial_edges == embedded_comb_edges);
+    }
+    assert(decode(serial, NULL, 0) == 0);
+
+    char *profiler = micro_blossom_profiler_json(embedded_comb);
+    assert(profiler != NULL && profiler[0] == '{');
+    printf("profiler: %s\n", profiler);
+    micro_blossom_string_free(profiler);
+
+    micro_blossom_solver_free(serial);
+    micro_blossom_solver_free(embedded_comb);
+    free(graph_json);
+    printf("C API test passed\n");
+    return 0;
+}
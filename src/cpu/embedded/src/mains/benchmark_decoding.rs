@@ -4,14 +4,19 @@ use crate::dual_driver::*;
 use crate::util::*;
 use core::cell::UnsafeCell;
 use core::hint::black_box;
+use core::mem::size_of;
 use include_bytes_plus::include_bytes;
 use konst::{option, primitive::parse_usize, result::unwrap_ctx};
+use micro_blossom_nostd::blossom_tracker::*;
+use micro_blossom_nostd::deadline::*;
 use micro_blossom_nostd::dual_driver_tracked::*;
 use micro_blossom_nostd::dual_module_stackless::*;
 use micro_blossom_nostd::instruction::*;
 use micro_blossom_nostd::interface::*;
 use micro_blossom_nostd::latency_benchmarker::*;
 use micro_blossom_nostd::primal_module_embedded::*;
+use micro_blossom_nostd::primal_nodes::*;
+use micro_blossom_nostd::storage::*;
 #[allow(unused_imports)]
 use num_traits::float::FloatCore;
 
@@ -21,6 +26,8 @@ EMBEDDED_BLOSSOM_MAIN=benchmark_decoding make aarch64
 * simulation (in src/cpu/blossom)
 EMBEDDED_BLOSSOM_MAIN=benchmark_decoding SUPPORT_LAYER_FUSION=1 SUPPORT_LOAD_STALL_EMULATOR=1 WITH_WAVEFORM=1 NUM_LAYER_FUSION=1 cargo run --release --bin embedded_simulator -- ../../../resources/syndromes/code_capacity_d3_p0.1.syndromes.json
 EMBEDDED_BLOSSOM_MAIN=benchmark_decoding SUPPORT_LAYER_FUSION=1 SUPPORT_LOAD_STALL_EMULATOR=1 SUPPORT_OFFLOADING=1 WITH_WAVEFORM=1 NUM_LAYER_FUSION=1 cargo run --release --bin embedded_simulator -- ../../../resources/syndromes/code_capacity_d3_p0.1.syndromes.json
+* report: convert the captured output into the schema of `--benchmark-profiler-output` (in src/cpu/blossom)
+cargo run --release -- embedded-report ../../../resources/graphs/example_code_capacity_d3.json output.txt --benchmark-profiler-output profile.txt
 * experiment (in this folder)
 make -C ../../fpga/Xilinx/VMK180_Micro_Blossom clean
 make -C ../../fpga/Xilinx/VMK180_Micro_Blossom DUAL_CONFIG_FILEPATH=$(pwd)/../../../resources/graphs/example_code_capacity_d3.json
@@ -28,8 +35,18 @@ make -C ../../fpga/Xilinx/VMK180_Micro_Blossom
 make -C ../../fpga/Xilinx/VMK180_Micro_Blossom run_a72
 */
 
-// guarantees decoding up to d=39
+// guarantees decoding up to d=39; the actual capacity is chosen from the hardware information at runtime
 pub const MAX_NODE_NUM: usize = unwrap_ctx!(parse_usize(option::unwrap_or!(option_env!("MAX_NODE_NUM"), "65536")));
+/// the memory of the primal module, the blossom tracker and the free defects of at most `MAX_NODE_NUM` nodes, plus
+/// alignment padding
+pub const ARENA_BYTES: usize = MAX_NODE_NUM
+    * (size_of::<Option<PrimalNode>>()
+        + size_of::<OptionCompactNodeIndex>()
+        + size_of::<HitZeroEvent>()
+        + size_of::<(CompactTimestamp, CompactWeight)>()
+        + size_of::<CompactGrowState>()
+        + size_of::<u32>())
+    + 64;
 pub const DEFECTS: &'static [u32] = &include_bytes!("./embedded.defects" as u32le);
 
 /// by default using batch decoding
@@ -45,10 +62,11 @@ pub const IGNORE_EMPTY_DEFECT: bool = option_env!("IGNORE_EMPTY_DEFECT").is_some
 pub const MAX_ROUND: usize = unwrap_ctx!(parse_usize(option::unwrap_or!(option_env!("MAX_ROUND"), "0")));
 /// disabling the detail print will significantly speed up the process
 pub const DISABLE_DETAIL_PRINT: bool = option_env!("DISABLE_DETAIL_PRINT").is_some();
+/// stop resolving obstacles this long after the last syndrome is ready and fall back to a non-optimal matching;
+/// by default 0 which disables the deadline
+pub const DEADLINE_NS: usize = unwrap_ctx!(parse_usize(option::unwrap_or!(option_env!("DEADLINE_NS"), "0")));
 
-static mut PRIMAL_MODULE: UnsafeCell<PrimalModuleEmbedded<MAX_NODE_NUM>> = UnsafeCell::new(PrimalModuleEmbedded::new());
-static mut DUAL_MODULE: UnsafeCell<DualModuleStackless<DualDriverTracked<DualDriver, MAX_NODE_NUM>>> =
-    UnsafeCell::new(DualModuleStackless::new(DualDriverTracked::new(DualDriver::new())));
+static mut ARENA_MEMORY: UnsafeCell<[u8; ARENA_BYTES]> = UnsafeCell::new([0; ARENA_BYTES]);
 static mut LATENCY_BENCHMARKER: UnsafeCell<LatencyBenchmarker> = UnsafeCell::new(LatencyBenchmarker::new_default());
 static mut CPU_WALL_BENCHMARKER: UnsafeCell<LatencyBenchmarker> = UnsafeCell::new(LatencyBenchmarker::new_default());
 
@@ -62,6 +80,7 @@ pub fn main() {
     println!("IGNORE_EMPTY_DEFECT: {IGNORE_EMPTY_DEFECT:?}");
     println!("MAX_ROUND: {MAX_ROUND:?}");
     println!("DISABLE_DETAIL_PRINT: {DISABLE_DETAIL_PRINT:?}");
+    println!("DEADLINE_NS: {DEADLINE_NS:?}");
     println!("-------- end of build parameters --------");
 
     // obtain hardware information
@@ -85,13 +104,21 @@ pub fn main() {
     // delay the syndrome by 1us to make sure the syndrome is stalled at the first decoding instance
     let syndrome_start_delay_ns = 1000u64;
     let syndrome_start_delay_cycle = ((syndrome_start_delay_ns as f32) * 1e-9 * native_frequency) as u64;
+    let deadline_native = ((DEADLINE_NS as f32) * 1e-9 * native_frequency) as u64;
 
-    // create primal and dual modules
+    // create primal and dual modules, sized by the bit width of the hardware so that node index will not overflow
     let context_id = 0;
-    let primal_module = unsafe { PRIMAL_MODULE.get().as_mut().unwrap() };
-    // adapt bit width of primal module so that node index will not overflow
-    primal_module.nodes.blossom_begin = (1 << hardware_info.vertex_bits) / 2;
-    let dual_module = unsafe { DUAL_MODULE.get().as_mut().unwrap() };
+    let node_num = 1usize << hardware_info.vertex_bits;
+    let mut arena = Arena::new(unsafe { ARENA_MEMORY.get().as_mut().unwrap() });
+    let mut primal_module =
+        PrimalModuleEmbeddedSlice::new_in(&mut arena, node_num, 0).expect("MAX_NODE_NUM too small for the hardware");
+    let blossom_tracker =
+        BlossomTrackerSlice::new_in(&mut arena, node_num).expect("MAX_NODE_NUM too small for the hardware");
+    let mut dual_module =
+        DualModuleStackless::new(DualDriverTrackedBase::new_with_tracker(DualDriver::new(), blossom_tracker));
+    let free_defects = arena
+        .alloc_slice(node_num, 0u32)
+        .expect("MAX_NODE_NUM too small for the hardware");
     dual_module.driver.driver.context_id = context_id;
     let mut defects_reader = DefectsReader::new(DEFECTS);
     // calculate useful constant across the evaluations
@@ -109,10 +136,12 @@ pub fn main() {
     let cpu_wall_benchmarker = unsafe { CPU_WALL_BENCHMARKER.get().as_mut().unwrap() };
     let all_begin_native_time = unsafe { extern_c::get_native_time() };
     let mut last_native_time = unsafe { extern_c::get_native_time() };
+    let mut non_optimal_count = 0;
     while let Some(defects) = defects_reader.next() {
         if IGNORE_EMPTY_DEFECT && defects.is_empty() {
             continue;
         }
+        let defect_num = defects.len();
         unsafe { extern_c::clear_instruction_counter() };
         // reset and load defects
         for (node_index, &vertex_index) in defects.iter().enumerate() {
@@ -134,6 +163,10 @@ pub fn main() {
         let syndrome_start = native_start + syndrome_start_delay_cycle; // wait for setting up everything
         let syndrome_finish = syndrome_start + finish_delta;
         unsafe { extern_c::setup_load_stall_emulator(syndrome_start, interval, context_id) };
+        let mut deadline = (DEADLINE_NS != 0)
+            .then(|| NativeTimeDeadline::new(|| unsafe { extern_c::get_native_time() }, syndrome_finish + deadline_native));
+        let mut iteration = 0;
+        let mut expired = false;
         // solve it
         while layer_id < NUM_LAYER_FUSION {
             unsafe {
@@ -155,13 +188,56 @@ pub fn main() {
                     layer_id += 1;
                 }
             }
-            // solve until no obstacle is found
+            // solve until no obstacle is found; after the deadline only the remaining layers are loaded
+            if expired {
+                continue;
+            }
             let (mut obstacle, _) = dual_module.find_obstacle();
             while !obstacle.is_none() {
+                if deadline.is_expired(iteration) {
+                    expired = true;
+                    break;
+                }
                 // println!("obstacle: {obstacle:?}");
-                primal_module.resolve(dual_module, obstacle);
+                primal_module.resolve(&mut dual_module, obstacle);
+                iteration += 1;
+                (obstacle, _) = dual_module.find_obstacle();
+            }
+        }
+        if expired {
+            // keep the matched nodes and decode the rest of the defects again on their own, so that they are matched
+            // to each other or to the virtual vertices; the defects unknown to the primal module are either still
+            // growing or pre-matched by the hardware, and both are safe to decode again
+            primal_module.break_alternating_trees(&mut dual_module);
+            let mut free_num = 0;
+            for (node_index, &vertex_index) in defects.iter().enumerate() {
+                let is_free = !primal_module.nodes.maintains_defect_node(ni!(node_index)) || {
+                    let outer_blossom = primal_module.nodes.get_outer_blossom(ni!(node_index));
+                    primal_module.nodes.get_node(outer_blossom).is_free()
+                };
+                if is_free {
+                    free_defects[free_num] = vertex_index;
+                    free_num += 1;
+                }
+            }
+            primal_module.reset();
+            dual_module.reset();
+            for (node_index, &vertex_index) in free_defects[..free_num].iter().enumerate() {
+                dual_module.add_defect(ni!(vertex_index), ni!(node_index));
+            }
+            layer_id = 0;
+            while layer_id < NUM_LAYER_FUSION {
+                unsafe {
+                    extern_c::execute_instruction(Instruction32::load_syndrome_external(ni!(layer_id)).into(), context_id)
+                };
+                layer_id += 1;
+            }
+            let (mut obstacle, _) = dual_module.find_obstacle();
+            while !obstacle.is_none() {
+                primal_module.resolve(&mut dual_module, obstacle);
                 (obstacle, _) = dual_module.find_obstacle();
             }
+            non_optimal_count += 1;
         }
         let cpu_wall_diff = (unsafe { extern_c::get_fast_cpu_duration_ns(fast_start) } as f64) * 1e-9;
         let counter = unsafe { extern_c::get_instruction_counter() };
@@ -175,14 +251,16 @@ pub fn main() {
         let hardware_diff = unsafe { extern_c::diff_native_time(syndrome_finish, finish_time) } as f64;
         if !DISABLE_DETAIL_PRINT {
             println!(
-                "[{}] time: {:.3}us, counter: {counter}, wall: {:.3}us",
+                "[{}] time: {:.3}us, counter: {counter}, wall: {:.3}us, defects: {}{}",
                 defects_reader.count,
                 hardware_diff * 1e6,
-                cpu_wall_diff * 1e6
+                cpu_wall_diff * 1e6,
+                defect_num,
+                if expired { ", non-optimal" } else { "" }
             );
         }
-        latency_benchmarker.record(hardware_diff);
-        cpu_wall_benchmarker.record(cpu_wall_diff);
+        latency_benchmarker.record(hardware_diff).unwrap();
+        cpu_wall_benchmarker.record(cpu_wall_diff).unwrap();
         primal_module.reset();
         dual_module.reset();
         // early break if reaching the limit
@@ -208,10 +286,17 @@ pub fn main() {
     cpu_wall_benchmarker.println();
     print!("latency_benchmarker");
     latency_benchmarker.println();
+    print!("cpu_wall_benchmarker_json");
+    cpu_wall_benchmarker.println_json();
+    print!("latency_benchmarker_json");
+    latency_benchmarker.println_json();
     println!("cpu_wall_benchmarker statistics:");
     cpu_wall_benchmarker.print_statistics();
     println!("latency_benchmarker statistics:");
     latency_benchmarker.print_statistics();
+    if DEADLINE_NS != 0 {
+        println!("non-optimal count: {non_optimal_count} (deadline expired)");
+    }
     // print overall time consumption for use of estimation
     let all_end_native_time = unsafe { extern_c::get_native_time() };
     let all_duration = unsafe { extern_c::diff_native_time(all_begin_native_time, all_end_native_time) };
@@ -20,17 +20,21 @@ object OffloadVirtualMatch {
       regularSpeed: Speed,
       neighborEdgeIsTight: Seq[Bool],
       neighborVertexIsUniqueTight: Seq[Bool],
-      neighborVertexIsDefect: Seq[Bool]
+      neighborVertexIsDefect: Seq[Bool],
+      neighborVertexIsVirtual: Seq[Bool]
   ) = {
     val numNeighbors = neighborEdgeIsTight.length
     require(neighborVertexIsDefect.length == numNeighbors)
+    require(neighborVertexIsVirtual.length == numNeighbors)
     require(neighborVertexIsUniqueTight.length == numNeighbors)
     require(neighborVertexStalled.length == numNeighbors)
 
     val vertexPreConditions = Vec.fill(numNeighbors)(Bool)
     for (neighborIndex <- 0 until numNeighbors) {
+      // another tight virtual neighbor would be pre-matched by its own offloader at the same time
       vertexPreConditions(neighborIndex) := !neighborEdgeIsTight(neighborIndex) ||
-        (neighborVertexIsUniqueTight(neighborIndex) && !neighborVertexIsDefect(neighborIndex))
+        (neighborVertexIsUniqueTight(neighborIndex) && !neighborVertexIsDefect(neighborIndex) &&
+          !neighborVertexIsVirtual(neighborIndex))
     }
 
     condition := edgeIsTight && virtualIsVirtual && regularIsDefect && (regularSpeed === Speed.Grow) && vertexPreConditions.andR
@@ -56,6 +60,7 @@ case class OffloadVirtualMatch(numNeighbors: Int) extends Component {
     val neighborEdgeIsTight = in(Vec.fill(numNeighbors)(Bool))
     val neighborVertexIsUniqueTight = in(Vec.fill(numNeighbors)(Bool))
     val neighborVertexIsDefect = in(Vec.fill(numNeighbors)(Bool))
+    val neighborVertexIsVirtual = in(Vec.fill(numNeighbors)(Bool))
 
     val condition = out(Bool)
     val neighborVertexStalled = out(Vec.fill(numNeighbors)(Bool))
@@ -70,7 +75,8 @@ case class OffloadVirtualMatch(numNeighbors: Int) extends Component {
     io.regularSpeed,
     io.neighborEdgeIsTight,
     io.neighborVertexIsUniqueTight,
-    io.neighborVertexIsDefect
+    io.neighborVertexIsDefect,
+    io.neighborVertexIsVirtual
   )
 
 }
@@ -102,6 +102,7 @@ case class Offloader(config: DualConfig, offloaderIndex: Int) extends Component
             offloadVirtualMatch.io.neighborEdgeIsTight(localIndex) := neighborEdgeOffloadGet3.isTight
             offloadVirtualMatch.io.neighborVertexIsUniqueTight(localIndex) := vertexOffloadGet3.isUniqueTight
             offloadVirtualMatch.io.neighborVertexIsDefect(localIndex) := vertexOffloadGet3.state.isDefect
+            offloadVirtualMatch.io.neighborVertexIsVirtual(localIndex) := vertexOffloadGet3.state.isVirtual
             stages.offloadSet4.stallVertex(localIndex) := offloadVirtualMatch.io.neighborVertexStalled(localIndex)
           }
         }